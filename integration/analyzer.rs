@@ -0,0 +1,919 @@
+// Analyzer Backend - Diagnostics from a persistent rust-analyzer process
+// The project stays loaded between checks, so feedback doesn't wait on cargo
+
+use proc_macro2::Span;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use syn::spanned::Spanned;
+use syn::visit::Visit;
+
+use crate::error::CompilerError;
+use crate::rust_compiler::{
+    collect_diagnostics, unwrap_positions, CompilationError, CompilationResult, ErrorLevel,
+    ParsedSnippet, SourceRange, WrappedSnippet,
+};
+
+/// How long `AnalyzerBackend::start` waits for rust-analyzer to load the project
+pub const DEFAULT_LOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a check waits for rust-analyzer to publish diagnostics
+pub const DEFAULT_DIAGNOSTICS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a completion candidate is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionKind {
+    Function,
+    Method,
+    Field,
+    Variable,
+    Constant,
+    Module,
+    Struct,
+    Enum,
+    Variant,
+    Trait,
+    TypeParameter,
+    Keyword,
+    Snippet,
+    Other,
+}
+
+/// A completion candidate, as returned by `AnalyzerBackend::complete`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Completion {
+    /// Text shown in the list, e.g. `push(…)`
+    pub name: String,
+    pub kind: CompletionKind,
+    /// Type or signature, e.g. `fn(&mut self, T)`
+    pub signature: Option<String>,
+    /// Doc comment, as markdown
+    pub docs: Option<String>,
+    /// Text to insert, when it differs from `name`
+    pub insert_text: Option<String>,
+}
+
+/// Type and docs of the symbol under the cursor, as returned by
+/// `AnalyzerBackend::hover`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hover {
+    /// Declaration with its inferred type, e.g. `let names: Vec<i32>` or a
+    /// function signature
+    pub signature: String,
+    /// Doc comment, as markdown
+    pub docs: Option<String>,
+    /// The symbol's range in the code
+    pub range: Option<SourceRange>,
+}
+
+/// What an `InferredType` is the type of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InferredTypeKind {
+    /// A variable bound by `let`
+    Binding,
+    /// What a function or method returns
+    Return,
+}
+
+/// The type of a `let` binding or function return, as returned by
+/// `AnalyzerBackend::inferred_types`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InferredType {
+    /// The variable's name, or the function's, with its type for methods,
+    /// e.g. `Cell::divide`
+    pub name: String,
+    pub kind: InferredTypeKind,
+    /// The type, e.g. `Vec<i32>`; `()` for functions without a return type
+    #[serde(rename = "type")]
+    pub type_name: String,
+    /// The variable's or function's name in the code
+    pub span: SourceRange,
+}
+
+/// A rust-analyzer language server with a check project loaded
+///
+/// Checks replace the project's `src/main.rs` in memory and return the
+/// diagnostics rust-analyzer computes itself, which takes milliseconds instead
+/// of the seconds a cargo check takes. rust-analyzer doesn't implement every
+/// check rustc does (most borrow checking, for example), so use this for
+/// feedback while editing and `RustCompiler` for the final word.
+pub struct AnalyzerBackend {
+    server: Child,
+    stdin: ChildStdin,
+    messages: Receiver<Value>,
+    project_dir: PathBuf,
+    next_id: u64,
+    version: i64,
+    diagnostics_timeout: Duration,
+}
+
+impl AnalyzerBackend {
+    /// Start rust-analyzer on a new check project in the system temp directory
+    /// and wait until it has loaded
+    pub fn start() -> Result<Self, CompilerError> {
+        let project_dir = std::env::temp_dir()
+            .join("blockly_rust_check")
+            .join(format!("analyzer_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(project_dir.join("src"))?;
+        fs::write(
+            project_dir.join("Cargo.toml"),
+            "[package]\nname = \"blockly_check\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        )?;
+        fs::write(project_dir.join("src/main.rs"), "fn main() {}\n")?;
+
+        let mut server = match Command::new("rust-analyzer")
+            .current_dir(&project_dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(server) => server,
+            Err(error) => {
+                let _ = fs::remove_dir_all(&project_dir);
+                return Err(CompilerError::ToolchainMissing(format!(
+                    "Failed to start rust-analyzer: {}",
+                    error
+                )));
+            }
+        };
+        let no_pipe = |pipe| CompilerError::ToolFailed(format!("rust-analyzer has no {}", pipe));
+        let stdin = server.stdin.take().ok_or_else(|| no_pipe("stdin"))?;
+        let stdout = server.stdout.take().ok_or_else(|| no_pipe("stdout"))?;
+
+        let (sender, messages) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            while let Some(message) = read_message(&mut reader) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut backend = Self {
+            server,
+            stdin,
+            messages,
+            project_dir,
+            next_id: 1,
+            version: 1,
+            diagnostics_timeout: DEFAULT_DIAGNOSTICS_TIMEOUT,
+        };
+        backend.initialize()?;
+        Ok(backend)
+    }
+
+    /// Set how long a check waits for diagnostics before failing
+    pub fn set_diagnostics_timeout(&mut self, timeout: Duration) {
+        self.diagnostics_timeout = timeout;
+    }
+
+    /// Check Rust code, returning rust-analyzer's diagnostics
+    ///
+    /// Like `RustCompiler::check_code`, the snippet is wrapped in a main
+    /// function if it doesn't have one.
+    pub fn check(&mut self, code: &str) -> Result<CompilationResult, CompilerError> {
+        let started = Instant::now();
+        let wrapped = self.set_code(code)?;
+        let uri = self.main_uri();
+
+        // Pull diagnostics, so the result is for exactly this version
+        let id = self.request(
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": uri } }),
+        )?;
+        let response = self.wait_for(self.diagnostics_timeout, |message| {
+            message.get("id") == Some(&json!(id))
+        })?;
+        if let Some(error) = response.get("error") {
+            return Err(CompilerError::ToolFailed(format!(
+                "rust-analyzer failed to compute diagnostics: {}",
+                error
+            )));
+        }
+
+        let items = response
+            .pointer("/result/items")
+            .and_then(|items| items.as_array())
+            .cloned()
+            .unwrap_or_default();
+        let mut result = diagnostics_result(&items, &wrapped.source);
+        unwrap_positions(&mut result, &wrapped);
+        result.duration_ms = started.elapsed().as_millis() as u64;
+        Ok(result)
+    }
+
+    /// Completion candidates at a 1-based `line` and `column` of `code`
+    ///
+    /// The snippet is wrapped like in `check`, and positions are given in the
+    /// unwrapped code.
+    pub fn complete(
+        &mut self,
+        code: &str,
+        line: usize,
+        column: usize,
+    ) -> Result<Vec<Completion>, CompilerError> {
+        let wrapped = self.set_code(code)?;
+        let id = self.request(
+            "textDocument/completion",
+            json!({
+                "textDocument": { "uri": self.main_uri() },
+                "position": lsp_position(&wrapped, line, column),
+                "context": { "triggerKind": 1 },
+            }),
+        )?;
+        let response = self.wait_for(self.diagnostics_timeout, |message| {
+            message.get("id") == Some(&json!(id))
+        })?;
+        if let Some(error) = response.get("error") {
+            return Err(CompilerError::ToolFailed(format!(
+                "rust-analyzer failed to complete: {}",
+                error
+            )));
+        }
+
+        // The result is either a list of items or an object holding one
+        let result = response.get("result").unwrap_or(&Value::Null);
+        let items = result.as_array().or_else(|| result.get("items").and_then(|i| i.as_array()));
+        Ok(items.into_iter().flatten().map(completion).collect())
+    }
+
+    /// Type and docs of the symbol at a 1-based `line` and `column` of `code`,
+    /// or `None` if there's nothing to describe there
+    ///
+    /// The snippet is wrapped like in `check`, and positions are given in the
+    /// unwrapped code.
+    pub fn hover(
+        &mut self,
+        code: &str,
+        line: usize,
+        column: usize,
+    ) -> Result<Option<Hover>, CompilerError> {
+        let wrapped = self.set_code(code)?;
+        self.hover_at(&wrapped, line, column)
+    }
+
+    /// The type of each `let` binding and function return in `code`, in
+    /// source order
+    ///
+    /// Binding types come from rust-analyzer, so annotated and inferred types
+    /// read the same; bindings whose type it can't work out are left out.
+    /// Return types are the declared ones, as functions must declare them. The
+    /// snippet is wrapped like in `check`, and spans are in the unwrapped code.
+    pub fn inferred_types(&mut self, code: &str) -> Result<Vec<InferredType>, CompilerError> {
+        let wrapped = self.set_code(code)?;
+        let mut found = Vec::new();
+        for item in typed_names(code) {
+            if item.kind == InferredTypeKind::Return {
+                found.push(item);
+                continue;
+            }
+            let hover = self.hover_at(&wrapped, item.span.line_start, item.span.column_start)?;
+            let type_name = hover.as_ref().and_then(|hover| binding_type(&hover.signature));
+            if let Some(type_name) = type_name {
+                found.push(InferredType { type_name, ..item });
+            }
+        }
+        Ok(found)
+    }
+
+    /// Hover at a 1-based `line` and `column` of the code `set_code` last set
+    fn hover_at(
+        &mut self,
+        wrapped: &WrappedSnippet,
+        line: usize,
+        column: usize,
+    ) -> Result<Option<Hover>, CompilerError> {
+        let id = self.request(
+            "textDocument/hover",
+            json!({
+                "textDocument": { "uri": self.main_uri() },
+                "position": lsp_position(wrapped, line, column),
+            }),
+        )?;
+        let response = self.wait_for(self.diagnostics_timeout, |message| {
+            message.get("id") == Some(&json!(id))
+        })?;
+        if let Some(error) = response.get("error") {
+            return Err(CompilerError::ToolFailed(format!(
+                "rust-analyzer failed to hover: {}",
+                error
+            )));
+        }
+
+        let Some(markdown) = response.pointer("/result/contents/value").and_then(|v| v.as_str())
+        else {
+            return Ok(None);
+        };
+        let (signature, docs) = split_hover(markdown);
+        let range = (|| {
+            let (line_start, column_start) =
+                position(response.pointer("/result/range/start")?, &wrapped.source)?;
+            let (line_end, column_end) =
+                position(response.pointer("/result/range/end")?, &wrapped.source)?;
+            Some(SourceRange {
+                line_start: wrapped.code_line(line_start),
+                column_start,
+                line_end: wrapped.code_line(line_end),
+                column_end,
+            })
+        })();
+        Ok(Some(Hover {
+            signature,
+            docs,
+            range,
+        }))
+    }
+
+    /// Replace the open `src/main.rs` with the (wrapped) code, returning the
+    /// wrapping to map positions with
+    fn set_code(&mut self, code: &str) -> Result<WrappedSnippet, CompilerError> {
+        self.version += 1;
+        let uri = self.main_uri();
+        let wrapped = WrappedSnippet::new(code);
+        self.notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": uri, "version": self.version },
+                "contentChanges": [{ "text": wrapped.source }],
+            }),
+        )?;
+        Ok(wrapped)
+    }
+
+    /// The LSP handshake, then open `src/main.rs` and wait for the project to load
+    fn initialize(&mut self) -> Result<(), CompilerError> {
+        let root = file_uri(&self.project_dir);
+        let id = self.request(
+            "initialize",
+            json!({
+                "processId": std::process::id(),
+                "rootUri": root,
+                "capabilities": {
+                    "textDocument": {
+                        "diagnostic": { "dynamicRegistration": false },
+                        "completion": {
+                            "completionItem": { "documentationFormat": ["markdown"] },
+                        },
+                        "hover": { "contentFormat": ["markdown"] },
+                    },
+                    "experimental": { "serverStatusNotification": true },
+                },
+                "initializationOptions": {
+                    // Diagnostics come from rust-analyzer itself, not cargo
+                    "checkOnSave": false,
+                    "cargo": { "buildScripts": { "enable": false } },
+                    "procMacro": { "enable": false },
+                },
+            }),
+        )?;
+        self.wait_for(DEFAULT_LOAD_TIMEOUT, |message| message.get("id") == Some(&json!(id)))?;
+        self.notify("initialized", json!({}))?;
+
+        let uri = self.main_uri();
+        self.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "rust",
+                    "version": self.version,
+                    "text": "fn main() {}\n",
+                },
+            }),
+        )?;
+
+        self.wait_for(DEFAULT_LOAD_TIMEOUT, |message| {
+            message.get("method").and_then(|m| m.as_str()) == Some("experimental/serverStatus")
+                && message.pointer("/params/quiescent") == Some(&json!(true))
+        })?;
+        Ok(())
+    }
+
+    fn main_uri(&self) -> String {
+        file_uri(&self.project_dir.join("src").join("main.rs"))
+    }
+
+    /// Send a request, returning its id
+    fn request(&mut self, method: &str, params: Value) -> Result<u64, CompilerError> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(&json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))?;
+        Ok(id)
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> Result<(), CompilerError> {
+        self.send(&json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    }
+
+    fn send(&mut self, message: &Value) -> Result<(), CompilerError> {
+        let body = message.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+
+    /// Read messages until one matches, answering requests from the server
+    /// along the way; fails if none matches within `timeout`
+    fn wait_for(
+        &mut self,
+        timeout: Duration,
+        matches: impl Fn(&Value) -> bool,
+    ) -> Result<Value, CompilerError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let message = match self.messages.recv_timeout(remaining) {
+                Ok(message) => message,
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(CompilerError::Timeout(
+                        "Timed out waiting for rust-analyzer".to_string(),
+                    ));
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(CompilerError::ToolFailed(
+                        "rust-analyzer exited unexpectedly".to_string(),
+                    ));
+                }
+            };
+
+            if matches(&message) {
+                return Ok(message);
+            }
+            // The server may ask for configuration or to register capabilities;
+            // an empty reply keeps it from waiting on us
+            if let (Some(id), Some(_)) = (message.get("id"), message.get("method")) {
+                let id = id.clone();
+                self.send(&json!({ "jsonrpc": "2.0", "id": id, "result": null }))?;
+            }
+        }
+    }
+}
+
+impl Drop for AnalyzerBackend {
+    fn drop(&mut self) {
+        let _ = self.request("shutdown", Value::Null);
+        let _ = self.notify("exit", Value::Null);
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.server.try_wait() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.server.kill();
+        let _ = self.server.wait();
+        let _ = fs::remove_dir_all(&self.project_dir);
+    }
+}
+
+/// Check if rust-analyzer is installed
+pub fn is_rust_analyzer_available() -> bool {
+    Command::new("rust-analyzer")
+        .arg("--version")
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Read one `Content-Length` framed LSP message, or `None` at end of input
+fn read_message(reader: &mut impl BufRead) -> Option<Value> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let mut body = vec![0; length?];
+    reader.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+/// The `file://` URI of an absolute path, percent-encoding everything but
+/// unreserved characters and separators
+fn file_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        // A Windows path like `C:/...` still needs the leading slash
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// The LSP position of a 1-based `line` and `column` of the snippet
+///
+/// LSP counts characters in UTF-16 code units, while columns count chars.
+fn lsp_position(wrapped: &WrappedSnippet, line: usize, column: usize) -> Value {
+    let line = wrapped.source_line(line).saturating_sub(1);
+    let text = wrapped.source.lines().nth(line).unwrap_or_default();
+    let character: usize = text.chars().take(column.saturating_sub(1)).map(char::len_utf16).sum();
+    json!({ "line": line, "character": character })
+}
+
+/// The 1-based line and char column of an LSP position in `source`
+fn position(position: &Value, source: &str) -> Option<(usize, usize)> {
+    let line = position.get("line")?.as_u64()? as usize;
+    let character = position.get("character")?.as_u64()? as usize;
+    let text = source.lines().nth(line).unwrap_or_default();
+
+    let mut units = 0;
+    let mut column = 1;
+    for c in text.chars() {
+        if units >= character {
+            break;
+        }
+        units += c.len_utf16();
+        column += 1;
+    }
+    Some((line + 1, column + character.saturating_sub(units)))
+}
+
+/// Split rust-analyzer's hover markdown into the declaration, i.e. the last
+/// code block before the first rule, and the docs after it
+fn split_hover(markdown: &str) -> (String, Option<String>) {
+    let (header, docs) = match markdown.split_once("\n---\n") {
+        Some((header, docs)) => (header, Some(docs.trim().to_string())),
+        None => (markdown, None),
+    };
+
+    let mut blocks = Vec::new();
+    let mut current: Option<Vec<&str>> = None;
+    for line in header.lines() {
+        if line.starts_with("```") {
+            match current.take() {
+                Some(block) => blocks.push(block.join("\n")),
+                None => current = Some(Vec::new()),
+            }
+        } else if let Some(block) = current.as_mut() {
+            block.push(line);
+        }
+    }
+
+    let signature = blocks.pop().unwrap_or_else(|| header.trim().to_string());
+    (signature, docs.filter(|docs| !docs.is_empty()))
+}
+
+/// The type in a hover signature for a binding, like `let mut total: i32`, or
+/// `None` if it's not for a binding or rust-analyzer couldn't infer it
+fn binding_type(signature: &str) -> Option<String> {
+    let declaration = signature.lines().next()?.strip_prefix("let ")?;
+    let (_, type_name) = declaration.split_once(": ")?;
+    let type_name = type_name.trim();
+    (!type_name.is_empty() && !type_name.contains("{unknown}")).then(|| type_name.to_string())
+}
+
+/// The `let` bindings and functions in `code`, parsed like `RustCompiler::metrics`
+/// parses it, with the declared return types of the functions; binding types
+/// are left empty for rust-analyzer to fill in
+fn typed_names(code: &str) -> Vec<InferredType> {
+    let mut names = TypedNames { line_offset: 0, owner: None, found: Vec::new() };
+    if let Some(parsed) = ParsedSnippet::parse(code) {
+        names.line_offset = parsed.line_offset();
+        parsed.visit(&mut names);
+    }
+    names.found.sort_by_key(|item| (item.span.line_start, item.span.column_start));
+    names.found
+}
+
+/// Collects `let` bindings and functions, with the type of the `impl` being
+/// visited so methods are named like `Cell::divide`
+struct TypedNames {
+    line_offset: usize,
+    owner: Option<String>,
+    found: Vec<InferredType>,
+}
+
+impl TypedNames {
+    fn push(&mut self, name: String, kind: InferredTypeKind, type_name: String, span: Span) {
+        let (start, end) = (span.start(), span.end());
+        self.found.push(InferredType {
+            name,
+            kind,
+            type_name,
+            span: SourceRange {
+                line_start: start.line - self.line_offset,
+                column_start: start.column + 1,
+                line_end: end.line - self.line_offset,
+                column_end: end.column + 1,
+            },
+        });
+    }
+
+    fn push_function(&mut self, sig: &syn::Signature) {
+        let name = match &self.owner {
+            Some(owner) => format!("{}::{}", owner, sig.ident),
+            None => sig.ident.to_string(),
+        };
+        let type_name = match &sig.output {
+            syn::ReturnType::Default => "()".to_string(),
+            syn::ReturnType::Type(_, ty) => ty.span().source_text().unwrap_or_default(),
+        };
+        self.push(name, InferredTypeKind::Return, type_name, sig.ident.span());
+    }
+}
+
+impl<'ast> Visit<'ast> for TypedNames {
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        // Functions nested in a method aren't its type's
+        let owner = self.owner.take();
+        self.push_function(&item.sig);
+        syn::visit::visit_item_fn(self, item);
+        self.owner = owner;
+    }
+
+    fn visit_item_impl(&mut self, item: &'ast syn::ItemImpl) {
+        let owner = match item.self_ty.as_ref() {
+            syn::Type::Path(path) => path.path.segments.last().map(|s| s.ident.to_string()),
+            _ => None,
+        };
+        let outer = std::mem::replace(&mut self.owner, owner);
+        syn::visit::visit_item_impl(self, item);
+        self.owner = outer;
+    }
+
+    fn visit_impl_item_fn(&mut self, method: &'ast syn::ImplItemFn) {
+        self.push_function(&method.sig);
+        let owner = self.owner.take();
+        syn::visit::visit_impl_item_fn(self, method);
+        self.owner = owner;
+    }
+
+    fn visit_local(&mut self, local: &'ast syn::Local) {
+        let mut bindings = Bindings(Vec::new());
+        bindings.visit_pat(&local.pat);
+        for ident in bindings.0 {
+            self.push(ident.to_string(), InferredTypeKind::Binding, String::new(), ident.span());
+        }
+        syn::visit::visit_local(self, local);
+    }
+}
+
+/// Collects the names a pattern binds
+struct Bindings(Vec<syn::Ident>);
+
+impl<'ast> Visit<'ast> for Bindings {
+    fn visit_pat_ident(&mut self, pat: &'ast syn::PatIdent) {
+        self.0.push(pat.ident.clone());
+        syn::visit::visit_pat_ident(self, pat);
+    }
+}
+
+/// Convert an LSP completion item into a `Completion`
+fn completion(item: &Value) -> Completion {
+    let text = |key: &str| item.get(key).and_then(|v| v.as_str()).map(String::from);
+    let name = text("label").unwrap_or_default();
+    // Documentation is a plain string or markup content
+    let docs = item.get("documentation").and_then(|docs| {
+        docs.as_str().or_else(|| docs.get("value").and_then(|v| v.as_str())).map(String::from)
+    });
+    let insert_text = item
+        .pointer("/textEdit/newText")
+        .and_then(|v| v.as_str())
+        .map(String::from)
+        .or_else(|| text("insertText"))
+        .filter(|insert| *insert != name);
+
+    Completion {
+        kind: match item.get("kind").and_then(|v| v.as_u64()) {
+            Some(2) => CompletionKind::Method,
+            Some(3) | Some(4) => CompletionKind::Function,
+            Some(5) | Some(10) => CompletionKind::Field,
+            Some(6) => CompletionKind::Variable,
+            Some(21) => CompletionKind::Constant,
+            Some(9) => CompletionKind::Module,
+            Some(7) | Some(22) => CompletionKind::Struct,
+            Some(13) => CompletionKind::Enum,
+            Some(20) => CompletionKind::Variant,
+            Some(8) => CompletionKind::Trait,
+            Some(25) => CompletionKind::TypeParameter,
+            Some(14) => CompletionKind::Keyword,
+            Some(15) => CompletionKind::Snippet,
+            _ => CompletionKind::Other,
+        },
+        name,
+        signature: text("detail"),
+        docs,
+        insert_text,
+    }
+}
+
+/// Convert LSP diagnostics on `source` into a `CompilationResult`
+fn diagnostics_result(items: &[Value], source: &str) -> CompilationResult {
+    let mut result = collect_diagnostics(std::iter::empty(), "", "");
+
+    for item in items {
+        let start = item.pointer("/range/start").and_then(|start| position(start, source));
+        let end = item.pointer("/range/end").and_then(|end| position(end, source));
+        let level = match item.get("severity").and_then(|v| v.as_u64()) {
+            Some(2) => ErrorLevel::Warning,
+            Some(3) => ErrorLevel::Note,
+            Some(4) => ErrorLevel::Help,
+            _ => ErrorLevel::Error,
+        };
+        let message = item.get("message").and_then(|v| v.as_str()).unwrap_or_default();
+        let error = CompilationError {
+            code: item.get("code").and_then(|v| v.as_str()).map(String::from),
+            line: start.map(|(line, _)| line),
+            column: start.map(|(_, column)| column),
+            line_end: end.map(|(line, _)| line),
+            column_end: end.map(|(_, column)| column),
+            file: Some("src/main.rs".to_string()),
+            ..CompilationError::new(level.clone(), message)
+        };
+
+        match level {
+            ErrorLevel::Error => result.errors.push(error),
+            ErrorLevel::Warning => result.warnings.push(error),
+            ErrorLevel::Note => result.notes.push(error),
+            ErrorLevel::Help => result.help.push(error),
+        }
+    }
+
+    result.success = result.errors.is_empty();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyzer_diagnostics() {
+        let items = [
+            json!({
+                "range": {
+                    "start": { "line": 1, "character": 4 },
+                    "end": { "line": 1, "character": 9 },
+                },
+                "severity": 1,
+                "code": "E0308",
+                "message": "expected i32, found &str",
+            }),
+            json!({
+                "range": {
+                    "start": { "line": 0, "character": 0 },
+                    "end": { "line": 0, "character": 2 },
+                },
+                "severity": 2,
+                "message": "unused variable",
+            }),
+        ];
+        let result = diagnostics_result(&items, "let x = 1;\nlet y: i32 = \"text\";");
+        assert!(!result.success);
+        assert_eq!(result.errors[0].code.as_deref(), Some("E0308"));
+        assert_eq!((result.errors[0].line, result.errors[0].column), (Some(2), Some(5)));
+        assert_eq!(result.errors[0].column_end, Some(10));
+        assert_eq!(result.warnings.len(), 1);
+
+        // LSP characters are UTF-16 code units, and `🦀` takes two of them
+        let crab = json!({ "line": 0, "character": 3 });
+        assert_eq!(position(&crab, "🦀 x"), Some((1, 3)));
+        let wrapped = WrappedSnippet::new("let s = \"🦀\"; s");
+        assert_eq!(lsp_position(&wrapped, 1, 14)["character"], 14);
+
+        let uri = file_uri(Path::new("/tmp/my project/src/main.rs"));
+        assert_eq!(uri, "file:///tmp/my%20project/src/main.rs");
+
+        if !is_rust_analyzer_available() {
+            println!("Skipping test: rust-analyzer not available");
+            return;
+        }
+
+        let mut backend = AnalyzerBackend::start().unwrap();
+        let result = backend.check("let x: i32 = \"text\";").unwrap();
+        assert!(!result.success, "The type mismatch should be reported");
+        assert!(result.errors[0].line.is_some());
+
+        let result = backend.check("let x: i32 = 1; println!(\"{}\", x);").unwrap();
+        assert!(result.success, "Errors: {:?}", result.errors);
+    }
+
+    #[test]
+    fn test_hover() {
+        let markdown = "```rust\nblockly_check\n```\n\n```rust\nfn double(x: i32) -> i32\n```\n\n\
+                        ---\n\nDoubles a number.";
+        let (signature, docs) = split_hover(markdown);
+        assert_eq!(signature, "fn double(x: i32) -> i32");
+        assert_eq!(docs.as_deref(), Some("Doubles a number."));
+
+        if !is_rust_analyzer_available() {
+            println!("Skipping test: rust-analyzer not available");
+            return;
+        }
+
+        let mut backend = AnalyzerBackend::start().unwrap();
+        let code = "let numbers = vec![1, 2, 3];\nlet total: i32 = numbers.iter().sum();";
+        let hover = backend.hover(code, 1, 6).unwrap().expect("numbers should have a type");
+        assert_eq!(hover.signature, "let numbers: Vec<i32>");
+        let range = hover.range.unwrap();
+        assert_eq!((range.line_start, range.column_start), (1, 5));
+
+        assert!(backend.hover(code, 1, 29).unwrap().is_none(), "Nothing past the end of a line");
+        assert!(backend.hover(code, 0, 0).is_ok(), "Line 0 is clamped to the first line");
+    }
+
+    #[test]
+    fn test_inferred_types() {
+        let code = "struct Cell { energy: u32 }\n\
+                    impl Cell {\n\
+                    \x20   fn split(&self) -> Vec<Cell> { Vec::new() }\n\
+                    }\n\
+                    fn main() {\n\
+                    \x20   let (a, mut b) = (1u8, \"x\");\n\
+                    }\n";
+        let names: Vec<_> = typed_names(code)
+            .into_iter()
+            .map(|item| (item.name, item.kind, item.type_name, item.span.line_start))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("Cell::split".to_string(), InferredTypeKind::Return, "Vec<Cell>".to_string(), 3),
+                ("main".to_string(), InferredTypeKind::Return, "()".to_string(), 5),
+                ("a".to_string(), InferredTypeKind::Binding, String::new(), 6),
+                ("b".to_string(), InferredTypeKind::Binding, String::new(), 6),
+            ]
+        );
+        assert_eq!(binding_type("let mut total: i32"), Some("i32".to_string()));
+        assert_eq!(binding_type("let x: {unknown}"), None);
+
+        if !is_rust_analyzer_available() {
+            println!("Skipping test: rust-analyzer not available");
+            return;
+        }
+
+        let mut backend = AnalyzerBackend::start().unwrap();
+        let code = "let numbers = vec![1, 2, 3];\n\
+                    let mut total: i64 = 0;\n\
+                    let doubled = numbers.len() * 2;";
+        let types: Vec<_> = backend
+            .inferred_types(code)
+            .unwrap()
+            .into_iter()
+            .map(|item| (item.name, item.type_name, item.span.column_start))
+            .collect();
+        assert_eq!(
+            types,
+            [
+                ("numbers".to_string(), "Vec<i32>".to_string(), 5),
+                ("total".to_string(), "i64".to_string(), 9),
+                ("doubled".to_string(), "usize".to_string(), 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_completion() {
+        let item = json!({
+            "label": "push(…)",
+            "kind": 2,
+            "detail": "fn(&mut self, T)",
+            "documentation": { "kind": "markdown", "value": "Appends an element." },
+            "textEdit": { "newText": "push($0)", "range": {} },
+        });
+        let parsed = completion(&item);
+        assert_eq!(parsed.kind, CompletionKind::Method);
+        assert_eq!(parsed.signature.as_deref(), Some("fn(&mut self, T)"));
+        assert_eq!(parsed.docs.as_deref(), Some("Appends an element."));
+        assert_eq!(parsed.insert_text.as_deref(), Some("push($0)"));
+
+        if !is_rust_analyzer_available() {
+            println!("Skipping test: rust-analyzer not available");
+            return;
+        }
+
+        let mut backend = AnalyzerBackend::start().unwrap();
+        let code = "let mut names: Vec<String> = Vec::new();\nnames.";
+        let completions = backend.complete(code, 2, 7).unwrap();
+        let push = completions.iter().find(|c| c.name.starts_with("push"));
+        let push = push.unwrap_or_else(|| panic!("Vec::push should be offered: {:?}", completions));
+        assert_eq!(push.kind, CompletionKind::Method);
+        assert!(push.signature.is_some());
+    }
+}
@@ -0,0 +1,187 @@
+// Compiler Backend - The checks and runs the service needs from a compiler
+// Deployments pick cargo, or bare rustc where cargo isn't installed
+
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::container::ContainerBackend;
+use crate::error::CompilerError;
+use crate::playground::PlaygroundBackend;
+use crate::rust_compiler::{
+    CheckOptions, CompilationResult, CrateType, RunOptions, RunResult, RustCompiler,
+};
+
+/// A way of checking and running code
+pub trait CompilerBackend: Send + Sync {
+    /// Check code with the given options
+    fn check(
+        &self,
+        code: &str,
+        options: &CheckOptions,
+    ) -> Result<CompilationResult, CompilerError>;
+
+    /// Check code as fast as the backend can, without dependencies or wrapping
+    fn quick_check(
+        &self,
+        code: &str,
+        crate_type: CrateType,
+    ) -> Result<CompilationResult, CompilerError>;
+
+    /// Build and run a program
+    fn run(&self, code: &str, options: &RunOptions) -> Result<RunResult, CompilerError>;
+}
+
+/// Checks with cargo, supporting every option
+impl CompilerBackend for RustCompiler {
+    fn check(
+        &self,
+        code: &str,
+        options: &CheckOptions,
+    ) -> Result<CompilationResult, CompilerError> {
+        self.check_with_options(code, options)
+    }
+
+    fn quick_check(
+        &self,
+        code: &str,
+        crate_type: CrateType,
+    ) -> Result<CompilationResult, CompilerError> {
+        self.quick_check_as(code, crate_type)
+    }
+
+    fn run(&self, code: &str, options: &RunOptions) -> Result<RunResult, CompilerError> {
+        self.run_code(code, options)
+    }
+}
+
+/// Checks with bare rustc, for machines without cargo
+///
+/// Checks that need cargo, e.g. with dependencies, fail with
+/// `CompilerError::InvalidInput`; see `RustCompiler::check_with_rustc`.
+pub struct RustcBackend {
+    compiler: Arc<RustCompiler>,
+}
+
+impl RustcBackend {
+    pub fn new(compiler: Arc<RustCompiler>) -> Self {
+        Self { compiler }
+    }
+}
+
+impl CompilerBackend for RustcBackend {
+    fn check(
+        &self,
+        code: &str,
+        options: &CheckOptions,
+    ) -> Result<CompilationResult, CompilerError> {
+        self.compiler.check_with_rustc(code, options)
+    }
+
+    fn quick_check(
+        &self,
+        code: &str,
+        crate_type: CrateType,
+    ) -> Result<CompilationResult, CompilerError> {
+        self.compiler.quick_check_as(code, crate_type)
+    }
+
+    fn run(&self, code: &str, options: &RunOptions) -> Result<RunResult, CompilerError> {
+        self.compiler.run_with_rustc(code, options)
+    }
+}
+
+/// The built-in backends, by name
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    #[default]
+    Cargo,
+    Rustc,
+    /// The official Rust Playground; see `PlaygroundBackend`
+    Playground,
+    /// Cargo in the compiler's containers; see `ContainerBackend`
+    Container,
+}
+
+impl BackendKind {
+    /// The backend, checking with `compiler`
+    ///
+    /// The container backend needs `compiler` to have a container set, and
+    /// fails with `CompilerError::InvalidInput` otherwise.
+    pub fn create(
+        self,
+        compiler: Arc<RustCompiler>,
+    ) -> Result<Arc<dyn CompilerBackend>, CompilerError> {
+        Ok(match self {
+            BackendKind::Cargo => compiler,
+            BackendKind::Rustc => Arc::new(RustcBackend::new(compiler)),
+            BackendKind::Playground => Arc::new(PlaygroundBackend::default()),
+            BackendKind::Container => Arc::new(ContainerBackend::shared(compiler)?),
+        })
+    }
+}
+
+impl std::str::FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "cargo" => Ok(BackendKind::Cargo),
+            "rustc" => Ok(BackendKind::Rustc),
+            "playground" => Ok(BackendKind::Playground),
+            "container" => Ok(BackendKind::Container),
+            _ => Err(format!(
+                "Unknown backend `{}`; expected cargo, rustc, playground or container",
+                name
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rust_compiler::{is_rust_available, Dependency};
+
+    #[test]
+    fn test_rustc_backend() {
+        if !is_rust_available() {
+            println!("Skipping test: rustc not available");
+            return;
+        }
+
+        let kind: BackendKind = "rustc".parse().unwrap();
+        let backend = kind.create(Arc::new(RustCompiler::new().unwrap())).unwrap();
+
+        let result = backend.check("let x: i32 = \"text\";", &CheckOptions::default()).unwrap();
+        assert!(!result.success);
+        assert_eq!(result.errors[0].line, Some(1), "Positions should map to the snippet");
+        assert_eq!(result.errors[0].spans[0].line_start, 1);
+
+        let run = backend.run("println!(\"hi from rustc\");", &RunOptions::default()).unwrap();
+        assert_eq!(run.exit_code, Some(0));
+        assert_eq!(run.stdout, "hi from rustc\n");
+
+        let options = CheckOptions {
+            dependencies: vec![Dependency::new("rand", "0.8")],
+            ..Default::default()
+        };
+        assert!(matches!(
+            backend.check("let x = 1;", &options),
+            Err(CompilerError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_container_backend_needs_container() {
+        let kind = BackendKind::Container;
+        assert!(matches!(
+            kind.create(Arc::new(RustCompiler::new().unwrap())),
+            Err(CompilerError::InvalidInput(_))
+        ));
+
+        let mut compiler = RustCompiler::new().unwrap();
+        compiler.set_container(Some(crate::container::ContainerConfig::default()));
+        assert!(kind.create(Arc::new(compiler)).is_ok());
+    }
+}
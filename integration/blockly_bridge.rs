@@ -1,25 +1,76 @@
 // Blockly Bridge - Integration between Blockly editor and Rust applications
 
+// The docs of the commented-out conversion stub below are kept as docs
+#![allow(clippy::empty_line_after_doc_comments)]
+
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
 
+use crate::error::CompilerError;
+
 /// Load a genome from Blockly-generated JSON
-pub fn load_blockly_genome(path: &Path) -> Result<BlocklyGenome, Box<dyn std::error::Error>> {
+///
+/// Mode fields missing from the file (as written by `save_blockly_genome_compact`)
+/// are filled in with their defaults.
+pub fn load_blockly_genome(path: &Path) -> Result<BlocklyGenome, CompilerError> {
     let content = fs::read_to_string(path)?;
     let genome: BlocklyGenome = serde_json::from_str(&content)?;
     Ok(genome)
 }
 
 /// Save a genome to Blockly-compatible JSON
-pub fn save_blockly_genome(genome: &BlocklyGenome, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+pub fn save_blockly_genome(genome: &BlocklyGenome, path: &Path) -> Result<(), CompilerError> {
     let json = serde_json::to_string_pretty(genome)?;
     fs::write(path, json)?;
     Ok(())
 }
 
+/// Save a genome, omitting mode settings that equal their defaults
+///
+/// Produces smaller files with less diff noise; `load_blockly_genome` fills the
+/// omitted settings back in.
+pub fn save_blockly_genome_compact(
+    genome: &BlocklyGenome,
+    path: &Path,
+) -> Result<(), CompilerError> {
+    let json = serde_json::to_string_pretty(&compact_genome_json(genome)?)?;
+    fs::write(path, json)?;
+    Ok(())
+}
+
+/// Serialize a genome to JSON with default-valued mode settings removed
+pub fn compact_genome_json(genome: &BlocklyGenome) -> Result<serde_json::Value, serde_json::Error> {
+    let mut json = serde_json::to_value(genome)?;
+    let defaults = serde_json::to_value(BlocklyMode::default())?;
+
+    if let Some(modes) = json.get_mut("modes").and_then(|m| m.as_array_mut()) {
+        for mode in modes {
+            strip_defaults(mode, &defaults);
+        }
+    }
+
+    Ok(json)
+}
+
+/// Remove fields of `value` that equal the corresponding field of `defaults`, recursively
+fn strip_defaults(value: &mut serde_json::Value, defaults: &serde_json::Value) {
+    let (Some(fields), Some(defaults)) = (value.as_object_mut(), defaults.as_object()) else {
+        return;
+    };
+
+    fields.retain(|key, field| match defaults.get(key) {
+        Some(default) if field == default => false,
+        Some(default) => {
+            strip_defaults(field, default);
+            true
+        }
+        None => true,
+    });
+}
+
 /// Blockly-compatible genome structure
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BlocklyGenome {
     pub name: String,
     pub initial_mode: usize,
@@ -27,7 +78,8 @@ pub struct BlocklyGenome {
     pub modes: Vec<BlocklyMode>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct BlocklyMode {
     pub name: String,
     pub default_name: String,
@@ -48,7 +100,8 @@ pub struct BlocklyMode {
     pub adhesion_settings: AdhesionSettings,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ChildSettings {
     pub mode_number: usize,
     pub orientation: Quaternion,
@@ -56,7 +109,8 @@ pub struct ChildSettings {
     pub enable_angle_snapping: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AdhesionSettings {
     pub can_break: bool,
     pub break_force: f32,
@@ -71,7 +125,8 @@ pub struct AdhesionSettings {
     pub enable_twist_constraint: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Quaternion {
     pub x: f32,
     pub y: f32,
@@ -79,19 +134,266 @@ pub struct Quaternion {
     pub w: f32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Color3 {
     pub x: f32,
     pub y: f32,
     pub z: f32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Vec2 {
     pub x: f32,
     pub y: f32,
 }
 
+/// Identity rotation
+impl Default for Quaternion {
+    fn default() -> Self {
+        Self { x: 0.0, y: 0.0, z: 0.0, w: 1.0 }
+    }
+}
+
+/// White
+impl Default for Color3 {
+    fn default() -> Self {
+        Self { x: 1.0, y: 1.0, z: 1.0 }
+    }
+}
+
+/// Children return to mode 0, keep their adhesion and snap to angles
+impl Default for ChildSettings {
+    fn default() -> Self {
+        Self {
+            mode_number: 0,
+            orientation: Quaternion::default(),
+            keep_adhesion: true,
+            enable_angle_snapping: true,
+        }
+    }
+}
+
+/// Breakable springs with moderate stiffness and twist constraints enabled
+impl Default for AdhesionSettings {
+    fn default() -> Self {
+        Self {
+            can_break: true,
+            break_force: 10.0,
+            rest_length: 1.0,
+            linear_spring_stiffness: 150.0,
+            linear_spring_damping: 5.0,
+            orientation_spring_stiffness: 10.0,
+            orientation_spring_damping: 2.0,
+            max_angular_deviation: 0.0,
+            twist_constraint_stiffness: 2.0,
+            twist_constraint_damping: 0.5,
+            enable_twist_constraint: true,
+        }
+    }
+}
+
+/// A white mode that divides every 5 seconds, at most `HIGH_MAX_SPLITS` times,
+/// so a new mode passes `BlocklyGenome::performance_lints`
+impl Default for BlocklyMode {
+    fn default() -> Self {
+        Self {
+            name: "Mode 0".to_string(),
+            default_name: "Mode 0".to_string(),
+            color: Color3::default(),
+            cell_type: 0,
+            parent_make_adhesion: false,
+            split_mass: 1.0,
+            split_interval: 5.0,
+            parent_split_direction: Vec2::default(),
+            max_adhesions: 20,
+            min_adhesions: 0,
+            enable_parent_angle_snapping: true,
+            max_splits: HIGH_MAX_SPLITS,
+            mode_a_after_splits: -1,
+            mode_b_after_splits: -1,
+            child_a: ChildSettings::default(),
+            child_b: ChildSettings::default(),
+            adhesion_settings: AdhesionSettings::default(),
+        }
+    }
+}
+
+/// A mode with only some fields set, e.g. while it is being built in the editor
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PartialBlocklyMode {
+    pub name: Option<String>,
+    pub default_name: Option<String>,
+    pub color: Option<Color3>,
+    pub cell_type: Option<u32>,
+    pub parent_make_adhesion: Option<bool>,
+    pub split_mass: Option<f32>,
+    pub split_interval: Option<f32>,
+    pub parent_split_direction: Option<Vec2>,
+    pub max_adhesions: Option<u32>,
+    pub min_adhesions: Option<u32>,
+    pub enable_parent_angle_snapping: Option<bool>,
+    pub max_splits: Option<i32>,
+    pub mode_a_after_splits: Option<i32>,
+    pub mode_b_after_splits: Option<i32>,
+    pub child_a: Option<ChildSettings>,
+    pub child_b: Option<ChildSettings>,
+    pub adhesion_settings: Option<AdhesionSettings>,
+}
+
+/// Split interval (seconds) below which a mode is considered fast-splitting
+pub const FAST_SPLIT_INTERVAL: f32 = 2.0;
+
+/// Split count above which a lineage can reach thousands of cells (2^10 = 1024)
+pub const HIGH_MAX_SPLITS: i32 = 10;
+
+/// Adhesion count above which per-cell constraint solving gets expensive
+pub const HIGH_MAX_ADHESIONS: u32 = 20;
+
+/// Advisory warning about a genome configuration that is likely to be expensive to simulate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenomeLint {
+    pub mode: usize,
+    pub mode_name: String,
+    pub field: String,
+    pub message: String,
+}
+
+impl BlocklyGenome {
+    /// Check the genome for configurations likely to be expensive to simulate
+    ///
+    /// Heuristics, applied per mode:
+    /// - `split_interval` <= 0 splits every simulation step
+    /// - `split_interval` below `FAST_SPLIT_INTERVAL` with unlimited (`max_splits < 0`)
+    ///   or more than `HIGH_MAX_SPLITS` splits grows the population exponentially
+    /// - a mode whose children both return to the same mode with unlimited splits never stops dividing
+    /// - `max_adhesions` above `HIGH_MAX_ADHESIONS` makes adhesion solving expensive
+    ///
+    /// These are advisory only; the genome is still valid.
+    pub fn performance_lints(&self) -> Vec<GenomeLint> {
+        let mut lints = Vec::new();
+
+        for (index, mode) in self.modes.iter().enumerate() {
+            let mut lint = |field: &str, message: String| {
+                lints.push(GenomeLint {
+                    mode: index,
+                    mode_name: mode.name.clone(),
+                    field: field.to_string(),
+                    message,
+                });
+            };
+
+            let unlimited_splits = mode.max_splits < 0;
+
+            if mode.split_interval <= 0.0 {
+                lint(
+                    "split_interval",
+                    format!(
+                        "split_interval of {} splits every simulation step",
+                        mode.split_interval
+                    ),
+                );
+            } else if mode.split_interval < FAST_SPLIT_INTERVAL
+                && (unlimited_splits || mode.max_splits > HIGH_MAX_SPLITS)
+            {
+                let growth = if unlimited_splits {
+                    "unbounded".to_string()
+                } else {
+                    format!("up to 2^{}", mode.max_splits)
+                };
+                lint(
+                    "split_interval",
+                    format!(
+                        "split_interval of {}s with max_splits {} can produce {} cells; consider a longer interval or fewer splits",
+                        mode.split_interval, mode.max_splits, growth
+                    ),
+                );
+            }
+
+            if unlimited_splits
+                && mode.child_a.mode_number == index
+                && mode.child_b.mode_number == index
+            {
+                lint(
+                    "max_splits",
+                    "both children return to this mode and max_splits is unlimited, so cells never stop dividing".to_string(),
+                );
+            }
+
+            if mode.max_adhesions > HIGH_MAX_ADHESIONS {
+                lint(
+                    "max_adhesions",
+                    format!(
+                        "max_adhesions of {} makes adhesion constraint solving expensive",
+                        mode.max_adhesions
+                    ),
+                );
+            }
+        }
+
+        lints
+    }
+
+    /// Complete a partially specified mode that is about to be appended to the genome
+    ///
+    /// Unset fields are copied from the last existing mode, so a new mode inherits
+    /// e.g. its siblings' adhesion settings, or from `BlocklyMode::default()` if the
+    /// genome has no modes yet. The name defaults to `Mode <index>` and children that
+    /// aren't specified split into copies of the new mode.
+    pub fn complete_mode(&self, partial: &PartialBlocklyMode) -> BlocklyMode {
+        let index = self.modes.len();
+        let template = self.modes.last().cloned().unwrap_or_default();
+        let name = format!("Mode {}", index);
+
+        let own_child = |template: ChildSettings| ChildSettings {
+            mode_number: index,
+            ..template
+        };
+
+        BlocklyMode {
+            name: partial.name.clone().unwrap_or_else(|| name.clone()),
+            default_name: partial.default_name.clone().unwrap_or(name),
+            color: partial.color.clone().unwrap_or(template.color),
+            cell_type: partial.cell_type.unwrap_or(template.cell_type),
+            parent_make_adhesion: partial
+                .parent_make_adhesion
+                .unwrap_or(template.parent_make_adhesion),
+            split_mass: partial.split_mass.unwrap_or(template.split_mass),
+            split_interval: partial.split_interval.unwrap_or(template.split_interval),
+            parent_split_direction: partial
+                .parent_split_direction
+                .clone()
+                .unwrap_or(template.parent_split_direction),
+            max_adhesions: partial.max_adhesions.unwrap_or(template.max_adhesions),
+            min_adhesions: partial.min_adhesions.unwrap_or(template.min_adhesions),
+            enable_parent_angle_snapping: partial
+                .enable_parent_angle_snapping
+                .unwrap_or(template.enable_parent_angle_snapping),
+            max_splits: partial.max_splits.unwrap_or(template.max_splits),
+            mode_a_after_splits: partial
+                .mode_a_after_splits
+                .unwrap_or(template.mode_a_after_splits),
+            mode_b_after_splits: partial
+                .mode_b_after_splits
+                .unwrap_or(template.mode_b_after_splits),
+            child_a: partial
+                .child_a
+                .clone()
+                .unwrap_or_else(|| own_child(template.child_a)),
+            child_b: partial
+                .child_b
+                .clone()
+                .unwrap_or_else(|| own_child(template.child_b)),
+            adhesion_settings: partial
+                .adhesion_settings
+                .clone()
+                .unwrap_or(template.adhesion_settings),
+        }
+    }
+}
+
 /// Convert Blockly genome to application-specific internal format
 /// Note: Implement this when integrating with your genome system
 // impl BlocklyGenome {
@@ -114,9 +416,9 @@ pub fn validate_wgsl_shader(source: &str) -> Result<(), String> {
     Ok(())
 }
 
-pub fn load_blockly_wgsl(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+pub fn load_blockly_wgsl(path: &Path) -> Result<String, CompilerError> {
     let source = fs::read_to_string(path)?;
-    validate_wgsl_shader(&source)?;
+    validate_wgsl_shader(&source).map_err(CompilerError::Parse)?;
     Ok(source)
 }
 
@@ -140,6 +442,131 @@ mod tests {
         assert_eq!(genome.initial_mode, deserialized.initial_mode);
     }
 
+    fn test_mode(split_interval: f32, max_splits: i32) -> BlocklyMode {
+        let child = ChildSettings {
+            mode_number: 0,
+            orientation: Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 },
+            keep_adhesion: true,
+            enable_angle_snapping: false,
+        };
+
+        BlocklyMode {
+            name: "Mode 0".to_string(),
+            default_name: "Mode 0".to_string(),
+            color: Color3 { x: 1.0, y: 1.0, z: 1.0 },
+            cell_type: 0,
+            parent_make_adhesion: false,
+            split_mass: 1.0,
+            split_interval,
+            parent_split_direction: Vec2 { x: 0.0, y: 0.0 },
+            max_adhesions: 4,
+            min_adhesions: 0,
+            enable_parent_angle_snapping: false,
+            max_splits,
+            mode_a_after_splits: 0,
+            mode_b_after_splits: 0,
+            child_a: child.clone(),
+            child_b: child,
+            adhesion_settings: AdhesionSettings {
+                can_break: true,
+                break_force: 10.0,
+                rest_length: 1.0,
+                linear_spring_stiffness: 150.0,
+                linear_spring_damping: 5.0,
+                orientation_spring_stiffness: 10.0,
+                orientation_spring_damping: 2.0,
+                max_angular_deviation: 0.0,
+                twist_constraint_stiffness: 2.0,
+                twist_constraint_damping: 0.5,
+                enable_twist_constraint: false,
+            },
+        }
+    }
+
+    #[test]
+    fn test_performance_lints() {
+        let mut genome = BlocklyGenome {
+            name: "Aggressive".to_string(),
+            initial_mode: 0,
+            initial_orientation: Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 },
+            modes: vec![test_mode(0.5, -1)],
+        };
+
+        let lints = genome.performance_lints();
+        assert!(lints.iter().any(|l| l.field == "split_interval"));
+
+        genome.modes = vec![test_mode(10.0, 4)];
+        assert!(genome.performance_lints().is_empty());
+    }
+
+    #[test]
+    fn test_default_genome_has_no_lints() {
+        let mut genome = BlocklyGenome {
+            name: "Default".to_string(),
+            initial_mode: 0,
+            initial_orientation: Quaternion::default(),
+            modes: Vec::new(),
+        };
+        genome.modes.push(genome.complete_mode(&PartialBlocklyMode::default()));
+        genome.modes.push(genome.complete_mode(&PartialBlocklyMode::default()));
+        assert_eq!(genome.modes[0], BlocklyMode::default());
+
+        let lints = genome.performance_lints();
+        assert!(lints.is_empty(), "Lints: {:?}", lints);
+    }
+
+    #[test]
+    fn test_complete_mode() {
+        let mut sibling = test_mode(6.0, 3);
+        sibling.adhesion_settings.break_force = 42.0;
+
+        let genome = BlocklyGenome {
+            name: "Partial".to_string(),
+            initial_mode: 0,
+            initial_orientation: Quaternion::default(),
+            modes: vec![sibling],
+        };
+
+        let partial: PartialBlocklyMode =
+            serde_json::from_str(r#"{ "name": "Stem", "split_interval": 8.0 }"#).unwrap();
+        let mode = genome.complete_mode(&partial);
+
+        assert_eq!(mode.name, "Stem");
+        assert_eq!(mode.default_name, "Mode 1");
+        assert_eq!(mode.split_interval, 8.0);
+        assert_eq!(mode.max_splits, 3);
+        assert_eq!(mode.adhesion_settings.break_force, 42.0);
+        assert_eq!(mode.child_a.mode_number, 1);
+        assert_eq!(mode.child_b.mode_number, 1);
+    }
+
+    #[test]
+    fn test_compact_round_trip() {
+        let mut custom = BlocklyMode {
+            name: "Stem".to_string(),
+            ..Default::default()
+        };
+        custom.adhesion_settings.break_force = 25.0;
+
+        let genome = BlocklyGenome {
+            name: "Compact".to_string(),
+            initial_mode: 0,
+            initial_orientation: Quaternion::default(),
+            modes: vec![BlocklyMode::default(), BlocklyMode::default(), custom],
+        };
+
+        let path = std::env::temp_dir().join(format!("genome_{}.json", uuid::Uuid::new_v4()));
+        save_blockly_genome_compact(&genome, &path).unwrap();
+        let compact = fs::read_to_string(&path).unwrap();
+        let loaded = load_blockly_genome(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        let full = serde_json::to_string_pretty(&genome).unwrap();
+        assert!(compact.len() < full.len() / 3, "Compact JSON should be much smaller");
+        assert!(compact.contains("break_force") && !compact.contains("rest_length"));
+        assert_eq!(loaded, genome);
+    }
+
     #[test]
     fn test_wgsl_validation() {
         let valid_shader = "@compute @workgroup_size(64) fn main() {}";
@@ -2,19 +2,53 @@
 // Can be run as a local HTTP service or integrated into a larger application
 
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tracing::Instrument;
 
 #[cfg(feature = "web-service")]
 use axum::{
-    extract::State,
-    http::StatusCode,
-    response::Json,
+    async_trait,
+    body::Body,
+    error_handling::HandleErrorLayer,
+    extract::{
+        rejection::JsonRejection, ConnectInfo, DefaultBodyLimit, FromRequest, Query, State,
+    },
+    http::{header, Request, StatusCode},
+    response::{IntoResponse, Json, Response},
     routing::post,
-    Router,
+    BoxError, Router,
 };
+#[cfg(feature = "web-service")]
+use tower::ServiceBuilder;
+#[cfg(feature = "web-service")]
+use tower_http::decompression::{DecompressionBody, RequestDecompressionLayer};
 
-mod rust_compiler;
-use rust_compiler::{CompilationResult, RustCompiler};
+use crate::analyzer::{AnalyzerBackend, Completion, Hover, InferredType};
+use crate::backend::{BackendKind, CompilerBackend};
+use crate::blockly_bridge::{BlocklyGenome, BlocklyMode, GenomeLint, PartialBlocklyMode};
+use crate::container::ContainerConfig;
+use crate::dead_code::{dead_code, DeadCode};
+use crate::deny_list::DenyList;
+use crate::error::CompilerError;
+use crate::friendly::simplify_diagnostics_in;
+use crate::locale::Locale;
+use crate::metrics::CodeMetrics;
+use crate::panic_sites::{panic_sites, PanicSite};
+use crate::playground::{PlaygroundBackend, DEFAULT_PLAYGROUND_URL};
+use crate::project_pool::{PoolConfig, PoolMetrics};
+use crate::resource_limits::ResourceLimits;
+use crate::rust_compiler::{
+    self, AppliedFix, BuildProfile, CancellationToken, CheckOptions, CompilationResult, CrateType,
+    Dependency, Edition, IrKind, LintLevel, LintPreset, RunMode, RunOptions, RunResult,
+    RustCompiler, Sanitizer, TestRunResult, WorkspaceCrate, WrapMode,
+};
+use crate::sandbox::Sandbox;
 
 /// Request to check Rust code
 #[derive(Debug, Deserialize)]
@@ -24,12 +58,129 @@ pub struct CheckRequest {
     pub dependencies: Vec<Dependency>,
     #[serde(default)]
     pub quick_check: bool,
+    /// Only parse the code in-process, reporting syntax errors without running
+    /// the toolchain; fast enough for every keystroke
+    #[serde(default)]
+    pub parse_only: bool,
+    /// Files made available to `include_str!`/`include_bytes!`, keyed by relative path
+    #[serde(default)]
+    pub assets: HashMap<String, String>,
+    /// Attach `rustc --explain` text to errors that carry an error code
+    #[serde(default)]
+    pub explain: bool,
+    /// Add plain-language `friendly_message`s to common errors and warnings
+    #[serde(default)]
+    pub simplify: bool,
+    /// Language of the friendly messages: `en` (default), `fr`, `de` or `es`;
+    /// setting it adds them as `simplify` does
+    #[serde(default)]
+    pub locale: Option<Locale>,
+    /// Include the exact cargo command line in the result (cargo checks only)
+    #[serde(default)]
+    pub include_command: bool,
+    /// Build with a sanitizer (`address`, `thread` or `leak`); requires nightly
+    #[serde(default)]
+    pub sanitizer: Option<Sanitizer>,
+    /// Run `cargo build` rather than `cargo check`, so link errors are reported
+    #[serde(default)]
+    pub build: bool,
+    /// Run clippy, adding its lints to the warnings
+    #[serde(default)]
+    pub clippy: bool,
+    /// Target triple to check for, e.g. `thumbv7em-none-eabihf` (cargo checks only)
+    #[serde(default)]
+    pub target: Option<String>,
+    /// Edition of the checked project: `2015`, `2018`, `2021` (default) or `2024`
+    /// (cargo checks only)
+    #[serde(default)]
+    pub edition: Edition,
+    /// Rustup toolchain to check with, e.g. `stable`, `beta`, `nightly` or `1.75.0`
+    /// (cargo checks only)
+    #[serde(default)]
+    pub toolchain: Option<String>,
+    /// `Cargo.lock` to resolve `dependencies` with, e.g. the `lockfile` of an
+    /// earlier result (cargo checks only)
+    #[serde(default)]
+    pub lockfile: Option<String>,
+    /// Cargo profile to compile with, e.g. `{"name": "release"}` (cargo checks only)
+    #[serde(default)]
+    pub profile: Option<BuildProfile>,
+    /// Check the code as a `#![no_std]` library (cargo checks only)
+    #[serde(default)]
+    pub no_std: bool,
+    /// Lint levels by lint name, e.g. `{"unused_variables": "allow"}` (cargo
+    /// checks only)
+    #[serde(default)]
+    pub lints: BTreeMap<String, LintLevel>,
+    /// Named set of lint levels: `beginner`, `standard` (default) or `strict`;
+    /// entries in `lints` take precedence (cargo checks only)
+    #[serde(default)]
+    pub lint_preset: LintPreset,
+    /// Fail the check if there are any warnings
+    #[serde(default)]
+    pub treat_warnings_as_errors: bool,
+    /// Reject unsafe code with `#![forbid(unsafe_code)]`
+    #[serde(default)]
+    pub forbid_unsafe: bool,
+    /// `auto` (default) to wrap snippets in `fn main`, `never` for complete
+    /// programs, or `{"template": "..."}` with a `{{code}}` placeholder (cargo
+    /// checks only)
+    #[serde(default)]
+    pub wrap_mode: WrapMode,
+    /// A complete `Cargo.toml` replacing the generated one (cargo checks only)
+    #[serde(default)]
+    pub manifest: Option<String>,
+    /// TOML appended to the generated `Cargo.toml`, e.g. `[patch.crates-io]`
+    /// entries (cargo checks only)
+    #[serde(default)]
+    pub manifest_extra: String,
+    /// A `build.rs` for the project; rejected unless the service allows build
+    /// scripts (cargo checks only)
+    #[serde(default)]
+    pub build_script: Option<String>,
+    /// Dependencies of the build script (cargo checks only)
+    #[serde(default)]
+    pub build_dependencies: Vec<Dependency>,
+    /// `bin`, `lib` or `proc-macro`; defaults to `lib` for quick checks and
+    /// `bin` otherwise
+    #[serde(default)]
+    pub crate_type: Option<CrateType>,
+    /// Add `syn`, `quote` and `proc-macro2` to the dependencies, unless
+    /// they're already declared
+    #[serde(default)]
+    pub proc_macro_presets: bool,
+    /// Include the rustfmt-formatted code in the response
+    #[serde(default)]
+    pub return_formatted: bool,
 }
 
+/// Request to check a project made of several source files
 #[derive(Debug, Deserialize)]
-pub struct Dependency {
-    pub name: String,
-    pub version: String,
+pub struct ProjectCheckRequest {
+    /// Source files by path relative to `src`, including `main.rs`
+    pub files: HashMap<PathBuf, String>,
+    #[serde(default)]
+    pub dependencies: Vec<Dependency>,
+    #[serde(default)]
+    pub edition: Edition,
+    /// Attach `rustc --explain` text to errors with an error code
+    #[serde(default)]
+    pub explain: bool,
+    /// Run clippy, adding its lints to the warnings
+    #[serde(default)]
+    pub clippy: bool,
+}
+
+/// Request to check several crates together as a Cargo workspace
+#[derive(Debug, Deserialize)]
+pub struct WorkspaceCheckRequest {
+    pub crates: Vec<WorkspaceCrate>,
+    /// Attach `rustc --explain` text to errors with an error code
+    #[serde(default)]
+    pub explain: bool,
+    /// Run clippy, adding its lints to the warnings
+    #[serde(default)]
+    pub clippy: bool,
 }
 
 /// Response from compilation check
@@ -37,85 +188,1324 @@ pub struct Dependency {
 pub struct CheckResponse {
     pub result: CompilationResult,
     pub rust_available: bool,
+    /// The submitted code formatted with rustfmt, when requested and the code
+    /// is syntactically valid
+    pub formatted_code: Option<String>,
+}
+
+/// Error body returned for rejected requests
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+#[cfg(feature = "web-service")]
+/// JSON body extractor that rejects malformed bodies with a structured error
+///
+/// Unlike axum's `Json`, bodies that don't match the request type produce a 400
+/// whose `error` names the missing or mistyped field.
+pub struct JsonBody<T>(pub T);
+
+#[cfg(feature = "web-service")]
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for JsonBody<T>
+where
+    Json<T>: FromRequest<S, B, Rejection = JsonRejection>,
+    S: Send + Sync,
+    B: Send + 'static,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        match Json::<T>::from_request(req, state).await {
+            Ok(Json(value)) => Ok(JsonBody(value)),
+            Err(rejection) => {
+                let status = match rejection {
+                    JsonRejection::JsonDataError(_) | JsonRejection::JsonSyntaxError(_) => {
+                        StatusCode::BAD_REQUEST
+                    }
+                    _ => rejection.status(),
+                };
+                let body = ErrorResponse {
+                    error: rejection.body_text(),
+                };
+                Err((status, Json(body)).into_response())
+            }
+        }
+    }
+}
+
+/// Request to check several snippets in one call
+#[derive(Debug, Deserialize)]
+pub struct BatchCheckRequest {
+    pub items: Vec<CheckRequest>,
+    /// How many items compile concurrently; defaults to the CPU count and is
+    /// never more than the service-wide compile slots
+    #[serde(default)]
+    pub max_parallel: Option<usize>,
+}
+
+/// Response from a batch check, in the same order as the request items
+#[derive(Debug, Serialize)]
+pub struct BatchCheckResponse {
+    pub results: Vec<CheckResponse>,
+}
+
+/// Request to fill in the unset fields of a mode being added to a genome
+#[derive(Debug, Deserialize)]
+pub struct CompleteModeRequest {
+    pub genome: BlocklyGenome,
+    pub mode: PartialBlocklyMode,
+}
+
+/// Request asking which of the given names are defined in some code
+#[derive(Debug, Deserialize)]
+pub struct SymbolsRequest {
+    pub code: String,
+    pub names: Vec<String>,
+}
+
+/// Request for the places in some code that could panic
+#[derive(Debug, Deserialize)]
+pub struct PanicSitesRequest {
+    pub code: String,
+}
+
+/// Request for the definitions a project never uses; `code` is checked as the
+/// project's `main.rs`
+#[derive(Debug, Deserialize)]
+pub struct DeadCodeRequest {
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub files: HashMap<PathBuf, String>,
+}
+
+/// Request for the size and complexity of some code
+#[derive(Debug, Deserialize)]
+pub struct CodeMetricsRequest {
+    pub code: String,
+}
+
+/// Request for the types of the bindings and function returns in some code
+#[derive(Debug, Deserialize)]
+pub struct InferredTypesRequest {
+    pub code: String,
+}
+
+/// Request to expand the macros in some code
+#[derive(Debug, Deserialize)]
+pub struct ExpandRequest {
+    pub code: String,
+}
+
+/// Code with its macros expanded
+#[derive(Debug, Serialize)]
+pub struct ExpandResponse {
+    pub expanded: String,
+}
+
+/// Request for an intermediate representation of some code
+#[derive(Debug, Deserialize)]
+pub struct EmitRequest {
+    pub code: String,
+    /// `hir`, `mir`, `llvm-ir` or `asm`
+    pub kind: IrKind,
+}
+
+/// Text emitted by the compiler
+#[derive(Debug, Serialize)]
+pub struct EmitResponse {
+    pub output: String,
+}
+
+/// Request for completions or hover information at a position in some code
+#[derive(Debug, Deserialize)]
+pub struct CompleteRequest {
+    pub code: String,
+    /// 1-based line of the cursor
+    pub line: usize,
+    /// 1-based column of the cursor
+    pub column: usize,
+}
+
+/// Request to build and run a program
+#[derive(Debug, Deserialize)]
+pub struct RunRequest {
+    pub code: String,
+    #[serde(default)]
+    pub dependencies: Vec<Dependency>,
+    /// How long the program may run, in milliseconds
+    #[serde(default)]
+    pub time_limit_ms: Option<u64>,
+    /// Input for the program's stdin
+    #[serde(default)]
+    pub stdin: Option<String>,
+    /// `native` (default) or `wasi` to run sandboxed under wasmtime; native
+    /// runs need `ServiceConfig::allow_native_runs`, the sandbox or containers
+    #[serde(default)]
+    pub mode: RunMode,
+    /// Fuel limit for `wasi` runs
+    #[serde(default)]
+    pub fuel: Option<u64>,
+    /// How the code is wrapped into a program, as for `/check`
+    #[serde(default)]
+    pub wrap_mode: WrapMode,
+}
+
+/// Request to compile a program to WebAssembly
+#[derive(Debug, Deserialize)]
+pub struct WasmRequest {
+    pub code: String,
+    #[serde(default)]
+    pub dependencies: Vec<Dependency>,
+}
+
+/// Request to apply the compiler's machine-applicable fixes to some code
+#[derive(Debug, Deserialize)]
+pub struct ApplyFixesRequest {
+    pub code: String,
+    /// How the code is wrapped into a program, as for `/check`
+    #[serde(default)]
+    pub wrap_mode: WrapMode,
+}
+
+/// Query parameters of `/apply-fixes`
+#[derive(Debug, Default, Deserialize)]
+pub struct ApplyFixesQuery {
+    /// `diff` for a unified diff instead of the fixed code
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
+/// Code with fixes applied, and the check result the fixes came from
+#[derive(Debug, Serialize)]
+pub struct ApplyFixesResponse {
+    pub code: String,
+    /// The suggestions that were applied
+    pub fixes: Vec<AppliedFix>,
+    pub result: CompilationResult,
+}
+
+/// Service metrics
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MetricsResponse {
+    /// Project pool metrics, when the pool is enabled
+    pub pool: Option<PoolMetrics>,
+}
+
+/// Default number of checks a single client IP may run at once
+pub const DEFAULT_MAX_CHECKS_PER_CLIENT: usize = 2;
+
+/// Default limit on request body size, measured after decompression
+pub const DEFAULT_MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Default number of check results cached by the service
+pub const DEFAULT_RESULT_CACHE_SIZE: usize = 256;
+
+/// Service configuration
+#[derive(Debug, Clone)]
+pub struct ServiceConfig {
+    /// How many checks a single client IP may run at once
+    pub max_checks_per_client: usize,
+    /// Maximum request body size in bytes; gzip bodies are limited by their
+    /// decompressed size
+    pub max_body_bytes: usize,
+    /// Checks slower than this get a note saying the code is expensive to compile
+    pub slow_check_threshold: Duration,
+    /// Maximum number of dependencies a single check may declare
+    pub max_dependencies: Option<usize>,
+    /// Check in a pool of reusable projects rather than a fresh project each
+    /// time; enabled by default
+    pub project_pool: Option<PoolConfig>,
+    /// Kill checks that take longer than this
+    pub check_timeout: Option<Duration>,
+    /// Memory and CPU time limits for each cargo, rustc and program process
+    pub resource_limits: ResourceLimits,
+    /// Confine cargo, rustc and programs to their project, without network
+    /// access; off by default, and only supported on Linux
+    pub sandbox: Option<Sandbox>,
+    /// APIs submitted code may not use, e.g. `std::process` in a classroom
+    pub deny_list: Option<DenyList>,
+    /// Target directory shared by all checks, so dependencies are compiled
+    /// once; not used with the sandbox or container backend
+    pub shared_target_dir: Option<PathBuf>,
+    /// `CARGO_HOME` used for checks
+    pub cargo_home: Option<PathBuf>,
+    /// Number of check results kept for identical requests; 0 disables caching
+    pub result_cache_size: usize,
+    /// Run `cargo audit` on checks with dependencies (needs cargo-audit)
+    pub audit_dependencies: bool,
+    /// Accept dependencies on local crates; off by default, since they can name
+    /// any crate on the server
+    pub allow_path_dependencies: bool,
+    /// Accept checks with a build script; off by default, since build scripts
+    /// run on the server
+    pub allow_build_scripts: bool,
+    /// Check code enabling unstable features on the nightly toolchain, if installed
+    pub nightly_for_feature_gates: bool,
+    /// Toolchains requests may ask for; `None` (the default) accepts any
+    /// installed toolchain
+    pub allowed_toolchains: Option<Vec<String>>,
+    /// Run programs and tests natively for `/run`, `/test` and `/doctest` even
+    /// without the sandbox or the container backend; off by default, since
+    /// they'd run on the server with its permissions. Without it, `/run` only
+    /// accepts `wasi` runs.
+    pub allow_native_runs: bool,
+    /// What `/check` and `/run` use: cargo (the default), bare rustc where
+    /// cargo isn't installed, or the Rust Playground
+    pub backend: BackendKind,
+    /// Containers every endpoint checks and runs code in with the `Container`
+    /// backend
+    pub container: ContainerConfig,
+    /// Playground the `Playground` backend sends code to
+    pub playground_url: String,
+    /// Use the playground when rustc isn't installed; off by default, since
+    /// it sends the checked code to a third party
+    pub playground_fallback: bool,
+    /// Language the service's startup message is printed in
+    pub locale: Locale,
+}
+
+impl Default for ServiceConfig {
+    fn default() -> Self {
+        Self {
+            max_checks_per_client: DEFAULT_MAX_CHECKS_PER_CLIENT,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            slow_check_threshold: rust_compiler::DEFAULT_SLOW_CHECK_THRESHOLD,
+            max_dependencies: None,
+            project_pool: Some(PoolConfig::default()),
+            check_timeout: None,
+            resource_limits: ResourceLimits::default(),
+            sandbox: None,
+            deny_list: None,
+            shared_target_dir: None,
+            cargo_home: None,
+            result_cache_size: DEFAULT_RESULT_CACHE_SIZE,
+            audit_dependencies: false,
+            allow_path_dependencies: false,
+            allow_build_scripts: false,
+            nightly_for_feature_gates: false,
+            allowed_toolchains: None,
+            allow_native_runs: false,
+            backend: BackendKind::Cargo,
+            container: ContainerConfig::default(),
+            playground_url: DEFAULT_PLAYGROUND_URL.to_string(),
+            playground_fallback: false,
+            locale: Locale::default(),
+        }
+    }
 }
 
 /// Application state
 pub struct AppState {
     compiler: Arc<RustCompiler>,
+    /// Checks and runs for `/check`, `/batch` and `/run`
+    backend: Arc<dyn CompilerBackend>,
+    /// Whether programs and tests may run natively for `/test`, `/doctest`,
+    /// and `/run` with a local backend
+    native_runs: bool,
+    /// Whether `/run` sends programs elsewhere to run, as the playground does
+    remote_runs: bool,
+    /// Whether rustc and cargo were found when the service started
+    rust_available: bool,
+    cargo_available: bool,
+    /// Service-wide cap on concurrently running compilations
+    compile_slots: Arc<Semaphore>,
+    /// Per-client cap, so one client can't occupy every compile slot
+    client_slots: Arc<ClientSlots>,
+    /// rust-analyzer for completions, started on the first request
+    analyzer: Arc<Mutex<Option<AnalyzerBackend>>>,
+}
+
+/// Tracks how many checks each client IP has in flight
+pub struct ClientSlots {
+    max_per_client: usize,
+    in_flight: Mutex<HashMap<IpAddr, usize>>,
+}
+
+/// A reserved per-client slot, released on drop
+pub struct ClientSlot {
+    slots: Arc<ClientSlots>,
+    ip: IpAddr,
+}
+
+impl ClientSlots {
+    pub fn new(max_per_client: usize) -> Self {
+        Self {
+            max_per_client,
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Reserve a slot for `ip`, or `None` if it already has the maximum number of checks running
+    pub fn try_acquire(self: &Arc<Self>, ip: IpAddr) -> Option<ClientSlot> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let count = in_flight.entry(ip).or_insert(0);
+
+        if *count >= self.max_per_client {
+            return None;
+        }
+        *count += 1;
+
+        Some(ClientSlot {
+            slots: self.clone(),
+            ip,
+        })
+    }
+}
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        let mut in_flight = self.slots.in_flight.lock().unwrap();
+        if let Some(count) = in_flight.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                in_flight.remove(&self.ip);
+            }
+        }
+    }
+}
+
+/// Default number of concurrent compilations, derived from the CPU count
+pub fn default_parallelism() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
 }
 
 #[cfg(feature = "web-service")]
 /// Create the web service router
 pub fn create_router() -> Router {
-    let compiler = Arc::new(RustCompiler::new().expect("Failed to create compiler"));
-    let state = Arc::new(AppState { compiler });
+    create_router_with_config(ServiceConfig::default())
+}
 
-    Router::new()
+#[cfg(feature = "web-service")]
+/// Create the web service router with the given configuration
+///
+/// Per-client limits need the peer address, so serve the router with
+/// `into_make_service_with_connect_info::<SocketAddr>()`; without it they are skipped.
+pub fn create_router_with_config(config: ServiceConfig) -> Router {
+    let compiler = Arc::new(configured_compiler(&config));
+    let backend = select_backend(
+        config.backend,
+        &config.playground_url,
+        config.playground_fallback,
+        compiler.clone(),
+    )
+    .expect("Failed to create backend");
+    router(config, compiler, backend)
+}
+
+#[cfg(feature = "web-service")]
+/// The backend of `kind`, with the playground at `playground_url`; with
+/// `playground_fallback`, the playground is also used if rustc isn't installed
+fn select_backend(
+    kind: BackendKind,
+    playground_url: &str,
+    playground_fallback: bool,
+    compiler: Arc<RustCompiler>,
+) -> Result<Arc<dyn CompilerBackend>, CompilerError> {
+    if kind == BackendKind::Playground
+        || (playground_fallback && !rust_compiler::is_rust_available())
+    {
+        return Ok(Arc::new(PlaygroundBackend::new(playground_url)));
+    }
+    kind.create(compiler)
+}
+
+#[cfg(feature = "web-service")]
+/// Create the web service router, checking and running code for `/check`,
+/// `/batch` and `/run` with `backend` instead of `config.backend`
+pub fn create_router_with_backend(
+    config: ServiceConfig,
+    backend: Arc<dyn CompilerBackend>,
+) -> Router {
+    let compiler = Arc::new(configured_compiler(&config));
+    router(config, compiler, backend)
+}
+
+#[cfg(feature = "web-service")]
+fn configured_compiler(config: &ServiceConfig) -> RustCompiler {
+    let mut compiler = RustCompiler::new().expect("Failed to create compiler");
+    compiler.set_slow_check_threshold(config.slow_check_threshold);
+    compiler.set_max_dependencies(config.max_dependencies);
+    compiler.set_timeout(config.check_timeout);
+    compiler.set_resource_limits(config.resource_limits);
+    compiler.set_sandbox(config.sandbox.clone());
+    compiler.set_deny_list(config.deny_list.clone());
+    compiler.set_shared_target_dir(config.shared_target_dir.clone());
+    compiler.set_cargo_home(config.cargo_home.clone());
+    compiler.enable_result_cache(config.result_cache_size);
+    compiler.set_audit_dependencies(config.audit_dependencies);
+    compiler.set_allow_path_dependencies(config.allow_path_dependencies);
+    compiler.set_allow_build_scripts(config.allow_build_scripts);
+    compiler.set_nightly_for_feature_gates(config.nightly_for_feature_gates);
+    compiler.set_allowed_toolchains(config.allowed_toolchains.clone());
+    if config.backend == BackendKind::Container {
+        compiler.set_container(Some(config.container.clone()));
+    }
+    if let Some(pool_config) = config.project_pool.clone() {
+        compiler
+            .enable_project_pool(pool_config)
+            .expect("Failed to create project pool");
+        spawn_pool_reaper(&compiler);
+    }
+    compiler
+}
+
+#[cfg(feature = "web-service")]
+fn router(
+    config: ServiceConfig,
+    compiler: Arc<RustCompiler>,
+    backend: Arc<dyn CompilerBackend>,
+) -> Router {
+    let state = Arc::new(AppState {
+        compiler,
+        backend,
+        native_runs: config.allow_native_runs
+            || config.sandbox.is_some()
+            || config.backend == BackendKind::Container,
+        remote_runs: config.backend == BackendKind::Playground,
+        rust_available: rust_compiler::is_rust_available(),
+        cargo_available: rust_compiler::is_cargo_available(),
+        compile_slots: Arc::new(Semaphore::new(default_parallelism())),
+        client_slots: Arc::new(ClientSlots::new(config.max_checks_per_client)),
+        analyzer: Arc::new(Mutex::new(None)),
+    });
+
+    // Routes see the decompressed body, so the body limit applies to the
+    // decompressed size and a small gzip bomb can't expand past it
+    Router::<Arc<AppState>, DecompressionBody<Body>>::new()
         .route("/check", post(check_code))
+        .route("/batch", post(batch_check))
+        .route("/check-project", post(check_project))
+        .route("/check-workspace", post(check_workspace))
+        .route("/apply-fixes", post(apply_fixes))
+        .route("/run", post(run_code))
+        .route("/test", post(run_tests))
+        .route("/doctest", post(run_doctests))
+        .route("/wasm", post(build_wasm))
+        .route("/lint-genome", post(lint_genome))
+        .route("/complete-mode", post(complete_mode))
+        .route("/symbols", post(symbols))
+        .route("/panic-sites", post(find_panic_sites))
+        .route("/dead-code", post(find_dead_code))
+        .route("/code-metrics", post(code_metrics))
+        .route("/expand", post(expand))
+        .route("/complete", post(complete))
+        .route("/hover", post(hover))
+        .route("/inferred-types", post(inferred_types))
+        .route("/emit", post(emit))
+        .route("/metrics", axum::routing::get(metrics))
         .route("/health", axum::routing::get(health_check))
+        .layer(DefaultBodyLimit::max(config.max_body_bytes))
+        .layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(invalid_body))
+                .layer(RequestDecompressionLayer::new()),
+        )
         .with_state(state)
 }
 
+#[cfg(feature = "web-service")]
+/// Periodically shrink the compiler's project pool while it is idle
+///
+/// The task holds a weak reference and ends once the pool is dropped. Outside a
+/// Tokio runtime nothing is spawned and the pool only shrinks on use.
+fn spawn_pool_reaper(compiler: &RustCompiler) {
+    let (Some(pool), Ok(runtime)) = (compiler.project_pool(), tokio::runtime::Handle::try_current())
+    else {
+        return;
+    };
+    let pool = Arc::downgrade(pool);
+
+    runtime.spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            match pool.upgrade() {
+                Some(pool) => {
+                    pool.shrink_idle();
+                }
+                None => break,
+            }
+        }
+    });
+}
+
+#[cfg(feature = "web-service")]
+/// Respond to a body that couldn't be decompressed
+async fn invalid_body(error: BoxError) -> (StatusCode, Json<ErrorResponse>) {
+    let body = ErrorResponse {
+        error: format!("Invalid request body: {}", error),
+    };
+    (StatusCode::BAD_REQUEST, Json(body))
+}
+
 #[cfg(feature = "web-service")]
 /// Health check endpoint
-async fn health_check() -> Json<serde_json::Value> {
+async fn health_check(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
     Json(serde_json::json!({
         "status": "ok",
-        "rust_available": rust_compiler::is_rust_available(),
-        "cargo_available": rust_compiler::is_cargo_available(),
+        "rust_available": state.rust_available,
+        "cargo_available": state.cargo_available,
     }))
 }
 
+#[cfg(feature = "web-service")]
+/// Service metrics endpoint
+async fn metrics(State(state): State<Arc<AppState>>) -> Json<MetricsResponse> {
+    Json(MetricsResponse {
+        pool: state.compiler.project_pool().map(|pool| pool.metrics()),
+    })
+}
+
 #[cfg(feature = "web-service")]
 /// Check Rust code endpoint
 async fn check_code(
     State(state): State<Arc<AppState>>,
-    Json(request): Json<CheckRequest>,
+    client: Option<ConnectInfo<SocketAddr>>,
+    JsonBody(request): JsonBody<CheckRequest>,
 ) -> Result<Json<CheckResponse>, StatusCode> {
-    let result = if request.quick_check {
+    let _client_slot = acquire_client_slot(&state, client)?;
+    let _permit = state
+        .compile_slots
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+
+    run_check(&state, request).await.map(Json)
+}
+
+#[cfg(feature = "web-service")]
+/// Check a project made of several files, e.g. one module per Blockly tab
+async fn check_project(
+    State(state): State<Arc<AppState>>,
+    client: Option<ConnectInfo<SocketAddr>>,
+    JsonBody(request): JsonBody<ProjectCheckRequest>,
+) -> Result<Json<CompilationResult>, StatusCode> {
+    let _client_slot = acquire_client_slot(&state, client)?;
+    let _permit = state
+        .compile_slots
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let options = CheckOptions {
+        dependencies: request.dependencies,
+        edition: request.edition,
+        explain: request.explain,
+        clippy: request.clippy,
+        ..Default::default()
+    };
+
+    // Project checks have no async variant, so they go to the blocking pool
+    let compiler = state.compiler.clone();
+    tokio::task::spawn_blocking(move || {
+        compiler
+            .check_project(&request.files, &options)
+            .map_err(|error| status_code(&error))
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .map(Json)
+}
+
+#[cfg(feature = "web-service")]
+/// Check crates as a workspace, e.g. a genome crate and a behavior crate using it
+async fn check_workspace(
+    State(state): State<Arc<AppState>>,
+    client: Option<ConnectInfo<SocketAddr>>,
+    JsonBody(request): JsonBody<WorkspaceCheckRequest>,
+) -> Result<Json<CompilationResult>, StatusCode> {
+    let _client_slot = acquire_client_slot(&state, client)?;
+    let _permit = state
+        .compile_slots
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let options = CheckOptions {
+        explain: request.explain,
+        clippy: request.clippy,
+        ..Default::default()
+    };
+
+    let compiler = state.compiler.clone();
+    tokio::task::spawn_blocking(move || {
+        compiler
+            .check_workspace(&request.crates, &options)
+            .map_err(|error| status_code(&error))
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .map(Json)
+}
+
+#[cfg(feature = "web-service")]
+/// Build and run a program, returning its output
+///
+/// Native runs are rejected with 403 unless `ServiceConfig::allow_native_runs`,
+/// the sandbox or the container backend is set.
+async fn run_code(
+    State(state): State<Arc<AppState>>,
+    client: Option<ConnectInfo<SocketAddr>>,
+    JsonBody(request): JsonBody<RunRequest>,
+) -> Result<Json<RunResult>, StatusCode> {
+    if request.mode == RunMode::Native && !state.native_runs && !state.remote_runs {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let _client_slot = acquire_client_slot(&state, client)?;
+    let _permit = state
+        .compile_slots
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let options = run_options(&request);
+    on_backend(&state, move |backend| backend.run(&request.code, &options)).await.map(Json)
+}
+
+#[cfg(feature = "web-service")]
+/// Cancels its token when dropped
+struct CancelOnDrop(CancellationToken);
+
+#[cfg(feature = "web-service")]
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+#[cfg(feature = "web-service")]
+/// Call the service's backend on the blocking pool, since backends are synchronous
+async fn on_backend<T, F>(state: &AppState, call: F) -> Result<T, StatusCode>
+where
+    T: Send + 'static,
+    F: FnOnce(&dyn CompilerBackend) -> Result<T, CompilerError> + Send + 'static,
+{
+    let backend = state.backend.clone();
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || {
+        let _entered = span.enter();
+        call(backend.as_ref()).map_err(|error| {
+            tracing::warn!(%error, "backend call failed");
+            status_code(&error)
+        })
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+}
+
+#[cfg(feature = "web-service")]
+/// Call the service's compiler on the blocking pool, for calls that start
+/// processes such as rustfmt or `rustc --explain`
+async fn on_compiler<T, F>(state: &AppState, call: F) -> Result<T, StatusCode>
+where
+    T: Send + 'static,
+    F: FnOnce(&RustCompiler) -> Result<T, CompilerError> + Send + 'static,
+{
+    let compiler = state.compiler.clone();
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || {
+        let _entered = span.enter();
+        call(&compiler).map_err(|error| status_code(&error))
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+}
+
+#[cfg(feature = "web-service")]
+/// Build and run the tests in a program
+///
+/// Takes the same request as `/run`; `stdin`, `mode` and `fuel` are ignored.
+/// Tests always run natively, so they're rejected like native runs.
+async fn run_tests(
+    state: State<Arc<AppState>>,
+    client: Option<ConnectInfo<SocketAddr>>,
+    request: JsonBody<RunRequest>,
+) -> Result<Json<TestRunResult>, StatusCode> {
+    run_test_suite(state, client, request, RustCompiler::run_tests).await
+}
+
+#[cfg(feature = "web-service")]
+/// Build code as a library and run the examples in its doc comments
+///
+/// Takes the same request as `/run`; `stdin`, `mode` and `fuel` are ignored.
+/// Examples always run natively, so they're rejected like native runs.
+async fn run_doctests(
+    state: State<Arc<AppState>>,
+    client: Option<ConnectInfo<SocketAddr>>,
+    request: JsonBody<RunRequest>,
+) -> Result<Json<TestRunResult>, StatusCode> {
+    run_test_suite(state, client, request, RustCompiler::run_doctests).await
+}
+
+#[cfg(feature = "web-service")]
+type TestRunner = fn(
+    &RustCompiler,
+    &str,
+    &RunOptions,
+) -> Result<TestRunResult, CompilerError>;
+
+#[cfg(feature = "web-service")]
+async fn run_test_suite(
+    State(state): State<Arc<AppState>>,
+    client: Option<ConnectInfo<SocketAddr>>,
+    JsonBody(request): JsonBody<RunRequest>,
+    runner: TestRunner,
+) -> Result<Json<TestRunResult>, StatusCode> {
+    if !state.native_runs {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let _client_slot = acquire_client_slot(&state, client)?;
+    let _permit = state
+        .compile_slots
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let options = run_options(&request);
+    let compiler = state.compiler.clone();
+    tokio::task::spawn_blocking(move || {
+        runner(&compiler, &request.code, &options).map_err(|error| status_code(&error))
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .map(Json)
+}
+
+#[cfg(feature = "web-service")]
+fn run_options(request: &RunRequest) -> RunOptions {
+    RunOptions {
+        check: CheckOptions {
+            dependencies: request.dependencies.clone(),
+            wrap_mode: request.wrap_mode.clone(),
+            ..Default::default()
+        },
+        time_limit: request
+            .time_limit_ms
+            .map(Duration::from_millis)
+            .unwrap_or(rust_compiler::DEFAULT_RUN_TIME_LIMIT),
+        stdin: request.stdin.clone(),
+        mode: request.mode,
+        fuel: request.fuel,
+    }
+}
+
+#[cfg(feature = "web-service")]
+/// Compile a program to WebAssembly
+///
+/// Responds with the `application/wasm` module, or with the compilation
+/// result and status 422 if the build failed.
+async fn build_wasm(
+    State(state): State<Arc<AppState>>,
+    client: Option<ConnectInfo<SocketAddr>>,
+    JsonBody(request): JsonBody<WasmRequest>,
+) -> Result<Response, StatusCode> {
+    let _client_slot = acquire_client_slot(&state, client)?;
+    let _permit = state
+        .compile_slots
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let options = CheckOptions {
+        dependencies: request.dependencies,
+        ..Default::default()
+    };
+
+    let compiler = state.compiler.clone();
+    let build = tokio::task::spawn_blocking(move || {
+        compiler
+            .build_wasm(&request.code, &options)
+            .map_err(|error| status_code(&error))
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)??;
+
+    Ok(match build.wasm {
+        Some(wasm) => ([(header::CONTENT_TYPE, "application/wasm")], wasm).into_response(),
+        None => (StatusCode::UNPROCESSABLE_ENTITY, Json(build.compilation)).into_response(),
+    })
+}
+
+#[cfg(feature = "web-service")]
+/// Apply the machine-applicable fixes from one check of the code
+///
+/// With `?format=diff` the response is a plain-text unified diff rather than
+/// the fixed code.
+async fn apply_fixes(
+    State(state): State<Arc<AppState>>,
+    client: Option<ConnectInfo<SocketAddr>>,
+    Query(query): Query<ApplyFixesQuery>,
+    JsonBody(request): JsonBody<ApplyFixesRequest>,
+) -> Result<Response, StatusCode> {
+    let _client_slot = acquire_client_slot(&state, client)?;
+    let _permit = state
+        .compile_slots
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let options = CheckOptions {
+        wrap_mode: request.wrap_mode,
+        ..Default::default()
+    };
+    let result = state
+        .compiler
+        .check_with_options_async(&request.code, &options)
+        .await
+        .map_err(|error| status_code(&error))?;
+
+    match query.format.as_deref() {
+        Some("diff") => {
+            let diff = result.fixes_as_diff(&request.code);
+            Ok(([(header::CONTENT_TYPE, "text/x-diff")], diff).into_response())
+        }
+        None | Some("code") => {
+            let (code, fixes) = result.apply_fixes(&request.code);
+            Ok(Json(ApplyFixesResponse { code, fixes, result }).into_response())
+        }
+        Some(_) => Err(StatusCode::BAD_REQUEST),
+    }
+}
+
+#[cfg(feature = "web-service")]
+/// Check several snippets, compiling up to `max_parallel` of them at once
+async fn batch_check(
+    State(state): State<Arc<AppState>>,
+    client: Option<ConnectInfo<SocketAddr>>,
+    JsonBody(request): JsonBody<BatchCheckRequest>,
+) -> Result<Json<BatchCheckResponse>, StatusCode> {
+    // A batch holds one client slot and is capped at the client's share of compilers
+    let _client_slot = acquire_client_slot(&state, client)?;
+    let max_parallel = request
+        .max_parallel
+        .unwrap_or_else(default_parallelism)
+        .min(state.client_slots.max_per_client);
+    let compile_slots = state.compile_slots.clone();
+
+    let results = check_batch(request.items, max_parallel, compile_slots, move |item| {
+        let state = state.clone();
+        async move { run_check(&state, item).await }
+    })
+    .await
+    .into_iter()
+    .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Json(BatchCheckResponse { results }))
+}
+
+#[cfg(feature = "web-service")]
+/// The status for a failed compiler call: 400 for a rejected request, 503 for
+/// a missing toolchain, 504 for a timeout and 500 otherwise
+fn status_code(error: &CompilerError) -> StatusCode {
+    match error {
+        CompilerError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+        CompilerError::ToolchainMissing(_) => StatusCode::SERVICE_UNAVAILABLE,
+        CompilerError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+#[cfg(feature = "web-service")]
+/// Reserve a per-client slot, or reject with 429 if the client is at its limit
+fn acquire_client_slot(
+    state: &AppState,
+    client: Option<ConnectInfo<SocketAddr>>,
+) -> Result<Option<ClientSlot>, StatusCode> {
+    match client {
+        Some(ConnectInfo(addr)) => state
+            .client_slots
+            .try_acquire(addr.ip())
+            .map(Some)
+            .ok_or(StatusCode::TOO_MANY_REQUESTS),
+        None => Ok(None),
+    }
+}
+
+#[cfg(feature = "web-service")]
+/// Run `check` over `items` as separate tasks, with at most `max_parallel`
+/// running at once and each holding one of the service-wide `slots`
+///
+/// Results are returned in the same order as `items`.
+async fn check_batch<T, R, F, Fut>(
+    items: Vec<T>,
+    max_parallel: usize,
+    slots: Arc<Semaphore>,
+    check: F,
+) -> Vec<Result<R, StatusCode>>
+where
+    T: Send + 'static,
+    R: Send + 'static,
+    F: Fn(T) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<R, StatusCode>> + Send + 'static,
+{
+    // Each item also takes a service-wide slot, so the global cap still applies
+    let batch_slots = Arc::new(Semaphore::new(max_parallel.max(1)));
+    let check = Arc::new(check);
+
+    let handles: Vec<_> = items
+        .into_iter()
+        .map(|item| {
+            let batch_slots = batch_slots.clone();
+            let slots = slots.clone();
+            let check = check.clone();
+
+            tokio::spawn(async move {
+                let _batch_permit = batch_slots
+                    .acquire_owned()
+                    .await
+                    .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+                let _permit = slots
+                    .acquire_owned()
+                    .await
+                    .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+
+                check(item).await
+            })
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(handles.len());
+    for handle in handles {
+        results.push(
+            handle
+                .await
+                .unwrap_or(Err(StatusCode::INTERNAL_SERVER_ERROR)),
+        );
+    }
+
+    results
+}
+
+/// Run a single check request against the service's backend
+async fn run_check(state: &AppState, request: CheckRequest) -> Result<CheckResponse, StatusCode> {
+    let span = tracing::info_span!(
+        "check_request",
+        bytes = request.code.len(),
+        quick = request.quick_check,
+        dependencies = request.dependencies.len()
+    );
+    check_request(state, request).instrument(span).await
+}
+
+async fn check_request(
+    state: &AppState,
+    request: CheckRequest,
+) -> Result<CheckResponse, StatusCode> {
+    let formatted_code = if request.return_formatted {
+        let code = request.code.clone();
+        on_compiler(state, move |compiler| compiler.format_code(&code)).await?
+    } else {
+        None
+    };
+
+    let locale = request.locale.or(request.simplify.then_some(Locale::En));
+    let mut result = if request.parse_only {
+        state.compiler.parse_check(&request.code)
+    } else if request.quick_check {
         // Quick syntax check
-        state
-            .compiler
-            .quick_check(&request.code)
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    } else if request.dependencies.is_empty() {
-        // Standard check without dependencies
-        state
-            .compiler
-            .check_code(&request.code)
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        let crate_type = request.crate_type.unwrap_or(CrateType::Lib);
+        let code = request.code.clone();
+        let mut result =
+            on_backend(state, move |backend| backend.quick_check(&code, crate_type)).await?;
+
+        if request.explain {
+            result = on_compiler(state, move |compiler| {
+                compiler.attach_explanations(&mut result);
+                Ok(result)
+            })
+            .await?;
+        }
+        if request.treat_warnings_as_errors {
+            result.treat_warnings_as_errors();
+        }
+
+        result
     } else {
-        // Check with dependencies
-        let deps: Vec<(&str, &str)> = request
-            .dependencies
-            .iter()
-            .map(|d| (d.name.as_str(), d.version.as_str()))
-            .collect();
-
-        state
-            .compiler
-            .check_code_with_deps(&request.code, &deps)
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        let preset_lints = request.lint_preset.lints();
+        let mut dependencies = request.dependencies;
+        if request.proc_macro_presets {
+            for preset in Dependency::proc_macro_presets() {
+                if !dependencies.iter().any(|dependency| dependency.name == preset.name) {
+                    dependencies.push(preset);
+                }
+            }
+        }
+        let cancel = CancellationToken::new();
+        let options = CheckOptions {
+            dependencies,
+            assets: request.assets,
+            explain: request.explain,
+            include_command: request.include_command,
+            sanitizer: request.sanitizer,
+            build: request.build,
+            clippy: request.clippy,
+            toolchain: request.toolchain,
+            edition: request.edition,
+            target: request.target,
+            lockfile: request.lockfile,
+            profile: request.profile,
+            no_std: request.no_std,
+            lints: preset_lints.into_iter().chain(request.lints).collect(),
+            treat_warnings_as_errors: request.treat_warnings_as_errors,
+            forbid_unsafe: request.forbid_unsafe,
+            wrap_mode: request.wrap_mode,
+            manifest: request.manifest,
+            manifest_extra: request.manifest_extra,
+            build_script: request.build_script,
+            build_dependencies: request.build_dependencies,
+            crate_type: request.crate_type.unwrap_or_default(),
+            cancel: Some(cancel.clone()),
+            ..Default::default()
+        };
+
+        // A client that gives up on a check (e.g. the editor sending a newer
+        // one) drops this future, which kills the stale cargo process
+        let _cancel_on_drop = CancelOnDrop(cancel);
+        let code = request.code;
+        on_backend(state, move |backend| backend.check(&code, &options)).await?
     };
+    if let Some(locale) = locale {
+        simplify_diagnostics_in(&mut result, locale);
+    }
 
-    Ok(Json(CheckResponse {
+    Ok(CheckResponse {
         result,
-        rust_available: rust_compiler::is_rust_available(),
-    }))
+        rust_available: state.rust_available,
+        formatted_code,
+    })
+}
+
+#[cfg(feature = "web-service")]
+/// Lint a genome for configurations likely to be expensive to simulate
+async fn lint_genome(JsonBody(genome): JsonBody<BlocklyGenome>) -> Json<Vec<GenomeLint>> {
+    Json(genome.performance_lints())
+}
+
+#[cfg(feature = "web-service")]
+/// Suggest values for the unset fields of a new mode based on its siblings
+async fn complete_mode(JsonBody(request): JsonBody<CompleteModeRequest>) -> Json<BlocklyMode> {
+    Json(request.genome.complete_mode(&request.mode))
+}
+
+#[cfg(feature = "web-service")]
+/// Report which of the requested names are defined in the code, without compiling
+async fn symbols(JsonBody(request): JsonBody<SymbolsRequest>) -> Json<HashMap<String, bool>> {
+    Json(rust_compiler::contains_symbols(&request.code, &request.names))
+}
+
+#[cfg(feature = "web-service")]
+/// List the places in the code that could panic at runtime, without compiling
+async fn find_panic_sites(JsonBody(request): JsonBody<PanicSitesRequest>) -> Json<Vec<PanicSite>> {
+    Json(panic_sites(&request.code))
+}
+
+#[cfg(feature = "web-service")]
+/// List the functions, types and variables nothing uses, without compiling
+async fn find_dead_code(JsonBody(request): JsonBody<DeadCodeRequest>) -> Json<Vec<DeadCode>> {
+    let mut files = request.files;
+    if let Some(code) = request.code {
+        files.insert(PathBuf::from("main.rs"), code);
+    }
+    Json(dead_code(&files))
+}
+
+#[cfg(feature = "web-service")]
+/// Measure lines of code and the complexity of each function, without compiling
+async fn code_metrics(
+    State(state): State<Arc<AppState>>,
+    JsonBody(request): JsonBody<CodeMetricsRequest>,
+) -> Json<CodeMetrics> {
+    Json(state.compiler.metrics(&request.code))
+}
+
+#[cfg(feature = "web-service")]
+/// Completion candidates at a position in some code, from rust-analyzer
+///
+/// Responds with status 503 if rust-analyzer isn't installed or can't start.
+async fn complete(
+    State(state): State<Arc<AppState>>,
+    JsonBody(request): JsonBody<CompleteRequest>,
+) -> Result<Json<Vec<Completion>>, StatusCode> {
+    with_analyzer(&state, move |backend| {
+        backend.complete(&request.code, request.line, request.column)
+    })
+    .await
+}
+
+#[cfg(feature = "web-service")]
+/// Type and documentation of the item at a position in some code, from
+/// rust-analyzer; `null` when there is nothing to show there
+///
+/// Responds with status 503 if rust-analyzer isn't installed or can't start.
+async fn hover(
+    State(state): State<Arc<AppState>>,
+    JsonBody(request): JsonBody<CompleteRequest>,
+) -> Result<Json<Option<Hover>>, StatusCode> {
+    with_analyzer(&state, move |backend| {
+        backend.hover(&request.code, request.line, request.column)
+    })
+    .await
+}
+
+#[cfg(feature = "web-service")]
+/// Type of each `let` binding and function return in some code, from
+/// rust-analyzer
+///
+/// Responds with status 503 if rust-analyzer isn't installed or can't start.
+async fn inferred_types(
+    State(state): State<Arc<AppState>>,
+    JsonBody(request): JsonBody<InferredTypesRequest>,
+) -> Result<Json<Vec<InferredType>>, StatusCode> {
+    with_analyzer(&state, move |backend| backend.inferred_types(&request.code)).await
+}
+
+#[cfg(feature = "web-service")]
+/// Run a query against the shared rust-analyzer, starting it if needed
+async fn with_analyzer<T, F>(state: &AppState, query: F) -> Result<Json<T>, StatusCode>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut AnalyzerBackend) -> Result<T, CompilerError> + Send + 'static,
+{
+    let analyzer = state.analyzer.clone();
+    tokio::task::spawn_blocking(move || {
+        // A query that panicked may have left the server mid-request, so start over
+        let mut analyzer = analyzer.lock().unwrap_or_else(|poisoned| {
+            analyzer.clear_poison();
+            let mut guard = poisoned.into_inner();
+            *guard = None;
+            guard
+        });
+        if analyzer.is_none() {
+            let backend = AnalyzerBackend::start().map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+            *analyzer = Some(backend);
+        }
+
+        let backend = analyzer.as_mut().expect("rust-analyzer was just started");
+        match query(backend) {
+            Ok(result) => Ok(Json(result)),
+            Err(error) => {
+                // Start over on the next request, in case the server is stuck
+                *analyzer = None;
+                Err(status_code(&error))
+            }
+        }
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+}
+
+#[cfg(feature = "web-service")]
+/// Expand the macros in some code; requires nightly
+///
+/// Responds with status 422 and the compiler's message if expansion fails.
+async fn expand(
+    state: State<Arc<AppState>>,
+    client: Option<ConnectInfo<SocketAddr>>,
+    JsonBody(request): JsonBody<ExpandRequest>,
+) -> Result<Response, StatusCode> {
+    compile_text(state, client, move |compiler| compiler.expand_code(&request.code), |expanded| {
+        Json(ExpandResponse { expanded }).into_response()
+    })
+    .await
+}
+
+#[cfg(feature = "web-service")]
+/// Emit an intermediate representation of some code
+///
+/// Responds with status 422 and the compiler's message if compilation fails.
+async fn emit(
+    state: State<Arc<AppState>>,
+    client: Option<ConnectInfo<SocketAddr>>,
+    JsonBody(request): JsonBody<EmitRequest>,
+) -> Result<Response, StatusCode> {
+    let kind = request.kind;
+    compile_text(state, client, move |compiler| compiler.emit_ir(&request.code, kind), |output| {
+        Json(EmitResponse { output }).into_response()
+    })
+    .await
+}
+
+#[cfg(feature = "web-service")]
+/// Run `produce` on the blocking pool and turn its text into a response, or
+/// its error into status 422
+async fn compile_text<F>(
+    State(state): State<Arc<AppState>>,
+    client: Option<ConnectInfo<SocketAddr>>,
+    produce: F,
+    respond: impl FnOnce(String) -> Response,
+) -> Result<Response, StatusCode>
+where
+    F: FnOnce(&RustCompiler) -> Result<String, CompilerError> + Send + 'static,
+{
+    let _client_slot = acquire_client_slot(&state, client)?;
+    let _permit = state
+        .compile_slots
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let compiler = state.compiler.clone();
+    let text = tokio::task::spawn_blocking(move || {
+        produce(&compiler).map_err(|error| error.to_string())
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(match text {
+        Ok(text) => respond(text),
+        Err(error) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, Json(ErrorResponse { error })).into_response()
+        }
+    })
 }
 
 #[cfg(feature = "web-service")]
 /// Start the web service
 pub async fn start_service(port: u16) -> Result<(), Box<dyn std::error::Error>> {
-    let app = create_router();
+    start_service_with_config(port, ServiceConfig::default()).await
+}
+
+#[cfg(feature = "web-service")]
+/// Start the web service with the given configuration
+pub async fn start_service_with_config(
+    port: u16,
+    config: ServiceConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let locale = config.locale;
+    let app = create_router_with_config(config);
     let addr = std::net::SocketAddr::from(([127, 0, 0, 1], port));
 
-    println!("🦀 Rust Compiler Service starting on http://{}", addr);
+    println!("{}", locale.service_starting(addr));
     println!("   POST /check - Check Rust code");
+    println!("   POST /check-project - Check a project of several source files");
+    println!("   POST /check-workspace - Check several crates as a Cargo workspace");
+    println!("   POST /batch - Check several snippets in parallel");
+    println!("   POST /run - Build and run a program");
+    println!("   POST /test - Build and run the tests in a program");
+    println!("   POST /doctest - Run the examples in a library's doc comments");
+    println!("   POST /wasm - Compile a program to WebAssembly");
+    println!("   POST /apply-fixes - Apply compiler fixes (?format=diff for a patch)");
+    println!("   POST /lint-genome - Lint a genome for simulation cost");
+    println!("   POST /complete-mode - Fill in defaults for a new genome mode");
+    println!("   POST /symbols - Check which names are defined in some code");
+    println!("   POST /panic-sites - Find code that could panic at runtime");
+    println!("   POST /dead-code - Find functions, types and variables that are never used");
+    println!("   POST /code-metrics - Lines of code and complexity of each function");
+    println!("   POST /expand - Expand the macros in some code (nightly)");
+    println!("   POST /complete - Completions at a position, via rust-analyzer");
+    println!("   POST /hover - Type information at a position, via rust-analyzer");
+    println!("   POST /inferred-types - Types of let bindings and function returns");
+    println!("   POST /emit - Emit an intermediate representation (MIR, LLVM IR, assembly)");
+    println!("   GET  /metrics - Service metrics");
     println!("   GET  /health - Health check");
 
     axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await?;
 
     Ok(())
@@ -127,6 +1517,9 @@ pub mod cli {
     use super::*;
     use clap::Parser;
 
+    use crate::container::{DEFAULT_CONTAINER_IMAGE, DEFAULT_CONTAINER_RUNTIME};
+    use crate::deny_list::DeniedApi;
+
     #[derive(Parser, Debug)]
     #[clap(name = "rust-compiler-service")]
     #[clap(about = "Rust compilation checking service for Blockly editor")]
@@ -138,18 +1531,161 @@ pub mod cli {
         /// Check a file directly (instead of starting service)
         #[clap(short, long)]
         pub file: Option<String>,
+
+        /// Maximum number of checks a single client IP may run at once
+        #[clap(long, default_value_t = DEFAULT_MAX_CHECKS_PER_CLIENT)]
+        pub max_checks_per_client: usize,
+
+        /// Maximum request body size in bytes (after decompression)
+        #[clap(long, default_value_t = DEFAULT_MAX_BODY_BYTES)]
+        pub max_body_bytes: usize,
+
+        /// Seconds after which a check is reported as slow to compile
+        #[clap(long, default_value_t = rust_compiler::DEFAULT_SLOW_CHECK_THRESHOLD.as_secs())]
+        pub slow_check_secs: u64,
+
+        /// Maximum number of dependencies a single check may declare
+        #[clap(long)]
+        pub max_dependencies: Option<usize>,
+
+        /// Check in a pool of up to this many reusable projects (0 disables the pool)
+        #[clap(long, default_value_t = PoolConfig::default().max_size)]
+        pub pool_max_size: usize,
+
+        /// Seconds after which a check is killed
+        #[clap(long)]
+        pub timeout_secs: Option<u64>,
+
+        /// Megabytes of memory each cargo, rustc or program process may allocate
+        #[clap(long)]
+        pub memory_limit_mb: Option<u64>,
+
+        /// Seconds of CPU time each cargo, rustc or program process may use
+        #[clap(long)]
+        pub cpu_limit_secs: Option<u64>,
+
+        /// Confine cargo, rustc and programs to their project, without network
+        /// access (Linux only)
+        #[clap(long)]
+        pub sandbox: bool,
+
+        /// Reject code using an API: process, network, file_write or unsafe
+        /// (repeatable)
+        #[clap(long)]
+        pub deny_api: Vec<DeniedApi>,
+
+        /// Check code using a denied API anyway, reporting the uses as warnings
+        #[clap(long)]
+        pub warn_denied_apis: bool,
+
+        /// Target directory shared by all checks, so dependencies are compiled once
+        /// (ignored with --sandbox or the container backend)
+        #[clap(long)]
+        pub target_dir: Option<PathBuf>,
+
+        /// CARGO_HOME to use for checks
+        #[clap(long)]
+        pub cargo_home: Option<PathBuf>,
+
+        /// Number of check results cached for identical requests (0 disables caching)
+        #[clap(long, default_value_t = DEFAULT_RESULT_CACHE_SIZE)]
+        pub cache_size: usize,
+
+        /// Audit dependencies with cargo-audit and report advisories
+        #[clap(long)]
+        pub audit: bool,
+
+        /// Accept dependencies on local crates by absolute path
+        #[clap(long)]
+        pub allow_path_dependencies: bool,
+
+        /// Accept checks with a build script, which runs on this machine
+        #[clap(long)]
+        pub allow_build_scripts: bool,
+
+        /// Check code using `#![feature(...)]` on the nightly toolchain
+        #[clap(long)]
+        pub nightly_features: bool,
+
+        /// Toolchain requests may ask for, e.g. stable or nightly (repeatable;
+        /// defaults to any installed toolchain)
+        #[clap(long)]
+        pub allow_toolchain: Vec<String>,
+
+        /// Run programs and tests natively without --sandbox or the container
+        /// backend, with this machine's permissions
+        #[clap(long)]
+        pub allow_native_runs: bool,
+
+        /// Check and run with `cargo`, `rustc` where cargo isn't installed,
+        /// `playground`, or cargo in a `container`
+        #[clap(long, default_value = "cargo")]
+        pub backend: BackendKind,
+
+        /// Image with the toolchain for the container backend
+        #[clap(long, default_value = DEFAULT_CONTAINER_IMAGE)]
+        pub container_image: String,
+
+        /// `docker`, `podman`, or a compatible runtime for the container backend
+        #[clap(long, default_value = DEFAULT_CONTAINER_RUNTIME)]
+        pub container_runtime: String,
+
+        /// Megabytes of memory each container may use
+        #[clap(long)]
+        pub container_memory_mb: Option<u64>,
+
+        /// CPUs each container may use, e.g. 1.5
+        #[clap(long)]
+        pub container_cpus: Option<f64>,
+
+        /// Playground URL for the playground backend
+        #[clap(long, default_value = DEFAULT_PLAYGROUND_URL)]
+        pub playground_url: String,
+
+        /// Forward checks to the playground when rustc isn't installed
+        #[clap(long)]
+        pub playground_fallback: bool,
+
+        /// Language of messages and of friendly diagnostics in file checks:
+        /// en, fr, de or es (defaults to the LANG environment variable)
+        #[clap(long)]
+        pub locale: Option<Locale>,
+    }
+
+    /// `mb` megabytes in bytes, capped at `u64::MAX` rather than overflowing
+    fn megabytes(mb: u64) -> u64 {
+        mb.saturating_mul(1024 * 1024)
     }
 
     pub async fn run() -> Result<(), Box<dyn std::error::Error>> {
         let args = Args::parse();
+        let locale = args.locale.unwrap_or_else(Locale::from_env);
+        let container = ContainerConfig {
+            runtime: args.container_runtime,
+            image: args.container_image,
+            memory_bytes: args.container_memory_mb.map(megabytes),
+            cpus: args.container_cpus,
+            ..Default::default()
+        };
 
         if let Some(file_path) = args.file {
             // Direct file check mode
             let code = std::fs::read_to_string(&file_path)?;
-            let compiler = RustCompiler::new()?;
-            let result = compiler.check_code(&code)?;
+            let mut compiler = RustCompiler::new()?;
+            if args.backend == BackendKind::Container {
+                compiler.set_container(Some(container));
+            }
+            let backend = select_backend(
+                args.backend,
+                &args.playground_url,
+                args.playground_fallback,
+                Arc::new(compiler),
+            )?;
+            let mut result = backend.check(&code, &CheckOptions::default())?;
+            simplify_diagnostics_in(&mut result, locale);
 
             println!("{}", serde_json::to_string_pretty(&result)?);
+            eprintln!("{}", locale.check_summary(result.errors.len(), result.warnings.len()));
 
             if !result.success {
                 std::process::exit(1);
@@ -157,7 +1693,44 @@ pub mod cli {
         } else {
             // Service mode
             #[cfg(feature = "web-service")]
-            start_service(args.port).await?;
+            {
+                let config = ServiceConfig {
+                    max_checks_per_client: args.max_checks_per_client,
+                    max_body_bytes: args.max_body_bytes,
+                    slow_check_threshold: Duration::from_secs(args.slow_check_secs),
+                    max_dependencies: args.max_dependencies,
+                    project_pool: (args.pool_max_size > 0).then(|| PoolConfig {
+                        max_size: args.pool_max_size,
+                        ..Default::default()
+                    }),
+                    check_timeout: args.timeout_secs.map(Duration::from_secs),
+                    resource_limits: ResourceLimits {
+                        memory_bytes: args.memory_limit_mb.map(megabytes),
+                        cpu_time: args.cpu_limit_secs.map(Duration::from_secs),
+                    },
+                    sandbox: args.sandbox.then(Sandbox::default),
+                    deny_list: (!args.deny_api.is_empty()).then_some(DenyList {
+                        apis: args.deny_api,
+                        warn_only: args.warn_denied_apis,
+                    }),
+                    shared_target_dir: args.target_dir,
+                    cargo_home: args.cargo_home,
+                    result_cache_size: args.cache_size,
+                    audit_dependencies: args.audit,
+                    allow_path_dependencies: args.allow_path_dependencies,
+                    allow_build_scripts: args.allow_build_scripts,
+                    nightly_for_feature_gates: args.nightly_features,
+                    allowed_toolchains: (!args.allow_toolchain.is_empty())
+                        .then_some(args.allow_toolchain),
+                    allow_native_runs: args.allow_native_runs,
+                    backend: args.backend,
+                    container,
+                    playground_url: args.playground_url,
+                    playground_fallback: args.playground_fallback,
+                    locale,
+                };
+                start_service_with_config(args.port, config).await?;
+            }
 
             #[cfg(not(feature = "web-service"))]
             {
@@ -173,6 +1746,7 @@ pub mod cli {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     #[test]
     fn test_check_request_deserialize() {
@@ -185,4 +1759,301 @@ mod tests {
         assert_eq!(request.code, "fn main() {}");
         assert!(request.quick_check);
     }
+
+    #[test]
+    fn test_check_request_assets_deserialize() {
+        let json = r#"{
+            "code": "fn main() { let _ = include_str!(\"data.txt\"); }",
+            "assets": { "data.txt": "hello" }
+        }"#;
+
+        let request: CheckRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(request.assets.get("data.txt").map(String::as_str), Some("hello"));
+    }
+
+    #[tokio::test]
+    async fn test_batch_respects_max_parallel() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let (running_in, peak_in) = (running.clone(), peak.clone());
+        let results = check_batch(
+            (0..6).collect(),
+            2,
+            Arc::new(Semaphore::new(8)),
+            move |item: usize| {
+                let (running, peak) = (running_in.clone(), peak_in.clone());
+                async move {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    Ok(item * 10)
+                }
+            },
+        )
+        .await;
+
+        let results: Vec<usize> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!(results, vec![0, 10, 20, 30, 40, 50], "Results should keep request order");
+        assert!(peak.load(Ordering::SeqCst) <= 2, "At most two checks should run at once");
+    }
+
+    #[test]
+    fn test_per_client_cap() {
+        let slots = Arc::new(ClientSlots::new(2));
+        let busy: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+
+        let held: Vec<_> = (0..5).filter_map(|_| slots.try_acquire(busy)).collect();
+        assert_eq!(held.len(), 2, "One client should be capped at two checks");
+        assert!(slots.try_acquire(other).is_some(), "Other clients should be unaffected");
+
+        drop(held);
+        assert!(slots.try_acquire(busy).is_some(), "Slots should free up when checks finish");
+    }
+
+    /// A backend whose checks wait until the test releases them
+    struct HeldBackend {
+        started: Mutex<std::sync::mpsc::Sender<()>>,
+        release: Mutex<std::sync::mpsc::Receiver<()>>,
+    }
+
+    impl CompilerBackend for HeldBackend {
+        fn check(
+            &self,
+            code: &str,
+            _options: &CheckOptions,
+        ) -> Result<CompilationResult, CompilerError> {
+            self.quick_check(code, CrateType::Lib)
+        }
+
+        fn quick_check(
+            &self,
+            _code: &str,
+            _crate_type: CrateType,
+        ) -> Result<CompilationResult, CompilerError> {
+            let _ = self.started.lock().unwrap().send(());
+            self.release.lock().unwrap().recv().unwrap();
+            use rust_compiler::{CompilationError, ErrorLevel};
+            let error = CompilationError::new(ErrorLevel::Error, "held");
+            Ok(rust_compiler::failed_result(error))
+        }
+
+        fn run(&self, _code: &str, _options: &RunOptions) -> Result<RunResult, CompilerError> {
+            unimplemented!("Only checks are held")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_per_client_cap_rejects_concurrent_check() {
+        use tower::ServiceExt;
+
+        let (started_sender, started) = std::sync::mpsc::channel();
+        let (release, release_receiver) = std::sync::mpsc::channel();
+        let backend = Arc::new(HeldBackend {
+            started: Mutex::new(started_sender),
+            release: Mutex::new(release_receiver),
+        });
+        let config = ServiceConfig {
+            max_checks_per_client: 1,
+            ..Default::default()
+        };
+        let router = create_router_with_backend(config, backend);
+
+        let check = |client: &str| {
+            let mut request = Request::builder()
+                .method("POST")
+                .uri("/check")
+                .header("content-type", "application/json")
+                .body(Body::from(r#"{"code": "let x = 1;", "quick_check": true}"#))
+                .unwrap();
+            let addr: SocketAddr = client.parse().unwrap();
+            request.extensions_mut().insert(ConnectInfo(addr));
+            router.clone().oneshot(request)
+        };
+
+        let first = tokio::spawn(check("10.0.0.1:4000"));
+        tokio::task::spawn_blocking(move || started.recv().unwrap()).await.unwrap();
+
+        let second = check("10.0.0.1:4001").await.unwrap();
+        assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+        assert_eq!(first.await.unwrap().unwrap().status(), StatusCode::OK);
+        let third = check("10.0.0.1:4002").await.unwrap();
+        assert_eq!(third.status(), StatusCode::OK, "The slot should be free once the check ends");
+    }
+
+    #[tokio::test]
+    async fn test_malformed_body_is_bad_request() {
+        use tower::ServiceExt;
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/check")
+            .header("content-type", "application/json")
+            .body(axum::body::Body::from(r#"{"cod": "x"}"#))
+            .unwrap();
+
+        let response = create_router().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert!(error.error.contains("missing field `code`"), "Got: {}", error.error);
+    }
+
+    #[tokio::test]
+    async fn test_return_formatted() {
+        use tower::ServiceExt;
+
+        if !rust_compiler::is_rust_available() {
+            println!("Skipping test: rustc not available");
+            return;
+        }
+
+        let messy = "pub fn   add(a:i32,b:i32)->i32{a+b}";
+        let body = serde_json::json!({
+            "code": messy,
+            "quick_check": true,
+            "return_formatted": true,
+        });
+        let request = Request::builder()
+            .method("POST")
+            .uri("/check")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+
+        let response = create_router().oneshot(request).await.unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        let formatted = response["formatted_code"]
+            .as_str()
+            .expect("Formatted code should be returned");
+        assert_ne!(formatted, messy);
+        assert_eq!(formatted, "pub fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n");
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint() {
+        use tower::ServiceExt;
+
+        let config = ServiceConfig {
+            project_pool: Some(PoolConfig {
+                min_idle: 2,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let request = Request::builder().uri("/metrics").body(Body::empty()).unwrap();
+        let response = create_router_with_config(config).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let metrics: MetricsResponse = serde_json::from_slice(&body).unwrap();
+        let pool = metrics.pool.expect("Pool metrics should be reported");
+        assert_eq!((pool.available, pool.in_use, pool.created), (2, 0, 2));
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn gzip_request(uri: &str, json: &str) -> Request<axum::body::Body> {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("content-type", "application/json")
+            .header("content-encoding", "gzip")
+            .body(axum::body::Body::from(gzip(json.as_bytes())))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_gzip_request_body() {
+        use tower::ServiceExt;
+
+        if !rust_compiler::is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let request = gzip_request("/check", r#"{"code": "fn main() {}"}"#);
+        let response = create_router().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(response["result"]["success"], true);
+    }
+
+    #[tokio::test]
+    async fn test_gzip_body_limit_applies_after_decompression() {
+        use tower::ServiceExt;
+
+        // Compresses to a few KB but expands well past the limit
+        let padding = " ".repeat(64 * 1024);
+        let json = format!(r#"{{"code": "fn main() {{}}{}"}}"#, padding);
+        let config = ServiceConfig {
+            max_body_bytes: 16 * 1024,
+            ..Default::default()
+        };
+
+        let request = gzip_request("/check", &json);
+        assert!(gzip(json.as_bytes()).len() < 16 * 1024);
+
+        let response = create_router_with_config(config).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_rustc_backend_rejects_dependencies() {
+        use tower::ServiceExt;
+
+        let config = ServiceConfig {
+            backend: BackendKind::Rustc,
+            ..Default::default()
+        };
+        let body = serde_json::json!({
+            "code": "let x = 1;",
+            "dependencies": [{ "name": "rand", "version": "0.8" }],
+        });
+        let request = Request::builder()
+            .method("POST")
+            .uri("/check")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+
+        let response = create_router_with_config(config).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_native_runs_need_isolation() {
+        use tower::ServiceExt;
+
+        let router = create_router_with_config(ServiceConfig::default());
+        for (uri, mode) in [("/run", "native"), ("/test", "wasi"), ("/doctest", "native")] {
+            let body = serde_json::json!({ "code": "println!(\"hi\");", "mode": mode });
+            let request = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+
+            let response = router.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN, "{}", uri);
+        }
+    }
 }
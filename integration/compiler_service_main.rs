@@ -3,6 +3,15 @@
 #[cfg(all(feature = "web-service", feature = "cli"))]
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    use tracing_subscriber::fmt::format::FmtSpan;
+    use tracing_subscriber::EnvFilter;
+
+    // Events, and how long each span took, to stderr, filtered by `RUST_LOG`
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(std::io::stderr)
+        .init();
     blockly_rust_compiler::compiler_service::cli::run().await
 }
 
@@ -0,0 +1,244 @@
+// Container Backend - Checks and runs code inside short-lived containers
+// The deployment model for a public-facing service: compiled code never runs on the host
+
+use serde::Serialize;
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::backend::CompilerBackend;
+use crate::error::CompilerError;
+use crate::resource_limits::ResourceLimits;
+use crate::rust_compiler::{
+    CheckOptions, CompilationResult, CrateType, RunOptions, RunResult, RustCompiler,
+};
+
+/// Image used when none is configured; it has cargo and the stable toolchain
+pub const DEFAULT_CONTAINER_IMAGE: &str = "rust:slim";
+
+/// Container runtime used when none is configured
+pub const DEFAULT_CONTAINER_RUNTIME: &str = "docker";
+
+/// How cargo, rustc and programs being run are put in containers
+///
+/// Every process gets its own container, removed once it exits, with its
+/// project directory and `CARGO_HOME` mounted at the same paths as on the
+/// host; the shared target directory isn't used. Other host files, such as path dependencies
+/// or the projects of other checks, aren't visible. On Unix, processes run as the current user so
+/// the host can clean up the files they create.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContainerConfig {
+    /// `docker`, `podman`, or another runtime taking the same arguments
+    pub runtime: String,
+    /// Image with the toolchain preinstalled, e.g. `rust:1.75-slim`
+    pub image: String,
+    /// Memory the whole container may use, in bytes
+    pub memory_bytes: Option<u64>,
+    /// CPUs the container may use, e.g. `1.5`
+    pub cpus: Option<f64>,
+    /// Processes and threads the container may run at once
+    pub pids_limit: Option<u32>,
+    /// Give containers network access; without it, dependencies must already
+    /// be in the mounted `CARGO_HOME` (see `RustCompiler::set_cargo_home`)
+    pub allow_network: bool,
+}
+
+impl Default for ContainerConfig {
+    fn default() -> Self {
+        Self {
+            runtime: DEFAULT_CONTAINER_RUNTIME.to_string(),
+            image: DEFAULT_CONTAINER_IMAGE.to_string(),
+            memory_bytes: None,
+            cpus: None,
+            pids_limit: Some(512),
+            allow_network: false,
+        }
+    }
+}
+
+impl ContainerConfig {
+    /// Containers with `image`, started by docker
+    pub fn new(image: impl Into<String>) -> Self {
+        Self {
+            image: image.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Whether the runtime is installed and can reach its daemon
+    pub fn is_available(&self) -> bool {
+        Command::new(&self.runtime)
+            .arg("info")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+
+    /// The runtime command running `command` in a new container, with `mounts`
+    /// visible, `limits` on each process in it, and killed after `timeout`
+    ///
+    /// The timeout is enforced inside the container as well, since killing the
+    /// runtime client leaves the container running.
+    pub(crate) fn wrap(
+        &self,
+        command: &Command,
+        mounts: &[&Path],
+        limits: &ResourceLimits,
+        timeout: Option<Duration>,
+    ) -> Command {
+        let mut wrapped = Command::new(&self.runtime);
+        wrapped.args(["run", "--rm", "--interactive"]);
+        if !self.allow_network {
+            wrapped.arg("--network=none");
+        }
+        if let Some(bytes) = self.memory_bytes {
+            wrapped.arg(format!("--memory={}b", bytes));
+        }
+        if let Some(cpus) = self.cpus {
+            wrapped.arg(format!("--cpus={}", cpus));
+        }
+        if let Some(pids) = self.pids_limit {
+            wrapped.arg(format!("--pids-limit={}", pids));
+        }
+        if let Some(bytes) = limits.memory_bytes {
+            wrapped.arg(format!("--ulimit=data={}", bytes));
+        }
+        if let Some(cpu_time) = limits.cpu_time {
+            let seconds = cpu_time.as_secs() + u64::from(cpu_time.subsec_nanos() > 0);
+            wrapped.arg(format!("--ulimit=cpu={}", seconds.max(1)));
+        }
+        #[cfg(unix)]
+        {
+            // SAFETY: getuid and getgid can't fail
+            let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+            wrapped.arg(format!("--user={}:{}", uid, gid));
+        }
+
+        for mount in mounts {
+            let mut volume = OsString::from("--volume=");
+            volume.push(mount);
+            volume.push(":");
+            volume.push(mount);
+            wrapped.arg(volume);
+        }
+        if let Some(dir) = command.get_current_dir() {
+            let mut workdir = OsString::from("--workdir=");
+            workdir.push(dir);
+            wrapped.arg(workdir);
+        }
+        for (name, value) in command.get_envs() {
+            if let Some(value) = value {
+                let mut variable = name.to_os_string();
+                variable.push("=");
+                variable.push(value);
+                wrapped.arg("--env").arg(variable);
+            }
+        }
+
+        wrapped.arg(&self.image);
+        if let Some(timeout) = timeout {
+            let seconds = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+            wrapped.args(["timeout", "--signal=KILL", &seconds.max(1).to_string()]);
+        }
+        wrapped.arg(command.get_program()).args(command.get_args());
+        wrapped
+    }
+}
+
+/// Checks and runs code with cargo inside containers; see `ContainerConfig`
+///
+/// Tools that don't run the checked code, like rustfmt, still run on the host.
+pub struct ContainerBackend {
+    compiler: Arc<RustCompiler>,
+}
+
+impl ContainerBackend {
+    /// A backend with its own compiler, starting containers as `config` says
+    pub fn new(config: ContainerConfig) -> Result<Self, CompilerError> {
+        Ok(Self::with_compiler(RustCompiler::new()?, config))
+    }
+
+    /// A backend checking with `compiler`, keeping its other settings
+    pub fn with_compiler(mut compiler: RustCompiler, config: ContainerConfig) -> Self {
+        compiler.set_container(Some(config));
+        Self {
+            compiler: Arc::new(compiler),
+        }
+    }
+
+    /// A backend checking with `compiler`, which must already have a container
+    /// set; see `RustCompiler::set_container`
+    pub fn shared(compiler: Arc<RustCompiler>) -> Result<Self, CompilerError> {
+        if compiler.container().is_none() {
+            let reason = "The container backend needs a compiler with a container set";
+            return Err(CompilerError::InvalidInput(reason.to_string()));
+        }
+        Ok(Self { compiler })
+    }
+}
+
+impl CompilerBackend for ContainerBackend {
+    fn check(
+        &self,
+        code: &str,
+        options: &CheckOptions,
+    ) -> Result<CompilationResult, CompilerError> {
+        self.compiler.check_with_options(code, options)
+    }
+
+    fn quick_check(
+        &self,
+        code: &str,
+        crate_type: CrateType,
+    ) -> Result<CompilationResult, CompilerError> {
+        self.compiler.quick_check_as(code, crate_type)
+    }
+
+    fn run(&self, code: &str, options: &RunOptions) -> Result<RunResult, CompilerError> {
+        self.compiler.run_code(code, options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_container_command() {
+        let config = ContainerConfig {
+            runtime: "podman".to_string(),
+            memory_bytes: Some(1 << 30),
+            ..ContainerConfig::new("rust:1.75-slim")
+        };
+        let limits = ResourceLimits {
+            memory_bytes: None,
+            cpu_time: Some(Duration::from_millis(2500)),
+        };
+        let mut command = Command::new("cargo");
+        command.args(["check", "--quiet"]).current_dir("/work/check_1").env("CARGO_HOME", "/cargo");
+
+        let wrapped =
+            config.wrap(&command, &[Path::new("/work")], &limits, Some(Duration::from_secs(10)));
+        let args: Vec<_> = wrapped.get_args().map(|arg| arg.to_string_lossy()).collect();
+        assert_eq!(wrapped.get_program(), "podman");
+        for expected in [
+            "--network=none",
+            "--memory=1073741824b",
+            "--pids-limit=512",
+            "--ulimit=cpu=3",
+            "--volume=/work:/work",
+            "--workdir=/work/check_1",
+            "CARGO_HOME=/cargo",
+        ] {
+            assert!(args.contains(&expected.into()), "{} missing from {:?}", expected, args);
+        }
+        let image = args.iter().position(|arg| arg == "rust:1.75-slim").unwrap();
+        assert_eq!(
+            args[image + 1..],
+            ["timeout", "--signal=KILL", "10", "cargo", "check", "--quiet"]
+        );
+    }
+}
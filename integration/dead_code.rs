@@ -0,0 +1,397 @@
+// Dead Code - Finds functions, types and variables a project never uses
+// Lets the editor grey out the blocks whose generated code has no effect
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use proc_macro2::{LineColumn, Span, TokenStream, TokenTree};
+use serde::{Deserialize, Serialize};
+use syn::spanned::Spanned;
+use syn::visit::Visit;
+
+use crate::rust_compiler::{ParsedSnippet, SourceRange};
+
+/// What kind of definition a `DeadCode` entry is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeadCodeKind {
+    Function,
+    /// A method of an inherent `impl`; trait methods are assumed used
+    Method,
+    Struct,
+    Enum,
+    Const,
+    /// A `let` binding inside a function
+    Variable,
+}
+
+/// A definition nothing in the project uses, as found by `dead_code`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeadCode {
+    /// The name, with its type for methods, e.g. `Cell::divide`
+    pub name: String,
+    pub kind: DeadCodeKind,
+    /// The file it's in, as given to `dead_code`
+    pub file: PathBuf,
+    /// The whole item or `let` statement, with 1-based lines and columns and
+    /// an exclusive end
+    pub span: SourceRange,
+}
+
+/// Definitions in `files` that nothing in any of them uses, by file and position
+///
+/// Files are keyed by path relative to `src`, like `RustCompiler::check_project`
+/// takes them, or a single snippet can be passed as `main.rs`. This is a pure
+/// parse and matches uses by name, so it can miss dead code when an unrelated
+/// item shares its name, but never reports code as dead that's used. Items are
+/// dead if nothing outside their own definition names them; `main`, tests,
+/// trait impls and, in libraries without a `main.rs`, `pub` items count as used.
+/// Variables are dead if nothing after them in their function names them.
+pub fn dead_code(files: &HashMap<PathBuf, String>) -> Vec<DeadCode> {
+    let library = !files.contains_key(&PathBuf::from("main.rs"));
+    let mut definitions = Definitions { library, file: PathBuf::new(), found: Vec::new() };
+    let mut uses = Uses { own: Vec::new(), names: HashSet::new() };
+
+    let mut paths: Vec<&PathBuf> = files.keys().collect();
+    paths.sort();
+    for path in paths {
+        definitions.file = path.clone();
+        let code = &files[path];
+        let Some(parsed) = ParsedSnippet::parse(code) else {
+            continue;
+        };
+        let start = definitions.found.len();
+        parsed.visit(&mut definitions);
+        if let ParsedSnippet::Block(block) = &parsed {
+            definitions.find_dead_variables(block);
+        }
+        for (_, dead) in &mut definitions.found[start..] {
+            dead.span.line_start -= parsed.line_offset();
+            dead.span.line_end -= parsed.line_offset();
+        }
+        parsed.visit(&mut uses);
+    }
+
+    let mut dead: Vec<DeadCode> = definitions
+        .found
+        .into_iter()
+        .filter(|(name, dead)| dead.kind == DeadCodeKind::Variable || !uses.names.contains(name))
+        .map(|(_, dead)| dead)
+        .collect();
+    dead.sort_by(|a, b| {
+        let position = |dead: &DeadCode| (dead.span.line_start, dead.span.column_start);
+        a.file.cmp(&b.file).then_with(|| position(a).cmp(&position(b)))
+    });
+    dead
+}
+
+/// Collects definitions that could be dead with the name uses would refer to
+/// them by; variables are only collected once known to be dead
+struct Definitions {
+    library: bool,
+    file: PathBuf,
+    found: Vec<(String, DeadCode)>,
+}
+
+impl Definitions {
+    /// Record a definition used by `name`, reported as `display_name`
+    fn push(&mut self, name: String, display_name: String, kind: DeadCodeKind, span: SourceRange) {
+        let dead = DeadCode { name: display_name, kind, file: self.file.clone(), span };
+        self.found.push((name, dead));
+    }
+
+    fn push_item(
+        &mut self,
+        ident: &syn::Ident,
+        kind: DeadCodeKind,
+        vis: &syn::Visibility,
+        span: Span,
+    ) {
+        if !self.is_exported(vis) {
+            self.push(ident.to_string(), ident.to_string(), kind, range(span));
+        }
+    }
+
+    /// Whether an item is part of a library's API, and so used by its users
+    fn is_exported(&self, vis: &syn::Visibility) -> bool {
+        self.library && !matches!(vis, syn::Visibility::Inherited)
+    }
+
+    /// Collect the `let` bindings in `body` that nothing after them names
+    fn find_dead_variables(&mut self, body: &syn::Block) {
+        let mut locals = Locals { bindings: Vec::new(), uses: Vec::new() };
+        locals.visit_block(body);
+        for (name, at, span) in locals.bindings {
+            let used = locals.uses.iter().any(|(used, used_at)| *used == name && *used_at > at);
+            if !used {
+                self.push(name.clone(), name, DeadCodeKind::Variable, span);
+            }
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for Definitions {
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        let is_test = item.attrs.iter().any(|attr| attr.path().is_ident("test"));
+        if item.sig.ident != "main" && !is_test {
+            self.push_item(&item.sig.ident, DeadCodeKind::Function, &item.vis, item.span());
+        }
+        self.find_dead_variables(&item.block);
+        syn::visit::visit_item_fn(self, item);
+    }
+
+    fn visit_item_struct(&mut self, item: &'ast syn::ItemStruct) {
+        self.push_item(&item.ident, DeadCodeKind::Struct, &item.vis, item.span());
+    }
+
+    fn visit_item_enum(&mut self, item: &'ast syn::ItemEnum) {
+        self.push_item(&item.ident, DeadCodeKind::Enum, &item.vis, item.span());
+    }
+
+    fn visit_item_const(&mut self, item: &'ast syn::ItemConst) {
+        self.push_item(&item.ident, DeadCodeKind::Const, &item.vis, item.span());
+        syn::visit::visit_item_const(self, item);
+    }
+
+    fn visit_item_impl(&mut self, item: &'ast syn::ItemImpl) {
+        let owner = match item.self_ty.as_ref() {
+            syn::Type::Path(path) => path.path.segments.last().map(|s| s.ident.to_string()),
+            _ => None,
+        };
+        for impl_item in &item.items {
+            let syn::ImplItem::Fn(method) = impl_item else {
+                continue;
+            };
+            self.find_dead_variables(&method.block);
+            syn::visit::visit_block(self, &method.block);
+
+            if item.trait_.is_none() && !self.is_exported(&method.vis) {
+                let name = method.sig.ident.to_string();
+                let display_name = match &owner {
+                    Some(owner) => format!("{}::{}", owner, name),
+                    None => name.clone(),
+                };
+                self.push(name, display_name, DeadCodeKind::Method, range(method.span()));
+            }
+        }
+    }
+}
+
+/// Collects every name used anywhere, except inside the item it names, so a
+/// recursive function or a struct holding a `Box` of itself isn't used
+/// just by referring to itself
+struct Uses {
+    own: Vec<String>,
+    names: HashSet<String>,
+}
+
+impl Uses {
+    fn add(&mut self, name: String) {
+        if !self.own.contains(&name) {
+            self.names.insert(name);
+        }
+    }
+
+    fn within(&mut self, ident: &syn::Ident, visit: impl FnOnce(&mut Self)) {
+        self.own.push(ident.to_string());
+        visit(self);
+        self.own.pop();
+    }
+}
+
+impl<'ast> Visit<'ast> for Uses {
+    fn visit_ident(&mut self, ident: &'ast proc_macro2::Ident) {
+        self.add(ident.to_string());
+    }
+
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        syn::visit::visit_macro(self, mac);
+        let mut names = Vec::new();
+        names_in_tokens(mac.tokens.clone(), &mut names);
+        for (name, _) in names {
+            self.add(name);
+        }
+    }
+
+    /// Imports name items without using them
+    fn visit_item_use(&mut self, _: &'ast syn::ItemUse) {}
+
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        self.within(&item.sig.ident, |uses| syn::visit::visit_item_fn(uses, item));
+    }
+
+    fn visit_item_struct(&mut self, item: &'ast syn::ItemStruct) {
+        self.within(&item.ident, |uses| syn::visit::visit_item_struct(uses, item));
+    }
+
+    fn visit_item_enum(&mut self, item: &'ast syn::ItemEnum) {
+        self.within(&item.ident, |uses| syn::visit::visit_item_enum(uses, item));
+    }
+
+    fn visit_item_const(&mut self, item: &'ast syn::ItemConst) {
+        self.within(&item.ident, |uses| syn::visit::visit_item_const(uses, item));
+    }
+
+    /// Implementing methods or traits for a type doesn't use it
+    fn visit_item_impl(&mut self, item: &'ast syn::ItemImpl) {
+        for impl_item in &item.items {
+            match impl_item {
+                syn::ImplItem::Fn(method) => {
+                    self.within(&method.sig.ident, |uses| uses.visit_impl_item_fn(method));
+                }
+                other => self.visit_impl_item(other),
+            }
+        }
+    }
+
+    /// Only the method's name, and not what it's defined with, is its own
+    fn visit_signature(&mut self, signature: &'ast syn::Signature) {
+        for input in &signature.inputs {
+            self.visit_fn_arg(input);
+        }
+        self.visit_return_type(&signature.output);
+    }
+}
+
+/// The `let` bindings of a function body and the single names used in it,
+/// each with where it is; nested items are left to their own scan
+struct Locals {
+    bindings: Vec<(String, LineColumn, SourceRange)>,
+    uses: Vec<(String, LineColumn)>,
+}
+
+impl Locals {
+    fn bind(&mut self, pat: &syn::Pat, statement: SourceRange) {
+        match pat {
+            syn::Pat::Ident(binding) => {
+                let name = binding.ident.to_string();
+                if !name.starts_with('_') {
+                    let at = binding.ident.span().start();
+                    self.bindings.push((name, at, statement));
+                }
+            }
+            syn::Pat::Tuple(tuple) => tuple.elems.iter().for_each(|pat| self.bind(pat, statement)),
+            syn::Pat::Type(typed) => self.bind(&typed.pat, statement),
+            syn::Pat::Reference(reference) => self.bind(&reference.pat, statement),
+            _ => {}
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for Locals {
+    fn visit_item(&mut self, _: &'ast syn::Item) {}
+
+    fn visit_local(&mut self, local: &'ast syn::Local) {
+        self.bind(&local.pat, range(local.span()));
+        syn::visit::visit_local(self, local);
+    }
+
+    fn visit_expr_path(&mut self, expr: &'ast syn::ExprPath) {
+        if let Some(ident) = expr.path.get_ident() {
+            self.uses.push((ident.to_string(), ident.span().start()));
+        }
+        syn::visit::visit_expr_path(self, expr);
+    }
+
+    fn visit_field_value(&mut self, field: &'ast syn::FieldValue) {
+        // `Cell { energy }` uses a variable named `energy`
+        if field.colon_token.is_none() {
+            if let syn::Member::Named(ident) = &field.member {
+                self.uses.push((ident.to_string(), ident.span().start()));
+            }
+        }
+        syn::visit::visit_field_value(self, field);
+    }
+
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        names_in_tokens(mac.tokens.clone(), &mut self.uses);
+    }
+}
+
+/// Every identifier in `tokens`, and each name captured by a format string
+/// like `"{count}"`, with where it is
+fn names_in_tokens(tokens: TokenStream, names: &mut Vec<(String, LineColumn)>) {
+    for token in tokens {
+        match token {
+            TokenTree::Ident(ident) => names.push((ident.to_string(), ident.span().start())),
+            TokenTree::Group(group) => names_in_tokens(group.stream(), names),
+            TokenTree::Literal(literal) => {
+                let text = literal.to_string();
+                if !text.starts_with('"') {
+                    continue;
+                }
+                let at = literal.span().start();
+                for placeholder in text.split('{').skip(1) {
+                    let name: String = placeholder
+                        .chars()
+                        .take_while(|c| c.is_alphanumeric() || *c == '_')
+                        .collect();
+                    if name.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+                        names.push((name, at));
+                    }
+                }
+            }
+            TokenTree::Punct(_) => {}
+        }
+    }
+}
+
+fn range(span: Span) -> SourceRange {
+    let (start, end) = (span.start(), span.end());
+    SourceRange {
+        line_start: start.line,
+        column_start: start.column + 1,
+        line_end: end.line,
+        column_end: end.column + 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dead_code() {
+        let main = "mod shapes;\n\
+                    struct Unused;\n\
+                    fn main() {\n\
+                    \x20   let side = 3;\n\
+                    \x20   let scratch = side * 2;\n\
+                    \x20   let shown = shapes::area(side, side);\n\
+                    \x20   println!(\"{shown}\");\n\
+                    }\n\
+                    fn countdown(n: u32) {\n\
+                    \x20   if n > 0 { countdown(n - 1) }\n\
+                    }\n";
+        let shapes = "pub struct Square { pub side: u32 }\n\
+                      impl Square {\n\
+                      \x20   pub fn grow(&mut self) { self.side += 1; }\n\
+                      }\n\
+                      pub fn area(w: u32, h: u32) -> u32 { w * h }\n";
+        let files = HashMap::from([
+            (PathBuf::from("main.rs"), main.to_string()),
+            (PathBuf::from("shapes.rs"), shapes.to_string()),
+        ]);
+
+        let dead = dead_code(&files);
+        let found: Vec<_> = dead
+            .iter()
+            .map(|d| (d.file.to_str().unwrap(), d.name.as_str(), d.kind, d.span.line_start))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("main.rs", "Unused", DeadCodeKind::Struct, 2),
+                ("main.rs", "scratch", DeadCodeKind::Variable, 5),
+                ("main.rs", "countdown", DeadCodeKind::Function, 9),
+                ("shapes.rs", "Square", DeadCodeKind::Struct, 1),
+                ("shapes.rs", "Square::grow", DeadCodeKind::Method, 3),
+            ]
+        );
+
+        let snippet = HashMap::from([(PathBuf::from("main.rs"), "let x = 1;\nlet y = x;".into())]);
+        let dead = dead_code(&snippet);
+        assert_eq!(dead.len(), 1);
+        assert_eq!((dead[0].name.as_str(), dead[0].span.line_start), ("y", 2));
+    }
+}
@@ -0,0 +1,418 @@
+// Deny List - Finds uses of dangerous APIs in code before it's compiled
+// Lets classroom deployments keep code from starting processes, using the network or changing files
+
+use std::collections::HashMap;
+
+use proc_macro2::Span;
+use serde::{Deserialize, Serialize};
+use syn::spanned::Spanned;
+use syn::visit::Visit;
+
+use crate::rust_compiler::{
+    visit_macro_arguments, CompilationError, ErrorCategory, ErrorLevel, ParsedSnippet,
+};
+
+/// Functions and types in `std::fs` that create, change or remove files
+const FILE_WRITE_ITEMS: &[&str] = &[
+    "write",
+    "create_dir",
+    "create_dir_all",
+    "remove_file",
+    "remove_dir",
+    "remove_dir_all",
+    "rename",
+    "copy",
+    "hard_link",
+    "soft_link",
+    "set_permissions",
+    "OpenOptions",
+];
+
+/// A group of APIs a deny list can forbid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeniedApi {
+    /// `std::process`: starting programs, and exiting or aborting
+    Process,
+    /// `std::net`
+    Network,
+    /// The `std::fs` functions that change files, `File::create` and `OpenOptions`
+    FileWrite,
+    /// `unsafe` blocks, functions, impls and traits
+    Unsafe,
+}
+
+impl DeniedApi {
+    pub const ALL: [DeniedApi; 4] =
+        [DeniedApi::Process, DeniedApi::Network, DeniedApi::FileWrite, DeniedApi::Unsafe];
+
+    /// What using the API does, to start a message with
+    fn description(&self) -> &'static str {
+        match self {
+            DeniedApi::Process => "Starting or controlling processes",
+            DeniedApi::Network => "Network access",
+            DeniedApi::FileWrite => "Changing files",
+            DeniedApi::Unsafe => "Unsafe code",
+        }
+    }
+}
+
+impl std::str::FromStr for DeniedApi {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "process" => Ok(DeniedApi::Process),
+            "network" => Ok(DeniedApi::Network),
+            "file_write" => Ok(DeniedApi::FileWrite),
+            "unsafe" => Ok(DeniedApi::Unsafe),
+            _ => Err(format!(
+                "Unknown API `{}`; expected process, network, file_write or unsafe",
+                name
+            )),
+        }
+    }
+}
+
+/// APIs submitted code may not use, found by scanning its syntax tree
+///
+/// This catches code written against the APIs directly, including inside
+/// macro arguments like `println!`. It's a guard for teaching, not a security
+/// boundary: code can still reach the APIs through dependencies or macros it
+/// defines, so public services should also use a `Sandbox` or container.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DenyList {
+    pub apis: Vec<DeniedApi>,
+    /// Report uses as warnings and check the code anyway, instead of failing
+    /// the check before it's compiled
+    #[serde(default)]
+    pub warn_only: bool,
+}
+
+impl Default for DenyList {
+    /// Every API, rejected
+    fn default() -> Self {
+        Self {
+            apis: DeniedApi::ALL.to_vec(),
+            warn_only: false,
+        }
+    }
+}
+
+impl DenyList {
+    /// Uses of denied APIs in `code`, in source order: errors, or warnings
+    /// with `warn_only`
+    ///
+    /// Snippets are parsed like `RustCompiler::list_symbols` parses them.
+    /// Names brought in with `use` or `extern crate`, renamed or not, are
+    /// followed, so `use std::fs as f;` makes `f::write` a use of
+    /// `std::fs::write`. Code that doesn't parse can't be scanned, so it gets
+    /// a single error (or warning) saying so.
+    pub fn scan(&self, code: &str) -> Vec<CompilationError> {
+        let level = if self.warn_only { ErrorLevel::Warning } else { ErrorLevel::Error };
+        let Some(parsed) = ParsedSnippet::parse(code) else {
+            let message = "This code couldn't be parsed, so it couldn't be checked for APIs \
+                           that aren't allowed. Fix its syntax errors first.";
+            return vec![CompilationError {
+                category: Some(ErrorCategory::DeniedApi),
+                ..CompilationError::new(level, message)
+            }];
+        };
+
+        let mut aliases = Aliases::default();
+        parsed.visit(&mut aliases);
+        let mut scanner = Scanner {
+            apis: &self.apis,
+            aliases: aliases.0,
+            found: Vec::new(),
+        };
+        parsed.visit(&mut scanner);
+        let line_offset = parsed.line_offset();
+
+        let mut found = scanner.found;
+        found.sort_by_key(|(_, _, span)| (span.start().line, span.start().column));
+        found
+            .into_iter()
+            .map(|(api, path, span)| {
+                let message = match path {
+                    Some(path) => format!("{} is not allowed: `{}`", api.description(), path),
+                    None => format!("{} is not allowed", api.description()),
+                };
+                let (start, end) = (span.start(), span.end());
+                CompilationError {
+                    line: Some(start.line - line_offset),
+                    column: Some(start.column + 1),
+                    line_end: Some(end.line - line_offset),
+                    column_end: Some(end.column + 1),
+                    category: Some(ErrorCategory::DeniedApi),
+                    ..CompilationError::new(level.clone(), message)
+                }
+            })
+            .collect()
+    }
+}
+
+/// The full path of each name a `use` or `extern crate` brings into scope,
+/// such as `p` for `std::process` in `use std::process as p;`
+///
+/// Scopes aren't tracked, so a name imported anywhere counts everywhere.
+#[derive(Default)]
+struct Aliases(HashMap<String, Vec<String>>);
+
+impl Aliases {
+    fn add_use_tree(&mut self, tree: &syn::UseTree, prefix: &mut Vec<String>) {
+        match tree {
+            syn::UseTree::Path(path) => {
+                prefix.push(path.ident.to_string());
+                self.add_use_tree(&path.tree, prefix);
+                prefix.pop();
+            }
+            syn::UseTree::Name(name) if name.ident == "self" => {
+                if let Some(last) = prefix.last() {
+                    self.0.insert(last.clone(), prefix.clone());
+                }
+            }
+            syn::UseTree::Name(name) => {
+                let path = prefix.iter().cloned().chain([name.ident.to_string()]).collect();
+                self.0.insert(name.ident.to_string(), path);
+            }
+            syn::UseTree::Rename(rename) => {
+                let mut path = prefix.clone();
+                if rename.ident != "self" {
+                    path.push(rename.ident.to_string());
+                }
+                self.0.insert(rename.rename.to_string(), path);
+            }
+            syn::UseTree::Glob(_) => {}
+            syn::UseTree::Group(group) => {
+                for tree in &group.items {
+                    self.add_use_tree(tree, prefix);
+                }
+            }
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for Aliases {
+    fn visit_item_use(&mut self, item: &'ast syn::ItemUse) {
+        self.add_use_tree(&item.tree, &mut Vec::new());
+    }
+
+    fn visit_item_extern_crate(&mut self, item: &'ast syn::ItemExternCrate) {
+        if let Some((_, rename)) = &item.rename {
+            self.0.insert(rename.to_string(), vec![item.ident.to_string()]);
+        }
+    }
+}
+
+/// Collects denied uses with the path used, if any, and where they are
+struct Scanner<'a> {
+    apis: &'a [DeniedApi],
+    aliases: HashMap<String, Vec<String>>,
+    found: Vec<(DeniedApi, Option<String>, Span)>,
+}
+
+impl Scanner<'_> {
+    fn push(&mut self, api: DeniedApi, path: Option<String>, span: Span) {
+        if self.apis.contains(&api) {
+            self.found.push((api, path, span));
+        }
+    }
+
+    /// Report `segments` if it's denied as written or once its first segment
+    /// is replaced by the path it was imported as
+    fn check_path(&mut self, segments: &[String], span: Span) {
+        if let Some(api) = denied_path(segments) {
+            self.push(api, Some(segments.join("::")), span);
+            return;
+        }
+        let resolved = self.resolve(segments);
+        if let Some(api) = denied_path(&resolved) {
+            let path = format!("{}`, which is `{}", segments.join("::"), resolved.join("::"));
+            self.push(api, Some(path), span);
+        }
+    }
+
+    /// `segments` with imported names at the start replaced by their full
+    /// paths, following aliases of aliases a few levels deep
+    fn resolve(&self, segments: &[String]) -> Vec<String> {
+        let mut resolved = segments.to_vec();
+        for _ in 0..8 {
+            let Some(path) = resolved.first().and_then(|first| self.aliases.get(first)) else {
+                break;
+            };
+            if path.first() == resolved.first() {
+                break;
+            }
+            resolved = path.iter().chain(&resolved[1..]).cloned().collect();
+        }
+        resolved
+    }
+
+    /// Check each path a `use` brings in, like `std::fs::write` in
+    /// `use std::{fs::write, io};`
+    fn check_use_tree(&mut self, tree: &syn::UseTree, prefix: &mut Vec<String>) {
+        match tree {
+            syn::UseTree::Path(path) => {
+                prefix.push(path.ident.to_string());
+                self.check_use_tree(&path.tree, prefix);
+                prefix.pop();
+            }
+            syn::UseTree::Name(name) if name.ident == "self" => {
+                self.check_path(prefix, name.ident.span());
+            }
+            syn::UseTree::Name(syn::UseName { ident })
+            | syn::UseTree::Rename(syn::UseRename { ident, .. }) => {
+                prefix.push(ident.to_string());
+                self.check_path(prefix, ident.span());
+                prefix.pop();
+            }
+            syn::UseTree::Glob(glob) => {
+                prefix.push("*".to_string());
+                self.check_path(prefix, glob.star_token.span);
+                prefix.pop();
+            }
+            syn::UseTree::Group(group) => {
+                for tree in &group.items {
+                    self.check_use_tree(tree, prefix);
+                }
+            }
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for Scanner<'_> {
+    fn visit_path(&mut self, path: &'ast syn::Path) {
+        let segments: Vec<String> =
+            path.segments.iter().map(|segment| segment.ident.to_string()).collect();
+        self.check_path(&segments, path.span());
+        syn::visit::visit_path(self, path);
+    }
+
+    fn visit_item_use(&mut self, item: &'ast syn::ItemUse) {
+        self.check_use_tree(&item.tree, &mut Vec::new());
+    }
+
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        syn::visit::visit_macro(self, mac);
+        visit_macro_arguments(self, mac);
+    }
+
+    fn visit_expr_unsafe(&mut self, expr: &'ast syn::ExprUnsafe) {
+        self.push(DeniedApi::Unsafe, None, expr.unsafe_token.span);
+        syn::visit::visit_expr_unsafe(self, expr);
+    }
+
+    fn visit_signature(&mut self, signature: &'ast syn::Signature) {
+        if let Some(unsafety) = signature.unsafety {
+            self.push(DeniedApi::Unsafe, None, unsafety.span);
+        }
+        syn::visit::visit_signature(self, signature);
+    }
+
+    fn visit_item_impl(&mut self, item: &'ast syn::ItemImpl) {
+        if let Some(unsafety) = item.unsafety {
+            self.push(DeniedApi::Unsafe, None, unsafety.span);
+        }
+        syn::visit::visit_item_impl(self, item);
+    }
+
+    fn visit_item_trait(&mut self, item: &'ast syn::ItemTrait) {
+        if let Some(unsafety) = item.unsafety {
+            self.push(DeniedApi::Unsafe, None, unsafety.span);
+        }
+        syn::visit::visit_item_trait(self, item);
+    }
+}
+
+/// The API a path refers to, if it's denied: anything under `std::process`
+/// or `std::net`, or a file-changing item of `std::fs` named through `std`,
+/// an imported `fs`, or the item itself
+fn denied_path(segments: &[String]) -> Option<DeniedApi> {
+    let names: Vec<&str> = segments.iter().map(String::as_str).collect();
+    let relative = match names.as_slice() {
+        ["std", "process", ..] => return Some(DeniedApi::Process),
+        ["std", "net", ..] => return Some(DeniedApi::Network),
+        ["std", "os", .., "fs", link] if link.starts_with("symlink") => {
+            return Some(DeniedApi::FileWrite)
+        }
+        ["std", rest @ ..] => rest,
+        all => all,
+    };
+    let writes = match relative {
+        [.., "File", "create" | "create_new"] => true,
+        ["fs", "*"] => true,
+        ["fs", name, ..] => FILE_WRITE_ITEMS.contains(name),
+        ["OpenOptions", ..] => true,
+        _ => false,
+    };
+    writes.then_some(DeniedApi::FileWrite)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deny_list_scan() {
+        let code = "use std::process::Command;\n\
+                    use std::fs;\n\
+                    let text = fs::read_to_string(\"in.txt\").unwrap();\n\
+                    fs::write(\"out.txt\", &text).unwrap();\n\
+                    println!(\"{:?}\", std::net::TcpStream::connect(\"example.com:80\"));\n\
+                    let x = unsafe { *(&1 as *const i32) };";
+        let uses = DenyList::default().scan(code);
+
+        let found: Vec<_> =
+            uses.iter().map(|e| (e.line.unwrap(), e.column.unwrap(), e.message.as_str())).collect();
+        assert_eq!(
+            found,
+            [
+                (1, 19, "Starting or controlling processes is not allowed: \
+                         `std::process::Command`"),
+                (4, 1, "Changing files is not allowed: `fs::write`"),
+                (5, 18, "Network access is not allowed: `std::net::TcpStream::connect`"),
+                (6, 9, "Unsafe code is not allowed"),
+            ]
+        );
+        assert!(uses.iter().all(|e| e.category == Some(ErrorCategory::DeniedApi)));
+        assert!(matches!(uses[0].level, ErrorLevel::Error));
+
+        let warn_only = DenyList {
+            apis: vec![DeniedApi::FileWrite],
+            warn_only: true,
+        };
+        let uses = warn_only.scan(code);
+        assert_eq!(uses.len(), 1);
+        assert!(matches!(uses[0].level, ErrorLevel::Warning));
+        assert_eq!((uses[0].column, uses[0].column_end), (Some(1), Some(10)));
+    }
+
+    #[test]
+    fn test_deny_list_follows_aliases() {
+        let code = "use std::fs as f;\n\
+                    use std as s;\n\
+                    fn main() {\n\
+                    f::write(\"out.txt\", \"\").unwrap();\n\
+                    s::process::Command::new(\"ls\").status().unwrap();\n\
+                    }";
+        let messages: Vec<_> =
+            DenyList::default().scan(code).into_iter().map(|error| error.message).collect();
+        assert_eq!(
+            messages,
+            [
+                "Changing files is not allowed: `f::write`, which is `std::fs::write`",
+                "Starting or controlling processes is not allowed: \
+                 `s::process::Command::new`, which is `std::process::Command::new`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deny_list_rejects_unparsable_code() {
+        let uses = DenyList::default().scan("fn main() { std::process::exit(1)");
+        assert_eq!(uses.len(), 1);
+        assert!(matches!(uses[0].level, ErrorLevel::Error));
+        assert!(uses[0].message.starts_with("This code couldn't be parsed"));
+    }
+}
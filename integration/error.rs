@@ -0,0 +1,55 @@
+// Compiler Error - The ways a compiler call can fail
+// Callers match on the variant to tell a missing toolchain from an I/O failure
+
+use thiserror::Error;
+
+/// Why a call failed before producing a result
+///
+/// Problems in the checked code are not errors; they come back as a
+/// `CompilationResult` with `success: false`.
+#[derive(Debug, Error)]
+pub enum CompilerError {
+    /// A required tool or toolchain (nightly, rust-analyzer, a target) is not installed
+    #[error("{0}")]
+    ToolchainMissing(String),
+    /// A tool ran but failed, e.g. rustdoc or `cargo metadata` exiting non-zero
+    #[error("{0}")]
+    ToolFailed(String),
+    /// The request itself is invalid, e.g. an unsafe asset path or a rejected dependency
+    #[error("{0}")]
+    InvalidInput(String),
+    /// A tool's output or an input file could not be parsed
+    #[error("{0}")]
+    Parse(String),
+    /// A tool took longer than the configured timeout
+    #[error("{0}")]
+    Timeout(String),
+    /// The call was cancelled through its `CancellationToken`
+    #[error("The check was cancelled")]
+    Cancelled,
+    /// Reading or writing files, or spawning a process, failed
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// JSON could not be read or written
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_conversions() {
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "gone");
+        let error = CompilerError::from(io);
+        assert!(matches!(error, CompilerError::Io(_)));
+        assert!(std::error::Error::source(&error).is_some());
+
+        let json = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        assert!(matches!(CompilerError::from(json), CompilerError::Json(_)));
+
+        let missing = CompilerError::ToolchainMissing("nightly is not installed".to_string());
+        assert_eq!(missing.to_string(), "nightly is not installed");
+    }
+}
@@ -0,0 +1,622 @@
+// Friendly Messages - Plain-language explanations of common compiler diagnostics
+// Lets the visual editor show beginners what went wrong without rustc's jargon
+
+use crate::locale::Locale;
+use crate::rust_compiler::{CompilationError, CompilationResult};
+
+/// Explanations by error code or lint name; `{}` stands for the first name in
+/// backticks in the compiler's message, such as the variable it's about, or
+/// the last for the codes in `LAST_NAME_CODES`.
+/// Each translation has the same codes, with `{}` used at most once and as
+/// the object of a sentence where the language changes words to match it.
+const FRIENDLY_MESSAGES: &[(&str, &str)] = &[
+    (
+        "E0061",
+        "This function is given a different number of values than it takes. \
+         Check how many inputs it expects.",
+    ),
+    (
+        "E0063",
+        "Some fields of `{}` are missing. Every field needs a value when you create it.",
+    ),
+    (
+        "E0106",
+        "Rust needs to know how long this borrowed value lives. Try returning an \
+         owned value, like `String` instead of `&str`.",
+    ),
+    (
+        "E0277",
+        "This value's type can't be used here: it doesn't support what the code \
+         asks of it, like being printed, compared or added.",
+    ),
+    (
+        "E0308",
+        "The value here has a different type than expected, like text where a \
+         number should be. Check what type the block needs.",
+    ),
+    (
+        "E0369",
+        "This operator, like `+` or `==`, doesn't work with these types of values.",
+    ),
+    (
+        "E0373",
+        "The closure borrows `{}`, which might be gone before the closure runs. \
+         Put `move` before the closure so it takes the value with it.",
+    ),
+    (
+        "E0382",
+        "`{}` was given away (moved) earlier, so it can't be used any more. Use \
+         `.clone()` to give away a copy instead, or borrow it with `&`.",
+    ),
+    (
+        "E0384",
+        "`{}` can't be changed after it's set. Declare it with `let mut` to \
+         allow changing it.",
+    ),
+    (
+        "E0412",
+        "There's no type called `{}` here. Check the spelling, or define it first.",
+    ),
+    (
+        "E0425",
+        "There's nothing called `{}` here. Check the spelling, and make sure it's \
+         created before it's used and in the same block.",
+    ),
+    (
+        "E0432",
+        "`{}` couldn't be found to import. Check the spelling, or add the crate it \
+         comes from as a dependency.",
+    ),
+    (
+        "E0433",
+        "`{}` couldn't be found. Check the spelling, or import it with `use`.",
+    ),
+    (
+        "E0499",
+        "`{}` is being changed from two places at once. Finish using one mutable \
+         borrow before starting the next.",
+    ),
+    (
+        "E0502",
+        "`{}` is being changed while it's also being read somewhere else. Finish \
+         reading it before changing it.",
+    ),
+    (
+        "E0505",
+        "`{}` is given away (moved) while something still borrows it. Finish using \
+         the borrow first.",
+    ),
+    (
+        "E0507",
+        "This takes a value out of something that's only borrowed. Use `.clone()` \
+         to take a copy, or borrow it with `&`.",
+    ),
+    (
+        "E0596",
+        "`{}` can't be changed, because it isn't declared as changeable. Declare it \
+         with `let mut`.",
+    ),
+    (
+        "E0597",
+        "`{}` stops existing at the end of its block while it's still borrowed. \
+         Create it in an outer block, or keep an owned copy.",
+    ),
+    (
+        "E0599",
+        "There's no method called `{}` for this type of value. Check the spelling, \
+         and what type the value is.",
+    ),
+    (
+        "E0609",
+        "There's no field called `{}` on this type of value. Check the spelling.",
+    ),
+    (
+        "unused_variables",
+        "`{}` is never used. Remove it, or start its name with `_` if that's on purpose.",
+    ),
+    (
+        "unused_mut",
+        "`{}` is declared as changeable with `mut`, but never changed. Remove the `mut`.",
+    ),
+    (
+        "unused_imports",
+        "`{}` is imported but never used, so the `use` can be removed.",
+    ),
+    (
+        "dead_code",
+        "`{}` is never used. Remove it, or use it somewhere.",
+    ),
+];
+
+/// Codes whose message names what `{}` stands for last, like the struct in
+/// "missing fields `x` and `y` in initializer of `Point`"
+const LAST_NAME_CODES: &[&str] = &["E0063"];
+
+/// `FRIENDLY_MESSAGES` in French
+const FRIENDLY_MESSAGES_FR: &[(&str, &str)] = &[
+    (
+        "E0061",
+        "Cette fonction reçoit un nombre de valeurs différent de celui qu'elle attend. \
+         Vérifie combien d'entrées elle demande.",
+    ),
+    (
+        "E0063",
+        "Il manque des champs pour créer `{}`. Chaque champ a besoin d'une valeur.",
+    ),
+    (
+        "E0106",
+        "Rust doit savoir combien de temps vit cette valeur empruntée. Essaie de renvoyer \
+         une valeur possédée, comme `String` au lieu de `&str`.",
+    ),
+    (
+        "E0277",
+        "Le type de cette valeur ne convient pas ici : il ne permet pas ce que le code \
+         lui demande, comme l'afficher, la comparer ou l'additionner.",
+    ),
+    (
+        "E0308",
+        "La valeur ici n'a pas le type attendu, par exemple du texte à la place d'un \
+         nombre. Vérifie le type dont le bloc a besoin.",
+    ),
+    (
+        "E0369",
+        "Cet opérateur, comme `+` ou `==`, ne fonctionne pas avec ces types de valeurs.",
+    ),
+    (
+        "E0373",
+        "La closure emprunte `{}`, qui pourrait disparaître avant qu'elle s'exécute. Mets \
+         `move` devant la closure pour qu'elle emporte la valeur avec elle.",
+    ),
+    (
+        "E0382",
+        "Tu ne peux plus utiliser `{}` : sa valeur a été donnée (déplacée) plus tôt. \
+         Utilise `.clone()` pour donner une copie, ou emprunte-la avec `&`.",
+    ),
+    (
+        "E0384",
+        "Tu ne peux pas modifier `{}` après sa création. Déclare la variable avec `let \
+         mut` pour pouvoir la modifier.",
+    ),
+    (
+        "E0412",
+        "Il n'existe pas de type appelé `{}` ici. Vérifie l'orthographe, ou définis-le d'abord.",
+    ),
+    (
+        "E0425",
+        "Rien ne s'appelle `{}` ici. Vérifie l'orthographe, et assure-toi de le créer \
+         avant de l'utiliser, dans le même bloc.",
+    ),
+    (
+        "E0432",
+        "Impossible de trouver `{}` pour l'importer. Vérifie l'orthographe, ou ajoute \
+         comme dépendance la crate d'où il vient.",
+    ),
+    (
+        "E0433",
+        "Impossible de trouver `{}`. Vérifie l'orthographe, ou importe-le avec `use`.",
+    ),
+    (
+        "E0499",
+        "Tu modifies `{}` depuis deux endroits à la fois. Termine un emprunt mutable \
+         avant de commencer le suivant.",
+    ),
+    (
+        "E0502",
+        "Tu modifies `{}` pendant qu'un autre endroit lit encore sa valeur. Termine la \
+         lecture avant de faire la modification.",
+    ),
+    (
+        "E0505",
+        "Tu donnes (déplaces) `{}` alors que quelque chose l'emprunte encore. Termine \
+         d'abord d'utiliser l'emprunt.",
+    ),
+    (
+        "E0507",
+        "Ce code prend une valeur dans quelque chose qui est seulement emprunté. Utilise \
+         `.clone()` pour prendre une copie, ou emprunte-la avec `&`.",
+    ),
+    (
+        "E0596",
+        "Tu ne peux pas modifier `{}`, car la variable n'est pas déclarée modifiable. \
+         Déclare-la avec `let mut`.",
+    ),
+    (
+        "E0597",
+        "`{}` disparaît à la fin de son bloc alors qu'on l'emprunte encore. Déplace sa \
+         création dans un bloc extérieur, ou garde une copie possédée.",
+    ),
+    (
+        "E0599",
+        "Il n'y a pas de méthode appelée `{}` pour ce type de valeur. Vérifie \
+         l'orthographe, et le type de la valeur.",
+    ),
+    (
+        "E0609",
+        "Il n'y a pas de champ appelé `{}` sur ce type de valeur. Vérifie l'orthographe.",
+    ),
+    (
+        "unused_variables",
+        "Tu n'utilises jamais `{}`. Supprime la variable, ou fais commencer son nom par \
+         `_` si c'est voulu.",
+    ),
+    (
+        "unused_mut",
+        "Tu as déclaré `{}` modifiable avec `mut`, mais sa valeur ne change jamais. \
+         Enlève le `mut`.",
+    ),
+    (
+        "unused_imports",
+        "Tu importes `{}` sans jamais l'utiliser, donc ce `use` peut être supprimé.",
+    ),
+    (
+        "dead_code",
+        "Tu n'utilises jamais `{}`. Supprime ce code, ou utilise-le quelque part.",
+    ),
+];
+
+/// `FRIENDLY_MESSAGES` in German
+const FRIENDLY_MESSAGES_DE: &[(&str, &str)] = &[
+    (
+        "E0061",
+        "Diese Funktion bekommt eine andere Anzahl von Werten, als sie erwartet. Prüfe, \
+         wie viele Eingaben sie braucht.",
+    ),
+    (
+        "E0063",
+        "Für `{}` fehlen einige Felder. Jedes Feld braucht beim Erstellen einen Wert.",
+    ),
+    (
+        "E0106",
+        "Rust muss wissen, wie lange dieser geliehene Wert lebt. Gib stattdessen einen \
+         eigenen Wert zurück, z. B. `String` statt `&str`.",
+    ),
+    (
+        "E0277",
+        "Dieser Wert kann hier nicht verwendet werden: Sein Typ unterstützt nicht, was \
+         der Code verlangt, z. B. Ausgeben, Vergleichen oder Addieren.",
+    ),
+    (
+        "E0308",
+        "Der Wert hier hat einen anderen Typ als erwartet, z. B. Text statt einer Zahl. \
+         Prüfe, welchen Typ der Block braucht.",
+    ),
+    (
+        "E0369",
+        "Dieser Operator, wie `+` oder `==`, funktioniert nicht mit diesen Arten von Werten.",
+    ),
+    (
+        "E0373",
+        "Die Closure leiht sich `{}` aus, aber der Wert könnte verschwunden sein, bevor \
+         sie läuft. Schreibe `move` vor die Closure, damit sie den Wert mitnimmt.",
+    ),
+    (
+        "E0382",
+        "Du kannst `{}` nicht mehr benutzen, weil der Wert vorher weggegeben (verschoben) \
+         wurde. Benutze `.clone()`, um eine Kopie weiterzugeben, oder leihe ihn mit `&` \
+         aus.",
+    ),
+    (
+        "E0384",
+        "Du kannst `{}` nach dem Setzen nicht mehr ändern. Deklariere die Variable mit \
+         `let mut`, damit sie sich ändern darf.",
+    ),
+    (
+        "E0412",
+        "Hier gibt es keinen Typ namens `{}`. Prüfe die Schreibweise oder definiere ihn zuerst.",
+    ),
+    (
+        "E0425",
+        "Hier gibt es nichts namens `{}`. Prüfe die Schreibweise und ob es vor der \
+         Benutzung im selben Block erstellt wird.",
+    ),
+    (
+        "E0432",
+        "Du kannst `{}` nicht importieren, weil es nicht gefunden wurde. Prüfe die \
+         Schreibweise oder füge die Crate, aus der es kommt, als Abhängigkeit hinzu.",
+    ),
+    (
+        "E0433",
+        "Rust findet `{}` nicht. Prüfe die Schreibweise oder importiere es mit `use`.",
+    ),
+    (
+        "E0499",
+        "Du änderst `{}` von zwei Stellen gleichzeitig. Beende eine veränderliche \
+         Ausleihe, bevor du die nächste beginnst.",
+    ),
+    (
+        "E0502",
+        "Du änderst `{}`, während eine andere Stelle den Wert noch liest. Lies ihn zu \
+         Ende, bevor du ihn änderst.",
+    ),
+    (
+        "E0505",
+        "Du gibst `{}` weiter, während noch etwas den Wert ausleiht. Beende zuerst die Ausleihe.",
+    ),
+    (
+        "E0507",
+        "Hier wird ein Wert aus etwas genommen, das nur geliehen ist. Benutze `.clone()`, \
+         um eine Kopie zu nehmen, oder leihe ihn mit `&` aus.",
+    ),
+    (
+        "E0596",
+        "Du kannst `{}` nicht ändern, weil die Variable nicht als veränderlich deklariert \
+         ist. Deklariere sie mit `let mut`.",
+    ),
+    (
+        "E0597",
+        "Du leihst `{}` noch aus, obwohl der Wert am Ende seines Blocks verschwindet. \
+         Erstelle ihn in einem äußeren Block oder behalte eine eigene Kopie.",
+    ),
+    (
+        "E0599",
+        "Für diese Art von Wert gibt es keine Methode namens `{}`. Prüfe die Schreibweise \
+         und welchen Typ der Wert hat.",
+    ),
+    (
+        "E0609",
+        "Diese Art von Wert hat kein Feld namens `{}`. Prüfe die Schreibweise.",
+    ),
+    (
+        "unused_variables",
+        "Du benutzt `{}` nie. Entferne die Variable oder lass ihren Namen mit `_` \
+         beginnen, wenn das Absicht ist.",
+    ),
+    (
+        "unused_mut",
+        "Du hast `{}` mit `mut` als veränderlich deklariert, aber nie geändert. Entferne \
+         das `mut`.",
+    ),
+    (
+        "unused_imports",
+        "Du importierst `{}`, benutzt es aber nie, also kann das `use` weg.",
+    ),
+    (
+        "dead_code",
+        "Du benutzt `{}` nie. Entferne es oder benutze es irgendwo.",
+    ),
+];
+
+/// `FRIENDLY_MESSAGES` in Spanish
+const FRIENDLY_MESSAGES_ES: &[(&str, &str)] = &[
+    (
+        "E0061",
+        "Esta función recibe una cantidad de valores distinta de la que espera. Revisa \
+         cuántas entradas necesita.",
+    ),
+    (
+        "E0063",
+        "Faltan campos para crear `{}`. Cada campo necesita un valor.",
+    ),
+    (
+        "E0106",
+        "Rust necesita saber cuánto tiempo vive este valor prestado. Prueba a devolver un \
+         valor propio, como `String` en lugar de `&str`.",
+    ),
+    (
+        "E0277",
+        "El tipo de este valor no sirve aquí: no admite lo que el código le pide, como \
+         imprimirlo, compararlo o sumarlo.",
+    ),
+    (
+        "E0308",
+        "El valor aquí tiene un tipo distinto del esperado, como texto donde debería \
+         haber un número. Revisa qué tipo necesita el bloque.",
+    ),
+    (
+        "E0369",
+        "Este operador, como `+` o `==`, no funciona con estos tipos de valores.",
+    ),
+    (
+        "E0373",
+        "La closure toma prestado `{}`, que podría desaparecer antes de que la closure se \
+         ejecute. Pon `move` delante de la closure para que se lleve el valor consigo.",
+    ),
+    (
+        "E0382",
+        "Ya no puedes usar `{}` porque se entregó (movió) antes. Usa `.clone()` para \
+         entregar una copia, o préstalo con `&`.",
+    ),
+    (
+        "E0384",
+        "No puedes cambiar `{}` después de darle un valor. Declara la variable con `let \
+         mut` para poder cambiarla.",
+    ),
+    (
+        "E0412",
+        "Aquí no hay ningún tipo llamado `{}`. Revisa cómo está escrito, o defínelo primero.",
+    ),
+    (
+        "E0425",
+        "Aquí no hay nada llamado `{}`. Revisa cómo está escrito, y asegúrate de crearlo \
+         antes de usarlo y en el mismo bloque.",
+    ),
+    (
+        "E0432",
+        "No se encontró `{}` para importarlo. Revisa cómo está escrito, o añade como \
+         dependencia la crate de la que viene.",
+    ),
+    (
+        "E0433",
+        "No se encontró `{}`. Revisa cómo está escrito, o impórtalo con `use`.",
+    ),
+    (
+        "E0499",
+        "Estás cambiando `{}` desde dos sitios a la vez. Termina de usar un préstamo \
+         mutable antes de empezar el siguiente.",
+    ),
+    (
+        "E0502",
+        "Estás cambiando `{}` mientras otra parte del código todavía lo lee. Termina de \
+         leerlo antes de cambiarlo.",
+    ),
+    (
+        "E0505",
+        "Estás entregando (moviendo) `{}` mientras algo todavía lo tiene prestado. \
+         Termina primero de usar el préstamo.",
+    ),
+    (
+        "E0507",
+        "Esto saca un valor de algo que solo está prestado. Usa `.clone()` para sacar una \
+         copia, o préstalo con `&`.",
+    ),
+    (
+        "E0596",
+        "No puedes cambiar `{}` porque no está declarado como modificable. Declara la \
+         variable con `let mut`.",
+    ),
+    (
+        "E0597",
+        "`{}` deja de existir al final de su bloque mientras sigue prestado. Créalo en un \
+         bloque exterior, o guarda una copia propia.",
+    ),
+    (
+        "E0599",
+        "No hay ningún método llamado `{}` para este tipo de valor. Revisa cómo está \
+         escrito y de qué tipo es el valor.",
+    ),
+    (
+        "E0609",
+        "No hay ningún campo llamado `{}` en este tipo de valor. Revisa cómo está escrito.",
+    ),
+    (
+        "unused_variables",
+        "Nunca usas `{}`. Borra la variable, o haz que su nombre empiece por `_` si es a \
+         propósito.",
+    ),
+    (
+        "unused_mut",
+        "Declaraste `{}` como modificable con `mut`, pero nunca cambia. Quita el `mut`.",
+    ),
+    (
+        "unused_imports",
+        "Importas `{}` pero nunca lo usas, así que puedes quitar el `use`.",
+    ),
+    (
+        "dead_code",
+        "Nunca usas `{}`. Bórralo, o úsalo en algún sitio.",
+    ),
+];
+
+/// The explanations bundled for `locale`
+fn friendly_messages(locale: Locale) -> &'static [(&'static str, &'static str)] {
+    match locale {
+        Locale::En => FRIENDLY_MESSAGES,
+        Locale::Fr => FRIENDLY_MESSAGES_FR,
+        Locale::De => FRIENDLY_MESSAGES_DE,
+        Locale::Es => FRIENDLY_MESSAGES_ES,
+    }
+}
+
+/// Attach a plain-language `friendly_message` to each error and warning in
+/// `result` whose code has one
+///
+/// An optional pass for beginners: the compiler's own message and notes are
+/// kept, so editors can show both.
+pub fn simplify_diagnostics(result: &mut CompilationResult) {
+    simplify_diagnostics_in(result, Locale::En);
+}
+
+/// `simplify_diagnostics`, with the friendly messages in `locale`
+pub fn simplify_diagnostics_in(result: &mut CompilationResult, locale: Locale) {
+    for error in result.errors.iter_mut().chain(&mut result.warnings) {
+        if let Some(message) = friendly_message_in(error, locale) {
+            error.friendly_message = Some(message);
+        }
+    }
+}
+
+/// The plain-language explanation of `error`, if its code has one
+pub fn friendly_message(error: &CompilationError) -> Option<String> {
+    friendly_message_in(error, Locale::En)
+}
+
+/// `friendly_message` in `locale`
+pub fn friendly_message_in(error: &CompilationError, locale: Locale) -> Option<String> {
+    let code = error.code.as_deref()?;
+    let messages = friendly_messages(locale);
+    let (_, template) = messages.iter().find(|(known, _)| *known == code)?;
+    let mut names = quoted_names(&error.message);
+    let name = if LAST_NAME_CODES.contains(&code) { names.last() } else { names.next() };
+    if let Some(name) = name {
+        return Some(template.replace("{}", name));
+    }
+
+    // Without a name, call it "this value" or the locale's equivalent
+    let message = template.replace("`{}`", locale.unnamed_value());
+    let mut chars = message.chars();
+    chars.next().map(|first| first.to_uppercase().chain(chars).collect())
+}
+
+/// The names in backticks in the first line of `message`, e.g. `x` in
+/// "borrow of moved value: `x`"; later lines are rustc's rendering of the code
+fn quoted_names(message: &str) -> impl Iterator<Item = &str> {
+    let headline = message.lines().next().unwrap_or_default();
+    headline.split('`').skip(1).step_by(2).filter(|name| !name.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rust_compiler::{failed_result, ErrorLevel};
+
+    #[test]
+    fn test_simplify_diagnostics() {
+        let diagnostic = |level, message: &str, code: &str| CompilationError {
+            code: Some(code.to_string()),
+            ..CompilationError::new(level, message)
+        };
+        let mut result = failed_result(diagnostic(
+            ErrorLevel::Error,
+            "borrow of moved value: `names`",
+            "E0382",
+        ));
+        result.errors.push(diagnostic(ErrorLevel::Error, "mismatched types", "E0308"));
+        result.errors.push(diagnostic(ErrorLevel::Error, "unknown thing", "E9999"));
+        result.warnings.extend([
+            diagnostic(ErrorLevel::Warning, "unused variable: `x`", "unused_variables"),
+            diagnostic(ErrorLevel::Warning, "variable does not need to be mutable", "unused_mut"),
+        ]);
+        simplify_diagnostics(&mut result);
+
+        let moved = result.errors[0].friendly_message.as_deref().unwrap();
+        assert!(moved.starts_with("`names` was given away"), "{}", moved);
+        assert!(result.errors[1].friendly_message.as_deref().unwrap().contains("type"));
+        assert_eq!(result.errors[2].friendly_message, None);
+        assert_eq!(result.errors[0].message, "borrow of moved value: `names`");
+        let unused = result.warnings[0].friendly_message.as_deref().unwrap();
+        assert!(unused.starts_with("`x` is never"), "{}", unused);
+        let unused_mut = result.warnings[1].friendly_message.as_deref().unwrap();
+        assert!(unused_mut.starts_with("This value is declared"), "{}", unused_mut);
+
+        simplify_diagnostics_in(&mut result, Locale::Fr);
+        let moved = result.errors[0].friendly_message.as_deref().unwrap();
+        assert!(moved.starts_with("Tu ne peux plus utiliser `names`"), "{}", moved);
+
+        for locale in Locale::ALL {
+            let codes: Vec<_> = friendly_messages(locale).iter().map(|(code, _)| code).collect();
+            let english: Vec<_> = FRIENDLY_MESSAGES.iter().map(|(code, _)| code).collect();
+            assert_eq!(codes, english, "{:?} should translate every message", locale);
+        }
+    }
+
+    #[test]
+    fn test_missing_fields_names_struct() {
+        if !crate::rust_compiler::is_rust_available() {
+            println!("Skipping test: rustc not available");
+            return;
+        }
+
+        let code = "pub struct Point { pub x: i32, pub y: i32, pub z: i32 }\n\
+                    pub fn origin() -> Point { Point { x: 0 } }";
+        let compiler = crate::rust_compiler::RustCompiler::new().unwrap();
+        let mut result = compiler.quick_check(code).unwrap();
+        simplify_diagnostics(&mut result);
+
+        let error = &result.errors[0];
+        assert_eq!(error.code.as_deref(), Some("E0063"), "{}", error.message);
+        assert_eq!(
+            error.friendly_message.as_deref(),
+            Some("Some fields of `Point` are missing. Every field needs a value when you create it.")
+        );
+    }
+}
@@ -1,17 +1,63 @@
 // Blockly Rust Compiler Library
 // Provides Rust compilation checking for the Blockly visual editor
 
+pub mod analyzer;
+pub mod backend;
 pub mod blockly_bridge;
+pub mod container;
+pub mod dead_code;
+pub mod deny_list;
+pub mod error;
+pub mod friendly;
+pub mod locale;
+pub mod metrics;
+pub mod panic_sites;
+pub mod playground;
+pub mod project_pool;
+pub mod resource_limits;
+pub mod result_cache;
 pub mod rust_compiler;
+pub mod sandbox;
 
 #[cfg(feature = "web-service")]
 pub mod compiler_service;
 
+#[cfg(feature = "testing")]
+pub mod testing;
+
 // Re-export main types
+pub use analyzer::{
+    AnalyzerBackend, Completion, CompletionKind, Hover, InferredType, InferredTypeKind,
+    is_rust_analyzer_available,
+};
+pub use backend::{BackendKind, CompilerBackend, RustcBackend};
+pub use container::{ContainerBackend, ContainerConfig};
+pub use dead_code::{DeadCode, DeadCodeKind, dead_code};
+pub use deny_list::{DeniedApi, DenyList};
+pub use error::CompilerError;
+pub use friendly::{
+    friendly_message, friendly_message_in, simplify_diagnostics, simplify_diagnostics_in,
+};
+pub use locale::Locale;
+pub use metrics::{CodeMetrics, FunctionMetrics};
+pub use panic_sites::{PanicKind, PanicSite, panic_sites};
+pub use playground::PlaygroundBackend;
+pub use project_pool::{PoolConfig, PoolMetrics};
+pub use resource_limits::ResourceLimits;
+pub use sandbox::Sandbox;
 pub use rust_compiler::{
-    CompilationError, CompilationResult, ErrorLevel, RustCompiler,
-    is_cargo_available, is_rust_available,
+    Advisory, ApiItem, ApiItemKind, AppliedFix, BuildProfile, CancellationToken, CheckOptions,
+    CheckPhase, CommandLine, CompilationError, CompilationResult, CrateType, Dependency,
+    DependencyNode, DiagnosticSpan, Edition, ErrorCategory, ErrorLevel, IrKind, LintGroup,
+    LintLevel, LintPreset, Linter, MissingCrate, ProgressObserver, RunMode, RunOptions, RunResult,
+    RustCompiler, RustCompilerBuilder, Sanitizer, SourceRange, Symbol, SymbolKind, TestCase,
+    TestOutcome, TestRunResult, WasmBuild, WorkspaceCrate, WrapMode,
+    contains_symbols, is_cargo_available, is_clippy_available, is_nightly_available,
+    is_rust_available, is_target_installed, is_toolchain_available, is_wasmtime_available,
 };
 
 #[cfg(feature = "web-service")]
-pub use compiler_service::{CheckRequest, CheckResponse, create_router, start_service};
+pub use compiler_service::{
+    CheckRequest, CheckResponse, ServiceConfig, create_router, create_router_with_backend,
+    create_router_with_config, start_service, start_service_with_config,
+};
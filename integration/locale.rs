@@ -0,0 +1,120 @@
+// Locale - Languages diagnostics and CLI messages can be shown in
+// Lets classrooms get friendly messages in the language their students read
+
+use serde::{Deserialize, Serialize};
+
+/// A language with bundled translations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    En,
+    Fr,
+    De,
+    Es,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 4] = [Locale::En, Locale::Fr, Locale::De, Locale::Es];
+
+    /// The locale named by `LC_ALL`, `LC_MESSAGES` or `LANG`, like `fr_FR.UTF-8`,
+    /// or English if none is set or supported
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// What to call a value that the compiler's message doesn't name, as the
+    /// object of a sentence
+    pub(crate) fn unnamed_value(self) -> &'static str {
+        match self {
+            Locale::En => "this value",
+            Locale::Fr => "cette valeur",
+            Locale::De => "diesen Wert",
+            Locale::Es => "este valor",
+        }
+    }
+
+    /// First line printed when the service starts
+    pub fn service_starting(self, address: impl std::fmt::Display) -> String {
+        match self {
+            Locale::En => format!("🦀 Rust Compiler Service starting on http://{}", address),
+            Locale::Fr => format!("🦀 Service de compilation Rust démarré sur http://{}", address),
+            Locale::De => format!("🦀 Rust-Compiler-Dienst startet auf http://{}", address),
+            Locale::Es => {
+                format!("🦀 Servicio de compilación de Rust iniciado en http://{}", address)
+            }
+        }
+    }
+
+    /// One-line summary of a check, e.g. "2 errors, 1 warning"
+    pub fn check_summary(self, errors: usize, warnings: usize) -> String {
+        if errors == 0 && warnings == 0 {
+            return match self {
+                Locale::En => "No problems found",
+                Locale::Fr => "Aucun problème trouvé",
+                Locale::De => "Keine Probleme gefunden",
+                Locale::Es => "No se encontraron problemas",
+            }
+            .to_string();
+        }
+
+        let count = |n: usize, one: &str, many: &str| {
+            format!("{} {}", n, if n == 1 { one } else { many })
+        };
+        let (errors, warnings) = match self {
+            Locale::En => {
+                (count(errors, "error", "errors"), count(warnings, "warning", "warnings"))
+            }
+            Locale::Fr => (
+                count(errors, "erreur", "erreurs"),
+                count(warnings, "avertissement", "avertissements"),
+            ),
+            Locale::De => {
+                (count(errors, "Fehler", "Fehler"), count(warnings, "Warnung", "Warnungen"))
+            }
+            Locale::Es => (
+                count(errors, "error", "errores"),
+                count(warnings, "advertencia", "advertencias"),
+            ),
+        };
+        format!("{}, {}", errors, warnings)
+    }
+}
+
+impl std::str::FromStr for Locale {
+    type Err = String;
+
+    /// Parses a language tag like `fr`, `fr-CA` or `de_DE.UTF-8` by its language
+    fn from_str(tag: &str) -> Result<Self, Self::Err> {
+        let language = tag.split(['-', '_', '.']).next().unwrap_or_default();
+        match language.to_ascii_lowercase().as_str() {
+            "en" | "c" | "posix" => Ok(Locale::En),
+            "fr" => Ok(Locale::Fr),
+            "de" => Ok(Locale::De),
+            "es" => Ok(Locale::Es),
+            _ => Err(format!("Unsupported locale `{}`; expected en, fr, de or es", tag)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locale_parsing() {
+        assert_eq!("fr".parse(), Ok(Locale::Fr));
+        assert_eq!("de_DE.UTF-8".parse(), Ok(Locale::De));
+        assert_eq!("es-MX".parse(), Ok(Locale::Es));
+        assert_eq!("C".parse(), Ok(Locale::En));
+        assert!("ja_JP".parse::<Locale>().is_err());
+
+        assert_eq!(Locale::Fr.check_summary(1, 2), "1 erreur, 2 avertissements");
+        assert_eq!(Locale::En.check_summary(0, 1), "0 errors, 1 warning");
+    }
+}
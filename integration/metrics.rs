@@ -0,0 +1,290 @@
+// Code Metrics - Size and complexity measures of a snippet, from its syntax tree
+// Lets teachers cap how complex the functions built for an assignment may get
+
+use proc_macro2::Span;
+use serde::{Deserialize, Serialize};
+use syn::spanned::Spanned;
+use syn::visit::Visit;
+
+use crate::rust_compiler::{ParsedSnippet, SourceRange};
+
+/// Size and complexity of a snippet, as measured by `RustCompiler::metrics`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CodeMetrics {
+    /// Lines with code on them, not counting blank lines and comments
+    pub lines_of_code: usize,
+    /// Functions and methods with a body
+    pub function_count: usize,
+    /// Highest cyclomatic complexity of any function
+    pub max_complexity: usize,
+    /// Deepest nesting of control flow in any function
+    pub max_nesting_depth: usize,
+    /// Each function, in source order
+    pub functions: Vec<FunctionMetrics>,
+}
+
+/// Complexity of one function or method
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionMetrics {
+    /// The function's name, with its type for methods, e.g. `Cell::divide`;
+    /// the statements of a snippet without `fn main` are measured as `main`
+    pub name: String,
+    /// The whole function, with 1-based lines and columns and an exclusive end
+    pub span: SourceRange,
+    /// 1 plus the number of decisions: `if`, `while`, `for`, match arms after
+    /// the first, match guards, `&&` and `||`
+    pub complexity: usize,
+    /// How deeply `if`, loops, `match` and closures nest; 0 for straight-line code
+    pub nesting_depth: usize,
+}
+
+/// Measure `code`, parsed like `RustCompiler::list_symbols` parses it; code
+/// that doesn't parse only has its lines counted
+pub(crate) fn measure(code: &str) -> CodeMetrics {
+    let mut collector = Collector { line_offset: 0, open: Vec::new(), functions: Vec::new() };
+    let parsed = ParsedSnippet::parse(code);
+    collector.line_offset = parsed.as_ref().map_or(0, ParsedSnippet::line_offset);
+    match &parsed {
+        Some(ParsedSnippet::File(file)) => collector.visit_file(file),
+        Some(ParsedSnippet::Block(block)) => {
+            let statements = block.stmts.first().zip(block.stmts.last());
+            let span = statements.map_or((block.span(), block.span()), |(first, last)| {
+                (first.span(), last.span())
+            });
+            collector.measure("main".to_string(), span, |c| c.visit_block(block));
+        }
+        None => {}
+    }
+
+    let mut functions = collector.functions;
+    functions.sort_by_key(|function| (function.span.line_start, function.span.column_start));
+    CodeMetrics {
+        lines_of_code: lines_of_code(code),
+        function_count: functions.len(),
+        max_complexity: functions.iter().map(|f| f.complexity).max().unwrap_or(0),
+        max_nesting_depth: functions.iter().map(|f| f.nesting_depth).max().unwrap_or(0),
+        functions,
+    }
+}
+
+/// Lines that aren't blank and aren't only a comment
+fn lines_of_code(code: &str) -> usize {
+    let mut in_block_comment = false;
+    code.lines()
+        .filter(|line| {
+            let mut line = line.trim();
+            if in_block_comment {
+                match line.find("*/") {
+                    Some(end) => {
+                        in_block_comment = false;
+                        line = line[end + 2..].trim();
+                    }
+                    None => return false,
+                }
+            }
+            if let Some(rest) = line.strip_prefix("/*") {
+                match rest.find("*/") {
+                    Some(end) => line = rest[end + 2..].trim(),
+                    None => {
+                        in_block_comment = true;
+                        return false;
+                    }
+                }
+            }
+            !line.is_empty() && !line.starts_with("//")
+        })
+        .count()
+}
+
+/// A function being measured
+struct Open {
+    complexity: usize,
+    depth: usize,
+    max_depth: usize,
+}
+
+/// Measures each function, keeping the ones whose bodies are being visited
+/// on a stack so nested functions are measured separately
+struct Collector {
+    line_offset: usize,
+    open: Vec<Open>,
+    functions: Vec<FunctionMetrics>,
+}
+
+impl Collector {
+    /// Measure the function from `span.0` to `span.1` as `body` visits it
+    fn measure(&mut self, name: String, span: (Span, Span), body: impl FnOnce(&mut Self)) {
+        self.open.push(Open { complexity: 1, depth: 0, max_depth: 0 });
+        body(self);
+        let open = self.open.pop().expect("pushed above");
+
+        let (start, end) = (span.0.start(), span.1.end());
+        self.functions.push(FunctionMetrics {
+            name,
+            span: SourceRange {
+                line_start: start.line - self.line_offset,
+                column_start: start.column + 1,
+                line_end: end.line - self.line_offset,
+                column_end: end.column + 1,
+            },
+            complexity: open.complexity,
+            nesting_depth: open.max_depth,
+        });
+    }
+
+    fn add_decisions(&mut self, count: usize) {
+        if let Some(open) = self.open.last_mut() {
+            open.complexity += count;
+        }
+    }
+
+    /// Visit code one level deeper
+    fn nested(&mut self, visit: impl FnOnce(&mut Self)) {
+        if let Some(open) = self.open.last_mut() {
+            open.depth += 1;
+            open.max_depth = open.max_depth.max(open.depth);
+        }
+        visit(self);
+        if let Some(open) = self.open.last_mut() {
+            open.depth -= 1;
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for Collector {
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        let name = item.sig.ident.to_string();
+        self.measure(name, (item.span(), item.span()), |c| {
+            syn::visit::visit_item_fn(c, item)
+        });
+    }
+
+    fn visit_item_impl(&mut self, item: &'ast syn::ItemImpl) {
+        let owner = match item.self_ty.as_ref() {
+            syn::Type::Path(path) => path.path.segments.last().map(|s| s.ident.to_string()),
+            _ => None,
+        };
+        for impl_item in &item.items {
+            match impl_item {
+                syn::ImplItem::Fn(method) => {
+                    let name = match &owner {
+                        Some(owner) => format!("{}::{}", owner, method.sig.ident),
+                        None => method.sig.ident.to_string(),
+                    };
+                    let span = (method.span(), method.span());
+                    self.measure(name, span, |c| c.visit_block(&method.block));
+                }
+                other => self.visit_impl_item(other),
+            }
+        }
+    }
+
+    fn visit_trait_item_fn(&mut self, method: &'ast syn::TraitItemFn) {
+        if let Some(body) = &method.default {
+            let name = method.sig.ident.to_string();
+            self.measure(name, (method.span(), method.span()), |c| c.visit_block(body));
+        }
+    }
+
+    fn visit_expr_if(&mut self, expr: &'ast syn::ExprIf) {
+        self.add_decisions(1);
+        self.visit_expr(&expr.cond);
+        self.nested(|c| c.visit_block(&expr.then_branch));
+        match expr.else_branch.as_ref().map(|(_, branch)| branch.as_ref()) {
+            // `else if` continues the chain rather than nesting in it
+            Some(chained @ syn::Expr::If(_)) => self.visit_expr(chained),
+            Some(branch) => self.nested(|c| c.visit_expr(branch)),
+            None => {}
+        }
+    }
+
+    fn visit_expr_while(&mut self, expr: &'ast syn::ExprWhile) {
+        self.add_decisions(1);
+        self.visit_expr(&expr.cond);
+        self.nested(|c| c.visit_block(&expr.body));
+    }
+
+    fn visit_expr_for_loop(&mut self, expr: &'ast syn::ExprForLoop) {
+        self.add_decisions(1);
+        self.visit_expr(&expr.expr);
+        self.nested(|c| c.visit_block(&expr.body));
+    }
+
+    fn visit_expr_loop(&mut self, expr: &'ast syn::ExprLoop) {
+        self.nested(|c| c.visit_block(&expr.body));
+    }
+
+    fn visit_expr_match(&mut self, expr: &'ast syn::ExprMatch) {
+        let guards = expr.arms.iter().filter(|arm| arm.guard.is_some()).count();
+        self.add_decisions(expr.arms.len().saturating_sub(1) + guards);
+        self.visit_expr(&expr.expr);
+        self.nested(|c| {
+            for arm in &expr.arms {
+                c.visit_arm(arm);
+            }
+        });
+    }
+
+    fn visit_expr_closure(&mut self, expr: &'ast syn::ExprClosure) {
+        self.nested(|c| syn::visit::visit_expr_closure(c, expr));
+    }
+
+    fn visit_expr_binary(&mut self, expr: &'ast syn::ExprBinary) {
+        if matches!(expr.op, syn::BinOp::And(_) | syn::BinOp::Or(_)) {
+            self.add_decisions(1);
+        }
+        syn::visit::visit_expr_binary(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_metrics() {
+        let code = "// Sorts cells by energy\n\
+                    struct Cell { energy: u32 }\n\
+                    \n\
+                    impl Cell {\n\
+                    \x20   fn grade(&self) -> &str {\n\
+                    \x20       if self.energy > 10 && self.energy < 100 {\n\
+                    \x20           \"ok\"\n\
+                    \x20       } else if self.energy == 0 {\n\
+                    \x20           \"empty\"\n\
+                    \x20       } else {\n\
+                    \x20           \"odd\"\n\
+                    \x20       }\n\
+                    \x20   }\n\
+                    }\n\
+                    \n\
+                    fn total(cells: &[Cell]) -> u32 {\n\
+                    \x20   /* Skips the empty ones */\n\
+                    \x20   let mut sum = 0;\n\
+                    \x20   for cell in cells {\n\
+                    \x20       match cell.energy {\n\
+                    \x20           0 => {}\n\
+                    \x20           e if e > 50 => sum += 50,\n\
+                    \x20           e => sum += e,\n\
+                    \x20       }\n\
+                    \x20   }\n\
+                    \x20   sum\n\
+                    }\n";
+        let metrics = measure(code);
+
+        assert_eq!(metrics.lines_of_code, 23);
+        assert_eq!(metrics.function_count, 2);
+        let found: Vec<_> = metrics
+            .functions
+            .iter()
+            .map(|f| (f.name.as_str(), f.span.line_start, f.complexity, f.nesting_depth))
+            .collect();
+        // grade: if, &&, else if; total: for, two more match arms and a guard
+        assert_eq!(found, [("Cell::grade", 5, 4, 1), ("total", 16, 5, 2)]);
+        assert_eq!((metrics.max_complexity, metrics.max_nesting_depth), (5, 2));
+
+        let snippet = measure("let x = 3;\nif x > 2 {\n    println!(\"big\");\n}");
+        assert_eq!(snippet.functions[0].name, "main");
+        assert_eq!((snippet.functions[0].complexity, snippet.functions[0].span.line_start), (2, 1));
+    }
+}
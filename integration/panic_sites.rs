@@ -0,0 +1,210 @@
+// Panic Sites - Finds the places in a snippet that could crash at runtime
+// Lets the editor mark blocks that unwrap, index or divide, with an explanation for beginners
+
+use proc_macro2::Span;
+use serde::{Deserialize, Serialize};
+use syn::spanned::Spanned;
+use syn::visit::Visit;
+
+use crate::rust_compiler::{visit_macro_arguments, ParsedSnippet, SourceRange};
+
+/// Macros that panic when they're reached, or when their condition fails
+const PANIC_MACROS: &[&str] = &[
+    "panic",
+    "todo",
+    "unimplemented",
+    "unreachable",
+    "assert",
+    "assert_eq",
+    "assert_ne",
+];
+
+/// Why code at a `PanicSite` could panic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PanicKind {
+    /// `unwrap` or `unwrap_err` on an `Option` or `Result`
+    Unwrap,
+    /// `expect` or `expect_err`
+    Expect,
+    /// Indexing like `list[i]` or `map["key"]`
+    Index,
+    /// Integer division or remainder by something that could be zero
+    Division,
+    /// A macro like `panic!`, `todo!` or `assert!`
+    PanicMacro,
+}
+
+impl PanicKind {
+    fn explanation(&self) -> &'static str {
+        match self {
+            PanicKind::Unwrap => {
+                "`unwrap` stops the program if there's no value (`None`) or an error \
+                 (`Err`). Handle both cases with `match` or `if let`, or pick a fallback \
+                 with `unwrap_or`."
+            }
+            PanicKind::Expect => {
+                "`expect` stops the program with its message if there's no value (`None`) \
+                 or an error (`Err`). Handle both cases with `match` or `if let` to keep going."
+            }
+            PanicKind::Index => {
+                "Indexing stops the program if the position is past the end of the list, or \
+                 the key isn't in the map. `.get(..)` gives `None` instead."
+            }
+            PanicKind::Division => {
+                "Dividing whole numbers stops the program if the number you divide by is \
+                 zero. Check it first, or use `checked_div` or `checked_rem`."
+            }
+            PanicKind::PanicMacro => {
+                "This stops the program on purpose when it's reached, or when its condition \
+                 isn't true."
+            }
+        }
+    }
+}
+
+/// A place in a snippet that could panic, as found by `panic_sites`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PanicSite {
+    pub kind: PanicKind,
+    /// The call, indexing, division or macro, with 1-based lines and columns
+    /// and an exclusive end
+    pub span: SourceRange,
+    /// What could go wrong and how to avoid it, for beginners
+    pub explanation: String,
+}
+
+/// Every place in `code` that could panic, in source order
+///
+/// This is a pure parse, like `RustCompiler::list_symbols`: code that doesn't
+/// parse has no sites. Without types, every `/` and `%` counts unless the
+/// divisor is a non-zero integer or a float appears as either operand, and
+/// methods named `unwrap` or `expect` count whatever their receiver.
+pub fn panic_sites(code: &str) -> Vec<PanicSite> {
+    let mut finder = Finder { found: Vec::new() };
+    let Some(parsed) = ParsedSnippet::parse(code) else {
+        return Vec::new();
+    };
+    parsed.visit(&mut finder);
+    let line_offset = parsed.line_offset();
+
+    let mut sites: Vec<PanicSite> = finder
+        .found
+        .into_iter()
+        .map(|(kind, start, end)| {
+            let (start, end) = (start.start(), end.end());
+            PanicSite {
+                kind,
+                span: SourceRange {
+                    line_start: start.line - line_offset,
+                    column_start: start.column + 1,
+                    line_end: end.line - line_offset,
+                    column_end: end.column + 1,
+                },
+                explanation: kind.explanation().to_string(),
+            }
+        })
+        .collect();
+    sites.sort_by_key(|site| (site.span.line_start, site.span.column_start));
+    sites
+}
+
+/// Collects sites with the spans they start and end at
+struct Finder {
+    found: Vec<(PanicKind, Span, Span)>,
+}
+
+impl<'ast> Visit<'ast> for Finder {
+    fn visit_expr_method_call(&mut self, call: &'ast syn::ExprMethodCall) {
+        let kind = match call.method.to_string().as_str() {
+            "unwrap" | "unwrap_err" => Some(PanicKind::Unwrap),
+            "expect" | "expect_err" => Some(PanicKind::Expect),
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            // From the method name, so chained calls each get their own site
+            self.found.push((kind, call.method.span(), call.span()));
+        }
+        syn::visit::visit_expr_method_call(self, call);
+    }
+
+    fn visit_expr_index(&mut self, index: &'ast syn::ExprIndex) {
+        self.found.push((PanicKind::Index, index.span(), index.span()));
+        syn::visit::visit_expr_index(self, index);
+    }
+
+    fn visit_expr_binary(&mut self, binary: &'ast syn::ExprBinary) {
+        let divides = matches!(
+            binary.op,
+            syn::BinOp::Div(_) | syn::BinOp::Rem(_) | syn::BinOp::DivAssign(_)
+                | syn::BinOp::RemAssign(_)
+        );
+        if divides && may_divide_by_zero(&binary.left, &binary.right) {
+            self.found.push((PanicKind::Division, binary.span(), binary.span()));
+        }
+        syn::visit::visit_expr_binary(self, binary);
+    }
+
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        let name = mac.path.segments.last().map(|segment| segment.ident.to_string());
+        if name.is_some_and(|name| PANIC_MACROS.contains(&name.as_str())) {
+            self.found.push((PanicKind::PanicMacro, mac.span(), mac.span()));
+        }
+        syn::visit::visit_macro(self, mac);
+        visit_macro_arguments(self, mac);
+    }
+}
+
+/// Whether dividing `left` by `right` could be an integer division by zero
+fn may_divide_by_zero(left: &syn::Expr, right: &syn::Expr) -> bool {
+    let literal = |expr: &syn::Expr| match expr {
+        syn::Expr::Lit(syn::ExprLit { lit, .. }) => Some(lit.clone()),
+        _ => None,
+    };
+    let is_float = |lit: &Option<syn::Lit>| matches!(lit, Some(syn::Lit::Float(_)));
+    let (left, right) = (literal(left), literal(right));
+    if is_float(&left) || is_float(&right) {
+        return false;
+    }
+    match right {
+        Some(syn::Lit::Int(int)) => int.base10_parse::<u128>().map_or(true, |value| value == 0),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_panic_sites() {
+        let code = "let numbers = vec![4, 2];\n\
+                    let first = numbers[0];\n\
+                    let parsed: i32 = \"7\".parse().unwrap();\n\
+                    let half = parsed / 2;\n\
+                    let ratio = first / parsed;\n\
+                    let scaled = 1.5 / 3.0;\n\
+                    println!(\"{}\", numbers.get(5).expect(\"no sixth number\"));\n\
+                    assert!(half > 0);";
+        let sites = panic_sites(code);
+
+        let found: Vec<_> = sites
+            .iter()
+            .map(|site| (site.kind, site.span.line_start, site.span.column_start))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (PanicKind::Index, 2, 13),
+                (PanicKind::Unwrap, 3, 31),
+                (PanicKind::Division, 5, 13),
+                (PanicKind::Expect, 7, 31),
+                (PanicKind::PanicMacro, 8, 1),
+            ]
+        );
+        assert_eq!(sites[1].span.column_end, 39, "`unwrap()` should be highlighted");
+        assert!(sites[2].explanation.contains("zero"));
+
+        assert!(panic_sites("fn main() {\n    let x = ;\n").is_empty());
+    }
+}
@@ -0,0 +1,384 @@
+// Playground Backend - Checks and runs code on the Rust Playground
+// Keeps the Blockly editor working on machines without a Rust toolchain
+
+use serde::Deserialize;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::backend::CompilerBackend;
+use crate::error::CompilerError;
+use crate::rust_compiler::{
+    check_source, collect_diagnostics, rustc_crate_type, unwrap_positions, CheckOptions,
+    CompilationResult, CrateType, RunMode, RunOptions, RunResult,
+};
+
+/// The official Rust Playground
+pub const DEFAULT_PLAYGROUND_URL: &str = "https://play.rust-lang.org";
+
+/// How long a playground request may take by default
+pub const DEFAULT_PLAYGROUND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Forwards checks and runs to the Rust Playground, or a compatible endpoint
+///
+/// Requests are sent with `curl`, which must be installed. The playground
+/// provides its own set of popular crates, so checks declaring dependencies,
+/// a build script or a custom manifest fail with `CompilerError::InvalidInput`.
+/// Code is sent to a third party, so this is only used when configured.
+pub struct PlaygroundBackend {
+    url: String,
+    timeout: Duration,
+}
+
+impl Default for PlaygroundBackend {
+    fn default() -> Self {
+        Self::new(DEFAULT_PLAYGROUND_URL)
+    }
+}
+
+impl PlaygroundBackend {
+    /// A backend for the playground at `url`, e.g. `https://play.rust-lang.org`
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into().trim_end_matches('/').to_string(),
+            timeout: DEFAULT_PLAYGROUND_TIMEOUT,
+        }
+    }
+
+    /// Give up on requests taking longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Compile `source` as a crate of `crate_type`, emitting MIR so the
+    /// borrow checker runs without building a binary
+    fn compile(
+        &self,
+        source: &str,
+        crate_type: CrateType,
+        options: &CheckOptions,
+    ) -> Result<CompilationResult, CompilerError> {
+        let started = Instant::now();
+        let mut request = request_body(source, crate_type, options);
+        request["target"] = "mir".into();
+        request["assemblyFlavor"] = "att".into();
+        request["demangleAssembly"] = "demangle".into();
+        request["processAssembly"] = "filter".into();
+
+        let response = self.post("compile", &request)?;
+        let mut result = parse_playground_output("", &response.stderr);
+        result.duration_ms = started.elapsed().as_millis() as u64;
+        Ok(result)
+    }
+
+    /// POST `body` to an endpoint of the playground
+    fn post(
+        &self,
+        endpoint: &str,
+        body: &serde_json::Value,
+    ) -> Result<PlaygroundResponse, CompilerError> {
+        let spawned = Command::new("curl")
+            .args(["--silent", "--show-error", "--fail", "--max-time"])
+            .arg(self.timeout.as_secs().max(1).to_string())
+            .args(["--header", "Content-Type: application/json", "--data-binary", "@-"])
+            .arg(format!("{}/{}", self.url, endpoint))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                let message = "The playground backend needs curl to be installed";
+                return Err(CompilerError::ToolchainMissing(message.to_string()));
+            }
+            Err(error) => return Err(error.into()),
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(body.to_string().as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+
+        // curl exits with 28 when --max-time passes
+        match output.status.code() {
+            Some(0) => Ok(serde_json::from_slice(&output.stdout)?),
+            Some(28) => Err(CompilerError::Timeout(format!(
+                "The playground did not respond within {}s",
+                self.timeout.as_secs()
+            ))),
+            _ => Err(CompilerError::ToolFailed(format!(
+                "The playground request failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))),
+        }
+    }
+}
+
+impl CompilerBackend for PlaygroundBackend {
+    fn check(
+        &self,
+        code: &str,
+        options: &CheckOptions,
+    ) -> Result<CompilationResult, CompilerError> {
+        reject_cargo_options(options)?;
+        let wrapped = check_source(code, options);
+        let mut result = self.compile(&wrapped.source, rustc_crate_type(options), options)?;
+        unwrap_positions(&mut result, &wrapped);
+        result.sort_diagnostics();
+        if options.treat_warnings_as_errors {
+            result.treat_warnings_as_errors();
+        }
+        Ok(result)
+    }
+
+    fn quick_check(
+        &self,
+        code: &str,
+        crate_type: CrateType,
+    ) -> Result<CompilationResult, CompilerError> {
+        let mut result = self.compile(code, crate_type, &CheckOptions::default())?;
+        result.sort_diagnostics();
+        Ok(result)
+    }
+
+    fn run(&self, code: &str, options: &RunOptions) -> Result<RunResult, CompilerError> {
+        if options.mode != RunMode::Native {
+            let message = "The playground only runs native programs";
+            return Err(CompilerError::InvalidInput(message.to_string()));
+        }
+        reject_cargo_options(&options.check)?;
+
+        let started = Instant::now();
+        let wrapped = check_source(code, &options.check);
+        let request = request_body(&wrapped.source, CrateType::Bin, &options.check);
+        let response = self.post("execute", &request)?;
+
+        // Cargo's output ends where it starts the program
+        let (build_output, program_stderr) = match response.stderr.find("     Running `") {
+            Some(start) => {
+                let end = response.stderr[start..]
+                    .find('\n')
+                    .map_or(response.stderr.len(), |end| start + end + 1);
+                (&response.stderr[..start], &response.stderr[end..])
+            }
+            None => (response.stderr.as_str(), ""),
+        };
+        let mut compilation = parse_playground_output("", build_output);
+        unwrap_positions(&mut compilation, &wrapped);
+        compilation.sort_diagnostics();
+        if !compilation.success {
+            return Ok(RunResult::not_run(compilation));
+        }
+
+        Ok(RunResult {
+            compilation,
+            exit_code: exit_code(&response),
+            stdout: response.stdout,
+            stderr: program_stderr.to_string(),
+            duration_ms: started.elapsed().as_millis() as u64,
+            timed_out: false,
+            out_of_fuel: false,
+        })
+    }
+}
+
+/// A playground response; `/compile` and `/execute` share these fields
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlaygroundResponse {
+    success: bool,
+    #[serde(default)]
+    exit_detail: String,
+    #[serde(default)]
+    stdout: String,
+    #[serde(default)]
+    stderr: String,
+}
+
+/// The request fields `/compile` and `/execute` share
+fn request_body(source: &str, crate_type: CrateType, options: &CheckOptions) -> serde_json::Value {
+    // The playground only has the three release channels
+    let channel = match options.toolchain.as_deref() {
+        Some(toolchain) if toolchain.starts_with("nightly") => "nightly",
+        Some(toolchain) if toolchain.starts_with("beta") => "beta",
+        _ => "stable",
+    };
+    serde_json::json!({
+        "channel": channel,
+        "mode": "debug",
+        "edition": options.edition.as_str(),
+        "crateType": crate_type.rustc_name(),
+        "tests": false,
+        "code": source,
+        "backtrace": false,
+    })
+}
+
+fn reject_cargo_options(options: &CheckOptions) -> Result<(), CompilerError> {
+    if options.all_dependencies().next().is_some()
+        || options.build_script.is_some()
+        || options.manifest.is_some()
+        || !options.manifest_extra.is_empty()
+    {
+        let message = "The playground can't add dependencies, build scripts or Cargo.toml \
+                       sections; it provides the most popular crates already";
+        return Err(CompilerError::InvalidInput(message.to_string()));
+    }
+    Ok(())
+}
+
+/// The program's exit code, from an `exitDetail` like "Exited with status 101"
+fn exit_code(response: &PlaygroundResponse) -> Option<i32> {
+    if response.success {
+        return Some(0);
+    }
+    response.exit_detail.strip_prefix("Exited with status ")?.trim().parse().ok()
+}
+
+/// Diagnostics from cargo's human-readable output, as the playground returns
+/// no JSON
+///
+/// Each diagnostic is turned into the JSON rustc would have emitted, with
+/// its primary position, so it's reported like a local check's.
+fn parse_playground_output(stdout: &str, stderr: &str) -> CompilationResult {
+    let mut messages = Vec::new();
+    let mut lines = stderr.lines().peekable();
+    while let Some(line) = lines.next() {
+        let Some((level, code, message)) = diagnostic_header(line) else {
+            continue;
+        };
+        let mut rendered = format!("{}\n", line);
+        let mut location = None;
+        while let Some(line) = lines.next_if(|line| !line.is_empty()) {
+            if location.is_none() {
+                location = line.trim_start().strip_prefix("--> ").and_then(parse_location);
+            }
+            rendered.push_str(line);
+            rendered.push('\n');
+        }
+
+        let spans: Vec<serde_json::Value> = location
+            .into_iter()
+            .map(|(file, line, column)| {
+                serde_json::json!({
+                    "file_name": file,
+                    "line_start": line,
+                    "line_end": line,
+                    "column_start": column,
+                    "column_end": column,
+                    "is_primary": true,
+                })
+            })
+            .collect();
+        messages.push(serde_json::json!({
+            "level": level,
+            "message": message,
+            "code": code.map(|code| serde_json::json!({ "code": code })),
+            "rendered": rendered,
+            "spans": spans,
+        }));
+    }
+    collect_diagnostics(messages.into_iter(), stdout, stderr)
+}
+
+/// Level, error code and message of a line like `error[E0308]: mismatched types`,
+/// unless it's one of cargo's summary lines
+fn diagnostic_header(line: &str) -> Option<(&str, Option<&str>, &str)> {
+    let (head, message) = line.split_once(": ")?;
+    let (level, code) = match head.split_once('[') {
+        Some((level, code)) => (level, Some(code.strip_suffix(']')?)),
+        None => (head, None),
+    };
+    if !matches!(level, "error" | "warning") {
+        return None;
+    }
+    let summary = message.starts_with("could not compile")
+        || message.starts_with("aborting due to")
+        || (message.starts_with('`') && message.contains(" generated "));
+    (!summary).then_some((level, code, message))
+}
+
+/// File, line and column of a location like `src/main.rs:2:18`
+fn parse_location(location: &str) -> Option<(String, usize, usize)> {
+    let mut parts = location.trim().rsplitn(3, ':');
+    let column = parts.next()?.parse().ok()?;
+    let line = parts.next()?.parse().ok()?;
+    Some((parts.next()?.to_string(), line, column))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_playground_backend() {
+        if Command::new("curl").arg("--version").output().is_err() {
+            println!("Skipping test: curl not available");
+            return;
+        }
+
+        // A stand-in for the playground, answering one request
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some(value) = header.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            let response = serde_json::json!({
+                "success": false,
+                "exitDetail": "Exited with status 101",
+                "stdout": "",
+                "stderr": "   Compiling playground v0.0.1 (/playground)\n\
+                           error[E0308]: mismatched types\n \
+                           --> src/main.rs:2:14\n  |\n\
+                           2 | let x: i32 = \"text\";\n  |        ---   ^^^^^^ expected `i32`\n\n\
+                           error: could not compile `playground` (bin \"playground\") \
+                           due to 1 previous error\n",
+            })
+            .to_string();
+            let mut stream = reader.into_inner();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                response.len(),
+                response
+            )
+            .unwrap();
+            (request_line, String::from_utf8(body).unwrap())
+        });
+
+        let backend = PlaygroundBackend::new(format!("{}/", url));
+        let result = backend.check("let x: i32 = \"text\";", &CheckOptions::default()).unwrap();
+        let (request_line, body) = server.join().unwrap();
+
+        assert!(request_line.starts_with("POST /compile "));
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["crateType"], "bin");
+        assert_eq!(body["channel"], "stable");
+        assert!(body["code"].as_str().unwrap().contains("fn main() {"));
+
+        assert!(!result.success);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].code.as_deref(), Some("E0308"));
+        assert_eq!(result.errors[0].line, Some(1), "Positions should map to the snippet");
+        assert_eq!(result.errors[0].column, Some(14));
+    }
+}
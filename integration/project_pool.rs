@@ -0,0 +1,220 @@
+// Project Pool - Reusable Cargo project directories for checks
+// Reusing a project keeps its target directory, so later checks build incrementally
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error::CompilerError;
+
+/// Sizing of a project pool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolConfig {
+    /// Projects created up front and kept even when idle
+    pub min_idle: usize,
+    /// Most projects that may exist at once, idle or in use
+    pub max_size: usize,
+    /// Idle projects beyond `min_idle` are removed after this long unused
+    pub idle_timeout: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            min_idle: 1,
+            max_size: 4,
+            idle_timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Snapshot of a pool's state for monitoring
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PoolMetrics {
+    /// Idle projects ready to be handed out
+    pub available: usize,
+    /// Projects currently used by a check
+    pub in_use: usize,
+    /// Projects created since the pool started
+    pub created: u64,
+    /// Idle projects removed since the pool started
+    pub evicted: u64,
+}
+
+/// A pool of project directories that grows up to `max_size` under load and
+/// shrinks back to `min_idle` when projects sit idle
+pub struct ProjectPool {
+    root: PathBuf,
+    config: PoolConfig,
+    state: Mutex<PoolState>,
+}
+
+struct PoolState {
+    /// Idle projects and when they were returned, most recently used last
+    idle: Vec<(PathBuf, Instant)>,
+    in_use: usize,
+    created: u64,
+    evicted: u64,
+}
+
+/// A project taken from the pool, returned to it on drop
+pub struct PooledProject {
+    pool: Arc<ProjectPool>,
+    dir: PathBuf,
+}
+
+impl ProjectPool {
+    /// Create a pool keeping its projects under `root`, with `min_idle` projects ready
+    pub fn new(
+        root: impl Into<PathBuf>,
+        config: PoolConfig,
+    ) -> Result<Self, CompilerError> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+
+        let pool = Self {
+            root,
+            config,
+            state: Mutex::new(PoolState {
+                idle: Vec::new(),
+                in_use: 0,
+                created: 0,
+                evicted: 0,
+            }),
+        };
+
+        {
+            let mut state = pool.state.lock().unwrap();
+            for _ in 0..pool.config.min_idle.min(pool.config.max_size) {
+                let dir = pool.create_project(&mut state)?;
+                state.idle.push((dir, Instant::now()));
+            }
+        }
+
+        Ok(pool)
+    }
+
+    /// Take a project, creating one if none are idle and the pool isn't full
+    ///
+    /// Returns `None` when all `max_size` projects are in use.
+    pub fn acquire(self: &Arc<Self>) -> Result<Option<PooledProject>, CompilerError> {
+        self.shrink_idle();
+
+        let mut state = self.state.lock().unwrap();
+        let dir = match state.idle.pop() {
+            Some((dir, _)) => dir,
+            None if state.idle.len() + state.in_use < self.config.max_size => {
+                self.create_project(&mut state)?
+            }
+            None => return Ok(None),
+        };
+        state.in_use += 1;
+
+        Ok(Some(PooledProject {
+            pool: self.clone(),
+            dir,
+        }))
+    }
+
+    /// Remove projects idle for longer than the idle timeout, keeping at least
+    /// `min_idle`. Returns how many were removed.
+    pub fn shrink_idle(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let mut removed = Vec::new();
+
+        // Oldest projects are at the front
+        while state.idle.len() > self.config.min_idle
+            && state.idle[0].1.elapsed() >= self.config.idle_timeout
+        {
+            removed.push(state.idle.remove(0).0);
+        }
+        state.evicted += removed.len() as u64;
+        drop(state);
+
+        for dir in &removed {
+            let _ = fs::remove_dir_all(dir);
+        }
+
+        removed.len()
+    }
+
+    /// Current pool metrics
+    pub fn metrics(&self) -> PoolMetrics {
+        let state = self.state.lock().unwrap();
+        PoolMetrics {
+            available: state.idle.len(),
+            in_use: state.in_use,
+            created: state.created,
+            evicted: state.evicted,
+        }
+    }
+
+    fn create_project(&self, state: &mut PoolState) -> Result<PathBuf, CompilerError> {
+        let dir = self.root.join(format!("pool_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir)?;
+        state.created += 1;
+        Ok(dir)
+    }
+}
+
+impl PooledProject {
+    /// The project directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Drop for PooledProject {
+    fn drop(&mut self) {
+        let mut state = self.pool.state.lock().unwrap();
+        state.in_use -= 1;
+        state.idle.push((self.dir.clone(), Instant::now()));
+    }
+}
+
+impl Drop for ProjectPool {
+    fn drop(&mut self) {
+        let state = self.state.lock().unwrap();
+        for (dir, _) in &state.idle {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_grows_and_shrinks() {
+        let root = std::env::temp_dir().join(format!("blockly_pool_{}", uuid::Uuid::new_v4()));
+        let config = PoolConfig {
+            min_idle: 1,
+            max_size: 3,
+            idle_timeout: Duration::from_millis(50),
+        };
+        let pool = Arc::new(ProjectPool::new(&root, config).unwrap());
+        assert_eq!(pool.metrics().created, 1, "min_idle projects should be created up front");
+
+        let held: Vec<_> = (0..4).filter_map(|_| pool.acquire().unwrap()).collect();
+        assert_eq!(held.len(), 3, "Pool should grow up to its maximum and no further");
+
+        let metrics = pool.metrics();
+        assert_eq!((metrics.available, metrics.in_use, metrics.created), (0, 3, 3));
+
+        drop(held);
+        assert_eq!(pool.metrics().available, 3);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(pool.shrink_idle(), 2, "Idle projects beyond min_idle should be reclaimed");
+
+        let metrics = pool.metrics();
+        assert_eq!((metrics.available, metrics.evicted), (1, 2));
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 1);
+
+        drop(pool);
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,260 @@
+// Resource Limits - Memory and CPU caps for the processes a check spawns
+// Uses rlimits on Unix and a Job Object on Windows, which also lets the
+// whole process tree be killed
+
+use serde::Serialize;
+use std::process::Command;
+use std::time::Duration;
+
+/// Limits on each process a check or run spawns: cargo, every rustc it starts,
+/// and programs being run
+///
+/// Limits apply per process, not to a check as a whole. A process that
+/// exceeds its memory limit fails to allocate, and one that exceeds its CPU
+/// time is killed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ResourceLimits {
+    /// Memory a process may allocate, in bytes. On Linux this is the data
+    /// segment limit, so address space a program only reserves (as wasmtime
+    /// does) doesn't count; other Unixes limit the address space.
+    pub memory_bytes: Option<u64>,
+    /// CPU time a process may use, rounded up to whole seconds on Unix
+    pub cpu_time: Option<Duration>,
+}
+
+impl ResourceLimits {
+    pub fn is_unlimited(&self) -> bool {
+        self.memory_bytes.is_none() && self.cpu_time.is_none()
+    }
+
+    /// Set the limits in processes started by `command`, before they execute
+    #[cfg(unix)]
+    pub(crate) fn apply(&self, command: &mut Command) {
+        use std::os::unix::process::CommandExt;
+
+        if self.is_unlimited() {
+            return;
+        }
+        let limits = *self;
+        // SAFETY: the closure only calls setrlimit, which is async-signal-safe
+        unsafe {
+            command.pre_exec(move || limits.set_rlimits());
+        }
+    }
+
+    /// Windows limits the process once it's started; see `ProcessTree::new`
+    #[cfg(not(unix))]
+    pub(crate) fn apply(&self, _command: &mut Command) {}
+
+    #[cfg(unix)]
+    fn set_rlimits(&self) -> std::io::Result<()> {
+        #[cfg(target_os = "linux")]
+        let memory = libc::RLIMIT_DATA;
+        #[cfg(not(target_os = "linux"))]
+        let memory = libc::RLIMIT_AS;
+
+        let set = |resource, value: u64| {
+            let limit = libc::rlimit {
+                rlim_cur: value as libc::rlim_t,
+                rlim_max: value as libc::rlim_t,
+            };
+            // SAFETY: `limit` is a valid rlimit for the duration of the call
+            if unsafe { libc::setrlimit(resource, &limit) } == 0 {
+                Ok(())
+            } else {
+                Err(std::io::Error::last_os_error())
+            }
+        };
+        if let Some(bytes) = self.memory_bytes {
+            set(memory, bytes)?;
+        }
+        if let Some(cpu_time) = self.cpu_time {
+            let seconds = cpu_time.as_secs() + u64::from(cpu_time.subsec_nanos() > 0);
+            set(libc::RLIMIT_CPU, seconds.max(1))?;
+        }
+        Ok(())
+    }
+}
+
+/// A spawned command and every process it starts, e.g. cargo with its rustc
+/// and build script processes: a process group on Unix, a Job Object on Windows
+///
+/// Dropping it kills whatever is left of the tree.
+pub(crate) struct ProcessTree {
+    #[cfg(unix)]
+    group: libc::pid_t,
+    #[cfg(windows)]
+    job: windows_sys::Win32::Foundation::HANDLE,
+}
+
+impl ProcessTree {
+    /// Start `command` in a process group of its own, so the tree can be
+    /// killed without touching our own process
+    #[cfg(unix)]
+    pub(crate) fn own_group(command: &mut Command) {
+        use std::os::unix::process::CommandExt;
+
+        command.process_group(0);
+    }
+
+    /// Start `command` suspended, so it can't start any processes of its own
+    /// before `new` has put it in a Job Object
+    #[cfg(windows)]
+    pub(crate) fn own_group(command: &mut Command) {
+        use std::os::windows::process::CommandExt;
+        use windows_sys::Win32::System::Threading::CREATE_SUSPENDED;
+
+        command.creation_flags(CREATE_SUSPENDED);
+    }
+
+    /// The tree of the process `pid`, started after `own_group`
+    #[cfg(unix)]
+    pub(crate) fn new(pid: u32) -> Self {
+        Self { group: pid as libc::pid_t }
+    }
+
+    /// Put a process started suspended by `own_group`, and the processes it
+    /// starts, in a Job Object with `limits`, then let it run
+    ///
+    /// On failure the process never runs, and the caller should kill it.
+    #[cfg(windows)]
+    pub(crate) fn new(
+        process: std::os::windows::io::RawHandle,
+        limits: &ResourceLimits,
+    ) -> std::io::Result<Self> {
+        use windows_sys::Win32::Foundation::CloseHandle;
+        use windows_sys::Win32::System::JobObjects::{
+            AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation,
+            SetInformationJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+            JOB_OBJECT_LIMIT_PROCESS_MEMORY, JOB_OBJECT_LIMIT_PROCESS_TIME,
+        };
+        use windows_sys::Win32::System::Threading::GetProcessId;
+
+        // SAFETY: a zeroed JOBOBJECT_EXTENDED_LIMIT_INFORMATION sets no limits
+        let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { std::mem::zeroed() };
+        if let Some(bytes) = limits.memory_bytes {
+            info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_MEMORY;
+            info.ProcessMemoryLimit = usize::try_from(bytes).unwrap_or(usize::MAX);
+        }
+        if let Some(cpu_time) = limits.cpu_time {
+            // Job Objects count CPU time in 100ns ticks
+            info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_TIME;
+            info.BasicLimitInformation.PerProcessUserTimeLimit =
+                i64::try_from(cpu_time.as_nanos() / 100).unwrap_or(i64::MAX);
+        }
+
+        // SAFETY: the job handle is checked before use, and kept until the
+        // tree is dropped or closed here on failure
+        unsafe {
+            let job = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+            if job == 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            let assigned = SetInformationJobObject(
+                job,
+                JobObjectExtendedLimitInformation,
+                &info as *const _ as *const _,
+                std::mem::size_of_val(&info) as u32,
+            ) != 0
+                && AssignProcessToJobObject(job, process as _) != 0;
+            if !assigned {
+                let error = std::io::Error::last_os_error();
+                CloseHandle(job);
+                return Err(error);
+            }
+            let tree = Self { job };
+            resume_threads(GetProcessId(process as _))?;
+            Ok(tree)
+        }
+    }
+
+    /// Kill every process left in the tree
+    pub(crate) fn kill(&self) {
+        // SAFETY: killing a process group or terminating a job we own has no
+        // memory safety requirements; failures mean nothing is left to kill
+        #[cfg(unix)]
+        unsafe {
+            libc::killpg(self.group, libc::SIGKILL);
+        }
+        #[cfg(windows)]
+        unsafe {
+            windows_sys::Win32::System::JobObjects::TerminateJobObject(self.job, 1);
+        }
+    }
+}
+
+/// Resume the threads of the suspended process `pid`; a process started with
+/// `CREATE_SUSPENDED` has only its main thread
+#[cfg(windows)]
+fn resume_threads(pid: u32) -> std::io::Result<()> {
+    use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD, THREADENTRY32,
+    };
+    use windows_sys::Win32::System::Threading::{OpenThread, ResumeThread, THREAD_SUSPEND_RESUME};
+
+    // SAFETY: the snapshot and thread handles are checked before use and
+    // closed before returning; `entry` has its size set as the API requires
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0);
+        if snapshot == INVALID_HANDLE_VALUE {
+            return Err(std::io::Error::last_os_error());
+        }
+        let mut entry: THREADENTRY32 = std::mem::zeroed();
+        entry.dwSize = std::mem::size_of::<THREADENTRY32>() as u32;
+
+        let mut resumed = false;
+        let mut found = Thread32First(snapshot, &mut entry) != 0;
+        while found {
+            if entry.th32OwnerProcessID == pid {
+                let thread = OpenThread(THREAD_SUSPEND_RESUME, 0, entry.th32ThreadID);
+                if thread != 0 {
+                    resumed |= ResumeThread(thread) != u32::MAX;
+                    CloseHandle(thread);
+                }
+            }
+            found = Thread32Next(snapshot, &mut entry) != 0;
+        }
+        CloseHandle(snapshot);
+
+        if resumed {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        }
+    }
+}
+
+impl Drop for ProcessTree {
+    fn drop(&mut self) {
+        self.kill();
+        // SAFETY: the handle is owned by this tree and not used again
+        #[cfg(windows)]
+        unsafe {
+            windows_sys::Win32::Foundation::CloseHandle(self.job);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_rlimits() {
+        let limits = ResourceLimits {
+            memory_bytes: Some(64 * 1024 * 1024),
+            cpu_time: Some(Duration::from_millis(4500)),
+        };
+        assert!(!limits.is_unlimited());
+        assert!(ResourceLimits::default().is_unlimited());
+
+        // The shell reports the data segment limit in KiB
+        let mut command = Command::new("sh");
+        command.args(["-c", "ulimit -d; ulimit -t"]);
+        limits.apply(&mut command);
+        let output = command.output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "65536\n5\n");
+    }
+}
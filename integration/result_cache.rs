@@ -0,0 +1,110 @@
+// Result Cache - Least-recently-used cache of check results
+// Blockly regenerates identical code often, so repeated checks can skip the compiler
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use crate::rust_compiler::CompilationResult;
+
+/// Check results keyed by the code, check options and checker configuration,
+/// evicting the least recently used entry once `capacity` is reached
+///
+/// Keys are compared in full, not by hash, so two different checks never
+/// share a result.
+pub struct ResultCache {
+    capacity: usize,
+    entries: HashMap<Arc<str>, CompilationResult>,
+    /// Keys from least to most recently used
+    order: VecDeque<Arc<str>>,
+}
+
+impl ResultCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// The cached result for `key`, marking it as recently used
+    pub fn get(&mut self, key: &str) -> Option<CompilationResult> {
+        let result = self.entries.get(key)?.clone();
+        self.touch(key);
+        Some(result)
+    }
+
+    /// Cache `result` under `key`, evicting the least recently used entry if full
+    pub fn insert(&mut self, key: String, result: CompilationResult) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if let Some(entry) = self.entries.get_mut(key.as_str()) {
+            *entry = result;
+            self.touch(&key);
+            return;
+        }
+
+        let key: Arc<str> = key.into();
+        self.entries.insert(key.clone(), result);
+
+        self.order.push_back(key);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn touch(&mut self, key: &str) {
+        if let Some(position) = self.order.iter().position(|k| **k == *key) {
+            if let Some(key) = self.order.remove(position) {
+                self.order.push_back(key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rust_compiler::{CompilationError, ErrorLevel};
+
+    fn result(message: &str) -> CompilationResult {
+        let mut result: CompilationResult = serde_json::from_value(serde_json::json!({
+            "success": true,
+            "errors": [],
+            "warnings": [],
+            "stdout": "",
+            "stderr": "",
+        }))
+        .unwrap();
+        result.warnings.push(CompilationError::new(ErrorLevel::Warning, message));
+        result
+    }
+
+    #[test]
+    fn test_least_recently_used_is_evicted() {
+        let mut cache = ResultCache::new(2);
+        cache.insert("1".to_string(), result("one"));
+        cache.insert("2".to_string(), result("two"));
+
+        // Using 1 makes 2 the least recently used
+        assert!(cache.get("1").is_some());
+        cache.insert("3".to_string(), result("three"));
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("2").is_none(), "Least recently used entry should be evicted");
+        assert_eq!(cache.get("1").unwrap().warnings[0].message, "one");
+        assert_eq!(cache.get("3").unwrap().warnings[0].message, "three");
+    }
+}
@@ -2,10 +2,328 @@
 // Provides compilation checking and error reporting for generated Rust code
 
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::io::Write;
-use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::container::ContainerConfig;
+use crate::deny_list::DenyList;
+use crate::error::CompilerError;
+use crate::metrics::CodeMetrics;
+use crate::project_pool::{PoolConfig, PooledProject, ProjectPool};
+use crate::resource_limits::{ProcessTree, ResourceLimits};
+use crate::result_cache::ResultCache;
+use crate::sandbox::Sandbox;
+
+/// Maximum number of `rustc --explain` lookups performed for a single check
+pub const MAX_EXPLANATIONS_PER_CHECK: usize = 5;
+
+/// Checks taking longer than this get a note saying the code is slow to compile
+pub const DEFAULT_SLOW_CHECK_THRESHOLD: Duration = Duration::from_secs(10);
+
+/// Edition rustfmt formats code for unless another is set
+pub const DEFAULT_FORMAT_EDITION: Edition = Edition::E2021;
+
+/// Pages of the rustc error code index, one per code
+const ERROR_INDEX_URL: &str = "https://doc.rust-lang.org/error_codes";
+
+/// Pages listing rustc's lints by default level
+const LINT_LISTING_URL: &str = "https://doc.rust-lang.org/rustc/lints/listing";
+
+/// The list of every Clippy lint, with an anchor per lint
+const CLIPPY_LINTS_URL: &str = "https://rust-lang.github.io/rust-clippy/master/index.html";
+
+// The default-level lint tables follow `rustc -W help` for Rust 1.95. A
+// name in none of them gets no documentation link rather than a guess.
+
+/// Lints rustc denies unless configured otherwise
+const DENY_BY_DEFAULT_LINTS: &[&str] = &[
+    "ambiguous_associated_items",
+    "arithmetic_overflow",
+    "binary_asm_labels",
+    "bindings_with_variant_name",
+    "conflicting_repr_hints",
+    "dangerous_implicit_autorefs",
+    "default_overrides_default_fields",
+    "dependency_on_unit_never_type_fallback",
+    "deref_nullptr",
+    "elided_lifetimes_in_associated_constant",
+    "enum_intrinsics_non_enums",
+    "explicit_builtin_cfgs_in_flags",
+    "ill_formed_attribute_input",
+    "incomplete_include",
+    "ineffective_unstable_trait_impl",
+    "invalid_atomic_ordering",
+    "invalid_from_utf8_unchecked",
+    "invalid_macro_export_arguments",
+    "invalid_null_arguments",
+    "invalid_reference_casting",
+    "invalid_type_param_default",
+    "legacy_derive_helpers",
+    "let_underscore_lock",
+    "long_running_const_eval",
+    "macro_expanded_macro_exports_accessed_by_absolute_paths",
+    "mutable_transmutes",
+    "named_asm_labels",
+    "never_type_fallback_flowing_into_unsafe",
+    "no_mangle_const_items",
+    "out_of_scope_macro_calls",
+    "overflowing_literals",
+    "patterns_in_fns_without_body",
+    "proc_macro_derive_resolution_fallback",
+    "pub_use_of_private_extern_crate",
+    "repr_transparent_non_zst_fields",
+    "semicolon_in_expressions_from_macros",
+    "soft_unstable",
+    "test_unstable_lint",
+    "text_direction_codepoint_in_comment",
+    "text_direction_codepoint_in_literal",
+    "unconditional_panic",
+    "undropped_manually_drops",
+    "unknown_crate_types",
+    "useless_deprecated",
+];
+
+/// Lints rustc warns about unless configured otherwise
+const WARN_BY_DEFAULT_LINTS: &[&str] = &[
+    "aarch64_softfloat_neon",
+    "ambiguous_derive_helpers",
+    "ambiguous_glob_imported_traits",
+    "ambiguous_glob_imports",
+    "ambiguous_glob_reexports",
+    "ambiguous_import_visibilities",
+    "ambiguous_panic_imports",
+    "ambiguous_wide_pointer_comparisons",
+    "anonymous_parameters",
+    "array_into_iter",
+    "asm_sub_register",
+    "async_fn_in_trait",
+    "bad_asm_style",
+    "bare_trait_objects",
+    "boxed_slice_into_iter",
+    "break_with_label_and_loop",
+    "clashing_extern_declarations",
+    "coherence_leak_check",
+    "confusable_idents",
+    "const_evaluatable_unchecked",
+    "const_item_interior_mutations",
+    "const_item_mutation",
+    "dangling_pointers_from_locals",
+    "dangling_pointers_from_temporaries",
+    "dead_code",
+    "deprecated",
+    "deprecated_where_clause_location",
+    "double_negations",
+    "drop_bounds",
+    "dropping_copy_types",
+    "dropping_references",
+    "duplicate_macro_attributes",
+    "dyn_drop",
+    "ellipsis_inclusive_range_patterns",
+    "exported_private_dependencies",
+    "for_loops_over_fallibles",
+    "forbidden_lint_groups",
+    "forgetting_copy_types",
+    "forgetting_references",
+    "function_casts_as_integer",
+    "function_item_references",
+    "hidden_glob_reexports",
+    "improper_ctypes",
+    "improper_ctypes_definitions",
+    "improper_gpu_kernel_arg",
+    "incomplete_features",
+    "inline_always_mismatching_target_features",
+    "inline_no_sanitize",
+    "integer_to_ptr_transmutes",
+    "internal_eq_trait_method_impls",
+    "internal_features",
+    "invalid_doc_attributes",
+    "invalid_from_utf8",
+    "invalid_nan_comparisons",
+    "invalid_value",
+    "irrefutable_let_patterns",
+    "large_assignments",
+    "late_bound_lifetime_arguments",
+    "malformed_diagnostic_attributes",
+    "malformed_diagnostic_format_literals",
+    "map_unit_fn",
+    "mismatched_lifetime_syntaxes",
+    "misplaced_diagnostic_attributes",
+    "missing_abi",
+    "missing_gpu_kernel_export_name",
+    "mixed_script_confusables",
+    "named_arguments_used_positionally",
+    "no_mangle_generic_items",
+    "non_camel_case_types",
+    "non_contiguous_range_endpoints",
+    "non_fmt_panics",
+    "non_local_definitions",
+    "non_shorthand_field_patterns",
+    "non_snake_case",
+    "non_upper_case_globals",
+    "noop_method_call",
+    "opaque_hidden_inferred_bound",
+    "overlapping_range_endpoints",
+    "path_statements",
+    "private_bounds",
+    "private_interfaces",
+    "ptr_to_integer_transmute_in_consts",
+    "redundant_semicolons",
+    "refining_impl_trait_internal",
+    "refining_impl_trait_reachable",
+    "renamed_and_removed_lints",
+    "repr_c_enums_larger_than_int",
+    "rtsan_nonblocking_async",
+    "self_constructor_from_outer_item",
+    "special_module_name",
+    "stable_features",
+    "static_mut_refs",
+    "suspicious_double_ref_op",
+    "trivial_bounds",
+    "type_alias_bounds",
+    "tyvar_behind_raw_pointer",
+    "uncommon_codepoints",
+    "unconditional_recursion",
+    "uncovered_param_in_projection",
+    "unexpected_cfgs",
+    "unfulfilled_lint_expectations",
+    "ungated_async_fn_track_caller",
+    "uninhabited_static",
+    "unknown_diagnostic_attributes",
+    "unknown_lints",
+    "unnameable_test_items",
+    "unnecessary_transmutes",
+    "unpredictable_function_pointer_comparisons",
+    "unreachable_cfg_select_predicates",
+    "unreachable_code",
+    "unreachable_patterns",
+    "unstable_name_collisions",
+    "unstable_syntax_pre_expansion",
+    "unsupported_calling_conventions",
+    "unused_allocation",
+    "unused_assignments",
+    "unused_associated_type_bounds",
+    "unused_attributes",
+    "unused_braces",
+    "unused_comparisons",
+    "unused_doc_comments",
+    "unused_features",
+    "unused_imports",
+    "unused_labels",
+    "unused_macros",
+    "unused_must_use",
+    "unused_mut",
+    "unused_parens",
+    "unused_unsafe",
+    "unused_variables",
+    "unused_visibilities",
+    "useless_ptr_null_checks",
+    "uses_power_alignment",
+    "varargs_without_pattern",
+    "warnings",
+    "while_true",
+];
+
+/// Lints in rustc's `unused` group not named `unused_*`
+const UNUSED_LINTS: &[&str] = &[
+    "dead_code",
+    "map_unit_fn",
+    "path_statements",
+    "redundant_semicolons",
+    "unreachable_code",
+    "unreachable_patterns",
+];
+
+/// rustc lints about naming and formatting conventions
+const STYLE_LINTS: &[&str] = &[
+    "non_camel_case_types",
+    "non_snake_case",
+    "non_upper_case_globals",
+    "while_true",
+];
+
+/// rustc lints about code that's probably wrong
+const CORRECTNESS_LINTS: &[&str] = &[
+    "arithmetic_overflow",
+    "dropping_references",
+    "for_loops_over_fallibles",
+    "invalid_value",
+    "irrefutable_let_patterns",
+    "overflowing_literals",
+    "unconditional_panic",
+    "unconditional_recursion",
+    "useless_ptr_null_checks",
+];
+
+/// Lints rustc only reports once they're enabled, e.g. by a `LintPreset`
+const ALLOW_BY_DEFAULT_LINTS: &[&str] = &[
+    "absolute_paths_not_starting_with_crate",
+    "ambiguous_negative_literals",
+    "closure_returning_async_block",
+    "deprecated_in_future",
+    "deprecated_safe_2024",
+    "deref_into_dyn_supertrait",
+    "edition_2024_expr_fragment_specifier",
+    "elided_lifetimes_in_paths",
+    "explicit_outlives_requirements",
+    "ffi_unwind_calls",
+    "fuzzy_provenance_casts",
+    "if_let_rescope",
+    "impl_trait_overcaptures",
+    "impl_trait_redundant_captures",
+    "keyword_idents_2018",
+    "keyword_idents_2024",
+    "let_underscore_drop",
+    "linker_messages",
+    "lossy_provenance_casts",
+    "macro_use_extern_crate",
+    "meta_variable_misuse",
+    "missing_copy_implementations",
+    "missing_debug_implementations",
+    "missing_docs",
+    "missing_unsafe_on_extern",
+    "multiple_supertrait_upcastable",
+    "must_not_suspend",
+    "non_ascii_idents",
+    "non_exhaustive_omitted_patterns",
+    "redundant_imports",
+    "redundant_lifetimes",
+    "resolving_to_items_shadowing_supertrait_items",
+    "rust_2021_incompatible_closure_captures",
+    "rust_2021_incompatible_or_patterns",
+    "rust_2021_prefixes_incompatible_syntax",
+    "rust_2021_prelude_collisions",
+    "rust_2024_guarded_string_incompatible_syntax",
+    "rust_2024_incompatible_pat",
+    "rust_2024_prelude_collisions",
+    "shadowing_supertrait_items",
+    "single_use_lifetimes",
+    "tail_expr_drop_order",
+    "trivial_casts",
+    "trivial_numeric_casts",
+    "unit_bindings",
+    "unnameable_types",
+    "unqualified_local_imports",
+    "unreachable_pub",
+    "unsafe_attr_outside_unsafe",
+    "unsafe_code",
+    "unsafe_op_in_unsafe_fn",
+    "unstable_features",
+    "unused_crate_dependencies",
+    "unused_extern_crates",
+    "unused_import_braces",
+    "unused_lifetimes",
+    "unused_macro_rules",
+    "unused_qualifications",
+    "unused_results",
+    "variant_size_differences",
+];
 
 /// Compilation result with errors and warnings
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,8 +331,217 @@ pub struct CompilationResult {
     pub success: bool,
     pub errors: Vec<CompilationError>,
     pub warnings: Vec<CompilationError>,
+    #[serde(default)]
+    pub notes: Vec<CompilationError>,
+    #[serde(default)]
+    pub help: Vec<CompilationError>,
     pub stdout: String,
     pub stderr: String,
+    /// The command that produced this result, when requested
+    #[serde(default)]
+    pub command: Option<CommandLine>,
+    /// Wall-clock time the check took, in milliseconds
+    #[serde(default)]
+    pub duration_ms: u64,
+    /// The compiler was killed for exceeding the configured timeout
+    #[serde(default)]
+    pub timed_out: bool,
+    /// This result was served from the result cache
+    #[serde(default)]
+    pub cached: bool,
+    /// Security advisories and yanked releases among the dependencies, when
+    /// dependency auditing is enabled
+    #[serde(default)]
+    pub advisories: Vec<Advisory>,
+    /// The `Cargo.lock` the dependencies were resolved to, for checks with
+    /// dependencies; pass it back as `CheckOptions::lockfile` to get the same
+    /// versions next time
+    #[serde(default)]
+    pub lockfile: Option<String>,
+    /// Lines added before the code when it was checked, such as the
+    /// `fn main() {` a snippet is wrapped in, or 0 if its lines weren't all
+    /// moved the same amount; diagnostic positions have already been mapped
+    /// back to the submitted code
+    #[serde(default)]
+    pub line_offset: usize,
+    /// How the code was wrapped when it was checked, so `apply_fixes` can map
+    /// the compiler's positions back onto it
+    #[serde(default)]
+    pub wrap_mode: WrapMode,
+    /// Number of warnings in each lint group, e.g. to collapse the unused
+    /// ones into a single badge
+    #[serde(default)]
+    pub lint_groups: BTreeMap<LintGroup, usize>,
+}
+
+/// A crate in the resolved dependency graph of a check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DependencyNode {
+    /// `name@version`, unique within the graph
+    pub id: String,
+    pub name: String,
+    pub version: String,
+    /// Enabled features
+    pub features: Vec<String>,
+    /// Ids of the crates in the graph that depend on this one; the checked
+    /// crate isn't one of them, so a crate only it depends on has none
+    pub parents: Vec<String>,
+    /// Declared by the checked code itself rather than pulled in by another crate
+    pub direct: bool,
+}
+
+/// A problem `cargo audit` found with a dependency
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Advisory {
+    /// `vulnerability`, `yanked`, `unmaintained` or `unsound`
+    pub kind: String,
+    pub package: String,
+    pub version: String,
+    /// RustSec advisory ID, e.g. `RUSTSEC-2020-0071`; yanked releases have none
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub url: Option<String>,
+}
+
+impl CompilationResult {
+    /// `original` with the machine-applicable fixes from this result applied
+    ///
+    /// `original` must be the code that was checked to produce this result;
+    /// it's wrapped again as `wrap_mode` says. Returns it unchanged if there
+    /// are no fixes.
+    pub fn fixed_code(&self, original: &str) -> String {
+        self.apply_fixes(original).0
+    }
+
+    /// `original` with the machine-applicable fixes applied, and the fixes
+    /// that were applied
+    ///
+    /// Like `fixed_code`, `original` must be the code that was checked.
+    pub fn apply_fixes(&self, original: &str) -> (String, Vec<AppliedFix>) {
+        // Spans refer to the checked source, which may be wrapped in main
+        let wrapped = WrappedSnippet::with_mode(original, &self.wrap_mode);
+        let suggestions = machine_applicable_fixes(&self.stdout, &wrapped);
+        let Some((fixed, chosen)) = apply_fixes(original, &suggestions) else {
+            return (original.to_string(), Vec::new());
+        };
+
+        let applied = chosen
+            .into_iter()
+            .map(|index| {
+                let suggestion = &suggestions[index];
+                let start = suggestion.edits.iter().map(|edit| edit.byte_start).min();
+                let (line, column) = line_column(original, start.unwrap_or(0));
+                AppliedFix {
+                    message: suggestion.message.clone(),
+                    line,
+                    column,
+                }
+            })
+            .collect();
+
+        (fixed, applied)
+    }
+
+    /// Order each group of diagnostics by file and position, so repeated
+    /// checks of the same code give the same result
+    ///
+    /// Done once positions are mapped back onto the submitted code and every
+    /// diagnostic, including linters' and the deny list's, is in.
+    pub(crate) fn sort_diagnostics(&mut self) {
+        for group in [&mut self.errors, &mut self.warnings, &mut self.notes, &mut self.help] {
+            sort_by_position(group);
+        }
+    }
+
+    /// Mark the result failed if it has any warnings, for grading that
+    /// requires a clean build
+    pub fn treat_warnings_as_errors(&mut self) {
+        if !self.warnings.is_empty() {
+            self.success = false;
+        }
+    }
+
+    /// A unified diff from `original` to the code with this result's
+    /// machine-applicable fixes applied, empty if there are none
+    pub fn fixes_as_diff(&self, original: &str) -> String {
+        let fixed = self.fixed_code(original);
+        if fixed == original {
+            return String::new();
+        }
+
+        similar::TextDiff::from_lines(original, &fixed)
+            .unified_diff()
+            .header("original", "fixed")
+            .to_string()
+    }
+}
+
+/// A compiler suggestion applied by `CompilationResult::apply_fixes`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppliedFix {
+    /// The suggestion's message, e.g. "remove this `mut`"
+    pub message: String,
+    /// Where the first edit starts in the original code, 1-based
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A command line as run by the checker, for reproducing a check locally
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandLine {
+    pub program: String,
+    pub args: Vec<String>,
+    /// Cargo/rustc related environment variables, with secrets scrubbed
+    pub env: HashMap<String, String>,
+    pub working_dir: Option<String>,
+}
+
+/// A range of source code, with 1-based lines and columns; the end is exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceRange {
+    pub line_start: usize,
+    pub column_start: usize,
+    pub line_end: usize,
+    pub column_end: usize,
+}
+
+/// What kind of item a `Symbol` is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolKind {
+    Function,
+    Struct,
+    Enum,
+    Const,
+}
+
+/// An item defined in a snippet, as listed by `RustCompiler::list_symbols`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The item's header as written, e.g. `fn area(w: f32, h: f32) -> f32`
+    /// or `struct Cell<T>`
+    pub signature: String,
+    /// The whole item, with 1-based lines and columns and an exclusive end
+    pub span: SourceRange,
+}
+
+/// One location a diagnostic refers to, with 1-based lines and columns
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticSpan {
+    pub file: String,
+    pub line_start: usize,
+    pub column_start: usize,
+    /// Exclusive, like `column_end`
+    pub line_end: usize,
+    pub column_end: usize,
+    pub byte_start: usize,
+    pub byte_end: usize,
+    /// Whether this is the main location of the diagnostic
+    pub is_primary: bool,
+    /// Text shown at the location, e.g. "value moved here"
+    pub label: Option<String>,
 }
 
 /// Individual compilation error or warning
@@ -25,8 +552,151 @@ pub struct CompilationError {
     pub code: Option<String>,
     pub line: Option<usize>,
     pub column: Option<usize>,
+    /// End of the highlighted code (exclusive), 1-based like `line` and `column`
+    #[serde(default)]
+    pub line_end: Option<usize>,
+    #[serde(default)]
+    pub column_end: Option<usize>,
+    /// Byte offsets of the highlighted code in `file`
+    #[serde(default)]
+    pub byte_start: Option<usize>,
+    #[serde(default)]
+    pub byte_end: Option<usize>,
     pub file: Option<String>,
+    /// Every location the diagnostic refers to; `line`, `column` and the other
+    /// position fields describe the primary one
+    #[serde(default)]
+    pub spans: Vec<DiagnosticSpan>,
+    /// Replacement text suggested by the compiler, e.g. `_x` for an unused `x`
     pub suggestion: Option<String>,
+    /// The code `suggestion` replaces
+    #[serde(default)]
+    pub suggestion_range: Option<SourceRange>,
+    /// Additional long-form notes
+    #[serde(default)]
+    pub notes: Vec<String>,
+    /// Notes and help attached to this diagnostic, to render under it
+    #[serde(default)]
+    pub related: Vec<CompilationError>,
+    /// `rustc --explain` text for `code`, when explanations were requested
+    #[serde(default)]
+    pub explanation: Option<String>,
+    /// A plain-language explanation for beginners, when diagnostics were
+    /// simplified (see `simplify_diagnostics`)
+    #[serde(default)]
+    pub friendly_message: Option<String>,
+    /// Documentation for `code`: its page in the error code index, or the
+    /// lint's entry in the rustc or Clippy lint listing
+    #[serde(default)]
+    pub docs_url: Option<String>,
+    /// The group of the lint in `code` that reported this, for diagnostics
+    /// from lints rather than hard errors
+    #[serde(default)]
+    pub lint_group: Option<LintGroup>,
+    /// What kind of failure this is, for errors raised outside rustc's own
+    /// type and borrow checking
+    #[serde(default)]
+    pub category: Option<ErrorCategory>,
+    /// A well-known crate the code uses without declaring it, e.g. `rand` for
+    /// an unresolved `use rand::Rng`
+    #[serde(default)]
+    pub missing_crate: Option<MissingCrate>,
+    /// The workspace member the diagnostic is in, for `check_workspace`
+    #[serde(default)]
+    pub crate_name: Option<String>,
+}
+
+/// A dependency to add so an unresolved import or path compiles
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MissingCrate {
+    pub name: String,
+    pub version: String,
+}
+
+impl MissingCrate {
+    /// The `Cargo.toml` line declaring the crate, e.g. `rand = "0.8"`
+    pub fn manifest_entry(&self) -> String {
+        format!("{} = \"{}\"", self.name, self.version)
+    }
+}
+
+/// A member of a workspace checked by `RustCompiler::check_workspace`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceCrate {
+    /// Directory of the crate in the workspace, used to tag its diagnostics
+    pub name: String,
+    /// The crate's `Cargo.toml`; other members are path dependencies like
+    /// `genome = { path = "../genome" }`
+    pub manifest: String,
+    /// Source files by path relative to the crate, e.g. `src/lib.rs`
+    pub files: HashMap<PathBuf, String>,
+}
+
+impl CompilationError {
+    /// A diagnostic with just a level and message, and no location
+    pub fn new(level: ErrorLevel, message: impl Into<String>) -> Self {
+        Self {
+            level,
+            message: message.into(),
+            code: None,
+            line: None,
+            column: None,
+            line_end: None,
+            column_end: None,
+            byte_start: None,
+            byte_end: None,
+            file: None,
+            spans: Vec::new(),
+            suggestion: None,
+            suggestion_range: None,
+            notes: Vec::new(),
+            related: Vec::new(),
+            explanation: None,
+            friendly_message: None,
+            docs_url: None,
+            lint_group: None,
+            category: None,
+            missing_crate: None,
+            crate_name: None,
+        }
+    }
+}
+
+/// Broad category of an error
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorCategory {
+    /// Dependencies couldn't be resolved or were rejected
+    Dependency,
+    /// The program compiled but couldn't be linked, e.g. a missing system library
+    Link,
+    /// Reported while the program ran, e.g. by a sanitizer
+    Runtime,
+    /// The check took longer than the configured timeout
+    Timeout,
+    /// The project's build script failed to compile or exited with an error
+    BuildScript,
+    /// The compiler was killed, e.g. for exceeding its memory or CPU time limit
+    ResourceLimit,
+    /// The code uses an API the deny list forbids; see `DenyList`
+    DeniedApi,
+}
+
+/// What a lint warns about, from its group in rustc or Clippy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintGroup {
+    /// Naming and formatting conventions, e.g. `non_snake_case` or
+    /// `clippy::needless_return`
+    Style,
+    /// Code that's probably wrong, e.g. `unconditional_recursion`
+    Correctness,
+    /// Code that's never used, e.g. `unused_variables` or `dead_code`
+    Unused,
+    /// Uses of deprecated items or syntax
+    Deprecated,
+    /// Any other lint
+    Other,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,299 +708,8227 @@ pub enum ErrorLevel {
     Help,
 }
 
-/// Rust compiler checker
-pub struct RustCompiler {
-    temp_dir: PathBuf,
+/// Runtime sanitizer to instrument the program with (nightly only)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sanitizer {
+    Address,
+    Thread,
+    Leak,
 }
 
-impl RustCompiler {
-    /// Create a new Rust compiler checker
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let temp_dir = std::env::temp_dir().join("blockly_rust_check");
-        fs::create_dir_all(&temp_dir)?;
-        
-        Ok(Self { temp_dir })
+impl Sanitizer {
+    /// Name as passed to `-Zsanitizer=`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Sanitizer::Address => "address",
+            Sanitizer::Thread => "thread",
+            Sanitizer::Leak => "leak",
+        }
     }
+}
 
-    /// Check Rust code for compilation errors
-    /// 
-    /// This creates a temporary Rust project and runs `cargo check` to validate the code
-    pub fn check_code(&self, code: &str) -> Result<CompilationResult, Box<dyn std::error::Error>> {
-        // Create a temporary Cargo project
-        let project_dir = self.temp_dir.join(format!("check_{}", uuid::Uuid::new_v4()));
-        fs::create_dir_all(&project_dir)?;
+/// Level a lint is reported at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+    Forbid,
+}
 
-        // Create Cargo.toml
-        let cargo_toml = r#"[package]
-name = "blockly_check"
-version = "0.1.0"
-edition = "2021"
+impl LintLevel {
+    /// Name as written in `Cargo.toml` and lint attributes
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LintLevel::Allow => "allow",
+            LintLevel::Warn => "warn",
+            LintLevel::Deny => "deny",
+            LintLevel::Forbid => "forbid",
+        }
+    }
+}
 
-[dependencies]
-"#;
-        fs::write(project_dir.join("Cargo.toml"), cargo_toml)?;
+/// Named set of lint levels for `CheckOptions::lints`, so frontends can pick
+/// a level of strictness instead of listing lints
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintPreset {
+    /// Silences unused code, which is common while a program is being built up
+    Beginner,
+    /// The compiler's own defaults
+    #[default]
+    Standard,
+    /// Adds pedantic clippy lints (when checking with clippy) and idiom lints
+    Strict,
+}
 
-        // Create src directory and main.rs
-        let src_dir = project_dir.join("src");
-        fs::create_dir_all(&src_dir)?;
-        
-        // Wrap code in a main function if it doesn't have one
-        let wrapped_code = if !code.contains("fn main") {
-            format!("fn main() {{\n{}\n}}", code)
-        } else {
-            code.to_string()
+impl LintPreset {
+    /// The lint levels this preset sets
+    pub fn lints(&self) -> BTreeMap<String, LintLevel> {
+        let lints: &[(&str, LintLevel)] = match self {
+            LintPreset::Beginner => {
+                &[("dead_code", LintLevel::Allow), ("unused", LintLevel::Allow)]
+            }
+            LintPreset::Standard => &[],
+            LintPreset::Strict => &[
+                ("clippy::pedantic", LintLevel::Warn),
+                ("rust_2018_idioms", LintLevel::Warn),
+                ("unused_qualifications", LintLevel::Warn),
+            ],
         };
-        
-        fs::write(src_dir.join("main.rs"), wrapped_code)?;
+        lints.iter().map(|&(name, level)| (name.to_string(), level)).collect()
+    }
+}
 
-        // Run cargo check with JSON output
-        let output = Command::new("cargo")
-            .arg("check")
-            .arg("--message-format=json")
-            .current_dir(&project_dir)
-            .output()?;
+/// Rust edition of the checked project
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Edition {
+    #[serde(rename = "2015")]
+    E2015,
+    #[serde(rename = "2018")]
+    E2018,
+    #[default]
+    #[serde(rename = "2021")]
+    E2021,
+    #[serde(rename = "2024")]
+    E2024,
+}
 
-        // Parse the output
-        let result = self.parse_cargo_output(&output.stdout, &output.stderr)?;
+impl Edition {
+    /// Name as written in `Cargo.toml`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Edition::E2015 => "2015",
+            Edition::E2018 => "2018",
+            Edition::E2021 => "2021",
+            Edition::E2024 => "2024",
+        }
+    }
+}
 
-        // Clean up temporary directory
-        let _ = fs::remove_dir_all(&project_dir);
+impl std::str::FromStr for Edition {
+    type Err = String;
 
-        Ok(result)
+    fn from_str(edition: &str) -> Result<Self, Self::Err> {
+        match edition {
+            "2015" => Ok(Edition::E2015),
+            "2018" => Ok(Edition::E2018),
+            "2021" => Ok(Edition::E2021),
+            "2024" => Ok(Edition::E2024),
+            _ => Err(format!(
+                "Unknown edition `{}`; expected 2015, 2018, 2021 or 2024",
+                edition
+            )),
+        }
     }
+}
 
-    /// Check Rust code with custom dependencies
-    pub fn check_code_with_deps(
-        &self,
-        code: &str,
-        dependencies: &[(&str, &str)],
-    ) -> Result<CompilationResult, Box<dyn std::error::Error>> {
-        // Create a temporary Cargo project
-        let project_dir = self.temp_dir.join(format!("check_{}", uuid::Uuid::new_v4()));
-        fs::create_dir_all(&project_dir)?;
+/// Kind of a public API item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiItemKind {
+    Function,
+    Struct,
+    Enum,
+    Trait,
+    TypeAlias,
+    Constant,
+    Static,
+}
 
-        // Create Cargo.toml with dependencies
-        let mut cargo_toml = String::from(
-            r#"[package]
-name = "blockly_check"
-version = "0.1.0"
-edition = "2021"
+/// A public item of a crate, as reported by `RustCompiler::public_api`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiItem {
+    pub kind: ApiItemKind,
+    /// Path within the crate, e.g. `shapes::Cell::new`
+    pub path: String,
+    /// Declaration as written in the source, without the body
+    pub signature: String,
+    pub line: Option<usize>,
+}
 
-[dependencies]
-"#,
-        );
+/// A crate dependency of a check, written to its `Cargo.toml`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Dependency {
+    pub name: String,
+    /// Version requirement, e.g. `1.0` or `^0.8`; may be empty for git and
+    /// path dependencies
+    #[serde(default)]
+    pub version: String,
+    /// Git repository to fetch the crate from instead of crates.io
+    #[serde(default)]
+    pub git: Option<String>,
+    /// Branch of `git` to use
+    #[serde(default)]
+    pub branch: Option<String>,
+    /// Commit of `git` to use
+    #[serde(default)]
+    pub rev: Option<String>,
+    /// Absolute path of a local crate; rejected unless path dependencies are
+    /// allowed with `RustCompiler::set_allow_path_dependencies`
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Crate features to enable, e.g. `derive` for serde
+    #[serde(default)]
+    pub features: Vec<String>,
+    #[serde(default = "default_features")]
+    pub default_features: bool,
+    #[serde(default)]
+    pub optional: bool,
+}
+
+fn default_features() -> bool {
+    true
+}
 
-        for (name, version) in dependencies {
-            cargo_toml.push_str(&format!("{} = \"{}\"\n", name, version));
+impl Dependency {
+    /// A dependency on `version` of `name` with its default features
+    pub fn new(name: impl Into<String>, version: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            version: version.into(),
+            git: None,
+            branch: None,
+            rev: None,
+            path: None,
+            features: Vec::new(),
+            default_features: true,
+            optional: false,
         }
+    }
 
-        fs::write(project_dir.join("Cargo.toml"), cargo_toml)?;
+    /// A dependency on the crate in the git repository at `url`, from its
+    /// default branch unless `branch` or `rev` is set
+    pub fn git(name: impl Into<String>, url: impl Into<String>) -> Self {
+        Self {
+            git: Some(url.into()),
+            ..Self::new(name, "")
+        }
+    }
 
-        // Create src directory and main.rs
-        let src_dir = project_dir.join("src");
-        fs::create_dir_all(&src_dir)?;
-        fs::write(src_dir.join("main.rs"), code)?;
+    /// `syn` (with full syntax support), `quote` and `proc-macro2`, the usual
+    /// dependencies of a `CrateType::ProcMacro` crate
+    pub fn proc_macro_presets() -> Vec<Dependency> {
+        vec![
+            Dependency::new("syn", "2").with_features(["full"]),
+            Dependency::new("quote", "1"),
+            Dependency::new("proc-macro2", "1"),
+        ]
+    }
 
-        // Run cargo check with JSON output
-        let output = Command::new("cargo")
-            .arg("check")
-            .arg("--message-format=json")
-            .current_dir(&project_dir)
-            .output()?;
+    /// A dependency on the local crate at `path`
+    pub fn path(name: impl Into<String>, path: impl Into<String>) -> Self {
+        Self {
+            path: Some(path.into()),
+            ..Self::new(name, "")
+        }
+    }
 
-        // Parse the output
-        let result = self.parse_cargo_output(&output.stdout, &output.stderr)?;
+    /// Enable `features` of the crate
+    pub fn with_features<S: Into<String>>(mut self, features: impl IntoIterator<Item = S>) -> Self {
+        self.features.extend(features.into_iter().map(Into::into));
+        self
+    }
 
-        // Clean up temporary directory
-        let _ = fs::remove_dir_all(&project_dir);
+    /// The `[dependencies]` line for this crate, in the short `name = "version"`
+    /// form when nothing else is set and as an inline table otherwise
+    pub fn manifest_entry(&self) -> String {
+        let registry = self.git.is_none() && self.path.is_none();
+        if registry && self.features.is_empty() && self.default_features && !self.optional {
+            return format!("{} = {}", self.name, toml_string(&self.version));
+        }
 
-        Ok(result)
+        let mut fields = Vec::new();
+        if !self.version.is_empty() {
+            fields.push(format!("version = {}", toml_string(&self.version)));
+        }
+        let sources = [
+            ("git", &self.git),
+            ("branch", &self.branch),
+            ("rev", &self.rev),
+            ("path", &self.path),
+        ];
+        for (key, value) in sources {
+            if let Some(value) = value {
+                fields.push(format!("{} = {}", key, toml_string(value)));
+            }
+        }
+        if !self.features.is_empty() {
+            let features: Vec<String> = self.features.iter().map(|f| toml_string(f)).collect();
+            fields.push(format!("features = [{}]", features.join(", ")));
+        }
+        if !self.default_features {
+            fields.push("default-features = false".to_string());
+        }
+        if self.optional {
+            fields.push("optional = true".to_string());
+        }
+        format!("{} = {{ {} }}", self.name, fields.join(", "))
     }
+}
 
-    /// Quick syntax check without full compilation
-    /// Uses rustc directly for faster feedback
-    pub fn quick_check(&self, code: &str) -> Result<CompilationResult, Box<dyn std::error::Error>> {
-        // Create temporary file
-        let temp_file = self.temp_dir.join(format!("check_{}.rs", uuid::Uuid::new_v4()));
-        fs::write(&temp_file, code)?;
-
-        // Run rustc with JSON output
-        let output = Command::new("rustc")
-            .arg("--crate-type=lib")
-            .arg("--error-format=json")
-            .arg(&temp_file)
-            .arg("-o")
-            .arg("/dev/null") // Don't create output file
-            .output()?;
+impl From<(&str, &str)> for Dependency {
+    fn from((name, version): (&str, &str)) -> Self {
+        Self::new(name, version)
+    }
+}
 
-        // Parse the output
-        let result = self.parse_rustc_output(&output.stdout, &output.stderr)?;
+impl From<(String, String)> for Dependency {
+    fn from((name, version): (String, String)) -> Self {
+        Self::new(name, version)
+    }
+}
 
-        // Clean up
-        let _ = fs::remove_file(&temp_file);
+/// Cargo profile to compile with, and settings overriding it
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BuildProfile {
+    /// `dev`, `release`, or the name of a custom profile
+    pub name: String,
+    /// Profile a custom profile is based on; `release` unless set
+    #[serde(default)]
+    pub inherits: Option<String>,
+    /// `opt-level`: `0` to `3`, `s` or `z`
+    #[serde(default)]
+    pub opt_level: Option<String>,
+    /// Whether to include debug info
+    #[serde(default)]
+    pub debug: Option<bool>,
+    /// `lto`: `off`, `thin`, `fat`, `true` or `false`
+    #[serde(default)]
+    pub lto: Option<String>,
+}
 
-        Ok(result)
+impl BuildProfile {
+    /// The `release` profile, unchanged
+    pub fn release() -> Self {
+        Self::custom("release")
     }
 
-    /// Parse cargo check JSON output
-    fn parse_cargo_output(
-        &self,
-        stdout: &[u8],
-        stderr: &[u8],
-    ) -> Result<CompilationResult, Box<dyn std::error::Error>> {
-        let stdout_str = String::from_utf8_lossy(stdout);
-        let stderr_str = String::from_utf8_lossy(stderr);
+    /// A profile named `name` without overrides; custom names inherit from `release`
+    pub fn custom(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            inherits: None,
+            opt_level: None,
+            debug: None,
+            lto: None,
+        }
+    }
 
-        let mut errors = Vec::new();
-        let mut warnings = Vec::new();
-
-        // Parse JSON messages from cargo
-        for line in stdout_str.lines() {
-            if let Ok(msg) = serde_json::from_str::<serde_json::Value>(line) {
-                if let Some(message) = msg.get("message") {
-                    if let Some(rendered) = message.get("rendered").and_then(|v| v.as_str()) {
-                        let level = message
-                            .get("level")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("error");
-
-                        let error = CompilationError {
-                            level: match level {
-                                "error" => ErrorLevel::Error,
-                                "warning" => ErrorLevel::Warning,
-                                "note" => ErrorLevel::Note,
-                                "help" => ErrorLevel::Help,
-                                _ => ErrorLevel::Error,
-                            },
-                            message: rendered.to_string(),
-                            code: message
-                                .get("code")
-                                .and_then(|c| c.get("code"))
-                                .and_then(|v| v.as_str())
-                                .map(String::from),
-                            line: message
-                                .get("spans")
-                                .and_then(|s| s.as_array())
-                                .and_then(|arr| arr.first())
-                                .and_then(|span| span.get("line_start"))
-                                .and_then(|v| v.as_u64())
-                                .map(|n| n as usize),
-                            column: message
-                                .get("spans")
-                                .and_then(|s| s.as_array())
-                                .and_then(|arr| arr.first())
-                                .and_then(|span| span.get("column_start"))
-                                .and_then(|v| v.as_u64())
-                                .map(|n| n as usize),
-                            file: message
-                                .get("spans")
-                                .and_then(|s| s.as_array())
-                                .and_then(|arr| arr.first())
-                                .and_then(|span| span.get("file_name"))
-                                .and_then(|v| v.as_str())
-                                .map(String::from),
-                            suggestion: None,
-                        };
-
-                        match error.level {
-                            ErrorLevel::Error => errors.push(error),
-                            ErrorLevel::Warning => warnings.push(error),
-                            _ => {}
-                        }
-                    }
-                }
+    fn is_builtin(&self) -> bool {
+        matches!(self.name.as_str(), "dev" | "release")
+    }
+
+    /// Cargo arguments selecting the profile
+    fn cargo_args(&self) -> Vec<String> {
+        match self.name.as_str() {
+            "dev" => Vec::new(),
+            "release" => vec!["--release".to_string()],
+            name => vec!["--profile".to_string(), name.to_string()],
+        }
+    }
+
+    /// The `[profile.<name>]` table for `Cargo.toml`, if the profile needs one
+    fn manifest_section(&self) -> Option<String> {
+        let mut fields = Vec::new();
+        if !self.is_builtin() {
+            let inherits = self.inherits.as_deref().unwrap_or("release");
+            fields.push(format!("inherits = {}", toml_string(inherits)));
+        }
+        if let Some(level) = &self.opt_level {
+            // Numeric levels are integers in TOML, `s` and `z` are strings
+            match level.parse::<u8>() {
+                Ok(level) => fields.push(format!("opt-level = {}", level)),
+                Err(_) => fields.push(format!("opt-level = {}", toml_string(level))),
+            }
+        }
+        if let Some(debug) = self.debug {
+            fields.push(format!("debug = {}", debug));
+        }
+        if let Some(lto) = &self.lto {
+            match lto.as_str() {
+                "true" | "false" => fields.push(format!("lto = {}", lto)),
+                lto => fields.push(format!("lto = {}", toml_string(lto))),
             }
         }
 
-        Ok(CompilationResult {
-            success: errors.is_empty(),
-            errors,
-            warnings,
-            stdout: stdout_str.to_string(),
-            stderr: stderr_str.to_string(),
-        })
+        if fields.is_empty() {
+            return None;
+        }
+        Some(format!("\n[profile.{}]\n{}\n", self.name, fields.join("\n")))
     }
+}
 
-    /// Parse rustc JSON output
-    fn parse_rustc_output(
-        &self,
-        stdout: &[u8],
-        stderr: &[u8],
-    ) -> Result<CompilationResult, Box<dyn std::error::Error>> {
-        // Similar to parse_cargo_output but for rustc
-        self.parse_cargo_output(stdout, stderr)
+/// A quoted TOML basic string
+fn toml_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+            c => quoted.push(c),
+        }
     }
+    quoted.push('"');
+    quoted
 }
 
-impl Default for RustCompiler {
-    fn default() -> Self {
-        Self::new().expect("Failed to create RustCompiler")
+/// Placeholder a `WrapMode::Template` puts the code in place of
+pub const WRAP_PLACEHOLDER: &str = "{{code}}";
+
+/// How code is made into a program before it's compiled
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WrapMode {
+    /// Wrap statements in a main function, unless the code already has one
+    #[default]
+    Auto,
+    /// Compile the code exactly as given
+    Never,
+    /// Put the code in place of `{{code}}` in a template, e.g. one with the
+    /// generator's own prelude and `main`
+    Template(String),
+}
+
+/// What kind of crate the checked code is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CrateType {
+    /// A program, wrapped in a main function as `wrap_mode` says
+    #[default]
+    Bin,
+    /// A library, checked as `src/lib.rs` without wrapping
+    Lib,
+    /// A procedural macro library, e.g. defining custom derives
+    ProcMacro,
+}
+
+impl CrateType {
+    /// The name rustc's `--crate-type` flag takes
+    pub(crate) fn rustc_name(self) -> &'static str {
+        match self {
+            CrateType::Bin => "bin",
+            CrateType::Lib => "lib",
+            CrateType::ProcMacro => "proc-macro",
+        }
     }
 }
 
-/// Check if Rust toolchain is available
-pub fn is_rust_available() -> bool {
-    Command::new("rustc")
-        .arg("--version")
-        .output()
-        .map(|output| output.status.success())
-        .unwrap_or(false)
+/// Options for a single check
+#[derive(Debug, Clone, Default)]
+pub struct CheckOptions {
+    /// Crate dependencies, written to `[dependencies]` in order
+    pub dependencies: Vec<Dependency>,
+    /// Extra files written next to `main.rs`, keyed by relative path, so that
+    /// `include_str!`/`include_bytes!` in the snippet resolve
+    pub assets: HashMap<String, String>,
+    /// Attach `rustc --explain` text to errors carrying an error code
+    pub explain: bool,
+    /// Return the exact command line used for the check in the result
+    pub include_command: bool,
+    /// Build with a sanitizer; requires the nightly toolchain
+    pub sanitizer: Option<Sanitizer>,
+    /// Run `cargo build` instead of `cargo check`, so that link errors are caught
+    pub build: bool,
+    /// Target triple to check for, such as `wasm32-unknown-unknown`, instead of
+    /// the host; its standard library must be installed
+    pub target: Option<String>,
+    /// Edition of the generated project, 2021 unless set
+    pub edition: Edition,
+    /// Rustup toolchain to check with, such as `stable`, `beta`, `nightly` or
+    /// `1.75.0`, overriding the compiler's own (see `RustCompiler::set_toolchain`)
+    pub toolchain: Option<String>,
+    /// Build the test harness (`cargo test --no-run`) instead of checking
+    pub test: bool,
+    /// Run `cargo clippy` instead of `cargo check`; lints are reported as
+    /// warnings with the lint name (e.g. `clippy::needless_return`) as `code`
+    pub clippy: bool,
+    /// `Cargo.lock` to resolve the dependencies with, so every check gets the
+    /// same versions; dependencies it doesn't cover are resolved as usual.
+    /// An exact requirement like `=0.8.5` also pins a direct dependency.
+    pub lockfile: Option<String>,
+    /// Cargo profile to compile with, `dev` unless set; use `release` to catch
+    /// problems that only show up with optimizations
+    pub profile: Option<BuildProfile>,
+    /// Extra flags passed to rustc through `CARGO_ENCODED_RUSTFLAGS`, e.g.
+    /// `--cfg` or `-Ctarget-cpu=native`; a flag may contain spaces
+    pub rustflags: Vec<String>,
+    /// Environment variables for cargo, the compiler (`env!` reads them) and
+    /// programs and tests run from the build, e.g. `RUST_BACKTRACE`
+    pub env: BTreeMap<String, String>,
+    /// Check the code as a `#![no_std]` library instead of a program, adding a
+    /// panic handler stub unless the code defines one; the code should consist
+    /// of items, since it isn't wrapped in a main function
+    pub no_std: bool,
+    /// Lint levels by lint name, e.g. `unused_variables` or
+    /// `clippy::needless_return`, written to the `[lints]` table of the project
+    pub lints: BTreeMap<String, LintLevel>,
+    /// Fail the check if there are any warnings; they're still reported as
+    /// warnings
+    pub treat_warnings_as_errors: bool,
+    /// Add `#![forbid(unsafe_code)]` to the crate root, so unsafe code fails
+    /// the check with the compiler's `unsafe_code` errors
+    pub forbid_unsafe: bool,
+    /// How the code is wrapped into a program; ignored for `no_std` checks
+    pub wrap_mode: WrapMode,
+    /// A complete `Cargo.toml` to use instead of the generated one, for a
+    /// package named `blockly_check`; `dependencies`, `edition`, `profile`,
+    /// `lints` and `manifest_extra` are ignored then
+    pub manifest: Option<String>,
+    /// TOML appended to the generated `Cargo.toml`, e.g. `[patch.crates-io]`
+    /// entries or a `[features]` table
+    pub manifest_extra: String,
+    /// A `build.rs` for the project, run before the code is compiled; needs
+    /// `RustCompiler::set_allow_build_scripts`
+    pub build_script: Option<String>,
+    /// Dependencies of the build script, written to `[build-dependencies]`
+    pub build_dependencies: Vec<Dependency>,
+    /// Kind of crate to check the code as; see `Dependency::proc_macro_presets`
+    /// for proc macros
+    pub crate_type: CrateType,
+    /// Token aborting the check (or run) once cancelled, killing cargo or the
+    /// program; the call then fails with `CompilerError::Cancelled`
+    pub cancel: Option<CancellationToken>,
+    /// Observer told how the check progresses, e.g. to show which crate is
+    /// being compiled
+    pub progress: Option<Arc<dyn ProgressObserver>>,
 }
 
-/// Check if Cargo is available
-pub fn is_cargo_available() -> bool {
-    Command::new("cargo")
-        .arg("--version")
-        .output()
-        .map(|output| output.status.success())
-        .unwrap_or(false)
+impl CheckOptions {
+    /// Whether the code is checked as a library, in `src/lib.rs`
+    fn is_library(&self) -> bool {
+        self.no_std || self.crate_type != CrateType::Bin
+    }
+
+    /// Dependencies of the code and of its build script
+    pub(crate) fn all_dependencies(&self) -> impl Iterator<Item = &Dependency> {
+        self.dependencies.iter().chain(&self.build_dependencies)
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Handle for cancelling an in-flight check from another thread
+///
+/// Clones share the same state, so the editor keeps one clone and puts the
+/// other in `CheckOptions::cancel`.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
 
-    #[test]
-    fn test_rust_available() {
-        // This test will pass if Rust is installed
-        let available = is_rust_available();
-        println!("Rust available: {}", available);
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    #[test]
-    fn test_valid_code() {
-        if !is_cargo_available() {
-            println!("Skipping test: cargo not available");
-            return;
-        }
+    /// Cancel every check holding a clone of this token
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
 
-        let compiler = RustCompiler::new().unwrap();
-        let code = r#"
-            fn add(a: i32, b: i32) -> i32 {
-                a + b
-            }
-        "#;
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
 
-        let result = compiler.check_code(code).unwrap();
-        assert!(result.success, "Valid code should compile");
+/// A step of a check, as reported to a `ProgressObserver`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckPhase {
+    /// Resolving and fetching the check's dependencies
+    DownloadingDependencies,
+    /// A crate finished compiling; `current` counts the crates compiled so
+    /// far, out of `total` if the resolved dependency graph is known
+    Compiling {
+        crate_name: String,
+        current: usize,
+        total: Option<usize>,
+    },
+    /// The check is done, whether or not it succeeded
+    Finished,
+}
+
+/// Receives progress updates for a check; see `CheckOptions::progress`
+///
+/// Updates come from the thread reading cargo's output, so implementations
+/// should return quickly.
+pub trait ProgressObserver: Send + Sync {
+    fn on_phase(&self, phase: &CheckPhase);
+}
+
+impl std::fmt::Debug for dyn ProgressObserver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProgressObserver")
     }
+}
 
-    #[test]
-    fn test_invalid_code() {
-        if !is_cargo_available() {
-            println!("Skipping test: cargo not available");
+/// Tell the check's observer, if any, about `phase`
+fn report_phase(options: &CheckOptions, phase: CheckPhase) {
+    if let Some(progress) = &options.progress {
+        progress.on_phase(&phase);
+    }
+}
+
+/// Handles each line a command writes to stdout as it's written
+type LineHandler = Box<dyn FnMut(&str) + Send>;
+
+/// A stdout handler reporting a `CheckPhase::Compiling` for every crate cargo
+/// finishes, out of `total`
+fn compiling_progress(progress: Arc<dyn ProgressObserver>, total: Option<usize>) -> LineHandler {
+    let mut compiled = HashSet::new();
+    Box::new(move |line| {
+        let Ok(message) = serde_json::from_str::<serde_json::Value>(line) else {
+            return;
+        };
+        let build_script = message["target"]["kind"]
+            .as_array()
+            .is_some_and(|kinds| kinds.iter().any(|kind| kind == "custom-build"));
+        if message["reason"] != "compiler-artifact" || build_script {
             return;
         }
-
-        let compiler = RustCompiler::new().unwrap();
-        let code = r#"
+        let (Some(package), Some(name)) =
+            (message["package_id"].as_str(), message["target"]["name"].as_str())
+        else {
+            return;
+        };
+        if compiled.insert(package.to_string()) {
+            progress.on_phase(&CheckPhase::Compiling {
+                crate_name: name.to_string(),
+                current: compiled.len(),
+                total,
+            });
+        }
+    })
+}
+
+/// How many crates a check will compile, from the project's lockfile
+fn crates_to_compile(project_dir: &Path, options: &CheckOptions) -> Option<usize> {
+    match fs::read_to_string(project_dir.join("Cargo.lock")) {
+        Ok(lockfile) => Some(lockfile.lines().filter(|line| *line == "[[package]]").count()),
+        Err(_) => options.all_dependencies().next().is_none().then_some(1),
+    }
+}
+
+/// Whether `cancel` is set and has been cancelled
+fn is_cancelled(cancel: Option<&CancellationToken>) -> bool {
+    cancel.is_some_and(CancellationToken::is_cancelled)
+}
+
+/// Default limit on how long a program started by `run_code` may run
+pub const DEFAULT_RUN_TIME_LIMIT: Duration = Duration::from_secs(5);
+
+/// Bytes of stdout and of stderr kept from each process; the rest is dropped
+/// and a note saying so is appended
+pub const MAX_CAPTURED_OUTPUT_BYTES: usize = 4 * 1024 * 1024;
+
+/// Target `RunMode::Wasi` compiles for
+pub const WASI_TARGET: &str = "wasm32-wasip1";
+
+/// How `run_code` executes the program
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunMode {
+    /// Build for the host and run the executable directly
+    #[default]
+    Native,
+    /// Build for `wasm32-wasip1` and run under wasmtime, without access to
+    /// the host's files, network or environment
+    Wasi,
+}
+
+/// Options for running a program
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// Options for building the program
+    pub check: CheckOptions,
+    /// The program is killed if it runs longer than this
+    pub time_limit: Duration,
+    /// Input piped to the program's stdin; without it stdin is empty
+    pub stdin: Option<String>,
+    /// How the program is executed
+    pub mode: RunMode,
+    /// Fuel a `RunMode::Wasi` program may use before it's stopped, roughly one
+    /// unit per WebAssembly instruction; `None` leaves only the time limit
+    pub fuel: Option<u64>,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            check: CheckOptions::default(),
+            time_limit: DEFAULT_RUN_TIME_LIMIT,
+            stdin: None,
+            mode: RunMode::Native,
+            fuel: None,
+        }
+    }
+}
+
+/// Outcome of building and running a program
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunResult {
+    /// Diagnostics from building the program; it only ran if this succeeded
+    pub compilation: CompilationResult,
+    /// Exit code, or `None` if the program didn't run or was killed by a signal
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    /// Wall-clock time the program ran, in milliseconds
+    pub duration_ms: u64,
+    /// The program was killed for exceeding its time limit
+    pub timed_out: bool,
+    /// The program was stopped for using up its fuel
+    #[serde(default)]
+    pub out_of_fuel: bool,
+}
+
+impl RunResult {
+    /// Result for a program that failed to build and never ran
+    pub(crate) fn not_run(compilation: CompilationResult) -> Self {
+        Self {
+            compilation,
+            exit_code: None,
+            stdout: String::new(),
+            stderr: String::new(),
+            duration_ms: 0,
+            timed_out: false,
+            out_of_fuel: false,
+        }
+    }
+}
+
+/// Outcome of a single test
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestOutcome {
+    Passed,
+    Failed,
+    Ignored,
+}
+
+/// A test run by `run_tests`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestCase {
+    /// Path of the test function, e.g. `tests::adds_numbers`
+    pub name: String,
+    pub outcome: TestOutcome,
+    /// Output captured while the test ran, including the panic message of a
+    /// failed test
+    pub stdout: String,
+    pub duration_ms: Option<u64>,
+}
+
+/// Outcome of building and running a program's tests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestRunResult {
+    /// Diagnostics from building the tests; they only ran if this succeeded
+    pub compilation: CompilationResult,
+    /// Tests that finished, in the order they finished
+    pub tests: Vec<TestCase>,
+    /// Wall-clock time the tests ran, in milliseconds
+    pub duration_ms: u64,
+    /// The test run was killed for exceeding its time limit
+    pub timed_out: bool,
+}
+
+/// Intermediate representation or generated code returned by `emit_ir`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IrKind {
+    /// High-level IR, after macro expansion and name resolution (nightly only)
+    Hir,
+    /// Mid-level IR, the control-flow graph borrow checking runs on
+    Mir,
+    /// LLVM IR handed to the code generator
+    LlvmIr,
+    /// Assembly for the host target
+    Asm,
+}
+
+/// How `emit_text` gets its output from rustc
+#[derive(Debug, Clone, Copy)]
+enum Emit {
+    /// `-Zunpretty=<mode>`, printed to stdout
+    Unpretty(&'static str),
+    /// `--emit=<kind>`, written to a file
+    File(&'static str),
+}
+
+/// Target `build_wasm` compiles for
+pub const WASM_TARGET: &str = "wasm32-unknown-unknown";
+
+/// Outcome of compiling a program to WebAssembly
+#[derive(Debug, Clone)]
+pub struct WasmBuild {
+    /// Diagnostics from the build
+    pub compilation: CompilationResult,
+    /// The `.wasm` module, if the build succeeded
+    pub wasm: Option<Vec<u8>>,
+}
+
+/// A custom check run on the submitted code alongside the compiler
+///
+/// Diagnostics returned by registered linters are merged into each check's
+/// result according to their level.
+pub trait Linter: Send + Sync {
+    fn lint(&self, code: &str) -> Vec<CompilationError>;
+}
+
+/// Rust compiler checker
+pub struct RustCompiler {
+    temp_dir: PathBuf,
+    explanations: Mutex<HashMap<String, Option<String>>>,
+    linters: Vec<Box<dyn Linter>>,
+    slow_check_threshold: Duration,
+    max_dependencies: Option<usize>,
+    pool: Option<Arc<ProjectPool>>,
+    timeout: Option<Duration>,
+    shared_target_dir: Option<PathBuf>,
+    cargo_home: Option<PathBuf>,
+    cache: Option<Mutex<ResultCache>>,
+    format_edition: Edition,
+    toolchain: Option<String>,
+    audit_dependencies: bool,
+    allow_path_dependencies: bool,
+    allow_build_scripts: bool,
+    nightly_for_feature_gates: bool,
+    keep_projects: bool,
+    resource_limits: ResourceLimits,
+    sandbox: Option<Sandbox>,
+    container: Option<ContainerConfig>,
+    deny_list: Option<DenyList>,
+    allowed_toolchains: Option<Vec<String>>,
+    /// Toolchains found to be installed, so they're only looked up once
+    installed_toolchains: Mutex<HashSet<String>>,
+    /// Targets found to be installed, with the toolchain they were looked up for
+    installed_targets: Mutex<HashSet<(Option<String>, String)>>,
+    /// Whether the default toolchain is a nightly one, once it's been looked up
+    default_nightly: OnceLock<bool>,
+}
+
+impl RustCompiler {
+    /// Create a new Rust compiler checker
+    pub fn new() -> Result<Self, CompilerError> {
+        Self::with_temp_dir(default_temp_dir())
+    }
+
+    /// Configure a checker option by option, e.g.
+    /// `RustCompiler::builder().timeout(Duration::from_secs(30)).build()`
+    pub fn builder() -> RustCompilerBuilder {
+        RustCompilerBuilder::default()
+    }
+
+    /// Create a checker that keeps its temporary projects under `temp_dir`
+    pub fn with_temp_dir(temp_dir: impl Into<PathBuf>) -> Result<Self, CompilerError> {
+        let temp_dir = temp_dir.into();
+        fs::create_dir_all(&temp_dir)?;
+
+        Ok(Self {
+            temp_dir,
+            explanations: Mutex::new(HashMap::new()),
+            linters: Vec::new(),
+            slow_check_threshold: DEFAULT_SLOW_CHECK_THRESHOLD,
+            max_dependencies: None,
+            pool: None,
+            timeout: None,
+            shared_target_dir: None,
+            cargo_home: None,
+            cache: None,
+            format_edition: DEFAULT_FORMAT_EDITION,
+            toolchain: None,
+            audit_dependencies: false,
+            allow_path_dependencies: false,
+            allow_build_scripts: false,
+            nightly_for_feature_gates: false,
+            keep_projects: false,
+            resource_limits: ResourceLimits::default(),
+            sandbox: None,
+            container: None,
+            deny_list: None,
+            allowed_toolchains: None,
+            installed_toolchains: Mutex::new(HashSet::new()),
+            installed_targets: Mutex::new(HashSet::new()),
+            default_nightly: OnceLock::new(),
+        })
+    }
+
+    /// Set how long a check may take before the result notes that the code is slow to compile
+    pub fn set_slow_check_threshold(&mut self, threshold: Duration) {
+        self.slow_check_threshold = threshold;
+    }
+
+    /// Kill the compiler if a check takes longer than `timeout`
+    ///
+    /// Timed out checks return a result with `timed_out` set and a single
+    /// `ErrorCategory::Timeout` error. `None` (the default) waits indefinitely.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Build every check into one shared target directory
+    ///
+    /// Dependencies are then compiled once per version and feature set and
+    /// reused by later checks, at the cost of cargo serializing checks that
+    /// build at the same time. `None` (the default) gives each project its own.
+    ///
+    /// Checks in a sandbox or container don't use it and build in their own
+    /// project instead, since one check's build script could otherwise change
+    /// artifacts that later checks link against.
+    pub fn set_shared_target_dir(&mut self, dir: Option<PathBuf>) {
+        self.shared_target_dir = dir;
+    }
+
+    /// Use `dir` as `CARGO_HOME`, so downloaded crates and the registry index
+    /// are kept apart from the user's own cargo cache
+    pub fn set_cargo_home(&mut self, dir: Option<PathBuf>) {
+        self.cargo_home = dir;
+    }
+
+    /// Run cargo and rustc with this rustup toolchain, e.g. `beta` or `1.75.0`
+    ///
+    /// Checks can override it with `CheckOptions::toolchain`. `None` (the
+    /// default) uses whatever toolchain is active.
+    pub fn set_toolchain(&mut self, toolchain: Option<String>) {
+        self.toolchain = toolchain;
+    }
+
+    /// Only check with these toolchains, e.g. `["stable", "nightly"]`
+    ///
+    /// `CheckOptions::toolchain` is otherwise passed to rustup, which could be
+    /// asked for any toolchain. `None` (the default) accepts any installed
+    /// toolchain; rustup is never allowed to install one.
+    pub fn set_allowed_toolchains(&mut self, toolchains: Option<Vec<String>>) {
+        self.allowed_toolchains = toolchains;
+    }
+
+    /// The toolchain a check runs with; sanitizers always need nightly
+    fn toolchain<'a>(&'a self, options: &'a CheckOptions) -> Option<&'a str> {
+        if options.sanitizer.is_some() {
+            return Some("nightly");
+        }
+        options.toolchain.as_deref().or(self.toolchain.as_deref())
+    }
+
+    /// Check code with `#![feature(...)]` attributes on the nightly toolchain
+    /// when it would otherwise run on stable or beta
+    ///
+    /// Off by default; such code then fails with a single error saying it
+    /// needs nightly, rather than the compiler's errors about each feature.
+    pub fn set_nightly_for_feature_gates(&mut self, enabled: bool) {
+        self.nightly_for_feature_gates = enabled;
+    }
+
+    /// The options to check `code` with: switched to nightly if it enables
+    /// unstable features and that's configured, or a failed result saying it
+    /// needs nightly
+    fn feature_gate_options<'a>(
+        &self,
+        code: &str,
+        options: &'a CheckOptions,
+    ) -> Result<Cow<'a, CheckOptions>, Box<CompilationResult>> {
+        let gates = feature_gates(code);
+        let Some(&(line, _)) = gates.first() else {
+            return Ok(Cow::Borrowed(options));
+        };
+        let on_nightly = match self.toolchain(options) {
+            Some(toolchain) => toolchain.starts_with("nightly"),
+            None => *self.default_nightly.get_or_init(is_default_toolchain_nightly),
+        };
+        if on_nightly {
+            return Ok(Cow::Borrowed(options));
+        }
+
+        if self.nightly_for_feature_gates && self.toolchain_installed("nightly") {
+            let mut options = options.clone();
+            options.toolchain = Some("nightly".to_string());
+            return Ok(Cow::Owned(options));
+        }
+
+        let features: Vec<String> =
+            gates.iter().flat_map(|(_, names)| names).map(|name| format!("`{}`", name)).collect();
+        let error = CompilationError {
+            line: Some(line),
+            column: Some(1),
+            ..CompilationError::new(
+                ErrorLevel::Error,
+                format!(
+                    "This code enables unstable features ({}), which require the nightly \
+                     toolchain. Install it with `rustup toolchain install nightly`.",
+                    features.join(", ")
+                ),
+            )
+        };
+        Err(Box::new(failed_result(error)))
+    }
+
+    /// A failed result if `toolchain` isn't a valid, allowed and installed toolchain
+    fn toolchain_failure(&self, toolchain: &str) -> Option<CompilationResult> {
+        if !is_valid_rustup_name(toolchain) {
+            let message = format!("Invalid toolchain name `{}`", toolchain);
+            return Some(failed_result(CompilationError::new(ErrorLevel::Error, message)));
+        }
+        if let Some(allowed) = &self.allowed_toolchains {
+            if !allowed.iter().any(|name| name == toolchain) {
+                let names: Vec<String> = allowed.iter().map(|name| format!("`{}`", name)).collect();
+                let message = format!(
+                    "The `{}` toolchain isn't available here. Use one of: {}.",
+                    toolchain,
+                    names.join(", ")
+                );
+                return Some(failed_result(CompilationError::new(ErrorLevel::Error, message)));
+            }
+        }
+
+        if self.toolchain_installed(toolchain) {
+            return None;
+        }
+
+        let message = format!(
+            "The `{0}` toolchain is not installed. Install it with `rustup toolchain install {0}`.",
+            toolchain
+        );
+        Some(failed_result(CompilationError::new(ErrorLevel::Error, message)))
+    }
+
+    /// Whether `toolchain` is installed, looking it up only until it's found
+    fn toolchain_installed(&self, toolchain: &str) -> bool {
+        if self.installed_toolchains.lock().unwrap().contains(toolchain) {
+            return true;
+        }
+        if is_toolchain_available(toolchain) {
+            self.installed_toolchains.lock().unwrap().insert(toolchain.to_string());
+            return true;
+        }
+        false
+    }
+
+    /// A failed result if `target` isn't a valid target name or its standard
+    /// library isn't installed for `toolchain`
+    fn target_failure(&self, target: &str, toolchain: Option<&str>) -> Option<CompilationResult> {
+        if !is_valid_rustup_name(target) {
+            let message = format!("Invalid target name `{}`", target);
+            return Some(failed_result(CompilationError::new(ErrorLevel::Error, message)));
+        }
+
+        let key = (toolchain.map(str::to_string), target.to_string());
+        if self.installed_targets.lock().unwrap().contains(&key) {
+            return None;
+        }
+        if target_installed(target, toolchain) {
+            self.installed_targets.lock().unwrap().insert(key);
+            return None;
+        }
+
+        let message = format!(
+            "The standard library for `{0}` is not installed. \
+             Install it with `rustup target add {0}`.",
+            target
+        );
+        Some(failed_result(CompilationError::new(ErrorLevel::Error, message)))
+    }
+
+    /// Run `cargo audit` after checks that declare dependencies, reporting
+    /// RustSec advisories and yanked releases in `CompilationResult::advisories`
+    ///
+    /// Requires `cargo install cargo-audit`; without it the result gets a note
+    /// saying so. Off by default, since fetching the advisory database is slow.
+    pub fn set_audit_dependencies(&mut self, enabled: bool) {
+        self.audit_dependencies = enabled;
+    }
+
+    /// Leave temporary projects on disk after each check instead of removing
+    /// them, to inspect what was built. Pooled projects are reused either way.
+    pub fn set_keep_projects(&mut self, keep: bool) {
+        self.keep_projects = keep;
+    }
+
+    /// Limit the memory and CPU time of cargo, rustc and programs being run,
+    /// so a pathological program can't exhaust the host
+    pub fn set_resource_limits(&mut self, limits: ResourceLimits) {
+        self.resource_limits = limits;
+    }
+
+    /// Confine cargo, rustc, build scripts and programs being run to their
+    /// project and the toolchain, without network access; see `Sandbox`
+    pub fn set_sandbox(&mut self, sandbox: Option<Sandbox>) {
+        self.sandbox = sandbox;
+    }
+
+    /// Run cargo, rustc and programs each in their own container, which takes
+    /// the place of the sandbox; see `ContainerConfig`
+    pub fn set_container(&mut self, container: Option<ContainerConfig>) {
+        self.container = container;
+    }
+
+    /// The containers processes run in, if set
+    pub fn container(&self) -> Option<&ContainerConfig> {
+        self.container.as_ref()
+    }
+
+    /// Scan code for APIs it may not use before checking or running it; uses
+    /// fail the check up front, or with `DenyList::warn_only` are added to
+    /// its warnings
+    pub fn set_deny_list(&mut self, deny_list: Option<DenyList>) {
+        self.deny_list = deny_list;
+    }
+
+    /// Set the edition `format_code` formats for
+    pub fn set_format_edition(&mut self, edition: Edition) {
+        self.format_edition = edition;
+    }
+
+    /// Cache up to `capacity` results, so checking identical code with
+    /// identical options returns the earlier result with `cached` set
+    ///
+    /// Results are only cached if they depend on nothing but the code, options
+    /// and this checker's configuration: not if the check timed out, hit a
+    /// resource limit, failed to resolve dependencies (e.g. with the network
+    /// down) or failed with an error of cargo's own. A capacity of 0 disables
+    /// the cache.
+    pub fn enable_result_cache(&mut self, capacity: usize) {
+        self.cache = (capacity > 0).then(|| Mutex::new(ResultCache::new(capacity)));
+    }
+
+    /// Look up a cached result for a check of `kind` on `code`
+    ///
+    /// Returns the cache key to store the result under, or `None` when the
+    /// cache is disabled.
+    fn cache_lookup(
+        &self,
+        kind: &str,
+        code: &str,
+        options: &CheckOptions,
+    ) -> (Option<String>, Option<CompilationResult>) {
+        let Some(cache) = &self.cache else {
+            return (None, None);
+        };
+        // A local crate can change between checks without the options changing
+        if options.all_dependencies().any(|dependency| dependency.path.is_some())
+            || has_custom_path_key(options)
+        {
+            return (None, None);
+        }
+
+        let Some(key) = self.cache_key(kind, code, options) else {
+            return (None, None);
+        };
+        let hit = cache.lock().unwrap().get(&key).map(|result| CompilationResult {
+            cached: true,
+            ..result
+        });
+        if hit.is_some() {
+            tracing::debug!(kind, "result cache hit");
+        }
+        (Some(key), hit)
+    }
+
+    fn cache_store(&self, key: Option<String>, result: &CompilationResult) {
+        if let (Some(cache), Some(key)) = (&self.cache, key) {
+            if is_cacheable(result) {
+                cache.lock().unwrap().insert(key, result.clone());
+            }
+        }
+    }
+
+    /// The result cache's key for a check of `kind` on `code` with `options`
+    fn cache_key(&self, kind: &str, code: &str, options: &CheckOptions) -> Option<String> {
+        let assets = options.assets.iter().map(|(name, text)| (name.as_str(), text.as_str()));
+        let key = CacheKey {
+            kind,
+            code,
+            dependencies: &options.dependencies,
+            assets: assets.collect(),
+            explain: options.explain,
+            include_command: options.include_command,
+            sanitizer: options.sanitizer,
+            build: options.build,
+            clippy: options.clippy,
+            test: options.test,
+            toolchain: options.toolchain.as_deref(),
+            edition: options.edition,
+            target: options.target.as_deref(),
+            lockfile: options.lockfile.as_deref(),
+            profile: options.profile.as_ref(),
+            rustflags: &options.rustflags,
+            env: &options.env,
+            no_std: options.no_std,
+            crate_type: options.crate_type,
+            lints: &options.lints,
+            treat_warnings_as_errors: options.treat_warnings_as_errors,
+            forbid_unsafe: options.forbid_unsafe,
+            wrap_mode: &options.wrap_mode,
+            manifest: options.manifest.as_deref(),
+            manifest_extra: &options.manifest_extra,
+            build_script: options.build_script.as_deref(),
+            build_dependencies: &options.build_dependencies,
+            default_toolchain: self.toolchain.as_deref(),
+            allowed_toolchains: self.allowed_toolchains.as_deref(),
+            format_edition: self.format_edition,
+            max_dependencies: self.max_dependencies,
+            deny_list: self.deny_list.as_ref(),
+            sandbox: self.sandbox.as_ref(),
+            container: self.container.as_ref(),
+            resource_limits: self.resource_limits,
+            timeout: self.timeout,
+            cargo_home: self.cargo_home.as_deref(),
+            audit_dependencies: self.audit_dependencies,
+            allow_path_dependencies: self.allow_path_dependencies,
+            allow_build_scripts: self.allow_build_scripts,
+            nightly_for_feature_gates: self.nightly_for_feature_gates,
+            linters: self.linters.len(),
+        };
+        serde_json::to_string(&key).ok()
+    }
+
+    /// Accept dependencies on local crates
+    ///
+    /// Off by default, since a path dependency lets the checked code read and
+    /// build any crate on this machine.
+    pub fn set_allow_path_dependencies(&mut self, allowed: bool) {
+        self.allow_path_dependencies = allowed;
+    }
+
+    /// Accept checks with a build script
+    ///
+    /// Off by default, since a build script runs on this machine when the
+    /// code is checked, not just when it's run.
+    pub fn set_allow_build_scripts(&mut self, allowed: bool) {
+        self.allow_build_scripts = allowed;
+    }
+
+    /// Limit how many dependencies a single check may declare
+    ///
+    /// Checks over the limit fail with an error before anything is built.
+    /// `None` (the default) allows any number.
+    pub fn set_max_dependencies(&mut self, limit: Option<usize>) {
+        self.max_dependencies = limit;
+    }
+
+    /// Run checks in a pool of reusable projects instead of a fresh project each time
+    ///
+    /// Reused projects keep their build artifacts, so repeated checks are
+    /// faster. When every pooled project is busy, checks fall back to a
+    /// temporary project.
+    pub fn enable_project_pool(
+        &mut self,
+        config: PoolConfig,
+    ) -> Result<(), CompilerError> {
+        self.pool = Some(Arc::new(ProjectPool::new(self.temp_dir.join("pool"), config)?));
+        Ok(())
+    }
+
+    /// The project pool, if enabled
+    pub fn project_pool(&self) -> Option<&Arc<ProjectPool>> {
+        self.pool.as_ref()
+    }
+
+    /// Register a custom linter whose diagnostics are added to every check
+    pub fn add_linter(&mut self, linter: impl Linter + 'static) {
+        self.linters.push(Box::new(linter));
+    }
+
+    /// Finish a check of `code` started at `started`: record its duration, note
+    /// if it was slow, and merge in diagnostics from the registered linters
+    fn finish_result(&self, code: &str, started: Instant, result: &mut CompilationResult) {
+        let elapsed = started.elapsed();
+        result.duration_ms = elapsed.as_millis() as u64;
+
+        if elapsed > self.slow_check_threshold {
+            result.notes.push(CompilationError::new(
+                ErrorLevel::Note,
+                format!(
+                    "Checking this code took {:.1}s, which is unusually slow. Heavy use of generics, \
+                     deeply nested macros or many dependencies can make compilation expensive.",
+                    elapsed.as_secs_f64()
+                ),
+            ));
+        }
+
+        let warned_apis = self.deny_list.as_ref().filter(|list| list.warn_only);
+        result.warnings.extend(warned_apis.map(|list| list.scan(code)).unwrap_or_default());
+
+        for diagnostic in self.linters.iter().flat_map(|linter| linter.lint(code)) {
+            match diagnostic.level {
+                ErrorLevel::Error => result.errors.push(diagnostic),
+                ErrorLevel::Warning => result.warnings.push(diagnostic),
+                ErrorLevel::Note => result.notes.push(diagnostic),
+                ErrorLevel::Help => result.help.push(diagnostic),
+            }
+        }
+
+        result.success = result.errors.is_empty();
+        result.lint_groups = count_lint_groups(&result.warnings);
+        result.sort_diagnostics();
+    }
+
+    /// The gates every check of `code` goes through before anything is built:
+    /// a cached result, uses of denied APIs, dependency limits and feature gates
+    ///
+    /// `kind` keeps the cached results of different kinds of check apart, and
+    /// `denied` holds the denied APIs used in the code. Fails with the result
+    /// to return as is when the check ends at a gate.
+    fn gate_check<'a>(
+        &self,
+        kind: &str,
+        code: &str,
+        denied: Vec<CompilationError>,
+        options: &'a CheckOptions,
+    ) -> Result<GatedCheck<'a>, Box<CompilationResult>> {
+        let (key, hit) = self.cache_lookup(kind, code, options);
+        let rejected = hit
+            .or_else(|| denied_result(denied))
+            .or_else(|| self.reject_dependencies(options));
+        if let Some(result) = rejected {
+            report_phase(options, CheckPhase::Finished);
+            return Err(Box::new(result));
+        }
+
+        let started = Instant::now();
+        let options = self
+            .feature_gate_options(code, options)
+            .inspect_err(|_| report_phase(options, CheckPhase::Finished))?;
+        Ok(GatedCheck { key, started, options })
+    }
+
+    /// Finish a check that passed `gate_check`: run the linters, note if it was
+    /// slow, apply `treat_warnings_as_errors` and cache the result
+    fn finish_check(
+        &self,
+        code: &str,
+        mut check: GatedCheck,
+        mut result: CompilationResult,
+    ) -> CompilationResult {
+        self.finish_result(code, check.started, &mut result);
+        if check.options.treat_warnings_as_errors {
+            result.treat_warnings_as_errors();
+        }
+        self.cache_store(check.key.take(), &result);
+        result
+    }
+
+    /// Run `check` with the options to check with, between `gate_check` and
+    /// `finish_check`
+    fn gated_check(
+        &self,
+        kind: &str,
+        code: &str,
+        denied: Vec<CompilationError>,
+        options: &CheckOptions,
+        check: impl FnOnce(&CheckOptions) -> Result<CompilationResult, CompilerError>,
+    ) -> Result<CompilationResult, CompilerError> {
+        let gated = match self.gate_check(kind, code, denied, options) {
+            Ok(gated) => gated,
+            Err(result) => return Ok(*result),
+        };
+        let result = check(&gated.options)?;
+        Ok(self.finish_check(code, gated, result))
+    }
+
+    /// Check Rust code for compilation errors
+    /// 
+    /// This creates a temporary Rust project and runs `cargo check` to validate the code
+    pub fn check_code(&self, code: &str) -> Result<CompilationResult, CompilerError> {
+        self.check_with_options(code, &CheckOptions::default())
+    }
+
+    /// Check Rust code with custom dependencies, given as `Dependency` values
+    /// or `(name, version)` pairs
+    pub fn check_code_with_deps<D: Clone + Into<Dependency>>(
+        &self,
+        code: &str,
+        dependencies: &[D],
+    ) -> Result<CompilationResult, CompilerError> {
+        let options = CheckOptions {
+            dependencies: dependencies.iter().cloned().map(Into::into).collect(),
+            ..Default::default()
+        };
+        self.gated_check("project", code, self.denied_apis(code), &options, |options| {
+            self.check_crate_root(code, options)
+        })
+    }
+
+    /// Check Rust code with the given options
+    ///
+    /// Like `check_code`, the snippet is wrapped in a main function if it doesn't have one
+    pub fn check_with_options(
+        &self,
+        code: &str,
+        options: &CheckOptions,
+    ) -> Result<CompilationResult, CompilerError> {
+        self.gated_check("snippet", code, self.denied_apis(code), options, |options| {
+            let wrapped = check_source(code, options);
+            let mut result = self.check_crate_root(&wrapped.source, options)?;
+            unwrap_positions(&mut result, &wrapped);
+            Ok(result)
+        })
+    }
+
+    /// Check several snippets concurrently, returning results in order
+    ///
+    /// Each snippet is checked in its own project, with at most one check per
+    /// available CPU running at once.
+    pub fn check_many(&self, snippets: &[&str]) -> Vec<Result<CompilationResult, CompilerError>> {
+        let parallelism = std::thread::available_parallelism().map_or(1, |n| n.get());
+        self.check_many_with_options(snippets, &CheckOptions::default(), parallelism)
+    }
+
+    /// Check several snippets with the given options, running at most
+    /// `max_parallel` checks at once
+    pub fn check_many_with_options(
+        &self,
+        snippets: &[&str],
+        options: &CheckOptions,
+        max_parallel: usize,
+    ) -> Vec<Result<CompilationResult, CompilerError>> {
+        let workers = max_parallel.clamp(1, snippets.len().max(1));
+        let next = AtomicUsize::new(0);
+        let mut results: Vec<_> = snippets.iter().map(|_| None).collect();
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut checked = Vec::new();
+                        loop {
+                            let index = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(code) = snippets.get(index) else {
+                                return checked;
+                            };
+                            checked.push((index, self.check_with_options(code, options)));
+                        }
+                    })
+                })
+                .collect();
+            for handle in handles {
+                for (index, result) in handle.join().expect("check thread panicked") {
+                    results[index] = Some(result);
+                }
+            }
+        });
+        results.into_iter().map(|result| result.expect("every snippet is checked")).collect()
+    }
+
+    /// Check a project made of several source files, such as a `main.rs` and
+    /// the modules it declares
+    ///
+    /// Paths are relative to `src`, and `main.rs` (`lib.rs` for `no_std`
+    /// checks) is the crate root. Files are checked as given, without wrapping,
+    /// and diagnostics name the file they're in, e.g. `src/shapes.rs`.
+    pub fn check_project(
+        &self,
+        files: &HashMap<PathBuf, String>,
+        options: &CheckOptions,
+    ) -> Result<CompilationResult, CompilerError> {
+        let root = Path::new(if options.is_library() { "lib.rs" } else { "main.rs" });
+        let Some(source) = files.get(root) else {
+            let message = format!("The project has no `{}`", root.display());
+            return Ok(failed_result(CompilationError::new(ErrorLevel::Error, message)));
+        };
+
+        // The other files are written like assets, next to the crate root
+        let mut options = options.clone();
+        for (path, contents) in files.iter().filter(|(path, _)| path.as_path() != root) {
+            options.assets.insert(path.to_string_lossy().into_owned(), contents.clone());
+        }
+
+        let denied = self.denied_apis_in_files(
+            files.iter().map(|(path, code)| (format!("src/{}", path.display()), code.as_str())),
+        );
+        self.gated_check("files", source, denied, &options, |options| {
+            if !options.forbid_unsafe {
+                return self.check_crate_root(source, options);
+            }
+            let mut wrapped = WrappedSnippet::with_mode(source, &WrapMode::Never);
+            wrapped.prepend(FORBID_UNSAFE);
+            let mut result = self.check_crate_root(&wrapped.source, options)?;
+            let root_file = format!("src/{}", root.display());
+            unwrap_file_positions(&mut result, &wrapped, Some(&root_file));
+            Ok(result)
+        })
+    }
+
+    /// Check several crates together as a Cargo workspace
+    ///
+    /// Each crate brings its own manifest, so `dependencies`, `manifest` and
+    /// the other manifest options are ignored; options for the cargo command,
+    /// like `clippy` or `toolchain`, apply. Diagnostics carry the member they're
+    /// in as `crate_name`, and their `file` is relative to the workspace, e.g.
+    /// `behavior/src/main.rs`. Unless path dependencies are allowed, members
+    /// may only depend on each other by path. Like other checks, workspaces go
+    /// through the dependency limit, deny list, feature gates, linters and
+    /// result cache, with the members' Rust files taken together as the code.
+    pub fn check_workspace(
+        &self,
+        crates: &[WorkspaceCrate],
+        options: &CheckOptions,
+    ) -> Result<CompilationResult, CompilerError> {
+        if let Some(message) = self.workspace_problem(crates) {
+            return Ok(failed_result(CompilationError {
+                category: Some(ErrorCategory::Dependency),
+                ..CompilationError::new(ErrorLevel::Error, message)
+            }));
+        }
+
+        // Every member file goes in the cache key as an asset, like the other
+        // files of a project
+        let mut gated_options = CheckOptions {
+            dependencies: Vec::new(),
+            build_dependencies: Vec::new(),
+            manifest: None,
+            manifest_extra: String::new(),
+            build_script: None,
+            assets: HashMap::new(),
+            ..options.clone()
+        };
+        let mut sources = Vec::new();
+        for krate in crates {
+            let manifest = format!("{}/Cargo.toml", krate.name);
+            gated_options.assets.insert(manifest, krate.manifest.clone());
+            for (path, contents) in &krate.files {
+                let file = format!("{}/{}", krate.name, path.display());
+                gated_options.assets.insert(file.clone(), contents.clone());
+                if path.extension().is_some_and(|extension| extension == "rs") {
+                    sources.push((file, contents.as_str()));
+                }
+            }
+        }
+        sources.sort();
+        let denied = self.denied_apis_in_files(sources.iter().cloned());
+        let joined = JoinedSources::new(&sources);
+
+        let mut result = match self.gate_check("workspace", &joined.code, denied, &gated_options) {
+            Ok(mut gated) => {
+                // A crate outside the workspace can change between checks
+                if outside_path_dependency(crates).is_some() {
+                    gated.key = None;
+                }
+                let result = self.check_workspace_dir(crates, &gated.options)?;
+                self.finish_check(&joined.code, gated, result)
+            }
+            Err(result) => *result,
+        };
+
+        joined.split_positions(&mut result);
+        let diagnostics = result.errors.iter_mut().chain(&mut result.warnings);
+        for error in diagnostics.chain(&mut result.notes).chain(&mut result.help) {
+            let member = error.file.as_deref().and_then(|file| file.split('/').next());
+            error.crate_name = crates
+                .iter()
+                .find(|krate| Some(krate.name.as_str()) == member)
+                .map(|krate| krate.name.clone());
+        }
+        Ok(result)
+    }
+
+    /// Write the workspace into a temporary directory and run the check command on it
+    fn check_workspace_dir(
+        &self,
+        crates: &[WorkspaceCrate],
+        options: &CheckOptions,
+    ) -> Result<CompilationResult, CompilerError> {
+        let workspace_dir = self.temp_dir.join(format!("workspace_{}", uuid::Uuid::new_v4()));
+        let result = write_workspace(&workspace_dir, crates).and_then(|_| {
+            let command = self.check_command(&workspace_dir, options)?;
+            let description = describe_command(&command);
+            match self.check_step_output(command, options, None)? {
+                Some(output) => self.check_output(&output, description, options),
+                None => Ok(self.timed_out_result()),
+            }
+        });
+        let _ = fs::remove_dir_all(&workspace_dir);
+        result
+    }
+
+    /// Why a workspace can't be checked: a bad or repeated member name, a
+    /// manifest that isn't valid TOML, more dependencies than allowed, or a
+    /// build script or path dependency outside the workspace that isn't allowed
+    fn workspace_problem(&self, crates: &[WorkspaceCrate]) -> Option<String> {
+        let mut names = HashSet::new();
+        for krate in crates {
+            let valid = !krate.name.is_empty()
+                && krate.name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'));
+            if !valid || !names.insert(krate.name.as_str()) {
+                return Some(format!("Invalid or repeated workspace member name `{}`", krate.name));
+            }
+        }
+        let mut summaries = Vec::new();
+        for krate in crates {
+            match ManifestSummary::parse(&krate.manifest) {
+                Ok(summary) => summaries.push(summary),
+                Err(error) => {
+                    return Some(format!("Invalid Cargo.toml in `{}`: {}", krate.name, error))
+                }
+            }
+        }
+        let count: usize = summaries.iter().map(|summary| summary.dependencies).sum();
+        if let Some(limit) = self.max_dependencies.filter(|&limit| count > limit) {
+            return Some(format!(
+                "Too many dependencies: {} declared, but at most {} are allowed",
+                count, limit
+            ));
+        }
+        let build_script = crates.iter().zip(&summaries).find(|(krate, summary)| {
+            krate.files.contains_key(Path::new("build.rs")) || summary.build_script
+        });
+        if let Some((krate, _)) = build_script.filter(|_| !self.allow_build_scripts) {
+            return Some(format!("Build scripts are not allowed, but `{}` has one", krate.name));
+        }
+        if self.allow_path_dependencies {
+            return None;
+        }
+
+        let (krate, outside) = outside_path_dependency(crates)?;
+        Some(format!(
+            "Path dependencies are not allowed, but `{}` depends on `{}`, which isn't a \
+             workspace member",
+            krate.name, outside
+        ))
+    }
+
+    /// Run clippy on Rust code, returning its lints alongside any compiler errors
+    ///
+    /// Lints come back as warnings (or errors for deny-by-default lints) with the
+    /// lint name in `code`, so they can be shown apart from compiler diagnostics.
+    pub fn check_clippy(
+        &self,
+        code: &str,
+    ) -> Result<CompilationResult, CompilerError> {
+        let options = CheckOptions {
+            clippy: true,
+            ..Default::default()
+        };
+        self.check_with_options(code, &options)
+    }
+
+    /// Write a temporary Cargo project with `source` as its crate root and run
+    /// `cargo check` on it
+    fn check_crate_root(
+        &self,
+        source: &str,
+        options: &CheckOptions,
+    ) -> Result<CompilationResult, CompilerError> {
+        let project = self.project_dir()?;
+        self.check_in_dir(project.path(), source, options)
+    }
+
+    fn check_in_dir(
+        &self,
+        project_dir: &Path,
+        source: &str,
+        options: &CheckOptions,
+    ) -> Result<CompilationResult, CompilerError> {
+        let _span = tracing::info_span!(
+            "check",
+            dir = %project_dir.display(),
+            dependencies = options.all_dependencies().count()
+        )
+        .entered();
+        self.check_steps(project_dir, source, options)
+    }
+
+    /// Prepare the project, resolve dependencies, then check it
+    fn check_steps(
+        &self,
+        project_dir: &Path,
+        source: &str,
+        options: &CheckOptions,
+    ) -> Result<CompilationResult, CompilerError> {
+        let resolved = dependencies_resolved(project_dir, options);
+        if let Some(result) = self.prepare_project(project_dir, source, options)? {
+            return Ok(result);
+        }
+
+        // Resolve dependencies first so missing crates fail fast
+        if options.all_dependencies().next().is_some() && !resolved {
+            report_phase(options, CheckPhase::DownloadingDependencies);
+            let preflight = self.preflight_command(project_dir, options);
+            let Some(output) = self.check_step_output(preflight, options, None)? else {
+                return Ok(self.timed_out_result());
+            };
+            if let Some(result) = preflight_failure(&output) {
+                return Ok(result);
+            }
+        }
+
+        // Run cargo check (or build) with JSON output
+        let command = self.check_command(project_dir, options)?;
+        let description = describe_command(&command);
+        let on_line = options.progress.clone().map(|progress| {
+            compiling_progress(progress, crates_to_compile(project_dir, options))
+        });
+        let Some(output) = self.check_step_output(command, options, on_line)? else {
+            return Ok(self.timed_out_result());
+        };
+
+        let mut result = self.check_output(&output, description, options)?;
+        result.lockfile = resolved_lockfile(project_dir, options);
+        if self.audits(options) {
+            let audit = self.output(self.audit_command(project_dir))?;
+            apply_audit(&mut result, audit.as_ref());
+        }
+        Ok(result)
+    }
+
+    /// Whether a check gets a `cargo audit` pass
+    fn audits(&self, options: &CheckOptions) -> bool {
+        self.audit_dependencies && options.all_dependencies().next().is_some()
+    }
+
+    /// `cargo audit` of the project's lockfile, with JSON output
+    fn audit_command(&self, project_dir: &Path) -> Command {
+        let mut command = Command::new("cargo");
+        command.args(["audit", "--json"]).current_dir(project_dir);
+        self.share_cargo_dirs(&mut command);
+        command
+    }
+
+    /// Fail a check up front if it declares more dependencies than allowed, a
+    /// dependency with an invalid name, or path dependencies or a build script
+    /// that aren't allowed
+    ///
+    /// Dependencies, build scripts and `path` keys are looked for in the parsed
+    /// manifest the check would use, so a custom manifest, quoted or dotted
+    /// keys, or target-specific tables can't hide them.
+    fn reject_dependencies(&self, options: &CheckOptions) -> Option<CompilationResult> {
+        let invalid =
+            options.all_dependencies().find(|dependency| !is_valid_crate_name(&dependency.name));
+        // An invalid name could change the generated manifest, so names are
+        // checked before the manifest is parsed
+        if let Some(dependency) = invalid {
+            let message = format!("Invalid dependency name `{}`", dependency.name);
+            return Some(dependency_failure(message));
+        }
+        if let Some(message) = invalid_manifest_name(options) {
+            return Some(failed_result(CompilationError::new(ErrorLevel::Error, message)));
+        }
+        let summary = match ManifestSummary::parse(&manifest(options)) {
+            Ok(summary) => summary,
+            Err(error) => return Some(dependency_failure(format!("Invalid Cargo.toml: {}", error))),
+        };
+
+        let count = summary.dependencies;
+        let local = options.all_dependencies().find(|dependency| dependency.path.is_some());
+        let has_build_script = options.build_script.is_some() || summary.build_script;
+        let message = match (self.max_dependencies, local) {
+            (Some(limit), _) if count > limit => format!(
+                "Too many dependencies: {} declared, but at most {} are allowed",
+                count, limit
+            ),
+            _ if has_build_script && !self.allow_build_scripts => {
+                "Build scripts are not allowed".to_string()
+            }
+            (_, Some(dependency)) if !self.allow_path_dependencies => format!(
+                "Path dependencies are not allowed, but `{}` is a path dependency",
+                dependency.name
+            ),
+            _ if !self.allow_path_dependencies && !summary.paths.is_empty() => {
+                "Path dependencies are not allowed, but the custom manifest has a `path` key"
+                    .to_string()
+            }
+            _ => {
+                let relative = options.all_dependencies().find(|dependency| {
+                    dependency.path.as_deref().is_some_and(|path| Path::new(path).is_relative())
+                })?;
+                format!(
+                    "The path of dependency `{}` must be absolute, since checks run in a \
+                     temporary directory",
+                    relative.name
+                )
+            }
+        };
+        Some(dependency_failure(message))
+    }
+
+    /// Uses of denied APIs in `code`, when the deny list rejects them
+    fn denied_apis(&self, code: &str) -> Vec<CompilationError> {
+        let rejecting = self.deny_list.as_ref().filter(|list| !list.warn_only);
+        rejecting.map(|list| list.scan(code)).unwrap_or_default()
+    }
+
+    /// Uses of denied APIs in several files, given with the path diagnostics
+    /// should name them by
+    fn denied_apis_in_files<'a>(
+        &self,
+        files: impl IntoIterator<Item = (String, &'a str)>,
+    ) -> Vec<CompilationError> {
+        let denied = files.into_iter().flat_map(|(file, code)| {
+            self.denied_apis(code).into_iter().map(move |error| CompilationError {
+                file: Some(file.clone()),
+                ..error
+            })
+        });
+        denied.collect()
+    }
+
+    /// Fail a check up front if `code` uses APIs the deny list rejects
+    fn reject_denied_apis(&self, code: &str) -> Option<CompilationResult> {
+        denied_result(self.denied_apis(code))
+    }
+
+    /// A directory to check in: a pooled project if one is free, otherwise a
+    /// new temporary project
+    fn project_dir(&self) -> Result<ProjectDir, CompilerError> {
+        if let Some(pool) = &self.pool {
+            if let Some(project) = pool.acquire()? {
+                return Ok(ProjectDir::Pooled(project));
+            }
+        }
+
+        self.temporary_dir()
+    }
+
+    /// A new temporary directory, removed when dropped unless projects are kept
+    fn temporary_dir(&self) -> Result<ProjectDir, CompilerError> {
+        let project_dir = self.temp_dir.join(format!("check_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&project_dir)?;
+        if self.keep_projects {
+            return Ok(ProjectDir::Kept(project_dir));
+        }
+        Ok(ProjectDir::Temporary(project_dir))
+    }
+
+    /// Write the project, returning a failed result if it can't be checked as requested
+    fn prepare_project(
+        &self,
+        project_dir: &Path,
+        source: &str,
+        options: &CheckOptions,
+    ) -> Result<Option<CompilationResult>, CompilerError> {
+        let _span = tracing::debug_span!("project_setup").entered();
+        if let Some(message) = invalid_manifest_name(options) {
+            return Ok(Some(failed_result(CompilationError::new(ErrorLevel::Error, message))));
+        }
+        if let WrapMode::Template(template) = &options.wrap_mode {
+            if !template.contains(WRAP_PLACEHOLDER) {
+                let message = format!("Wrap template has no `{}` placeholder", WRAP_PLACEHOLDER);
+                return Ok(Some(failed_result(CompilationError::new(ErrorLevel::Error, message))));
+            }
+        }
+
+        self.write_project(project_dir, source, options)?;
+
+        if options.sanitizer.is_some() && !self.toolchain_installed("nightly") {
+            return Ok(Some(failed_result(CompilationError::new(
+                ErrorLevel::Error,
+                NIGHTLY_REQUIRED_FOR_SANITIZER,
+            ))));
+        }
+
+        if let Some(failure) = self.toolchain(options).and_then(|t| self.toolchain_failure(t)) {
+            return Ok(Some(failure));
+        }
+
+        Ok(options
+            .target
+            .as_deref()
+            .and_then(|target| self.target_failure(target, self.toolchain(options))))
+    }
+
+    /// Turn the output of the check command into a result
+    fn check_output(
+        &self,
+        output: &Output,
+        command: CommandLine,
+        options: &CheckOptions,
+    ) -> Result<CompilationResult, CompilerError> {
+        let mut result = self.parse_cargo_output(&output.stdout, &output.stderr)?;
+        report_killed_compiler(&mut result, output);
+
+        if options.include_command {
+            result.command = Some(command);
+        }
+
+        if options.explain {
+            self.attach_explanations(&mut result);
+        }
+
+        Ok(result)
+    }
+
+    /// Run `command` to completion, or kill it and return `None` once the
+    /// configured timeout passes
+    fn output(&self, command: Command) -> std::io::Result<Option<Output>> {
+        let (output, killed) = self.run_process(command, None, self.timeout, None, None)?;
+        Ok((!killed).then_some(output))
+    }
+
+    /// `output_with_timeout`, held to the configured resource limits and
+    /// sandbox, or in a container
+    fn run_process(
+        &self,
+        command: Command,
+        stdin: Option<&[u8]>,
+        timeout: Option<Duration>,
+        cancel: Option<&CancellationToken>,
+        on_line: Option<LineHandler>,
+    ) -> std::io::Result<(Output, bool)> {
+        let (command, limits) = self.isolate(command, timeout)?;
+        output_with_timeout(command, &limits, stdin, timeout, cancel, on_line)
+    }
+
+    /// `command` confined to the sandbox, and the limits to apply to it; with
+    /// a container configured, the command starting one that runs it instead,
+    /// leaving the limits to the container
+    fn isolate(
+        &self,
+        mut command: Command,
+        timeout: Option<Duration>,
+    ) -> std::io::Result<(Command, ResourceLimits)> {
+        // A `+toolchain` that isn't installed fails instead of being downloaded
+        command.env("RUSTUP_AUTO_INSTALL", "0");
+        let Some(container) = &self.container else {
+            self.confine(&mut command)?;
+            return Ok((command, self.resource_limits));
+        };
+        // Only this process's project, not the projects of other checks
+        let mounts: Vec<&Path> =
+            command.get_current_dir().into_iter().chain(self.cargo_home.as_deref()).collect();
+        let wrapped = container.wrap(&command, &mounts, &self.resource_limits, timeout);
+        Ok((wrapped, ResourceLimits::default()))
+    }
+
+    /// Confine processes started by `command` to the sandbox, if one is set;
+    /// besides the project, they may write to cargo home
+    fn confine(&self, command: &mut Command) -> std::io::Result<()> {
+        let Some(sandbox) = &self.sandbox else {
+            return Ok(());
+        };
+        let writable: Vec<&Path> = self.cargo_home.as_deref().into_iter().collect();
+        sandbox.apply(command, &writable)
+    }
+
+    /// Like `output`, but also killing `command` once the check's
+    /// `CheckOptions::cancel` token is cancelled, and passing each line of its
+    /// stdout to `on_line` as it's written
+    fn check_step_output(
+        &self,
+        command: Command,
+        options: &CheckOptions,
+        on_line: Option<LineHandler>,
+    ) -> Result<Option<Output>, CompilerError> {
+        let cancel = options.cancel.as_ref();
+        if is_cancelled(cancel) {
+            return Err(CompilerError::Cancelled);
+        }
+
+        let (output, killed) = self.run_process(command, None, self.timeout, cancel, on_line)?;
+        if is_cancelled(cancel) {
+            return Err(CompilerError::Cancelled);
+        }
+        Ok((!killed).then_some(output))
+    }
+
+    /// Command resolving a project's dependencies without compiling anything
+    fn preflight_command(&self, project_dir: &Path, options: &CheckOptions) -> Command {
+        let mut command = Command::new("cargo");
+        if let Some(toolchain) = self.toolchain(options) {
+            command.arg(format!("+{}", toolchain));
+        }
+        command.arg("generate-lockfile").current_dir(project_dir);
+        self.share_cargo_dirs(&mut command);
+        command
+    }
+
+    /// The `cargo check` (or `cargo build`) command for a check
+    fn check_command(
+        &self,
+        project_dir: &Path,
+        options: &CheckOptions,
+    ) -> Result<Command, CompilerError> {
+        let subcommand = if options.clippy {
+            "clippy"
+        } else if options.test {
+            "test"
+        } else if options.build {
+            "build"
+        } else {
+            "check"
+        };
+        let mut command = self.cargo_command(subcommand, project_dir, options)?;
+        if options.test && !options.clippy {
+            command.arg("--no-run");
+        }
+        Ok(command)
+    }
+
+    /// Build a `cargo <subcommand>` invocation with JSON output for `project_dir`
+    fn cargo_command(
+        &self,
+        subcommand: &str,
+        project_dir: &Path,
+        options: &CheckOptions,
+    ) -> Result<Command, CompilerError> {
+        let mut command = Command::new("cargo");
+
+        if let Some(toolchain) = self.toolchain(options) {
+            command.arg(format!("+{}", toolchain));
+        }
+
+        command.arg(subcommand).arg("--message-format=json");
+
+        if let Some(profile) = &options.profile {
+            command.args(profile.cargo_args());
+        }
+
+        if let Some(target) = &options.target {
+            command.arg("--target").arg(target);
+        }
+
+        command.envs(&options.env);
+        let mut rustflags = options.rustflags.clone();
+        if let Some(sanitizer) = options.sanitizer {
+            // An explicit target keeps build scripts and proc macros uninstrumented
+            if options.target.is_none() {
+                command.arg("--target").arg(host_target()?);
+            }
+            rustflags.push(format!("-Zsanitizer={}", sanitizer.as_str()));
+        }
+        if !rustflags.is_empty() {
+            // The encoded form keeps flags containing spaces in one piece
+            command.env("CARGO_ENCODED_RUSTFLAGS", rustflags.join("\x1f"));
+        }
+
+        command.current_dir(project_dir);
+        self.share_cargo_dirs(&mut command);
+        Ok(command)
+    }
+
+    /// Point a cargo command at the shared target directory and cargo home, if set
+    ///
+    /// Sandboxed and containerized checks never share a target directory.
+    fn share_cargo_dirs(&self, command: &mut Command) {
+        let isolated = self.sandbox.is_some() || self.container.is_some();
+        if let Some(dir) = self.shared_target_dir.as_ref().filter(|_| !isolated) {
+            command.env("CARGO_TARGET_DIR", dir);
+        }
+        if let Some(dir) = &self.cargo_home {
+            command.env("CARGO_HOME", dir);
+        }
+    }
+
+    /// Result for a check killed by the timeout
+    fn timed_out_result(&self) -> CompilationResult {
+        let timeout = self.timeout.unwrap_or_default();
+        let error = CompilationError {
+            category: Some(ErrorCategory::Timeout),
+            ..CompilationError::new(
+                ErrorLevel::Error,
+                format!(
+                    "Compilation timed out after {:.1}s and was stopped",
+                    timeout.as_secs_f64()
+                ),
+            )
+        };
+
+        CompilationResult {
+            timed_out: true,
+            ..failed_result(error)
+        }
+    }
+
+    /// Write Cargo.toml, `src/main.rs` (`src/lib.rs` for `no_std` checks) and
+    /// any assets into `project_dir`
+    fn write_project(
+        &self,
+        project_dir: &Path,
+        source: &str,
+        options: &CheckOptions,
+    ) -> Result<(), CompilerError> {
+        // Create Cargo.toml with dependencies, leaving a reused project's
+        // manifest alone if it hasn't changed
+        let manifest_path = project_dir.join("Cargo.toml");
+        let cargo_toml = manifest(options);
+        if fs::read_to_string(&manifest_path).ok().as_deref() != Some(cargo_toml.as_str()) {
+            fs::write(&manifest_path, cargo_toml)?;
+        }
+        if let Some(lockfile) = &options.lockfile {
+            fs::write(project_dir.join("Cargo.lock"), lockfile)?;
+        }
+
+        // Cargo runs a `build.rs` it finds, so one left by an earlier check goes
+        let build_script = project_dir.join("build.rs");
+        match &options.build_script {
+            Some(script) => fs::write(&build_script, script)?,
+            None if build_script.exists() => fs::remove_file(&build_script)?,
+            None => {}
+        }
+
+        // Create src directory and the crate root, dropping sources and assets
+        // left behind if this project was used for an earlier check
+        let src_dir = project_dir.join("src");
+        let _ = fs::remove_dir_all(&src_dir);
+        fs::create_dir_all(&src_dir)?;
+        let root = if options.is_library() { "lib.rs" } else { "main.rs" };
+        fs::write(src_dir.join(root), source)?;
+
+        // Write asset files relative to main.rs, where include macros look for them
+        write_assets(&src_dir, &options.assets)
+    }
+
+    /// Build and run the program under a sanitizer, reporting its findings as errors
+    ///
+    /// Requires the nightly toolchain; on stable the result carries a single error
+    /// explaining that. Like `check_code`, the snippet is wrapped in a main function
+    /// if it doesn't have one.
+    pub fn run_with_sanitizer(
+        &self,
+        code: &str,
+        sanitizer: Sanitizer,
+    ) -> Result<CompilationResult, CompilerError> {
+        if !self.toolchain_installed("nightly") {
+            return Ok(failed_result(CompilationError::new(
+                ErrorLevel::Error,
+                NIGHTLY_REQUIRED_FOR_SANITIZER,
+            )));
+        }
+
+        let options = CheckOptions {
+            sanitizer: Some(sanitizer),
+            ..Default::default()
+        };
+
+        let project_dir = self.temp_dir.join(format!("run_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&project_dir)?;
+
+        let result = self
+            .write_project(&project_dir, &wrap_snippet(code), &options)
+            .and_then(|_| Ok(self.output(self.cargo_command("run", &project_dir, &options)?)?));
+
+        let _ = fs::remove_dir_all(&project_dir);
+
+        let Some(output) = result? else {
+            return Ok(self.timed_out_result());
+        };
+        let mut result = self.parse_cargo_output(&output.stdout, &output.stderr)?;
+        result
+            .errors
+            .extend(parse_sanitizer_reports(&String::from_utf8_lossy(&output.stderr)));
+        result.success = result.errors.is_empty();
+        result.sort_diagnostics();
+
+        Ok(result)
+    }
+
+    /// Build and run the program, returning its exit code and output alongside
+    /// the build diagnostics
+    ///
+    /// The program is killed if it runs longer than `options.time_limit`. Like
+    /// `check_code`, the snippet is wrapped in a main function if it doesn't have one.
+    pub fn run_code(
+        &self,
+        code: &str,
+        options: &RunOptions,
+    ) -> Result<RunResult, CompilerError> {
+        let mut check = options.check.clone();
+        if options.mode == RunMode::Wasi {
+            if !is_wasmtime_available() {
+                return Ok(RunResult::not_run(failed_result(CompilationError::new(
+                    ErrorLevel::Error,
+                    WASMTIME_REQUIRED_FOR_WASI,
+                ))));
+            }
+            check.target = Some(WASI_TARGET.to_string());
+        }
+
+        let (project, compilation) = self.build_program(code, &check)?;
+        let Some(project) = project.filter(|_| compilation.success) else {
+            return Ok(RunResult::not_run(compilation));
+        };
+
+        let executable = built_executable(&compilation.stdout)
+            .ok_or_else(|| missing_artifact("executable"))?;
+
+        // Run a copy, so a concurrent build into a shared target directory can't replace it
+        let suffix = match options.mode {
+            RunMode::Native => std::env::consts::EXE_SUFFIX,
+            RunMode::Wasi => ".wasm",
+        };
+        let program = project.path().join(format!("blockly_run{}", suffix));
+        fs::copy(executable, &program)?;
+
+        let mut command = match options.mode {
+            RunMode::Native => {
+                let mut command = Command::new(&program);
+                command.envs(&check.env);
+                command
+            }
+            RunMode::Wasi => wasmtime_command(&program, options.fuel, &check.env),
+        };
+        command.current_dir(project.path());
+
+        let result = run_program(self, command, compilation, options);
+        let _ = fs::remove_file(&program);
+        result
+    }
+
+    /// Check code with bare rustc instead of cargo, for machines without cargo
+    ///
+    /// The code is wrapped as in `check_with_options` and checked with the
+    /// options' edition, crate type, toolchain and assets; other options only
+    /// cargo understands are ignored. Checks that need cargo to build, with
+    /// dependencies, a build script or a custom manifest, fail with
+    /// `CompilerError::InvalidInput`.
+    pub fn check_with_rustc(
+        &self,
+        code: &str,
+        options: &CheckOptions,
+    ) -> Result<CompilationResult, CompilerError> {
+        if let Some(reason) = needs_cargo(options) {
+            return Err(CompilerError::InvalidInput(reason.to_string()));
+        }
+        self.gated_check("rustc", code, self.denied_apis(code), options, |options| {
+            if let Some(result) = self.toolchain(options).and_then(|t| self.toolchain_failure(t)) {
+                return Ok(result);
+            }
+
+            let dir = self.temporary_dir()?;
+            let wrapped = check_source(code, options);
+            let source_file = write_rustc_source(dir.path(), &wrapped.source, &options.assets)?;
+
+            let mut command = rustc_command(
+                &source_file,
+                rustc_crate_type(options),
+                Some(options.edition),
+                self.toolchain(options),
+            );
+            let out_dir = dir.path();
+            command.arg("--emit=metadata").arg("--out-dir").arg(out_dir).current_dir(out_dir);
+
+            let mut result = match self.check_step_output(command, options, None)? {
+                Some(output) => {
+                    let mut result = self.parse_rustc_output(&output.stdout, &output.stderr)?;
+                    report_killed_compiler(&mut result, &output);
+                    result
+                }
+                None => self.timed_out_result(),
+            };
+            unwrap_positions(&mut result, &wrapped);
+            Ok(result)
+        })
+    }
+
+    /// Build a program with bare rustc and run it, like `run_code`
+    ///
+    /// Only native programs can be run; the same options as in
+    /// `check_with_rustc` are supported.
+    pub fn run_with_rustc(
+        &self,
+        code: &str,
+        options: &RunOptions,
+    ) -> Result<RunResult, CompilerError> {
+        if options.mode != RunMode::Native {
+            let reason = "Only native programs can be run without cargo";
+            return Err(CompilerError::InvalidInput(reason.to_string()));
+        }
+        if let Some(reason) = needs_cargo(&options.check) {
+            return Err(CompilerError::InvalidInput(reason.to_string()));
+        }
+        let check = &options.check;
+        if let Some(result) = self.toolchain(check).and_then(|t| self.toolchain_failure(t)) {
+            return Ok(RunResult::not_run(result));
+        }
+        if let Some(result) = self.reject_denied_apis(code) {
+            return Ok(RunResult::not_run(result));
+        }
+
+        let started = Instant::now();
+        let dir = self.temporary_dir()?;
+        let wrapped = check_source(code, check);
+        let source_file = write_rustc_source(dir.path(), &wrapped.source, &check.assets)?;
+
+        let program = dir.path().join(format!("blockly_run{}", std::env::consts::EXE_SUFFIX));
+        let mut command =
+            rustc_command(&source_file, CrateType::Bin, Some(check.edition), self.toolchain(check));
+        command.arg("-o").arg(&program).current_dir(dir.path());
+
+        let mut compilation = match self.check_step_output(command, check, None)? {
+            Some(output) => {
+                let mut compilation = self.parse_rustc_output(&output.stdout, &output.stderr)?;
+                report_killed_compiler(&mut compilation, &output);
+                compilation
+            }
+            None => self.timed_out_result(),
+        };
+        unwrap_positions(&mut compilation, &wrapped);
+        self.finish_result(code, started, &mut compilation);
+        if !compilation.success {
+            return Ok(RunResult::not_run(compilation));
+        }
+
+        let mut command = Command::new(&program);
+        command.envs(&check.env).current_dir(dir.path());
+        run_program(self, command, compilation, options)
+    }
+
+    /// Build and run the `#[test]` functions in `code`, returning the outcome
+    /// and captured output of each
+    ///
+    /// The code is used as is, without wrapping it in a main function. The test
+    /// run is killed if it takes longer than `options.time_limit`; `stdin`,
+    /// `mode` and `fuel` are ignored.
+    pub fn run_tests(
+        &self,
+        code: &str,
+        options: &RunOptions,
+    ) -> Result<TestRunResult, CompilerError> {
+        let started = Instant::now();
+        let check = CheckOptions {
+            test: true,
+            ..options.check.clone()
+        };
+        let not_run = |compilation| TestRunResult {
+            compilation,
+            tests: Vec::new(),
+            duration_ms: 0,
+            timed_out: false,
+        };
+
+        if let Some(result) =
+            self.reject_dependencies(&check).or_else(|| self.reject_denied_apis(code))
+        {
+            report_phase(&check, CheckPhase::Finished);
+            return Ok(not_run(result));
+        }
+
+        let project = self.project_dir()?;
+        let compilation = self.check_in_dir(project.path(), code, &check);
+        report_phase(&check, CheckPhase::Finished);
+        let mut compilation = compilation?;
+        self.finish_result(code, started, &mut compilation);
+        if !compilation.success {
+            return Ok(not_run(compilation));
+        }
+
+        let harness = built_executable(&compilation.stdout)
+            .ok_or_else(|| missing_artifact("test harness"))?;
+
+        // JSON output is unstable in libtest; RUSTC_BOOTSTRAP is only set for the
+        // harness, so the code itself still can't use nightly features
+        let mut command = Command::new(harness);
+        command
+            .args(["-Z", "unstable-options", "--format", "json", "--show-output"])
+            .arg("--report-time")
+            .env("RUSTC_BOOTSTRAP", "1")
+            .env("RUST_BACKTRACE", "0")
+            .envs(&check.env)
+            .current_dir(project.path());
+
+        let run_started = Instant::now();
+        let cancel = check.cancel.as_ref();
+        let (output, timed_out) =
+            self.run_process(command, None, Some(options.time_limit), cancel, None)?;
+        if is_cancelled(cancel) {
+            return Err(CompilerError::Cancelled);
+        }
+
+        Ok(TestRunResult {
+            compilation,
+            tests: parse_test_events(&String::from_utf8_lossy(&output.stdout)),
+            duration_ms: run_started.elapsed().as_millis() as u64,
+            timed_out,
+        })
+    }
+
+    /// Build `code` as a library and run the examples in its doc comments,
+    /// returning which of them passed
+    ///
+    /// The library is named `blockly_check`, so examples refer to its items as
+    /// `blockly_check::item`. The run, including compiling the examples, is
+    /// killed if it takes longer than `options.time_limit`; `stdin`, `mode` and
+    /// `fuel` are ignored.
+    pub fn run_doctests(
+        &self,
+        code: &str,
+        options: &RunOptions,
+    ) -> Result<TestRunResult, CompilerError> {
+        let started = Instant::now();
+        let check = &options.check;
+        let not_run = |compilation| TestRunResult {
+            compilation,
+            tests: Vec::new(),
+            duration_ms: 0,
+            timed_out: false,
+        };
+
+        if let Some(result) =
+            self.reject_dependencies(check).or_else(|| self.reject_denied_apis(code))
+        {
+            return Ok(not_run(result));
+        }
+
+        let project = self.project_dir()?;
+        if let Some(result) = self.prepare_project(project.path(), code, check)? {
+            return Ok(not_run(result));
+        }
+        if !check.is_library() {
+            let src = project.path().join("src");
+            fs::rename(src.join("main.rs"), src.join("lib.rs"))?;
+        }
+
+        let mut command = self.cargo_command("test", project.path(), check)?;
+        command.arg("--doc").env("RUST_BACKTRACE", "0");
+        let description = describe_command(&command);
+
+        let cancel = check.cancel.as_ref();
+        let (output, timed_out) =
+            self.run_process(command, None, Some(options.time_limit), cancel, None)?;
+        if is_cancelled(cancel) {
+            return Err(CompilerError::Cancelled);
+        }
+        let mut compilation = self.check_output(&output, description, check)?;
+        self.finish_result(code, started, &mut compilation);
+
+        Ok(TestRunResult {
+            tests: parse_doctest_output(&String::from_utf8_lossy(&output.stdout)),
+            compilation,
+            duration_ms: started.elapsed().as_millis() as u64,
+            timed_out,
+        })
+    }
+
+    /// Compile the program for `wasm32-unknown-unknown`, returning the module
+    /// so it can be run in the browser
+    ///
+    /// `options.target` is ignored. No JavaScript bindings are generated; add
+    /// `wasm-bindgen` to `options.dependencies` and run its CLI on the module
+    /// for those. Like `check_code`, the snippet is wrapped in a main function
+    /// if it doesn't have one.
+    pub fn build_wasm(
+        &self,
+        code: &str,
+        options: &CheckOptions,
+    ) -> Result<WasmBuild, CompilerError> {
+        let options = CheckOptions {
+            target: Some(WASM_TARGET.to_string()),
+            ..options.clone()
+        };
+        let (project, compilation) = self.build_program(code, &options)?;
+        if project.is_none() || !compilation.success {
+            return Ok(WasmBuild {
+                compilation,
+                wasm: None,
+            });
+        }
+
+        let module = built_executable(&compilation.stdout)
+            .ok_or_else(|| missing_artifact("WebAssembly module"))?;
+        Ok(WasmBuild {
+            wasm: Some(fs::read(module)?),
+            compilation,
+        })
+    }
+
+    /// Resolve the dependencies in `options` and return every crate they pull
+    /// in, with its enabled features and the crates depending on it
+    ///
+    /// Uses `cargo metadata`, so nothing is compiled, though crates may be
+    /// downloaded to resolve the graph.
+    pub fn dependency_tree(
+        &self,
+        code: &str,
+        options: &CheckOptions,
+    ) -> Result<Vec<DependencyNode>, CompilerError> {
+        if let Some(result) = self.reject_dependencies(options) {
+            return Err(CompilerError::InvalidInput(result.errors[0].message.clone()));
+        }
+
+        let project = self.project_dir()?;
+        let source = check_source(code, options).source;
+        if let Some(result) = self.prepare_project(project.path(), &source, options)? {
+            return Err(CompilerError::InvalidInput(result.errors[0].message.clone()));
+        }
+
+        let mut command = Command::new("cargo");
+        if let Some(toolchain) = self.toolchain(options) {
+            command.arg(format!("+{}", toolchain));
+        }
+        command
+            .args(["metadata", "--format-version=1"])
+            .current_dir(project.path());
+        self.share_cargo_dirs(&mut command);
+
+        let output = self.output(command)?.ok_or_else(|| {
+            CompilerError::Timeout("Resolving dependencies timed out".to_string())
+        })?;
+        if !output.status.success() {
+            return Err(CompilerError::ToolFailed(format!(
+                "cargo failed to resolve the dependencies:\n{}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+
+        let metadata: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+        Ok(parse_dependency_tree(&metadata))
+    }
+
+    /// Build the program with `cargo build`, returning the project it was
+    /// built in (`None` if the build was rejected up front) and the result
+    fn build_program(
+        &self,
+        code: &str,
+        options: &CheckOptions,
+    ) -> Result<(Option<ProjectDir>, CompilationResult), CompilerError> {
+        let started = Instant::now();
+        let build_options = CheckOptions {
+            build: true,
+            ..options.clone()
+        };
+
+        if let Some(result) =
+            self.reject_dependencies(&build_options).or_else(|| self.reject_denied_apis(code))
+        {
+            report_phase(&build_options, CheckPhase::Finished);
+            return Ok((None, result));
+        }
+
+        let project = self.project_dir()?;
+        let wrapped = check_source(code, &build_options);
+        let compilation = self.check_in_dir(project.path(), &wrapped.source, &build_options);
+        report_phase(&build_options, CheckPhase::Finished);
+        let mut compilation = compilation?;
+        unwrap_positions(&mut compilation, &wrapped);
+        self.finish_result(code, started, &mut compilation);
+        Ok((Some(project), compilation))
+    }
+
+    /// Check `code` and apply the compiler's machine-applicable suggestions
+    ///
+    /// Returns the fixed code, the fixes that were applied and the result of
+    /// checking the original code. Use `autofix_until_clean` to keep fixing
+    /// until no suggestions remain.
+    pub fn fix_code(
+        &self,
+        code: &str,
+    ) -> Result<(String, Vec<AppliedFix>, CompilationResult), CompilerError> {
+        let result = self.check_code(code)?;
+        let (fixed, applied) = result.apply_fixes(code);
+        Ok((fixed, applied, result))
+    }
+
+    /// Repeatedly apply machine-applicable compiler fixes and re-check
+    ///
+    /// Stops when no fixes apply, when a fix would return to code already seen
+    /// (oscillating suggestions), or after `max_iterations` rounds of fixes.
+    /// Returns the final code and its check result.
+    pub fn autofix_until_clean(
+        &self,
+        code: &str,
+        max_iterations: usize,
+    ) -> Result<(String, CompilationResult), CompilerError> {
+        let mut code = code.to_string();
+        let mut result = self.check_code(&code)?;
+        let mut seen = HashSet::from([code.clone()]);
+
+        for _ in 0..max_iterations {
+            let fixed = result.fixed_code(&code);
+            if fixed == code || !seen.insert(fixed.clone()) {
+                break;
+            }
+
+            code = fixed;
+            result = self.check_code(&code)?;
+        }
+
+        Ok((code, result))
+    }
+
+    /// Syntax check in-process with `syn`, without running the toolchain
+    ///
+    /// Fast enough to run on every keystroke, but only catches syntax errors:
+    /// the result has at most one error, and success means the code parses,
+    /// not that it compiles. Like `check_code`, code that isn't a whole file
+    /// is parsed as the body of a main function.
+    pub fn parse_check(&self, code: &str) -> CompilationResult {
+        let started = Instant::now();
+        let error = syn::parse_file(code).err().and_then(|file_error| {
+            let block_error = parse_block(code).err()?;
+            // Report whichever parse got further, shifting the block's past
+            // its opening line
+            let file_start = file_error.span().start();
+            let block_start = block_error.span().start();
+            let block_line = block_start.line.saturating_sub(1).max(1);
+            Some(if (block_line, block_start.column) > (file_start.line, file_start.column) {
+                syntax_error(&block_error, block_line)
+            } else {
+                syntax_error(&file_error, file_start.line)
+            })
+        });
+
+        let mut result = collect_diagnostics(std::iter::empty(), "", "");
+        result.errors.extend(error);
+        result.success = result.errors.is_empty();
+        result.duration_ms = started.elapsed().as_millis() as u64;
+        result
+    }
+
+    /// Functions, structs, enums and consts defined in a snippet, in source order
+    ///
+    /// This is a pure parse with `syn`, like `parse_check`: items nested in
+    /// modules or function bodies are included, methods are not, and code
+    /// that doesn't parse has no symbols.
+    pub fn list_symbols(&self, code: &str) -> Vec<Symbol> {
+        use syn::spanned::Spanned;
+        use syn::visit::Visit;
+
+        struct Symbols<'a> {
+            code: &'a str,
+            line_offset: usize,
+            found: Vec<Symbol>,
+        }
+
+        impl Symbols<'_> {
+            fn push(
+                &mut self,
+                name: &syn::Ident,
+                kind: SymbolKind,
+                item: &syn::Item,
+                header: (proc_macro2::Span, proc_macro2::Span),
+            ) {
+                let (start, end) = (item.span().start(), item.span().end());
+                let signature =
+                    source_text(self.code, header.0.start(), header.1.end(), self.line_offset);
+                self.found.push(Symbol {
+                    name: name.to_string(),
+                    kind,
+                    signature,
+                    span: SourceRange {
+                        line_start: start.line - self.line_offset,
+                        column_start: start.column + 1,
+                        line_end: end.line - self.line_offset,
+                        column_end: end.column + 1,
+                    },
+                });
+            }
+        }
+
+        impl<'ast> Visit<'ast> for Symbols<'_> {
+            fn visit_item(&mut self, item: &'ast syn::Item) {
+                let type_header =
+                    |token: proc_macro2::Span, ident: &syn::Ident, generics: &syn::Generics| {
+                        (token, generics.gt_token.map_or(ident.span(), |gt| gt.span))
+                    };
+                match item {
+                    syn::Item::Fn(f) => {
+                        let header = (f.sig.span(), f.sig.span());
+                        self.push(&f.sig.ident, SymbolKind::Function, item, header);
+                    }
+                    syn::Item::Struct(s) => {
+                        let header = type_header(s.struct_token.span, &s.ident, &s.generics);
+                        self.push(&s.ident, SymbolKind::Struct, item, header);
+                    }
+                    syn::Item::Enum(e) => {
+                        let header = type_header(e.enum_token.span, &e.ident, &e.generics);
+                        self.push(&e.ident, SymbolKind::Enum, item, header);
+                    }
+                    syn::Item::Const(c) => {
+                        let header = (c.const_token.span, c.ty.span());
+                        self.push(&c.ident, SymbolKind::Const, item, header);
+                    }
+                    _ => {}
+                }
+                syn::visit::visit_item(self, item);
+            }
+        }
+
+        let mut symbols = Symbols { code, line_offset: 0, found: Vec::new() };
+        if let Some(parsed) = ParsedSnippet::parse(code) {
+            symbols.line_offset = parsed.line_offset();
+            parsed.visit(&mut symbols);
+        }
+        symbols.found
+    }
+
+    /// Lines of code, functions, and each function's cyclomatic complexity and
+    /// nesting depth, e.g. to cap the complexity of an assignment
+    ///
+    /// Like `list_symbols`, this is a pure parse with `syn`; code that doesn't
+    /// parse only has its lines counted.
+    pub fn metrics(&self, code: &str) -> CodeMetrics {
+        crate::metrics::measure(code)
+    }
+
+    /// Quick syntax check without full compilation
+    /// Uses rustc directly for faster feedback; the code is checked as a library
+    pub fn quick_check(&self, code: &str) -> Result<CompilationResult, CompilerError> {
+        self.quick_check_as(code, CrateType::Lib)
+    }
+
+    /// `quick_check` with the given crate type, e.g. `Bin` for code with a
+    /// `main` function. The code is not wrapped.
+    pub fn quick_check_as(
+        &self,
+        code: &str,
+        crate_type: CrateType,
+    ) -> Result<CompilationResult, CompilerError> {
+        let options = CheckOptions { crate_type, ..Default::default() };
+        let (key, hit) = self.cache_lookup("quick", code, &options);
+        if let Some(hit) = hit {
+            return Ok(hit);
+        }
+        if let Some(result) = self.toolchain.as_deref().and_then(|t| self.toolchain_failure(t)) {
+            return Ok(result);
+        }
+        if let Some(result) = self.reject_denied_apis(code) {
+            return Ok(result);
+        }
+
+        let started = Instant::now();
+
+        // rustc runs in a directory of its own, which is all the sandbox or
+        // container lets it see of the temporary directory
+        let dir = self.temporary_dir()?;
+        let source_file = write_rustc_source(dir.path(), code, &HashMap::new())?;
+        let mut command =
+            quick_check_command(&source_file, dir.path(), crate_type, self.toolchain.as_deref());
+        command.current_dir(dir.path());
+        let output = self.output(command);
+
+        let mut result = match output? {
+            Some(output) => self.parse_rustc_output(&output.stdout, &output.stderr)?,
+            None => self.timed_out_result(),
+        };
+        self.finish_result(code, started, &mut result);
+        self.cache_store(key, &result);
+        Ok(result)
+    }
+
+    /// List the public functions and types of `code`, compiled as a library crate
+    ///
+    /// Uses rustdoc's JSON output, which is only available on the nightly
+    /// toolchain; without nightly an error saying so is returned. Inherent
+    /// methods of public types are listed as `Type::method`.
+    pub fn public_api(&self, code: &str) -> Result<Vec<ApiItem>, CompilerError> {
+        if !self.toolchain_installed("nightly") {
+            let message = NIGHTLY_REQUIRED_FOR_PUBLIC_API.to_string();
+            return Err(CompilerError::ToolchainMissing(message));
+        }
+
+        let dir = self.temporary_dir()?;
+        fs::write(dir.path().join("lib.rs"), code)?;
+        let out_dir = dir.path().join("doc");
+
+        let mut command = Command::new("rustdoc");
+        command
+            .arg("+nightly")
+            .arg("-Zunstable-options")
+            .arg("--output-format=json")
+            .arg("--crate-type=lib")
+            .arg("--crate-name=blockly_check")
+            .arg("--edition=2021")
+            .arg("-o")
+            .arg(&out_dir)
+            .arg("lib.rs")
+            .current_dir(dir.path());
+
+        let output = self
+            .output(command)?
+            .ok_or_else(|| CompilerError::Timeout("rustdoc timed out".to_string()))?;
+        let json = fs::read_to_string(out_dir.join("blockly_check.json")).ok();
+        let Some(json) = json.filter(|_| output.status.success()) else {
+            return Err(CompilerError::ToolFailed(format!(
+                "rustdoc failed to document the code:\n{}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        };
+
+        let krate: serde_json::Value = serde_json::from_str(&json)?;
+        Ok(collect_public_api(&krate, code))
+    }
+
+    /// The source of `code` with all macros, including derives, expanded
+    ///
+    /// Uses `rustc -Zunpretty=expanded`, which is only available on the nightly
+    /// toolchain; without nightly an error saying so is returned. Like
+    /// `check_code`, the snippet is wrapped in a main function if it doesn't have one.
+    pub fn expand_code(&self, code: &str) -> Result<String, CompilerError> {
+        self.emit_text(code, Emit::Unpretty("expanded"))
+    }
+
+    /// The snippet's HIR, MIR, LLVM IR or assembly, as printed by rustc
+    ///
+    /// HIR uses `-Zunpretty=hir` and so needs the nightly toolchain; the rest
+    /// work on stable. Symbol names in LLVM IR and assembly are demangled. Like
+    /// `check_code`, the snippet is wrapped in a main function if it doesn't have one.
+    pub fn emit_ir(&self, code: &str, ir: IrKind) -> Result<String, CompilerError> {
+        match ir {
+            IrKind::Hir => self.emit_text(code, Emit::Unpretty("hir")),
+            IrKind::Mir => self.emit_text(code, Emit::File("mir")),
+            IrKind::LlvmIr => Ok(demangle_symbols(&self.emit_text(code, Emit::File("llvm-ir"))?)),
+            IrKind::Asm => Ok(demangle_symbols(&self.emit_text(code, Emit::File("asm"))?)),
+        }
+    }
+
+    /// Compile the wrapped snippet with rustc and return the requested text output
+    fn emit_text(&self, code: &str, emit: Emit) -> Result<String, CompilerError> {
+        if matches!(emit, Emit::Unpretty(_)) && !self.toolchain_installed("nightly") {
+            return Err(CompilerError::ToolchainMissing(NIGHTLY_REQUIRED_FOR_UNPRETTY.to_string()));
+        }
+
+        let dir = self.temporary_dir()?;
+        let source_file = write_rustc_source(dir.path(), &wrap_snippet(code), &HashMap::new())?;
+        let out_file = dir.path().join("blockly_check.out");
+
+        let mut command = Command::new("rustc");
+        match emit {
+            Emit::Unpretty(mode) => {
+                command.arg("+nightly").arg(format!("-Zunpretty={}", mode));
+            }
+            Emit::File(kind) => {
+                command.arg(format!("--emit={}", kind)).arg("-o").arg(&out_file);
+            }
+        }
+        command
+            .arg("--edition=2021")
+            .arg("--crate-name=blockly_check")
+            .arg(&source_file)
+            .current_dir(dir.path());
+
+        let output = self.output(command);
+        let emitted = fs::read_to_string(&out_file);
+
+        let output =
+            output?.ok_or_else(|| CompilerError::Timeout("rustc timed out".to_string()))?;
+        if !output.status.success() {
+            return Err(CompilerError::ToolFailed(format!(
+                "rustc failed to compile the code:\n{}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+
+        match emit {
+            Emit::Unpretty(_) => Ok(String::from_utf8_lossy(&output.stdout).to_string()),
+            Emit::File(_) => Ok(emitted?),
+        }
+    }
+
+    /// Format code with rustfmt, for the edition set by `set_format_edition`
+    ///
+    /// Bare statements are formatted as the body of a function. Returns `None`
+    /// if the code isn't syntactically valid.
+    pub fn format_code(&self, code: &str) -> Result<Option<String>, CompilerError> {
+        let is_file = syn::parse_file(code).is_ok();
+        if !is_file && parse_block(code).is_err() {
+            return Ok(None);
+        }
+
+        let input = if is_file { code.to_string() } else { format!("{}{}\n}}", MAIN_PREFIX, code) };
+
+        let mut child = Command::new("rustfmt")
+            .arg(format!("--edition={}", self.format_edition.as_str()))
+            .args(["--emit", "stdout"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        child
+            .stdin
+            .take()
+            .ok_or_else(|| CompilerError::ToolFailed("Failed to open rustfmt stdin".to_string()))?
+            .write_all(input.as_bytes())?;
+        let output = child.wait_with_output()?;
+
+        if !output.status.success() {
+            return Ok(None);
+        }
+        let formatted = String::from_utf8_lossy(&output.stdout).to_string();
+
+        if is_file {
+            return Ok(Some(formatted));
+        }
+
+        // Unwrap the function again, dropping its indentation
+        let lines: Vec<&str> = formatted.lines().collect();
+        let body = lines
+            .get(1..lines.len().saturating_sub(1))
+            .unwrap_or_default()
+            .iter()
+            .map(|line| line.strip_prefix("    ").unwrap_or(line))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Ok(Some(format!("{}\n", body)))
+    }
+
+    /// Get the long-form `rustc --explain` text for an error code such as `E0308`
+    ///
+    /// Returns `None` for codes rustc doesn't know about. Results are cached.
+    pub fn explain(&self, code: &str) -> Result<Option<String>, CompilerError> {
+        if let Some(cached) = self.explanations.lock().unwrap().get(code) {
+            return Ok(cached.clone());
+        }
+
+        let is_error_code = code.len() == 5
+            && code.starts_with('E')
+            && code[1..].chars().all(|c| c.is_ascii_digit());
+
+        let explanation = if is_error_code {
+            let output = Command::new("rustc").arg("--explain").arg(code).output()?;
+            if output.status.success() {
+                Some(String::from_utf8_lossy(&output.stdout).to_string())
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        self.explanations
+            .lock()
+            .unwrap()
+            .insert(code.to_string(), explanation.clone());
+
+        Ok(explanation)
+    }
+
+    /// Set the `explanation` of errors carrying a code to its `rustc --explain` text
+    ///
+    /// At most `MAX_EXPLANATIONS_PER_CHECK` distinct codes are looked up.
+    pub fn attach_explanations(&self, result: &mut CompilationResult) {
+        let mut looked_up: Vec<String> = Vec::new();
+
+        for error in &mut result.errors {
+            let Some(code) = error.code.clone() else {
+                continue;
+            };
+
+            if !looked_up.contains(&code) {
+                if looked_up.len() >= MAX_EXPLANATIONS_PER_CHECK {
+                    continue;
+                }
+                looked_up.push(code.clone());
+            }
+
+            if let Ok(explanation) = self.explain(&code) {
+                error.explanation = explanation;
+            }
+        }
+    }
+
+    /// Parse cargo check JSON output
+    fn parse_cargo_output(
+        &self,
+        stdout: &[u8],
+        stderr: &[u8],
+    ) -> Result<CompilationResult, CompilerError> {
+        let _span = tracing::debug_span!("parse", bytes = stdout.len() + stderr.len()).entered();
+        let stdout_str = String::from_utf8_lossy(stdout);
+        let stderr_str = String::from_utf8_lossy(stderr);
+
+        // Cargo wraps each compiler diagnostic in a `message` field on stdout
+        let messages = stdout_str
+            .lines()
+            .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+            .filter_map(|msg| msg.get("message").cloned());
+
+        let mut result = collect_diagnostics(messages, &stdout_str, &stderr_str);
+        // A build script that fails at run time leaves only cargo's own error
+        if result.errors.is_empty() {
+            if let Some(error) = build_script_failure(&stderr_str) {
+                result.errors.push(error);
+                result.success = false;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Parse rustc JSON output
+    fn parse_rustc_output(
+        &self,
+        stdout: &[u8],
+        stderr: &[u8],
+    ) -> Result<CompilationResult, CompilerError> {
+        let stdout_str = String::from_utf8_lossy(stdout);
+        let stderr_str = String::from_utf8_lossy(stderr);
+
+        // rustc writes bare diagnostics to stderr, including trailing summary
+        // lines ("aborting due to...", failure notes) that aren't real errors
+        let messages = stderr_str
+            .lines()
+            .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+            .filter(|msg| {
+                let level = msg.get("level").and_then(|v| v.as_str()).unwrap_or("");
+                let text = msg.get("message").and_then(|v| v.as_str()).unwrap_or("");
+                level != "failure-note" && !text.starts_with("aborting due to")
+            });
+
+        Ok(collect_diagnostics(messages, &stdout_str, &stderr_str))
+    }
+}
+
+/// Async variants of the checks, running the compiler with `tokio::process`
+/// so that callers on an async runtime aren't blocked while it runs
+#[cfg(feature = "async")]
+impl RustCompiler {
+    /// Async version of `check_code`
+    pub async fn check_code_async(
+        &self,
+        code: &str,
+    ) -> Result<CompilationResult, CompilerError> {
+        self.check_with_options_async(code, &CheckOptions::default()).await
+    }
+
+    /// Async version of `check_with_options`
+    pub async fn check_with_options_async(
+        &self,
+        code: &str,
+        options: &CheckOptions,
+    ) -> Result<CompilationResult, CompilerError> {
+        let gated = match self.gate_check("snippet", code, self.denied_apis(code), options) {
+            Ok(gated) => gated,
+            Err(result) => return Ok(*result),
+        };
+        let wrapped = check_source(code, &gated.options);
+        let mut result = self.check_crate_root_async(&wrapped.source, &gated.options).await?;
+        unwrap_positions(&mut result, &wrapped);
+        Ok(self.finish_check(code, gated, result))
+    }
+
+    /// Async version of `quick_check`
+    pub async fn quick_check_async(
+        &self,
+        code: &str,
+    ) -> Result<CompilationResult, CompilerError> {
+        self.quick_check_as_async(code, CrateType::Lib).await
+    }
+
+    /// Async version of `quick_check_as`
+    pub async fn quick_check_as_async(
+        &self,
+        code: &str,
+        crate_type: CrateType,
+    ) -> Result<CompilationResult, CompilerError> {
+        let options = CheckOptions { crate_type, ..Default::default() };
+        let (key, hit) = self.cache_lookup("quick", code, &options);
+        if let Some(hit) = hit {
+            return Ok(hit);
+        }
+        if let Some(result) = self.toolchain.as_deref().and_then(|t| self.toolchain_failure(t)) {
+            return Ok(result);
+        }
+        if let Some(result) = self.reject_denied_apis(code) {
+            return Ok(result);
+        }
+
+        let started = Instant::now();
+
+        let dir = self.temporary_dir()?;
+        let source_file = write_rustc_source(dir.path(), code, &HashMap::new())?;
+        let mut command =
+            quick_check_command(&source_file, dir.path(), crate_type, self.toolchain.as_deref());
+        command.current_dir(dir.path());
+        let output = self.output_async(command).await;
+
+        let mut result = match output? {
+            Some(output) => self.parse_rustc_output(&output.stdout, &output.stderr)?,
+            None => self.timed_out_result(),
+        };
+        self.finish_result(code, started, &mut result);
+        self.cache_store(key, &result);
+        Ok(result)
+    }
+
+    async fn check_crate_root_async(
+        &self,
+        source: &str,
+        options: &CheckOptions,
+    ) -> Result<CompilationResult, CompilerError> {
+        let project = self.project_dir()?;
+        let project_dir = project.path();
+
+        let resolved = dependencies_resolved(project_dir, options);
+        if let Some(result) = self.prepare_project(project_dir, source, options)? {
+            return Ok(result);
+        }
+
+        if options.all_dependencies().next().is_some() && !resolved {
+            let preflight = self.preflight_command(project_dir, options);
+            let Some(output) = self.output_async(preflight).await? else {
+                return Ok(self.timed_out_result());
+            };
+            if let Some(result) = preflight_failure(&output) {
+                return Ok(result);
+            }
+        }
+
+        let command = self.check_command(project_dir, options)?;
+        let description = describe_command(&command);
+        let Some(output) = self.output_async(command).await? else {
+            return Ok(self.timed_out_result());
+        };
+
+        let mut result = self.check_output(&output, description, options)?;
+        result.lockfile = resolved_lockfile(project_dir, options);
+        if self.audits(options) {
+            let audit = self.output_async(self.audit_command(project_dir)).await?;
+            apply_audit(&mut result, audit.as_ref());
+        }
+        Ok(result)
+    }
+
+    /// Run `command` to completion, or kill it and return `None` once the
+    /// configured timeout passes
+    async fn output_async(&self, command: Command) -> std::io::Result<Option<Output>> {
+        let (mut command, limits) = self.isolate(command, self.timeout)?;
+        limits.apply(&mut command);
+        ProcessTree::own_group(&mut command);
+        let mut command = tokio::process::Command::from(command);
+        let child = command
+            .kill_on_drop(true)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // Dropped when the output is read, or when the future is, e.g. on a
+        // timeout or a client going away, killing what's left of the tree
+        #[cfg(unix)]
+        let _tree = child.id().map(ProcessTree::new);
+        #[cfg(windows)]
+        let _tree = match child.raw_handle() {
+            Some(process) => Some(ProcessTree::new(process, &limits)?),
+            None => None,
+        };
+        let output = child.wait_with_output();
+
+        match self.timeout {
+            Some(timeout) => Ok(tokio::time::timeout(timeout, output).await.ok().transpose()?),
+            None => output.await.map(Some),
+        }
+    }
+}
+
+/// A check that passed `RustCompiler::gate_check`, waiting for its result
+///
+/// Dropping it, normally in `RustCompiler::finish_check` but also when the
+/// check fails, reports `CheckPhase::Finished`.
+struct GatedCheck<'a> {
+    /// Where to cache the result, if results are cached
+    key: Option<String>,
+    started: Instant,
+    /// The options to check with, switched to nightly for feature gates if
+    /// that's configured
+    options: Cow<'a, CheckOptions>,
+}
+
+impl Drop for GatedCheck<'_> {
+    fn drop(&mut self) {
+        report_phase(&self.options, CheckPhase::Finished);
+    }
+}
+
+/// Everything that can change a check's result: the check, its options and
+/// the checker's configuration
+///
+/// Serialized to JSON as the result cache's key, which is compared in full.
+#[derive(Serialize)]
+struct CacheKey<'a> {
+    kind: &'a str,
+    code: &'a str,
+    dependencies: &'a [Dependency],
+    assets: BTreeMap<&'a str, &'a str>,
+    explain: bool,
+    include_command: bool,
+    sanitizer: Option<Sanitizer>,
+    build: bool,
+    clippy: bool,
+    test: bool,
+    toolchain: Option<&'a str>,
+    edition: Edition,
+    target: Option<&'a str>,
+    lockfile: Option<&'a str>,
+    profile: Option<&'a BuildProfile>,
+    rustflags: &'a [String],
+    env: &'a BTreeMap<String, String>,
+    no_std: bool,
+    crate_type: CrateType,
+    lints: &'a BTreeMap<String, LintLevel>,
+    treat_warnings_as_errors: bool,
+    forbid_unsafe: bool,
+    wrap_mode: &'a WrapMode,
+    manifest: Option<&'a str>,
+    manifest_extra: &'a str,
+    build_script: Option<&'a str>,
+    build_dependencies: &'a [Dependency],
+    default_toolchain: Option<&'a str>,
+    allowed_toolchains: Option<&'a [String]>,
+    format_edition: Edition,
+    max_dependencies: Option<usize>,
+    deny_list: Option<&'a DenyList>,
+    sandbox: Option<&'a Sandbox>,
+    container: Option<&'a ContainerConfig>,
+    resource_limits: ResourceLimits,
+    timeout: Option<Duration>,
+    cargo_home: Option<&'a Path>,
+    audit_dependencies: bool,
+    allow_path_dependencies: bool,
+    allow_build_scripts: bool,
+    nightly_for_feature_gates: bool,
+    linters: usize,
+}
+
+/// Whether `result` would be the same if the check ran again
+///
+/// Errors without a file or category come from cargo or a tool rather than
+/// the code, e.g. a full disk or a lock that couldn't be taken.
+fn is_cacheable(result: &CompilationResult) -> bool {
+    let transient = |error: &CompilationError| match error.category {
+        Some(ErrorCategory::Dependency | ErrorCategory::Timeout | ErrorCategory::ResourceLimit) => {
+            true
+        }
+        Some(_) => false,
+        None => error.file.is_none(),
+    };
+    !result.timed_out && !result.errors.iter().any(transient)
+}
+
+/// Group parsed diagnostics by severity into a `CompilationResult`
+///
+/// Diagnostics cargo reports more than once, e.g. for both the binary and its
+/// tests, are kept once. They stay in cargo's order until
+/// `CompilationResult::sort_diagnostics`.
+pub(crate) fn collect_diagnostics(
+    messages: impl Iterator<Item = serde_json::Value>,
+    stdout: &str,
+    stderr: &str,
+) -> CompilationResult {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    let mut notes = Vec::new();
+    let mut help = Vec::new();
+    let mut seen = HashSet::new();
+
+    for message in messages {
+        if let Some(mut error) = parse_diagnostic(&message) {
+            let key = (
+                error.code.clone(),
+                error.file.clone(),
+                (error.line, error.column, error.line_end, error.column_end),
+                error.message.clone(),
+            );
+            if !seen.insert(key) {
+                continue;
+            }
+
+            // Child diagnostics carry the notes and help for this message
+            let children = message.get("children").and_then(|c| c.as_array());
+            for child in children.into_iter().flatten().filter_map(parse_diagnostic) {
+                match child.level {
+                    ErrorLevel::Note => notes.push(child.clone()),
+                    ErrorLevel::Help => help.push(child.clone()),
+                    _ => {}
+                }
+                error.related.push(child);
+            }
+
+            let mut error = if is_link_failure(&error) {
+                link_error(error)
+            } else {
+                error
+            };
+            suggest_missing_crate(&mut error);
+
+            match error.level {
+                ErrorLevel::Error => errors.push(error),
+                ErrorLevel::Warning => warnings.push(error),
+                ErrorLevel::Note => notes.push(error),
+                ErrorLevel::Help => help.push(error),
+            }
+        }
+    }
+
+    let lint_groups = count_lint_groups(&warnings);
+
+    CompilationResult {
+        success: errors.is_empty(),
+        errors,
+        warnings,
+        notes,
+        help,
+        stdout: stdout.to_string(),
+        stderr: stderr.to_string(),
+        command: None,
+        duration_ms: 0,
+        timed_out: false,
+        cached: false,
+        advisories: Vec::new(),
+        lockfile: None,
+        line_offset: 0,
+        wrap_mode: WrapMode::Auto,
+        lint_groups,
+    }
+}
+
+/// Number of `warnings` in each lint group
+fn count_lint_groups(warnings: &[CompilationError]) -> BTreeMap<LintGroup, usize> {
+    let mut counts = BTreeMap::new();
+    for group in warnings.iter().filter_map(|warning| warning.lint_group) {
+        *counts.entry(group).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Order diagnostics by file, then line and column, keeping the compiler's
+/// order for ties; diagnostics without a location go last
+fn sort_by_position(diagnostics: &mut [CompilationError]) {
+    diagnostics.sort_by(|a, b| {
+        let key = |error: &CompilationError| {
+            let file = (error.file.is_none(), error.file.clone());
+            (file, error.line.is_none(), error.line, error.column)
+        };
+        key(a).cmp(&key(b))
+    });
+}
+
+/// Crates commonly used without being declared, with the version to suggest
+const KNOWN_CRATES: &[(&str, &str)] = &[
+    ("anyhow", "1"),
+    ("base64", "0.22"),
+    ("bytes", "1"),
+    ("chrono", "0.4"),
+    ("clap", "4"),
+    ("crossbeam", "0.8"),
+    ("futures", "0.3"),
+    ("itertools", "0.13"),
+    ("lazy_static", "1"),
+    ("log", "0.4"),
+    ("num", "0.4"),
+    ("once_cell", "1"),
+    ("rand", "0.8"),
+    ("rayon", "1"),
+    ("regex", "1"),
+    ("reqwest", "0.12"),
+    ("serde", "1"),
+    ("serde_json", "1"),
+    ("thiserror", "1"),
+    ("tokio", "1"),
+    ("uuid", "1"),
+];
+
+/// Attach a `MissingCrate` to unresolved import and path errors naming a
+/// well-known crate, with a note saying which dependency to add
+fn suggest_missing_crate(error: &mut CompilationError) {
+    if !matches!(error.code.as_deref(), Some("E0432" | "E0433")) {
+        return;
+    }
+
+    // The primary span's label names the crate, across rustc versions:
+    // "use of unresolved module or unlinked crate `rand`",
+    // "use of undeclared crate or module `rand`", "you might be missing crate `rand`"
+    let Some((name, version)) = error
+        .spans
+        .iter()
+        .filter_map(|span| span.label.as_deref())
+        .filter(|label| label.contains("crate"))
+        .filter_map(|label| label.split('`').nth(1))
+        .find_map(|name| KNOWN_CRATES.iter().find(|(known, _)| *known == name))
+    else {
+        return;
+    };
+
+    let missing = MissingCrate {
+        name: name.to_string(),
+        version: version.to_string(),
+    };
+    error
+        .notes
+        .push(format!("add dependency `{}` to use this crate", missing.manifest_entry()));
+    error.missing_crate = Some(missing);
+}
+
+/// An error for a build script that ran and failed, from cargo's stderr,
+/// with what the script printed as a note
+fn build_script_failure(stderr: &str) -> Option<CompilationError> {
+    let start = stderr.find("error: failed to run custom build command")?;
+    let output = stderr[start..]
+        .split_once("--- stderr")
+        .map(|(_, output)| output)
+        .unwrap_or(&stderr[start..]);
+    let output: Vec<&str> = output
+        .lines()
+        .map(str::trim)
+        .take_while(|line| !line.starts_with("stack backtrace:"))
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    // A panic says where, e.g. "thread 'main' panicked at build.rs:2:5:"
+    let location = output
+        .iter()
+        .find_map(|line| line.split_once("panicked at build.rs:"))
+        .and_then(|(_, location)| {
+            let mut parts = location.trim_end_matches(':').split(':');
+            Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+        });
+
+    let mut error = CompilationError {
+        file: Some("build.rs".to_string()),
+        line: location.map(|(line, _)| line),
+        column: location.map(|(_, column)| column),
+        category: Some(ErrorCategory::BuildScript),
+        ..CompilationError::new(ErrorLevel::Error, "The build script failed")
+    };
+    if !output.is_empty() {
+        error.notes.push(output.join("\n"));
+    }
+    Some(error)
+}
+
+/// Whether a diagnostic reports the linker failing
+fn is_link_failure(error: &CompilationError) -> bool {
+    matches!(error.level, ErrorLevel::Error)
+        && error.code.is_none()
+        && error.message.contains("linking with `")
+}
+
+/// Restate a raw linker failure as a short `Link` error, keeping the full
+/// linker output as a note
+///
+/// Recognizes missing libraries and undefined symbols from GNU ld, lld, the
+/// macOS linker and MSVC's link.exe, and suggests a fix for them.
+fn link_error(error: CompilationError) -> CompilationError {
+    let output = error.message.as_str();
+
+    let missing_library = [
+        "cannot find -l",
+        "unable to find library -l",
+        "library not found for -l",
+    ]
+    .iter()
+    .find_map(|marker| {
+        let (_, rest) = output.split_once(marker)?;
+        rest.split(|c: char| c.is_whitespace() || c == ':' || c == '\'')
+            .next()
+            .filter(|name| !name.is_empty())
+    })
+    .or_else(|| {
+        // link.exe: cannot open input file 'foo.lib'
+        let (_, rest) = output.split_once("cannot open input file '")?;
+        rest.split('\'').next()?.strip_suffix(".lib")
+    });
+
+    let undefined_symbol = ["undefined reference to `", "undefined symbol: "]
+        .iter()
+        .find_map(|marker| {
+            let (_, rest) = output.split_once(marker)?;
+            rest.split(['\'', '`', '\n']).next().map(str::trim)
+        });
+
+    let (message, suggestion) = match (missing_library, undefined_symbol) {
+        (Some(library), _) => (
+            format!("Linking failed: the system library `{}` was not found", library),
+            Some(format!(
+                "Install the `{0}` library (on Debian/Ubuntu usually the `lib{0}-dev` package), \
+                 or remove the `#[link(name = \"{0}\")]` attribute if it isn't needed",
+                library
+            )),
+        ),
+        (None, Some(symbol)) => (
+            format!("Linking failed: undefined symbol `{}`", symbol),
+            Some(format!(
+                "Make sure the library that defines `{}` is linked, \
+                 e.g. with `#[link(name = \"...\")]`",
+                symbol
+            )),
+        ),
+        (None, None) => ("Linking failed".to_string(), None),
+    };
+
+    CompilationError {
+        message,
+        suggestion,
+        notes: vec![error.message.clone()],
+        category: Some(ErrorCategory::Link),
+        ..error
+    }
+}
+
+const NIGHTLY_REQUIRED_FOR_SANITIZER: &str =
+    "Sanitizers require the nightly toolchain. Install it with `rustup toolchain install nightly`.";
+
+const WASMTIME_REQUIRED_FOR_WASI: &str =
+    "Running in WASI mode requires wasmtime. Install it from https://wasmtime.dev.";
+
+const NIGHTLY_REQUIRED_FOR_UNPRETTY: &str =
+    "Expanding macros and printing HIR use `rustc -Zunpretty`, which requires the nightly \
+     toolchain. Install it with `rustup toolchain install nightly`.";
+
+const NIGHTLY_REQUIRED_FOR_PUBLIC_API: &str =
+    "Listing the public API uses rustdoc JSON output, which requires the nightly toolchain. \
+     Install it with `rustup toolchain install nightly`.";
+
+/// Walk a rustdoc JSON crate from its root module, collecting public items
+fn collect_public_api(krate: &serde_json::Value, source: &str) -> Vec<ApiItem> {
+    let index = &krate["index"];
+    let item = |id: &serde_json::Value| {
+        let key = match id {
+            serde_json::Value::String(id) => id.clone(),
+            id => id.to_string(),
+        };
+        index.get(key)
+    };
+    let ids = |value: &serde_json::Value| value.as_array().cloned().unwrap_or_default();
+
+    let mut items = Vec::new();
+    let mut modules = vec![(krate["root"].clone(), String::new())];
+
+    while let Some((module_id, prefix)) = modules.pop() {
+        let Some(module) = item(&module_id) else {
+            continue;
+        };
+
+        for id in ids(&module["inner"]["module"]["items"]) {
+            let Some(entry) = item(&id).filter(|entry| entry["visibility"] == "public") else {
+                continue;
+            };
+            let name = entry["name"].as_str().unwrap_or_default();
+            let path = format!("{}{}", prefix, name);
+            let Some((inner_kind, inner)) = entry["inner"].as_object().and_then(|o| o.iter().next())
+            else {
+                continue;
+            };
+
+            let kind = match inner_kind.as_str() {
+                "module" => {
+                    modules.push((id.clone(), format!("{}::", path)));
+                    continue;
+                }
+                "function" => ApiItemKind::Function,
+                "struct" => ApiItemKind::Struct,
+                "enum" => ApiItemKind::Enum,
+                "trait" => ApiItemKind::Trait,
+                "type_alias" => ApiItemKind::TypeAlias,
+                "constant" => ApiItemKind::Constant,
+                "static" => ApiItemKind::Static,
+                _ => continue,
+            };
+            items.push(api_item(kind, path.clone(), entry, source));
+
+            // Inherent impls written in the source; trait and synthetic impls have no use here
+            for impl_id in ids(&inner["impls"]) {
+                let Some(imp) = item(&impl_id) else {
+                    continue;
+                };
+                if !imp["inner"]["impl"]["trait"].is_null() || imp["span"].is_null() {
+                    continue;
+                }
+                for method_id in ids(&imp["inner"]["impl"]["items"]) {
+                    let Some(method) = item(&method_id).filter(|m| {
+                        m["visibility"] == "public" && m["inner"].get("function").is_some()
+                    }) else {
+                        continue;
+                    };
+                    let method_name = method["name"].as_str().unwrap_or_default();
+                    let method_path = format!("{}::{}", path, method_name);
+                    items.push(api_item(ApiItemKind::Function, method_path, method, source));
+                }
+            }
+        }
+    }
+
+    items.sort_by_key(|item| item.line);
+    items
+}
+
+/// Build an `ApiItem`, taking the signature from the item's span in `source`
+fn api_item(kind: ApiItemKind, path: String, entry: &serde_json::Value, source: &str) -> ApiItem {
+    // rustdoc's lines and columns are both 1-based, with `end` just past the
+    // item; `proc_macro2` counts columns from 0
+    let position = |key: &str| {
+        let at = |index: usize| entry["span"][key][index].as_u64().map(|n| n as usize);
+        at(0).zip(at(1)).map(|(line, column)| proc_macro2::LineColumn {
+            line,
+            column: column.saturating_sub(1),
+        })
+    };
+    let begin = position("begin");
+    let signature = match begin.zip(position("end")) {
+        Some((begin, end)) if begin.line >= 1 && end.line <= source.lines().count() => {
+            declaration(&source_text(source, begin, end, 0))
+        }
+        _ => String::new(),
+    };
+
+    ApiItem {
+        kind,
+        path,
+        signature,
+        line: begin.map(|begin| begin.line),
+    }
+}
+
+/// An item's declaration without its body, on one line: a function up to its
+/// block, a type up to its fields or variants, a constant up to its value
+///
+/// Found by parsing the item, so a `;` or `{` in its types, as in `[u8; 4]`,
+/// doesn't end it early. Text that doesn't parse as an item is kept whole.
+fn declaration(item_text: &str) -> String {
+    use syn::spanned::Spanned;
+
+    let bounds = syn::parse_str::<syn::Item>(item_text).ok().and_then(|item| {
+        let (vis, body) = match &item {
+            syn::Item::Fn(f) => (&f.vis, f.block.brace_token.span.open()),
+            syn::Item::Struct(s) => match &s.fields {
+                syn::Fields::Named(fields) => (&s.vis, fields.brace_token.span.open()),
+                _ => (&s.vis, s.semi_token?.span),
+            },
+            syn::Item::Enum(e) => (&e.vis, e.brace_token.span.open()),
+            syn::Item::Trait(t) => (&t.vis, t.brace_token.span.open()),
+            syn::Item::Type(t) => (&t.vis, t.semi_token.span),
+            syn::Item::Const(c) => (&c.vis, c.eq_token.span),
+            syn::Item::Static(s) => (&s.vis, s.eq_token.span),
+            _ => return None,
+        };
+        // Outer attributes and doc comments aren't part of the declaration
+        let start = match vis {
+            syn::Visibility::Inherited => item.span().start(),
+            vis => vis.span().start(),
+        };
+        Some((start, body.start()))
+    });
+    let text = match bounds {
+        Some((start, end)) => source_text(item_text, start, end, 0),
+        None => item_text.to_string(),
+    };
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// A failed result carrying a single error, for failures detected before compiling
+/// A failed result with `errors`, if there are any
+fn denied_result(errors: Vec<CompilationError>) -> Option<CompilationResult> {
+    let mut errors = errors.into_iter();
+    let mut result = failed_result(errors.next()?);
+    result.errors.extend(errors);
+    Some(result)
+}
+
+/// A failed result for a manifest problem `message`, pointing at `Cargo.toml`
+fn dependency_failure(message: String) -> CompilationResult {
+    failed_result(CompilationError {
+        file: Some("Cargo.toml".to_string()),
+        category: Some(ErrorCategory::Dependency),
+        ..CompilationError::new(ErrorLevel::Error, message)
+    })
+}
+
+pub(crate) fn failed_result(error: CompilationError) -> CompilationResult {
+    CompilationResult {
+        success: false,
+        errors: vec![error],
+        warnings: Vec::new(),
+        notes: Vec::new(),
+        help: Vec::new(),
+        stdout: String::new(),
+        stderr: String::new(),
+        command: None,
+        duration_ms: 0,
+        timed_out: false,
+        cached: false,
+        advisories: Vec::new(),
+        lockfile: None,
+        line_offset: 0,
+        wrap_mode: WrapMode::Auto,
+        lint_groups: BTreeMap::new(),
+    }
+}
+
+/// rustc with JSON output, emitting only metadata to skip codegen
+/// Metadata is written to `out_dir` rather than `/dev/null`, which doesn't
+/// exist on Windows
+fn quick_check_command(
+    source_file: &Path,
+    out_dir: &Path,
+    crate_type: CrateType,
+    toolchain: Option<&str>,
+) -> Command {
+    let mut command = rustc_command(source_file, crate_type, None, toolchain);
+    command.arg("--emit=metadata").arg("--out-dir").arg(out_dir);
+    command
+}
+
+/// rustc with JSON diagnostics, compiling `source_file` as a crate of
+/// `crate_type`; without an edition rustc's default, 2015, applies
+fn rustc_command(
+    source_file: &Path,
+    crate_type: CrateType,
+    edition: Option<Edition>,
+    toolchain: Option<&str>,
+) -> Command {
+    let mut command = Command::new("rustc");
+    if let Some(toolchain) = toolchain {
+        command.arg(format!("+{}", toolchain));
+    }
+    command
+        .arg(format!("--crate-type={}", crate_type.rustc_name()))
+        .arg("--crate-name=blockly_check")
+        .arg("--error-format=json");
+    if let Some(edition) = edition {
+        command.arg(format!("--edition={}", edition.as_str()));
+    }
+    command.arg(source_file);
+    command
+}
+
+/// The crate type rustc checks code as; `no_std` code is always a library
+pub(crate) fn rustc_crate_type(options: &CheckOptions) -> CrateType {
+    match options.crate_type {
+        CrateType::Bin if options.no_std => CrateType::Lib,
+        crate_type => crate_type,
+    }
+}
+
+/// Why a check can't be done without cargo, if it can't
+fn needs_cargo(options: &CheckOptions) -> Option<&'static str> {
+    if options.all_dependencies().next().is_some() {
+        Some("Dependencies can only be built with cargo")
+    } else if options.build_script.is_some() {
+        Some("Build scripts can only be run with cargo")
+    } else if options.manifest.is_some() || !options.manifest_extra.is_empty() {
+        Some("A custom Cargo.toml can only be used with cargo")
+    } else {
+        None
+    }
+}
+
+/// Run a built program with the run options' stdin and time limit
+fn run_program(
+    compiler: &RustCompiler,
+    command: Command,
+    compilation: CompilationResult,
+    options: &RunOptions,
+) -> Result<RunResult, CompilerError> {
+    let run_started = Instant::now();
+    let stdin = options.stdin.as_deref().map(str::as_bytes);
+    let cancel = options.check.cancel.as_ref();
+    let (output, timed_out) =
+        compiler.run_process(command, stdin, Some(options.time_limit), cancel, None)?;
+    if is_cancelled(cancel) {
+        return Err(CompilerError::Cancelled);
+    }
+    let duration_ms = run_started.elapsed().as_millis() as u64;
+
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    Ok(RunResult {
+        compilation,
+        exit_code: output.status.code(),
+        stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+        out_of_fuel: options.mode == RunMode::Wasi && stderr.contains("all fuel consumed"),
+        stderr,
+        duration_ms,
+        timed_out,
+    })
+}
+
+/// Write the crate root to `src/main.rs` under `dir`, as in a cargo project,
+/// with the assets next to it; returns the path relative to `dir`, so
+/// diagnostics name the same file as cargo's
+fn write_rustc_source(
+    dir: &Path,
+    source: &str,
+    assets: &HashMap<String, String>,
+) -> Result<PathBuf, CompilerError> {
+    let src_dir = dir.join("src");
+    fs::create_dir_all(&src_dir)?;
+    fs::write(src_dir.join("main.rs"), source)?;
+    write_assets(&src_dir, assets)?;
+    Ok(PathBuf::from("src/main.rs"))
+}
+
+/// Write asset files, keyed by relative path, under `dir`
+fn write_assets(dir: &Path, assets: &HashMap<String, String>) -> Result<(), CompilerError> {
+    for (name, contents) in assets {
+        let path = dir.join(sanitize_relative_path(name)?);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)?;
+    }
+    Ok(())
+}
+
+/// The dependency graph in `cargo metadata` output, without the root package
+fn parse_dependency_tree(metadata: &serde_json::Value) -> Vec<DependencyNode> {
+    let str_field = |value: &serde_json::Value, key: &str| {
+        value.get(key).and_then(|v| v.as_str()).unwrap_or_default().to_string()
+    };
+
+    // Package ids are opaque; name them `name@version` instead
+    let packages: HashMap<String, (String, String)> = metadata
+        .get("packages")
+        .and_then(|p| p.as_array())
+        .into_iter()
+        .flatten()
+        .map(|package| {
+            let name_version = (str_field(package, "name"), str_field(package, "version"));
+            (str_field(package, "id"), name_version)
+        })
+        .collect();
+    let display_id = |id: &str| {
+        packages
+            .get(id)
+            .map(|(name, version)| format!("{}@{}", name, version))
+            .unwrap_or_else(|| id.to_string())
+    };
+
+    let resolve = metadata.get("resolve");
+    let root = resolve.map(|r| str_field(r, "root")).unwrap_or_default();
+    let nodes: Vec<&serde_json::Value> = resolve
+        .and_then(|r| r.get("nodes"))
+        .and_then(|n| n.as_array())
+        .into_iter()
+        .flatten()
+        .collect();
+
+    let mut parents: HashMap<String, Vec<String>> = HashMap::new();
+    for node in &nodes {
+        let id = str_field(node, "id");
+        let dependencies = node.get("dependencies").and_then(|d| d.as_array());
+        for dependency in dependencies.into_iter().flatten().filter_map(|d| d.as_str()) {
+            parents.entry(dependency.to_string()).or_default().push(id.clone());
+        }
+    }
+
+    nodes
+        .iter()
+        .map(|node| str_field(node, "id"))
+        .filter(|id| *id != root)
+        .map(|id| {
+            let node = nodes.iter().find(|node| str_field(node, "id") == id);
+            let (name, version) = packages.get(&id).cloned().unwrap_or_default();
+            let node_parents = parents.get(&id).cloned().unwrap_or_default();
+            DependencyNode {
+                id: display_id(&id),
+                name,
+                version,
+                features: node
+                    .and_then(|node| node.get("features"))
+                    .and_then(|f| f.as_array())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|f| f.as_str().map(String::from))
+                    .collect(),
+                direct: node_parents.contains(&root),
+                parents: node_parents
+                    .iter()
+                    .filter(|parent| **parent != root)
+                    .map(|parent| display_id(parent))
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+/// Add the advisories from `cargo audit --json` output to `result`, or a note
+/// if the audit didn't run (`None` means it timed out)
+fn apply_audit(result: &mut CompilationResult, output: Option<&Output>) {
+    let report = output.and_then(|output| {
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).ok()
+    });
+    if let Some(report) = report {
+        result.advisories = parse_audit_report(&report);
+        return;
+    }
+
+    let message = match output {
+        None => "The dependency audit timed out".to_string(),
+        Some(output) if String::from_utf8_lossy(&output.stderr).contains("no such command") => {
+            "Dependencies weren't audited: install cargo-audit with `cargo install cargo-audit`"
+                .to_string()
+        }
+        Some(output) => format!(
+            "The dependency audit failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+    };
+    result.notes.push(CompilationError::new(ErrorLevel::Note, message));
+}
+
+/// Advisories in a `cargo audit --json` report: vulnerabilities, then warnings
+/// such as yanked or unmaintained crates
+fn parse_audit_report(report: &serde_json::Value) -> Vec<Advisory> {
+    let vulnerabilities = report
+        .pointer("/vulnerabilities/list")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .map(|entry| ("vulnerability", entry));
+    let warnings = report
+        .get("warnings")
+        .and_then(|w| w.as_object())
+        .into_iter()
+        .flatten()
+        .flat_map(|(kind, entries)| {
+            let entries = entries.as_array().into_iter().flatten();
+            entries.map(move |entry| (kind.as_str(), entry))
+        });
+
+    vulnerabilities
+        .chain(warnings)
+        .filter_map(|(kind, entry)| {
+            let package = entry.get("package")?;
+            let advisory = entry.get("advisory").filter(|a| !a.is_null());
+            let field = |key: &str| {
+                advisory
+                    .and_then(|a| a.get(key))
+                    .and_then(|v| v.as_str())
+                    .map(String::from)
+            };
+            Some(Advisory {
+                kind: kind.to_string(),
+                package: package.get("name")?.as_str()?.to_string(),
+                version: package.get("version")?.as_str()?.to_string(),
+                id: field("id"),
+                title: field("title"),
+                url: field("url"),
+            })
+        })
+        .collect()
+}
+
+/// Cargo succeeded but its output didn't name the artifact it built
+fn missing_artifact(artifact: &str) -> CompilerError {
+    CompilerError::ToolFailed(format!("Cargo did not report the built {}", artifact))
+}
+
+/// Path of the executable reported in raw cargo JSON output
+fn built_executable(stdout: &str) -> Option<PathBuf> {
+    stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter(|msg| msg.get("reason").and_then(|v| v.as_str()) == Some("compiler-artifact"))
+        .find_map(|msg| msg.get("executable")?.as_str().map(PathBuf::from))
+}
+
+/// Finished tests from libtest's JSON event stream
+fn parse_test_events(stdout: &str) -> Vec<TestCase> {
+    stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter(|event| event.get("type").and_then(|v| v.as_str()) == Some("test"))
+        .filter_map(|event| {
+            let outcome = match event.get("event")?.as_str()? {
+                "ok" => TestOutcome::Passed,
+                "failed" => TestOutcome::Failed,
+                "ignored" => TestOutcome::Ignored,
+                _ => return None,
+            };
+            Some(TestCase {
+                name: event.get("name")?.as_str()?.to_string(),
+                outcome,
+                stdout: event
+                    .get("stdout")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+                duration_ms: event
+                    .get("exec_time")
+                    .and_then(|v| v.as_f64())
+                    .map(|secs| (secs * 1000.0) as u64),
+            })
+        })
+        .collect()
+}
+
+/// Doctest results from the human-readable output of `cargo test --doc`
+///
+/// Rustdoc only emits JSON test events on nightly, so the `test <name> ... ok`
+/// lines and the `---- <name> stdout ----` failure sections are parsed instead.
+fn parse_doctest_output(stdout: &str) -> Vec<TestCase> {
+    let mut tests: Vec<TestCase> = stdout
+        .lines()
+        .filter_map(|line| {
+            let (name, outcome) = line.strip_prefix("test ")?.rsplit_once(" ... ")?;
+            let outcome = match outcome {
+                "ok" => TestOutcome::Passed,
+                "FAILED" => TestOutcome::Failed,
+                "ignored" => TestOutcome::Ignored,
+                _ => return None,
+            };
+            Some(TestCase {
+                name: name.to_string(),
+                outcome,
+                stdout: String::new(),
+                duration_ms: None,
+            })
+        })
+        .collect();
+
+    let mut current: Option<(&str, Vec<&str>)> = None;
+    for line in stdout.lines().chain(std::iter::once("failures:")) {
+        let header = line.strip_prefix("---- ").and_then(|l| l.strip_suffix(" stdout ----"));
+        if header.is_some() || line == "failures:" {
+            if let Some((name, output)) = current.take() {
+                if let Some(test) = tests.iter_mut().find(|t| t.name == name) {
+                    test.stdout = format!("{}\n", output.join("\n").trim_end());
+                }
+            }
+            current = header.map(|name| (name, Vec::new()));
+        } else if let Some((_, output)) = &mut current {
+            output.push(line);
+        }
+    }
+
+    tests
+}
+
+/// A `wasmtime run` invocation for `module`, stopping it after `fuel`
+/// instructions if set. The module gets no preopened directories, and no
+/// environment variables besides `env`.
+fn wasmtime_command(module: &Path, fuel: Option<u64>, env: &BTreeMap<String, String>) -> Command {
+    let mut command = Command::new("wasmtime");
+    command.arg("run");
+    if let Some(fuel) = fuel {
+        command.arg("-W").arg(format!("fuel={}", fuel));
+    }
+    for (key, value) in env {
+        command.arg("--env").arg(format!("{}={}", key, value));
+    }
+    command.arg(module);
+    command
+}
+
+/// Span covering a run of `command`, e.g. a cargo invocation
+fn process_span(command: &Command) -> tracing::Span {
+    tracing::debug_span!(
+        "process",
+        program = ?command.get_program(),
+        args = ?command.get_args().collect::<Vec<_>>()
+    )
+}
+
+/// Run `command` with `stdin` as its input, killing it once `timeout` passes
+/// or `cancel` is cancelled, and passing each line of its stdout to `on_line`
+///
+/// Returns its output and whether it was killed; output written before the
+/// kill is kept. The child runs in a process tree of its own, so killing it
+/// also kills the processes it started, such as rustc and build scripts under
+/// cargo. The child and the processes it starts are held to `limits`.
+fn output_with_timeout(
+    mut command: Command,
+    limits: &ResourceLimits,
+    stdin: Option<&[u8]>,
+    timeout: Option<Duration>,
+    cancel: Option<&CancellationToken>,
+    on_line: Option<LineHandler>,
+) -> std::io::Result<(Output, bool)> {
+    let _span = process_span(&command).entered();
+    limits.apply(&mut command);
+    ProcessTree::own_group(&mut command);
+    let mut child = command
+        .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    #[cfg(unix)]
+    let tree = ProcessTree::new(child.id());
+    #[cfg(windows)]
+    let tree = {
+        use std::os::windows::io::AsRawHandle;
+        match ProcessTree::new(child.as_raw_handle(), limits) {
+            Ok(tree) => tree,
+            Err(error) => {
+                let _ = child.kill();
+                return Err(error);
+            }
+        }
+    };
+
+    // Feed stdin from a thread, so a program that doesn't read all of it can't
+    // block us; dropping the pipe afterwards signals end of input
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        let input = input.to_vec();
+        std::thread::spawn(move || {
+            let _ = pipe.write_all(&input);
+        });
+    }
+
+    // Drain both pipes while waiting so a chatty child can't block on a full pipe
+    let drain = |pipe: Option<Box<dyn Read + Send>>, mut on_line: Option<LineHandler>| {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let sink = buffer.clone();
+        let reader = std::thread::spawn(move || {
+            let Some(mut pipe) = pipe else {
+                return;
+            };
+            let mut chunk = [0; 8192];
+            let mut line = Vec::new();
+            let (mut captured, mut truncated) = (0, false);
+            while let Ok(read) = pipe.read(&mut chunk) {
+                if read == 0 {
+                    break;
+                }
+                // Keep reading past the cap, so the child doesn't block on a full pipe
+                let kept = read.min(MAX_CAPTURED_OUTPUT_BYTES - captured);
+                captured += kept;
+                let mut sink = sink.lock().unwrap();
+                sink.extend_from_slice(&chunk[..kept]);
+                if kept < read && !truncated {
+                    truncated = true;
+                    let note = format!("\n[output truncated after {} bytes]\n", captured);
+                    sink.extend_from_slice(note.as_bytes());
+                }
+                drop(sink);
+                let Some(on_line) = &mut on_line else {
+                    continue;
+                };
+                for &byte in &chunk[..read] {
+                    if byte == b'\n' {
+                        on_line(&String::from_utf8_lossy(&line));
+                        line.clear();
+                    } else {
+                        line.push(byte);
+                    }
+                }
+            }
+        });
+        (buffer, reader)
+    };
+    let stdout_pipe = child.stdout.take().map(|pipe| Box::new(pipe) as _);
+    let (stdout, stdout_reader) = drain(stdout_pipe, on_line);
+    let (stderr, stderr_reader) = drain(child.stderr.take().map(|pipe| Box::new(pipe) as _), None);
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let (status, killed) = loop {
+        if let Some(status) = child.try_wait()? {
+            break (status, false);
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) || is_cancelled(cancel) {
+            tracing::info!(cancelled = is_cancelled(cancel), "killing process tree");
+            tree.kill();
+            break (child.wait()?, true);
+        }
+
+        std::thread::sleep(Duration::from_millis(10));
+    };
+
+    // The readers finish once the pipes close, but grandchildren that outlive
+    // a child exiting on its own may hold them open, so they only get a short
+    // grace period before the rest of the tree is killed
+    let grace = Instant::now() + Duration::from_millis(200);
+    while !(stdout_reader.is_finished() && stderr_reader.is_finished()) && Instant::now() < grace {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+
+    drop(tree);
+
+    let output = Output {
+        status,
+        stdout: std::mem::take(&mut *stdout.lock().unwrap()),
+        stderr: std::mem::take(&mut *stderr.lock().unwrap()),
+    };
+    Ok((output, killed))
+}
+
+/// The Cargo.toml for a check project
+fn manifest(options: &CheckOptions) -> String {
+    if let Some(custom) = &options.manifest {
+        return custom.clone();
+    }
+
+    let mut cargo_toml = format!(
+        r#"[package]
+name = "blockly_check"
+version = "0.1.0"
+edition = "{}"
+
+[dependencies]
+"#,
+        options.edition.as_str()
+    );
+
+    for dependency in &options.dependencies {
+        cargo_toml.push_str(&dependency.manifest_entry());
+        cargo_toml.push('\n');
+    }
+
+    if options.crate_type == CrateType::ProcMacro {
+        cargo_toml.push_str("\n[lib]\nproc-macro = true\n");
+    }
+
+    if !options.build_dependencies.is_empty() {
+        cargo_toml.push_str("\n[build-dependencies]\n");
+        for dependency in &options.build_dependencies {
+            cargo_toml.push_str(&dependency.manifest_entry());
+            cargo_toml.push('\n');
+        }
+    }
+
+    if let Some(section) = options.profile.as_ref().and_then(BuildProfile::manifest_section) {
+        cargo_toml.push_str(&section);
+    }
+
+    // Lints of a tool like clippy go in their own table, without the prefix.
+    // Groups get a lower priority so levels set for their lints win.
+    let mut tables: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for (lint, level) in &options.lints {
+        let (tool, name) = lint.split_once("::").unwrap_or(("rust", lint));
+        let entry = if is_lint_group(tool, name) {
+            format!("{} = {{ level = \"{}\", priority = -1 }}", name, level.as_str())
+        } else {
+            format!("{} = \"{}\"", name, level.as_str())
+        };
+        tables.entry(tool).or_default().push(entry);
+    }
+    for (tool, entries) in tables {
+        cargo_toml.push_str(&format!("\n[lints.{}]\n{}\n", tool, entries.join("\n")));
+    }
+
+    if !options.manifest_extra.is_empty() {
+        cargo_toml.push('\n');
+        cargo_toml.push_str(&options.manifest_extra);
+        if !options.manifest_extra.ends_with('\n') {
+            cargo_toml.push('\n');
+        }
+    }
+
+    cargo_toml
+}
+
+/// Whether the check's manifest sets a `path` key, which could point a
+/// dependency or patch at a local crate, or can't be parsed to tell
+fn has_custom_path_key(options: &CheckOptions) -> bool {
+    ManifestSummary::parse(&manifest(options)).map_or(true, |summary| !summary.paths.is_empty())
+}
+
+/// What the dependency gates look for in a manifest, read from its parsed
+/// tables so that quoting, dotted keys or inline tables can't hide anything
+#[derive(Debug, Default)]
+struct ManifestSummary {
+    /// Entries of the `[dependencies]`, `[dev-dependencies]` and
+    /// `[build-dependencies]` tables, including target-specific ones
+    dependencies: usize,
+    /// Values of `path` keys anywhere in the manifest, such as path
+    /// dependencies, patches and target sources, and `file:` git URLs
+    paths: Vec<String>,
+    /// Whether `package.build` names a build script
+    build_script: bool,
+}
+
+impl ManifestSummary {
+    /// Parse `toml`, failing with the parser's message if it isn't valid TOML
+    fn parse(toml: &str) -> Result<Self, String> {
+        let table: toml::Table = toml.parse().map_err(|error: toml::de::Error| {
+            error.message().to_string()
+        })?;
+        // `[project]` is the old name of `[package]`
+        let package = ["package", "project"].iter().filter_map(|name| table.get(*name));
+        let build_script = package
+            .filter_map(|package| package.get("build"))
+            .any(|build| build.as_bool() != Some(false));
+
+        let targets = table.get("target").and_then(toml::Value::as_table);
+        let platforms = targets.into_iter().flat_map(|targets| targets.values());
+        let dependencies = std::iter::once(&table)
+            .chain(platforms.filter_map(toml::Value::as_table))
+            .map(dependency_count)
+            .sum();
+
+        let mut paths = Vec::new();
+        collect_paths(&toml::Value::Table(table), &mut paths);
+        Ok(Self {
+            dependencies,
+            paths,
+            build_script,
+        })
+    }
+}
+
+/// Add the `path` values in `value` and the tables and arrays in it to
+/// `paths`, along with git URLs of local repositories
+fn collect_paths(value: &toml::Value, paths: &mut Vec<String>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                match (key.as_str(), value) {
+                    ("path", toml::Value::String(path)) => paths.push(path.clone()),
+                    ("git", toml::Value::String(url)) if url.starts_with("file:") => {
+                        paths.push(url.clone())
+                    }
+                    _ => collect_paths(value, paths),
+                }
+            }
+        }
+        toml::Value::Array(values) => values.iter().for_each(|value| collect_paths(value, paths)),
+        _ => {}
+    }
+}
+
+/// Write a virtual workspace manifest and each member's files into `dir`
+fn write_workspace(
+    dir: &Path,
+    crates: &[WorkspaceCrate],
+) -> Result<(), CompilerError> {
+    let members: Vec<String> = crates.iter().map(|krate| toml_string(&krate.name)).collect();
+    fs::create_dir_all(dir)?;
+    fs::write(
+        dir.join("Cargo.toml"),
+        format!("[workspace]\nresolver = \"2\"\nmembers = [{}]\n", members.join(", ")),
+    )?;
+
+    for krate in crates {
+        let crate_dir = dir.join(&krate.name);
+        fs::create_dir_all(&crate_dir)?;
+        fs::write(crate_dir.join("Cargo.toml"), &krate.manifest)?;
+        for (name, contents) in &krate.files {
+            let path = crate_dir.join(sanitize_relative_path(&name.to_string_lossy())?);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, contents)?;
+        }
+    }
+    Ok(())
+}
+
+/// A workspace member with a `path` key leading outside the workspace's
+/// members, e.g. to a crate that isn't one, and that path
+///
+/// Members whose manifest can't be parsed are skipped; `workspace_problem`
+/// rejects them.
+fn outside_path_dependency(crates: &[WorkspaceCrate]) -> Option<(&WorkspaceCrate, String)> {
+    crates.iter().find_map(|krate| {
+        let summary = ManifestSummary::parse(&krate.manifest).ok()?;
+        let outside = summary.paths.into_iter().find(|path| !is_in_workspace(krate, path, crates))?;
+        Some((krate, outside))
+    })
+}
+
+/// Whether `path`, relative to the directory of `krate`, stays inside one of
+/// the workspace's `crates`
+fn is_in_workspace(krate: &WorkspaceCrate, path: &str, crates: &[WorkspaceCrate]) -> bool {
+    // A local git repository is never a member
+    if path.starts_with("file:") {
+        return false;
+    }
+    let mut parts = vec![krate.name.as_str()];
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str().unwrap_or_default()),
+            Component::CurDir => {}
+            Component::ParentDir if !parts.is_empty() => {
+                parts.pop();
+            }
+            _ => return false,
+        }
+    }
+    parts.first().is_some_and(|member| crates.iter().any(|other| other.name == *member))
+}
+
+/// How many dependencies the dependency tables in `table` declare, under
+/// their current names or the underscored ones cargo still accepts
+fn dependency_count(table: &toml::Table) -> usize {
+    let names = [
+        "dependencies",
+        "dev-dependencies",
+        "build-dependencies",
+        "dev_dependencies",
+        "build_dependencies",
+    ];
+    names
+        .iter()
+        .filter_map(|name| table.get(*name).and_then(toml::Value::as_table))
+        .map(|dependencies| dependencies.len())
+        .sum()
+}
+
+/// The Rust files of several crates joined into one source, so the gates and
+/// linters can look at them together like at a single crate's code
+struct JoinedSources {
+    code: String,
+    /// Each file with the line of `code` it starts on
+    starts: Vec<(usize, String)>,
+}
+
+impl JoinedSources {
+    fn new(files: &[(String, &str)]) -> Self {
+        let mut code = String::new();
+        let mut starts = Vec::new();
+        for (file, contents) in files {
+            starts.push((code.lines().count() + 1, file.clone()));
+            code.push_str(contents);
+            if !contents.ends_with('\n') {
+                code.push('\n');
+            }
+        }
+        Self { code, starts }
+    }
+
+    /// Point diagnostics on the joined code, which name no file, at the file
+    /// and line they're in
+    fn split_positions(&self, result: &mut CompilationResult) {
+        let diagnostics = result.errors.iter_mut().chain(&mut result.warnings);
+        for error in diagnostics.chain(&mut result.notes).chain(&mut result.help) {
+            let Some(line) = error.line.filter(|_| error.file.is_none()) else {
+                continue;
+            };
+            let Some((start, file)) = self.starts.iter().rev().find(|(start, _)| *start <= line)
+            else {
+                continue;
+            };
+            error.file = Some(file.clone());
+            error.line = Some(line - start + 1);
+            error.line_end = error.line_end.map(|end| end.saturating_sub(start - 1).max(1));
+        }
+    }
+}
+
+/// Fail `result` if the compiler was killed by a signal, e.g. for exceeding its
+/// CPU time limit, since it then leaves no diagnostics of its own
+fn report_killed_compiler(result: &mut CompilationResult, output: &Output) {
+    if output.status.success() || !result.errors.is_empty() {
+        return;
+    }
+
+    // Cargo names the signal its rustc was killed by; bare rustc has no exit code
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let signal = match stderr.find("(signal: ") {
+        Some(start) => stderr[start + 1..].split(')').next().unwrap_or_default().to_string(),
+        None if output.status.code().is_none() => output.status.to_string(),
+        None => return,
+    };
+    result.errors.push(CompilationError {
+        category: Some(ErrorCategory::ResourceLimit),
+        ..CompilationError::new(
+            ErrorLevel::Error,
+            format!(
+                "The compiler was killed ({}), e.g. for exceeding its memory or CPU time limit",
+                signal
+            ),
+        )
+    });
+    result.success = false;
+}
+
+/// Whether a reused project already has this check's dependencies resolved,
+/// i.e. it has the requested lockfile (or any, if none was requested) and its
+/// manifest wouldn't change
+fn dependencies_resolved(project_dir: &Path, options: &CheckOptions) -> bool {
+    let lockfile = fs::read_to_string(project_dir.join("Cargo.lock")).ok();
+    let lockfile_matches = match &options.lockfile {
+        Some(requested) => lockfile.as_ref() == Some(requested),
+        None => lockfile.is_some(),
+    };
+    lockfile_matches
+        && fs::read_to_string(project_dir.join("Cargo.toml")).ok() == Some(manifest(options))
+}
+
+/// The project's `Cargo.lock`, if the check has dependencies
+fn resolved_lockfile(project_dir: &Path, options: &CheckOptions) -> Option<String> {
+    options.all_dependencies().next()?;
+    fs::read_to_string(project_dir.join("Cargo.lock")).ok()
+}
+
+/// A failed result with the resolution error if a dependency is missing,
+/// yanked or otherwise unresolvable, or `None` if resolution succeeded
+fn preflight_failure(output: &Output) -> Option<CompilationResult> {
+    if output.status.success() {
+        return None;
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let message = match stderr.find("error:") {
+        Some(start) => stderr[start..].trim_end().to_string(),
+        None => stderr.trim_end().to_string(),
+    };
+
+    let error = CompilationError {
+        file: Some("Cargo.toml".to_string()),
+        category: Some(ErrorCategory::Dependency),
+        ..CompilationError::new(ErrorLevel::Error, message)
+    };
+
+    Some(CompilationResult {
+        stderr: stderr.to_string(),
+        ..failed_result(error)
+    })
+}
+
+/// Whether the sysroot of `toolchain` (or the active one) has `target`'s std/core
+fn target_installed(target: &str, toolchain: Option<&str>) -> bool {
+    let mut command = Command::new("rustc");
+    command.env("RUSTUP_AUTO_INSTALL", "0");
+    if let Some(toolchain) = toolchain {
+        command.arg(format!("+{}", toolchain));
+    }
+
+    let Ok(output) = command.args(["--print", "sysroot"]).output() else {
+        return false;
+    };
+    let sysroot = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    output.status.success()
+        && Path::new(&sysroot).join("lib/rustlib").join(target).join("lib").is_dir()
+}
+
+/// Replace mangled Rust symbol names in `text` with their demangled form,
+/// without the trailing hash
+fn demangle_symbols(text: &str) -> String {
+    let is_symbol_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '$');
+    let mut demangled = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(is_symbol_char) {
+        demangled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(|c: char| !is_symbol_char(c)).unwrap_or(rest.len());
+        let token = &rest[..end];
+        // macOS puts an extra `_` in front of every symbol
+        let symbol = if token.starts_with("__") { &token[1..] } else { token };
+
+        match rustc_demangle::try_demangle(symbol) {
+            Ok(name) if symbol.starts_with("_ZN") || symbol.starts_with("_R") => {
+                demangled.push_str(&format!("{:#}", name));
+            }
+            _ => demangled.push_str(token),
+        }
+        rest = &rest[end..];
+    }
+
+    demangled.push_str(rest);
+    demangled
+}
+
+/// Whether `name` is a lint group of `tool` (`rust` for the compiler's own)
+fn is_lint_group(tool: &str, name: &str) -> bool {
+    let groups: &[&str] = match tool {
+        "rust" => &[
+            "future_incompatible",
+            "keyword_idents",
+            "let_underscore",
+            "nonstandard_style",
+            "refining_impl_trait",
+            "rust_2018_compatibility",
+            "rust_2018_idioms",
+            "rust_2021_compatibility",
+            "rust_2024_compatibility",
+            "unused",
+            "warnings",
+        ],
+        "clippy" => &[
+            "all",
+            "cargo",
+            "complexity",
+            "correctness",
+            "nursery",
+            "pedantic",
+            "perf",
+            "restriction",
+            "style",
+            "suspicious",
+        ],
+        _ => &[],
+    };
+    groups.contains(&name)
+}
+
+/// Whether `name` looks like a lint name, optionally with a tool prefix such
+/// as `clippy::`
+fn is_valid_lint_name(name: &str) -> bool {
+    let (tool, lint) = name.split_once("::").unwrap_or(("rust", name));
+    [tool, lint].iter().all(|part| {
+        !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// Whether `name` is safe to write into Cargo.toml as a dependency name
+fn is_valid_crate_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+}
+
+/// Why a profile or lint name in `options` can't be written to the manifest,
+/// if one can't
+fn invalid_manifest_name(options: &CheckOptions) -> Option<String> {
+    if let Some(profile) = &options.profile {
+        if !is_valid_rustup_name(&profile.name) || profile.name.contains('.') {
+            return Some(format!("Invalid profile name `{}`", profile.name));
+        }
+    }
+    let lint = options.lints.keys().find(|lint| !is_valid_lint_name(lint))?;
+    Some(format!("Invalid lint name `{}`", lint))
+}
+
+/// Whether `name` is safe to pass as a toolchain or target name
+fn is_valid_rustup_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+}
+
+/// The host target triple, as reported by `rustc -vV`
+fn host_target() -> Result<String, CompilerError> {
+    let output = Command::new("rustc").arg("-vV").output()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(|host| host.trim().to_string())
+        .ok_or_else(|| {
+            CompilerError::Parse("Could not determine host target from rustc -vV".to_string())
+        })
+}
+
+/// Extract sanitizer reports (from the `ERROR:`/`WARNING:` header to the
+/// `SUMMARY:` line) from a program's stderr
+fn parse_sanitizer_reports(stderr: &str) -> Vec<CompilationError> {
+    let mut reports = Vec::new();
+    let mut current: Option<Vec<&str>> = None;
+
+    for line in stderr.lines() {
+        let is_header = ["AddressSanitizer", "ThreadSanitizer", "LeakSanitizer"]
+            .iter()
+            .any(|name| {
+                line.contains(&format!("ERROR: {}", name))
+                    || line.contains(&format!("WARNING: {}", name))
+            });
+
+        if is_header {
+            current = Some(vec![line]);
+        } else if let Some(lines) = current.as_mut() {
+            lines.push(line);
+            if line.starts_with("SUMMARY:") {
+                reports.push(sanitizer_report(lines));
+                current = None;
+            }
+        }
+    }
+
+    if let Some(lines) = current {
+        reports.push(sanitizer_report(&lines));
+    }
+
+    reports
+}
+
+/// Turn one sanitizer report into an error located at the first frame in the snippet
+fn sanitizer_report(lines: &[&str]) -> CompilationError {
+    let mut error = CompilationError {
+        category: Some(ErrorCategory::Runtime),
+        ..CompilationError::new(ErrorLevel::Error, lines.join("\n"))
+    };
+
+    // Frames look like `#0 0x... in blockly_check::main /tmp/.../src/main.rs:5:22`
+    let location = lines.iter().find_map(|line| {
+        let (_, rest) = line.split_once("/src/main.rs:")?;
+        let mut parts = rest.split(':');
+        let line = parts.next()?.trim().parse::<usize>().ok()?;
+        let column = parts.next().and_then(|c| c.trim().parse::<usize>().ok());
+        Some((line, column))
+    });
+
+    if let Some((line, column)) = location {
+        error.file = Some("src/main.rs".to_string());
+        error.line = Some(line);
+        error.column = column;
+    }
+
+    error
+}
+
+/// Describe a command for reporting, including the cargo/rustc related
+/// environment it inherits or sets
+fn describe_command(command: &Command) -> CommandLine {
+    let relevant = |key: &str| key.starts_with("CARGO") || key.starts_with("RUST");
+
+    let mut env: HashMap<String, String> = std::env::vars()
+        .filter(|(key, _)| relevant(key))
+        .collect();
+
+    for (key, value) in command.get_envs() {
+        let key = key.to_string_lossy().to_string();
+        match value {
+            Some(value) => {
+                env.insert(key, value.to_string_lossy().to_string());
+            }
+            None => {
+                env.remove(&key);
+            }
+        }
+    }
+
+    for (key, value) in env.iter_mut() {
+        if is_secret_env(key) {
+            *value = "***".to_string();
+        }
+    }
+
+    CommandLine {
+        program: command.get_program().to_string_lossy().to_string(),
+        args: command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect(),
+        env,
+        working_dir: command
+            .get_current_dir()
+            .map(|dir| dir.to_string_lossy().to_string()),
+    }
+}
+
+/// Whether an environment variable likely holds a credential
+fn is_secret_env(key: &str) -> bool {
+    let key = key.to_ascii_uppercase();
+    ["TOKEN", "SECRET", "PASSWORD", "CREDENTIAL", "KEY"]
+        .iter()
+        .any(|marker| key.contains(marker))
+}
+
+/// Directory a check runs in: a pooled project, or a temporary project that
+/// is removed on drop unless it's kept
+enum ProjectDir {
+    Pooled(PooledProject),
+    Temporary(PathBuf),
+    Kept(PathBuf),
+}
+
+impl ProjectDir {
+    fn path(&self) -> &Path {
+        match self {
+            ProjectDir::Pooled(project) => project.dir(),
+            ProjectDir::Temporary(dir) | ProjectDir::Kept(dir) => dir,
+        }
+    }
+}
+
+impl Drop for ProjectDir {
+    fn drop(&mut self) {
+        if let ProjectDir::Temporary(dir) = self {
+            let _span = tracing::debug_span!("cleanup", dir = %dir.display()).entered();
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
+
+impl Default for RustCompiler {
+    fn default() -> Self {
+        Self::new().expect("Failed to create RustCompiler")
+    }
+}
+
+fn default_temp_dir() -> PathBuf {
+    std::env::temp_dir().join("blockly_rust_check")
+}
+
+/// Options for a `RustCompiler`, applied by `build`
+///
+/// Anything left unset keeps the default `RustCompiler::new` uses.
+#[derive(Default)]
+pub struct RustCompilerBuilder {
+    temp_dir: Option<PathBuf>,
+    timeout: Option<Duration>,
+    slow_check_threshold: Option<Duration>,
+    toolchain: Option<String>,
+    result_cache_size: usize,
+    max_dependencies: Option<usize>,
+    shared_target_dir: Option<PathBuf>,
+    cargo_home: Option<PathBuf>,
+    format_edition: Option<Edition>,
+    project_pool: Option<PoolConfig>,
+    audit_dependencies: bool,
+    allow_path_dependencies: bool,
+    allow_build_scripts: bool,
+    nightly_for_feature_gates: bool,
+    keep_projects: bool,
+    resource_limits: ResourceLimits,
+    sandbox: Option<Sandbox>,
+    container: Option<ContainerConfig>,
+    deny_list: Option<DenyList>,
+    allowed_toolchains: Option<Vec<String>>,
+}
+
+impl RustCompilerBuilder {
+    /// Keep temporary projects under `dir`, created if missing
+    pub fn temp_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.temp_dir = Some(dir.into());
+        self
+    }
+
+    /// See `RustCompiler::set_timeout`
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// See `RustCompiler::set_slow_check_threshold`
+    pub fn slow_check_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_check_threshold = Some(threshold);
+        self
+    }
+
+    /// See `RustCompiler::set_toolchain`
+    pub fn toolchain(mut self, toolchain: impl Into<String>) -> Self {
+        self.toolchain = Some(toolchain.into());
+        self
+    }
+
+    /// See `RustCompiler::enable_result_cache`
+    pub fn result_cache(mut self, capacity: usize) -> Self {
+        self.result_cache_size = capacity;
+        self
+    }
+
+    /// See `RustCompiler::set_max_dependencies`
+    pub fn max_dependencies(mut self, limit: usize) -> Self {
+        self.max_dependencies = Some(limit);
+        self
+    }
+
+    /// See `RustCompiler::set_shared_target_dir`
+    pub fn shared_target_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.shared_target_dir = Some(dir.into());
+        self
+    }
+
+    /// See `RustCompiler::set_cargo_home`
+    pub fn cargo_home(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cargo_home = Some(dir.into());
+        self
+    }
+
+    /// See `RustCompiler::set_format_edition`
+    pub fn format_edition(mut self, edition: Edition) -> Self {
+        self.format_edition = Some(edition);
+        self
+    }
+
+    /// See `RustCompiler::enable_project_pool`
+    pub fn project_pool(mut self, config: PoolConfig) -> Self {
+        self.project_pool = Some(config);
+        self
+    }
+
+    /// See `RustCompiler::set_audit_dependencies`
+    pub fn audit_dependencies(mut self, enabled: bool) -> Self {
+        self.audit_dependencies = enabled;
+        self
+    }
+
+    /// See `RustCompiler::set_allow_path_dependencies`
+    pub fn allow_path_dependencies(mut self, allowed: bool) -> Self {
+        self.allow_path_dependencies = allowed;
+        self
+    }
+
+    /// See `RustCompiler::set_allow_build_scripts`
+    pub fn allow_build_scripts(mut self, allowed: bool) -> Self {
+        self.allow_build_scripts = allowed;
+        self
+    }
+
+    /// See `RustCompiler::set_nightly_for_feature_gates`
+    pub fn nightly_for_feature_gates(mut self, enabled: bool) -> Self {
+        self.nightly_for_feature_gates = enabled;
+        self
+    }
+
+    /// See `RustCompiler::set_keep_projects`
+    pub fn keep_projects(mut self, keep: bool) -> Self {
+        self.keep_projects = keep;
+        self
+    }
+
+    /// See `RustCompiler::set_resource_limits`
+    pub fn resource_limits(mut self, limits: ResourceLimits) -> Self {
+        self.resource_limits = limits;
+        self
+    }
+
+    /// See `RustCompiler::set_sandbox`
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    /// See `RustCompiler::set_container`
+    pub fn container(mut self, container: ContainerConfig) -> Self {
+        self.container = Some(container);
+        self
+    }
+
+    /// See `RustCompiler::set_deny_list`
+    pub fn deny_list(mut self, deny_list: DenyList) -> Self {
+        self.deny_list = Some(deny_list);
+        self
+    }
+
+    /// See `RustCompiler::set_allowed_toolchains`
+    pub fn allowed_toolchains<I>(mut self, toolchains: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.allowed_toolchains = Some(toolchains.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Create the temporary directory and the checker
+    pub fn build(self) -> Result<RustCompiler, CompilerError> {
+        let mut compiler =
+            RustCompiler::with_temp_dir(self.temp_dir.unwrap_or_else(default_temp_dir))?;
+        compiler.set_timeout(self.timeout);
+        if let Some(threshold) = self.slow_check_threshold {
+            compiler.set_slow_check_threshold(threshold);
+        }
+        compiler.set_toolchain(self.toolchain);
+        compiler.enable_result_cache(self.result_cache_size);
+        compiler.set_max_dependencies(self.max_dependencies);
+        compiler.set_shared_target_dir(self.shared_target_dir);
+        compiler.set_cargo_home(self.cargo_home);
+        if let Some(edition) = self.format_edition {
+            compiler.set_format_edition(edition);
+        }
+        if let Some(config) = self.project_pool {
+            compiler.enable_project_pool(config)?;
+        }
+        compiler.set_audit_dependencies(self.audit_dependencies);
+        compiler.set_allow_path_dependencies(self.allow_path_dependencies);
+        compiler.set_allow_build_scripts(self.allow_build_scripts);
+        compiler.set_nightly_for_feature_gates(self.nightly_for_feature_gates);
+        compiler.set_keep_projects(self.keep_projects);
+        compiler.set_resource_limits(self.resource_limits);
+        compiler.set_sandbox(self.sandbox);
+        compiler.set_container(self.container);
+        compiler.set_deny_list(self.deny_list);
+        compiler.set_allowed_toolchains(self.allowed_toolchains);
+        Ok(compiler)
+    }
+}
+
+/// The group of the lint `code`, from the note saying which group enabled it
+/// (`part of #[warn(unused)]`) or else from the lint's name; `None` for
+/// error codes
+fn lint_group(code: &str, message: &serde_json::Value) -> Option<LintGroup> {
+    if code.starts_with('E') && code[1..].bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let children = message.get("children").and_then(|c| c.as_array());
+    let notes = children.into_iter().flatten().filter_map(|child| child.get("message")?.as_str());
+    let enabled_by = notes.filter_map(|note| {
+        let (_, rest) =
+            note.split_once("part of `#[").or_else(|| note.split_once("implied by `#["))?;
+        let (_, group) = rest.split_once('(')?;
+        group.split_once(')').map(|(group, _)| group)
+    });
+    let mut named = enabled_by.filter_map(|group| match group {
+        "unused" => Some(LintGroup::Unused),
+        "nonstandard_style" | "clippy::style" => Some(LintGroup::Style),
+        "clippy::correctness" | "clippy::suspicious" => Some(LintGroup::Correctness),
+        _ => None,
+    });
+    if let Some(group) = named.next() {
+        return Some(group);
+    }
+
+    let group = if code.starts_with("unused_") || UNUSED_LINTS.contains(&code) {
+        LintGroup::Unused
+    } else if code.starts_with("deprecated") {
+        LintGroup::Deprecated
+    } else if STYLE_LINTS.contains(&code) || code.starts_with("clippy::") {
+        // Clippy's lints that warn by default are mostly style and complexity
+        LintGroup::Style
+    } else if CORRECTNESS_LINTS.contains(&code) {
+        LintGroup::Correctness
+    } else {
+        LintGroup::Other
+    };
+    Some(group)
+}
+
+/// Where `code` is documented: an error code like `E0308`, a rustc lint like
+/// `unused_variables`, or a Clippy lint like `clippy::needless_return`
+fn docs_url(code: &str) -> Option<String> {
+    let is_name = |name: &str| {
+        !name.is_empty()
+            && name.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_')
+    };
+
+    if let Some(lint) = code.strip_prefix("clippy::") {
+        return is_name(lint).then(|| format!("{}#{}", CLIPPY_LINTS_URL, lint));
+    }
+    let digits = code.strip_prefix('E').unwrap_or_default();
+    if digits.len() == 4 && digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Some(format!("{}/{}.html", ERROR_INDEX_URL, code));
+    }
+    if !is_name(code) {
+        return None;
+    }
+
+    let page = match default_lint_level(code)? {
+        LintLevel::Allow => "allow-by-default",
+        LintLevel::Warn => "warn-by-default",
+        LintLevel::Deny | LintLevel::Forbid => "deny-by-default",
+    };
+    Some(format!("{}/{}.html#{}", LINT_LISTING_URL, page, code.replace('_', "-")))
+}
+
+/// The level rustc gives the lint `name` unless configured otherwise, or
+/// `None` if it isn't in the lint tables
+fn default_lint_level(name: &str) -> Option<LintLevel> {
+    if DENY_BY_DEFAULT_LINTS.contains(&name) {
+        Some(LintLevel::Deny)
+    } else if WARN_BY_DEFAULT_LINTS.contains(&name) {
+        Some(LintLevel::Warn)
+    } else if ALLOW_BY_DEFAULT_LINTS.contains(&name) {
+        Some(LintLevel::Allow)
+    } else {
+        None
+    }
+}
+
+/// Convert a rustc JSON diagnostic (top-level or child) into a `CompilationError`
+fn parse_diagnostic(message: &serde_json::Value) -> Option<CompilationError> {
+    // Top-level diagnostics carry the rendered text; children only have a message
+    let text = message
+        .get("rendered")
+        .and_then(|v| v.as_str())
+        .or_else(|| message.get("message").and_then(|v| v.as_str()))?;
+
+    let level = message
+        .get("level")
+        .and_then(|v| v.as_str())
+        .unwrap_or("error");
+
+    // Borrow errors list related locations before the primary one
+    let spans = message.get("spans").and_then(|s| s.as_array());
+    let primary_span = spans.and_then(|spans| {
+        spans
+            .iter()
+            .find(|span| span.get("is_primary").and_then(|v| v.as_bool()) == Some(true))
+            .or_else(|| spans.first())
+    });
+    let (suggestion, suggestion_range) = suggested_replacement(message).unzip();
+    let code = message.get("code").and_then(|c| c.get("code")).and_then(|v| v.as_str());
+    let position = |key: &str| {
+        primary_span
+            .and_then(|span| span.get(key))
+            .and_then(|v| v.as_u64())
+            .map(|n| n as usize)
+    };
+
+    Some(CompilationError {
+        level: match level {
+            "error" => ErrorLevel::Error,
+            "warning" => ErrorLevel::Warning,
+            "note" => ErrorLevel::Note,
+            "help" => ErrorLevel::Help,
+            _ => ErrorLevel::Error,
+        },
+        message: text.to_string(),
+        code: code.map(String::from),
+        docs_url: code.and_then(docs_url),
+        lint_group: code.and_then(|code| lint_group(code, message)),
+        line: position("line_start"),
+        column: position("column_start"),
+        line_end: position("line_end"),
+        column_end: position("column_end"),
+        byte_start: position("byte_start"),
+        byte_end: position("byte_end"),
+        file: primary_span
+            .and_then(|span| span.get("file_name"))
+            .and_then(|v| v.as_str())
+            .map(String::from),
+        spans: spans.into_iter().flatten().filter_map(diagnostic_span).collect(),
+        suggestion,
+        suggestion_range,
+        notes: Vec::new(),
+        related: Vec::new(),
+        explanation: None,
+        friendly_message: None,
+        category: None,
+        missing_crate: None,
+        crate_name: None,
+    })
+}
+
+/// Convert a rustc JSON span into a `DiagnosticSpan`
+fn diagnostic_span(span: &serde_json::Value) -> Option<DiagnosticSpan> {
+    let position = |key: &str| span.get(key).and_then(|v| v.as_u64()).map(|n| n as usize);
+    Some(DiagnosticSpan {
+        file: span.get("file_name")?.as_str()?.to_string(),
+        line_start: position("line_start")?,
+        column_start: position("column_start")?,
+        line_end: position("line_end")?,
+        column_end: position("column_end")?,
+        byte_start: position("byte_start")?,
+        byte_end: position("byte_end")?,
+        is_primary: span.get("is_primary").and_then(|v| v.as_bool()).unwrap_or(false),
+        label: span.get("label").and_then(|v| v.as_str()).map(String::from),
+    })
+}
+
+/// The first suggested replacement in a diagnostic's own spans or its children's
+fn suggested_replacement(message: &serde_json::Value) -> Option<(String, SourceRange)> {
+    let spans = |message: &serde_json::Value| -> Vec<serde_json::Value> {
+        message
+            .get("spans")
+            .and_then(|s| s.as_array())
+            .cloned()
+            .unwrap_or_default()
+    };
+    let children = message.get("children").and_then(|c| c.as_array());
+
+    spans(message)
+        .into_iter()
+        .chain(children.into_iter().flatten().flat_map(spans))
+        .find_map(|span| {
+            let text = span.get("suggested_replacement")?.as_str()?.to_string();
+            let position = |key: &str| span.get(key).and_then(|v| v.as_u64()).map(|n| n as usize);
+            let range = SourceRange {
+                line_start: position("line_start")?,
+                column_start: position("column_start")?,
+                line_end: position("line_end")?,
+                column_end: position("column_end")?,
+            };
+            Some((text, range))
+        })
+}
+
+/// Text inserted before a snippet that gets wrapped in a main function
+const MAIN_PREFIX: &str = "fn main() {\n";
+
+/// Wrap code in a main function if it doesn't have one
+pub(crate) fn wrap_snippet(code: &str) -> String {
+    WrappedSnippet::new(code).source
+}
+
+/// A snippet made into a crate root, remembering where each part came from
+///
+/// Code with a `main` function is used as is. Otherwise the snippet is parsed
+/// as statements: items like structs, impls and functions stay at module
+/// level, everything else goes in the body of a generated `main`, and a
+/// trailing expression gets a semicolon so its value doesn't have to be `()`.
+/// If an item shares a line with a statement, or the code doesn't parse, the
+/// whole snippet goes in `main`.
+pub(crate) struct WrappedSnippet {
+    pub(crate) source: String,
+    /// `(source byte, code byte, length)` of each run copied from the snippet
+    runs: Vec<(usize, usize, usize)>,
+    /// The 1-based snippet line each line of `source` came from, `None` for
+    /// lines added by the wrapping
+    lines: Vec<Option<usize>>,
+    /// The mode the snippet was wrapped with
+    mode: WrapMode,
+}
+
+impl WrappedSnippet {
+    pub(crate) fn new(code: &str) -> Self {
+        if has_main_fn(code) {
+            return Self::build(code, |wrapped| wrapped.copy(code, 0..code.len()));
+        }
+        // Macro calls like `println!(..);` also parse as items, but are meant as statements
+        let items_only = syn::parse_file(code).is_ok_and(|file| {
+            file.items.iter().all(|item| match item {
+                syn::Item::Macro(item) => item.mac.path.is_ident("macro_rules"),
+                _ => true,
+            })
+        });
+        if items_only {
+            return Self::build(code, |wrapped| {
+                wrapped.copy(code, 0..code.len());
+                wrapped.end_line();
+                wrapped.push("\nfn main() {}\n");
+            });
+        }
+
+        let line_starts: Vec<usize> = std::iter::once(0)
+            .chain(code.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        let line_range = |first: usize, last: usize| {
+            line_starts[first]..line_starts.get(last + 1).copied().unwrap_or(code.len())
+        };
+        let last_line = line_starts.len() - 1;
+
+        let Some(parts) = snippet_parts(code, &line_starts) else {
+            return Self::build(code, |wrapped| {
+                wrapped.push(MAIN_PREFIX);
+                wrapped.copy(code, 0..code.len());
+                wrapped.push("\n}");
+            });
+        };
+
+        Self::build(code, |wrapped| {
+            for line in (0..=last_line).filter(|&line| parts.items[line]) {
+                wrapped.copy(code, line_range(line, line));
+            }
+            wrapped.end_line();
+            wrapped.push(MAIN_PREFIX);
+            for line in (0..=last_line).filter(|&line| !parts.items[line]) {
+                let range = line_range(line, line);
+                match parts.trailing_expr_end {
+                    Some(end) if range.start < end && end <= range.end => {
+                        wrapped.copy(code, range.start..end);
+                        wrapped.push(";");
+                        wrapped.copy(code, end..range.end);
+                    }
+                    _ => wrapped.copy(code, range),
+                }
+            }
+            wrapped.end_line();
+            wrapped.push("}");
+        })
+    }
+
+    /// Code wrapped as `mode` says
+    fn with_mode(code: &str, mode: &WrapMode) -> Self {
+        let mut wrapped = match mode {
+            WrapMode::Auto => Self::new(code),
+            WrapMode::Never => Self::build(code, |wrapped| wrapped.copy(code, 0..code.len())),
+            WrapMode::Template(template) => Self::build(code, |wrapped| {
+                let (before, after) =
+                    template.split_once(WRAP_PLACEHOLDER).unwrap_or((template, ""));
+                wrapped.push(before);
+                wrapped.copy(code, 0..code.len());
+                wrapped.push(after);
+            }),
+        };
+        wrapped.mode = mode.clone();
+        wrapped
+    }
+
+    /// Code checked as a `#![no_std]` library, with a stub panic handler if
+    /// it doesn't define one
+    fn no_std(code: &str) -> Self {
+        let mut wrapped = Self::build(code, |wrapped| {
+            if !code.contains("#![no_std]") {
+                wrapped.push("#![no_std]\n");
+            }
+            wrapped.copy(code, 0..code.len());
+            if !code.contains("#[panic_handler]") {
+                wrapped.push(PANIC_HANDLER_STUB);
+            }
+        });
+        wrapped.mode = WrapMode::Never;
+        wrapped
+    }
+
+    fn build(code: &str, fill: impl FnOnce(&mut Self)) -> Self {
+        let mut wrapped = WrappedSnippet {
+            source: String::new(),
+            runs: Vec::new(),
+            lines: Vec::new(),
+            mode: WrapMode::Auto,
+        };
+        fill(&mut wrapped);
+
+        let code_line = |byte: usize| code[..byte].matches('\n').count() + 1;
+        let mut start = 0;
+        for line in wrapped.source.split_inclusive('\n') {
+            let end = start + line.len();
+            let run = wrapped.runs.iter().find(|&&(from, _, len)| from < end && start < from + len);
+            let origin =
+                run.map(|&(from, code_from, _)| code_line(code_from + start.max(from) - from));
+            wrapped.lines.push(origin);
+            start = end;
+        }
+        wrapped
+    }
+
+    fn push(&mut self, text: &str) {
+        self.source.push_str(text);
+    }
+
+    /// Add `text`, made of whole lines, before the source
+    fn prepend(&mut self, text: &str) {
+        self.source.insert_str(0, text);
+        for (from, _, _) in &mut self.runs {
+            *from += text.len();
+        }
+        self.lines.splice(0..0, text.lines().map(|_| None));
+    }
+
+    /// Finish the current line, if there is one
+    fn end_line(&mut self) {
+        if !self.source.is_empty() && !self.source.ends_with('\n') {
+            self.source.push('\n');
+        }
+    }
+
+    fn copy(&mut self, code: &str, range: std::ops::Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let start = self.source.len();
+        match self.runs.last_mut() {
+            Some((from, code_from, len))
+                if *from + *len == start && *code_from + *len == range.start =>
+            {
+                *len += range.len()
+            }
+            _ => self.runs.push((start, range.start, range.len())),
+        }
+        self.source.push_str(&code[range]);
+    }
+
+    /// The snippet line for a 1-based line of the source; added lines map to
+    /// the next copied line, or the last one at the end
+    pub(crate) fn code_line(&self, line: usize) -> usize {
+        let index = line.saturating_sub(1).min(self.lines.len().saturating_sub(1));
+        let after = self.lines.iter().skip(index).flatten().next();
+        let before = || self.lines.iter().take(index).flatten().last();
+        after.or_else(before).copied().unwrap_or(1)
+    }
+
+    /// The 1-based source line a snippet line was copied to
+    pub(crate) fn source_line(&self, line: usize) -> usize {
+        self.lines.iter().position(|&origin| origin == Some(line)).map_or(line, |index| index + 1)
+    }
+
+    /// The snippet byte for a source byte, clamped like `code_line`
+    fn code_byte(&self, byte: usize) -> usize {
+        let run = self.runs.iter().find(|&&(from, _, len)| byte <= from + len);
+        match run {
+            Some(&(from, code_from, _)) => code_from + byte.saturating_sub(from),
+            None => self.runs.last().map_or(0, |&(_, code_from, len)| code_from + len),
+        }
+    }
+
+    /// The snippet bytes for a range of source bytes, if it's all copied text
+    fn code_range(&self, start: usize, end: usize) -> Option<(usize, usize)> {
+        self.runs
+            .iter()
+            .find(|&&(from, _, len)| from <= start && end <= from + len)
+            .map(|&(from, code_from, _)| (code_from + start - from, code_from + end - from))
+    }
+
+    /// How many lines every snippet line was moved down by, or 0 if they
+    /// weren't all moved the same amount
+    fn line_offset(&self) -> usize {
+        let mut shifts = self
+            .lines
+            .iter()
+            .enumerate()
+            .filter_map(|(index, origin)| Some((index + 1).checked_sub((*origin)?)));
+        let first = shifts.next().flatten().unwrap_or(0);
+        if shifts.all(|shift| shift == Some(first)) {
+            first
+        } else {
+            0
+        }
+    }
+}
+
+/// How the lines of a snippet without `main` split into items and statements
+struct SnippetParts {
+    /// Whether each line belongs to an item
+    items: Vec<bool>,
+    /// Byte offset just past a trailing expression that needs a semicolon
+    trailing_expr_end: Option<usize>,
+}
+
+/// Split a snippet into item and statement lines, or `None` if it doesn't
+/// parse as statements or an item shares a line with a statement
+fn snippet_parts(code: &str, line_starts: &[usize]) -> Option<SnippetParts> {
+    use syn::spanned::Spanned;
+
+    let block = parse_block(code).ok()?;
+    // Block lines are 1-based and one below the code's
+    let position = |at: proc_macro2::LineColumn| {
+        let start = *line_starts.get(at.line.checked_sub(2)?)?;
+        let line = code[start..].split('\n').next().unwrap_or_default();
+        Some((at.line - 2, start + line.chars().take(at.column).map(char::len_utf8).sum::<usize>()))
+    };
+
+    let mut owners: Vec<Option<bool>> = vec![None; line_starts.len()];
+    for stmt in &block.stmts {
+        let is_item = match stmt {
+            syn::Stmt::Item(_) => true,
+            syn::Stmt::Macro(stmt) => stmt.mac.path.is_ident("macro_rules"),
+            _ => false,
+        };
+        let (first, _) = position(stmt.span().start())?;
+        let (last, _) = position(stmt.span().end())?;
+        for owner in &mut owners[first..=last] {
+            match owner {
+                Some(owned) if *owned != is_item => return None,
+                _ => *owner = Some(is_item),
+            }
+        }
+    }
+
+    // Comments and blank lines go with the code after them
+    let mut next = false;
+    let items = owners
+        .iter()
+        .rev()
+        .map(|owner| {
+            next = owner.unwrap_or(next);
+            next
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+
+    let trailing_expr_end = match block.stmts.last() {
+        Some(syn::Stmt::Expr(expr, None)) => Some(position(expr.span().end())?.1),
+        Some(syn::Stmt::Macro(stmt)) if stmt.semi_token.is_none() => {
+            Some(position(stmt.span().end())?.1)
+        }
+        _ => None,
+    };
+
+    Some(SnippetParts {
+        items,
+        trailing_expr_end,
+    })
+}
+
+/// Map the positions in a result for a wrapped snippet back onto the code as
+/// submitted, recording the line offset
+///
+/// Positions in code added by the wrapping are moved to the nearest line of
+/// the snippet.
+pub(crate) fn unwrap_positions(result: &mut CompilationResult, wrapped: &WrappedSnippet) {
+    unwrap_file_positions(result, wrapped, None);
+    result.line_offset = wrapped.line_offset();
+    result.wrap_mode = wrapped.mode.clone();
+}
+
+/// `unwrap_positions` for diagnostics in `file` only, such as the crate root
+/// of a project; with `None`, all diagnostics are in the snippet
+fn unwrap_file_positions(
+    result: &mut CompilationResult,
+    wrapped: &WrappedSnippet,
+    file: Option<&str>,
+) {
+    fn map(error: &mut CompilationError, wrapped: &WrappedSnippet, file: Option<&str>) {
+        for related in &mut error.related {
+            map(related, wrapped, file);
+        }
+        if file.is_some_and(|file| error.file.as_deref() != Some(file)) {
+            return;
+        }
+        let line = |line: usize| wrapped.code_line(line);
+        error.line = error.line.map(line);
+        error.line_end = error.line_end.map(line);
+        error.byte_start = error.byte_start.map(|byte| wrapped.code_byte(byte));
+        error.byte_end = error.byte_end.map(|byte| wrapped.code_byte(byte));
+        if let Some(range) = error.suggestion_range.as_mut() {
+            range.line_start = line(range.line_start);
+            range.line_end = line(range.line_end);
+        }
+        // Spans in other files, like a macro's definition, keep their positions
+        let in_file = |span: &&mut DiagnosticSpan| match file {
+            Some(file) => span.file == file,
+            None => span.file.starts_with("src/"),
+        };
+        for span in error.spans.iter_mut().filter(in_file) {
+            span.line_start = line(span.line_start);
+            span.line_end = line(span.line_end);
+            span.byte_start = wrapped.code_byte(span.byte_start);
+            span.byte_end = wrapped.code_byte(span.byte_end);
+        }
+    }
+
+    let diagnostics = result.errors.iter_mut().chain(&mut result.warnings);
+    for error in diagnostics.chain(&mut result.notes).chain(&mut result.help) {
+        map(error, wrapped, file);
+    }
+}
+
+/// The text of `code` between two span positions, shifted up `line_offset` lines
+fn source_text(
+    code: &str,
+    start: proc_macro2::LineColumn,
+    end: proc_macro2::LineColumn,
+    line_offset: usize,
+) -> String {
+    let lines: Vec<&str> = code.lines().collect();
+    let (first, last) = (start.line - 1 - line_offset, end.line - 1 - line_offset);
+    let mut text = String::new();
+    for (index, line) in lines.iter().enumerate().take(last + 1).skip(first) {
+        let from = if index == first { start.column } else { 0 };
+        let to = if index == last { end.column } else { line.chars().count() };
+        if index > first {
+            text.push('\n');
+        }
+        text.extend(line.chars().skip(from).take(to.saturating_sub(from)));
+    }
+    text
+}
+
+/// A `CompilationError` for a `syn` parse error on `line` of the code
+fn syntax_error(error: &syn::Error, line: usize) -> CompilationError {
+    let start = error.span().start();
+    let end = error.span().end();
+    // Spans are 0-based columns; an empty span highlights one character
+    let (line_end, column_end) = if (end.line, end.column) > (start.line, start.column) {
+        (line + (end.line - start.line), end.column + 1)
+    } else {
+        (line, start.column + 2)
+    };
+    CompilationError {
+        line: Some(line),
+        column: Some(start.column + 1),
+        line_end: Some(line_end),
+        column_end: Some(column_end),
+        ..CompilationError::new(ErrorLevel::Error, error.to_string())
+    }
+}
+
+/// Crate attribute added for `CheckOptions::forbid_unsafe`
+const FORBID_UNSAFE: &str = "#![forbid(unsafe_code)]\n";
+
+/// Panic handler added to `no_std` checks, which can't use std's
+const PANIC_HANDLER_STUB: &str = "
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+";
+
+/// The crate root checked for `code`: the code wrapped as `wrap_mode` says,
+/// or for `no_std` checks the code as a `#![no_std]` library; other libraries
+/// aren't wrapped in a main function
+pub(crate) fn check_source(code: &str, options: &CheckOptions) -> WrappedSnippet {
+    let mut wrapped = if options.no_std {
+        WrappedSnippet::no_std(code)
+    } else if options.is_library() && options.wrap_mode == WrapMode::Auto {
+        WrappedSnippet::with_mode(code, &WrapMode::Never)
+    } else {
+        WrappedSnippet::with_mode(code, &options.wrap_mode)
+    };
+    if options.forbid_unsafe {
+        wrapped.prepend(FORBID_UNSAFE);
+    }
+    wrapped
+}
+
+/// A single edit from a compiler suggestion, in byte offsets into the snippet
+#[derive(Debug, Clone)]
+struct Replacement {
+    byte_start: usize,
+    byte_end: usize,
+    text: String,
+}
+
+/// A machine-applicable suggestion, whose edits must be applied together
+#[derive(Debug, Clone)]
+struct Suggestion {
+    message: String,
+    edits: Vec<Replacement>,
+}
+
+/// Collect machine-applicable suggestions from raw cargo JSON output
+///
+/// Offsets are mapped back through `wrapped` to the submitted code, and
+/// suggestions touching code the wrapping added are dropped.
+fn machine_applicable_fixes(stdout: &str, wrapped: &WrappedSnippet) -> Vec<Suggestion> {
+    let mut fixes = Vec::new();
+
+    let messages = stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter_map(|msg| msg.get("message").cloned());
+
+    for message in messages {
+        let children = message.get("children").and_then(|c| c.as_array());
+        for child in children.into_iter().flatten() {
+            let spans = child.get("spans").and_then(|s| s.as_array());
+            let edits: Option<Vec<Replacement>> = spans
+                .into_iter()
+                .flatten()
+                .filter(|span| {
+                    span.get("suggestion_applicability").and_then(|v| v.as_str())
+                        == Some("MachineApplicable")
+                })
+                .map(|span| {
+                    let file = span.get("file_name").and_then(|v| v.as_str())?;
+                    let start = span.get("byte_start").and_then(|v| v.as_u64())? as usize;
+                    let end = span.get("byte_end").and_then(|v| v.as_u64())? as usize;
+                    let text = span.get("suggested_replacement").and_then(|v| v.as_str())?;
+
+                    if file != "src/main.rs" {
+                        return None;
+                    }
+
+                    let (byte_start, byte_end) = wrapped.code_range(start, end)?;
+                    Some(Replacement {
+                        byte_start,
+                        byte_end,
+                        text: text.to_string(),
+                    })
+                })
+                .collect();
+
+            if let Some(edits) = edits.filter(|edits| !edits.is_empty()) {
+                let message = child.get("message").and_then(|m| m.as_str()).unwrap_or_default();
+                fixes.push(Suggestion {
+                    message: message.to_string(),
+                    edits,
+                });
+            }
+        }
+    }
+
+    fixes
+}
+
+/// Apply non-overlapping suggestions to `code`, returning the fixed code and
+/// the indices of the applied suggestions, or `None` if none apply
+fn apply_fixes(code: &str, fixes: &[Suggestion]) -> Option<(String, Vec<usize>)> {
+    let mut chosen: Vec<&Replacement> = Vec::new();
+    let mut applied = Vec::new();
+
+    for (index, fix) in fixes.iter().enumerate() {
+        let fix = &fix.edits;
+        let overlaps = fix.iter().any(|edit| {
+            chosen
+                .iter()
+                .any(|other| edit.byte_start < other.byte_end && other.byte_start < edit.byte_end)
+        });
+        let in_bounds = fix.iter().all(|edit| {
+            code.is_char_boundary(edit.byte_start) && code.is_char_boundary(edit.byte_end)
+        });
+
+        if !overlaps && in_bounds {
+            chosen.extend(fix.iter());
+            applied.push(index);
+        }
+    }
+
+    if chosen.is_empty() {
+        return None;
+    }
+
+    // Apply from the end so earlier offsets stay valid
+    chosen.sort_by_key(|edit| std::cmp::Reverse(edit.byte_start));
+    let mut fixed = code.to_string();
+    for edit in chosen {
+        fixed.replace_range(edit.byte_start..edit.byte_end, &edit.text);
+    }
+
+    Some((fixed, applied))
+}
+
+/// 1-based line and column of byte `offset` in `code`
+fn line_column(code: &str, offset: usize) -> (usize, usize) {
+    let before = &code[..offset.min(code.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+/// Whether the snippet defines a top-level `fn main`
+///
+/// Parses the code with `syn` so that `fn main` inside strings or comments
+/// doesn't count. Falls back to a substring check if the code doesn't parse.
+pub(crate) fn has_main_fn(code: &str) -> bool {
+    match ParsedSnippet::parse(code) {
+        Some(ParsedSnippet::File(file)) => file
+            .items
+            .iter()
+            .any(|item| matches!(item, syn::Item::Fn(f) if f.sig.ident == "main")),
+        // Bare statements can't contain a top-level main
+        Some(ParsedSnippet::Block(_)) => false,
+        None => code.contains("fn main"),
+    }
+}
+
+/// A snippet parsed with `syn`: as a whole file, or failing that as the
+/// statements of a block
+pub(crate) enum ParsedSnippet {
+    File(syn::File),
+    Block(syn::Block),
+}
+
+impl ParsedSnippet {
+    /// Parse `code`, or `None` if it's neither a file nor statements
+    pub(crate) fn parse(code: &str) -> Option<Self> {
+        match syn::parse_file(code) {
+            Ok(file) => Some(Self::File(file)),
+            Err(_) => parse_block(code).ok().map(Self::Block),
+        }
+    }
+
+    /// How many lines the parsed positions are below the lines of the code
+    pub(crate) fn line_offset(&self) -> usize {
+        match self {
+            Self::File(_) => 0,
+            Self::Block(_) => 1,
+        }
+    }
+
+    pub(crate) fn visit<'ast>(&'ast self, visitor: &mut impl syn::visit::Visit<'ast>) {
+        match self {
+            Self::File(file) => visitor.visit_file(file),
+            Self::Block(block) => visitor.visit_block(block),
+        }
+    }
+}
+
+/// Parse `code` as the statements of a block, whose opening brace takes a
+/// line of its own
+pub(crate) fn parse_block(code: &str) -> syn::Result<syn::Block> {
+    syn::parse_str(&format!("{{\n{}\n}}", code))
+}
+
+/// Visit the arguments of a macro call when they parse as expressions, as
+/// `println!`, `assert!` and `vec!` arguments do; to `syn` they're only tokens
+pub(crate) fn visit_macro_arguments<V>(visitor: &mut V, mac: &syn::Macro)
+where
+    V: for<'ast> syn::visit::Visit<'ast>,
+{
+    use syn::punctuated::Punctuated;
+
+    let parser = Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated;
+    if let Ok(arguments) = mac.parse_body_with(parser) {
+        for argument in &arguments {
+            visitor.visit_expr(argument);
+        }
+    }
+}
+
+/// Report which of `names` are defined as items anywhere in `code`
+///
+/// Functions, methods, types, traits, constants, statics, modules and
+/// `macro_rules!` macros all count, including ones nested in modules, impls or
+/// function bodies. This is a pure parse; code that isn't a valid file is
+/// parsed as bare statements, and if that fails too no names are found.
+pub fn contains_symbols(code: &str, names: &[String]) -> HashMap<String, bool> {
+    use syn::visit::Visit;
+
+    #[derive(Default)]
+    struct Symbols(HashSet<String>);
+
+    impl<'ast> Visit<'ast> for Symbols {
+        fn visit_item(&mut self, item: &'ast syn::Item) {
+            let ident = match item {
+                syn::Item::Fn(item) => Some(&item.sig.ident),
+                syn::Item::Struct(item) => Some(&item.ident),
+                syn::Item::Enum(item) => Some(&item.ident),
+                syn::Item::Union(item) => Some(&item.ident),
+                syn::Item::Trait(item) => Some(&item.ident),
+                syn::Item::Type(item) => Some(&item.ident),
+                syn::Item::Const(item) => Some(&item.ident),
+                syn::Item::Static(item) => Some(&item.ident),
+                syn::Item::Mod(item) => Some(&item.ident),
+                syn::Item::Macro(item) => item.ident.as_ref(),
+                _ => None,
+            };
+            self.0.extend(ident.map(|ident| ident.to_string()));
+            syn::visit::visit_item(self, item);
+        }
+
+        fn visit_impl_item_fn(&mut self, method: &'ast syn::ImplItemFn) {
+            self.0.insert(method.sig.ident.to_string());
+            syn::visit::visit_impl_item_fn(self, method);
+        }
+
+        fn visit_trait_item_fn(&mut self, method: &'ast syn::TraitItemFn) {
+            self.0.insert(method.sig.ident.to_string());
+            syn::visit::visit_trait_item_fn(self, method);
+        }
+    }
+
+    let mut symbols = Symbols::default();
+    if let Some(parsed) = ParsedSnippet::parse(code) {
+        parsed.visit(&mut symbols);
+    }
+
+    names
+        .iter()
+        .map(|name| (name.clone(), symbols.0.contains(name)))
+        .collect()
+}
+
+fn invalid_asset_path(name: &str) -> CompilerError {
+    CompilerError::InvalidInput(format!("Invalid asset path: {}", name))
+}
+
+/// Validate a caller-supplied relative path so it can't escape the project directory
+fn sanitize_relative_path(name: &str) -> Result<PathBuf, CompilerError> {
+    let path = Path::new(name);
+    let mut sanitized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => sanitized.push(part),
+            Component::CurDir => {}
+            _ => return Err(invalid_asset_path(name)),
+        }
+    }
+
+    if sanitized.as_os_str().is_empty() {
+        return Err(invalid_asset_path(name));
+    }
+
+    Ok(sanitized)
+}
+
+/// Check if Rust toolchain is available
+pub fn is_rust_available() -> bool {
+    Command::new("rustc")
+        .arg("--version")
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Check if the nightly toolchain is installed
+pub fn is_nightly_available() -> bool {
+    is_toolchain_available("nightly")
+}
+
+/// Whether the toolchain used when none is requested is a nightly one
+fn is_default_toolchain_nightly() -> bool {
+    Command::new("rustc")
+        .arg("--version")
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).contains("-nightly"))
+        .unwrap_or(false)
+}
+
+/// The `#![feature(...)]` attributes at the start of `code`, as 1-based line
+/// numbers and the features each enables
+///
+/// Only the crate's inner attributes count, so the text in a string or a
+/// comment doesn't. Code that doesn't tokenize has none.
+fn feature_gates(code: &str) -> Vec<(usize, Vec<String>)> {
+    use syn::parse::{ParseStream, Parser};
+    use syn::punctuated::Punctuated;
+
+    let inner_attributes = |input: ParseStream| {
+        let attributes = syn::Attribute::parse_inner(input)?;
+        input.parse::<proc_macro2::TokenStream>()?;
+        Ok(attributes)
+    };
+    let Ok(attributes) = inner_attributes.parse_str(code) else {
+        return Vec::new();
+    };
+
+    let names = Punctuated::<syn::Ident, syn::Token![,]>::parse_terminated;
+    attributes
+        .iter()
+        .filter(|attribute| attribute.path().is_ident("feature"))
+        .filter_map(|attribute| {
+            let features = attribute.parse_args_with(names).ok()?;
+            let line = attribute.pound_token.span.start().line;
+            Some((line, features.iter().map(ToString::to_string).collect()))
+        })
+        .collect()
+}
+
+/// Check if a rustup toolchain such as `beta` or `1.75.0` is installed
+pub fn is_toolchain_available(toolchain: &str) -> bool {
+    Command::new("rustc")
+        .env("RUSTUP_AUTO_INSTALL", "0")
+        .arg(format!("+{}", toolchain))
+        .arg("--version")
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Check if the standard library for `target`, such as
+/// `wasm32-unknown-unknown`, is installed for the active toolchain
+pub fn is_target_installed(target: &str) -> bool {
+    is_valid_rustup_name(target) && target_installed(target, None)
+}
+
+/// Check if the wasmtime CLI is installed, for `RunMode::Wasi`
+pub fn is_wasmtime_available() -> bool {
+    Command::new("wasmtime")
+        .arg("--version")
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Check if Cargo is available
+pub fn is_cargo_available() -> bool {
+    Command::new("cargo")
+        .arg("--version")
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Check if clippy is installed for the default toolchain
+pub fn is_clippy_available() -> bool {
+    Command::new("cargo")
+        .args(["clippy", "--version"])
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rust_available() {
+        // This test will pass if Rust is installed
+        let available = is_rust_available();
+        println!("Rust available: {}", available);
+    }
+
+    #[test]
+    fn test_valid_code() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let code = r#"
+            fn add(a: i32, b: i32) -> i32 {
+                a + b
+            }
+        "#;
+
+        let result = compiler.check_code(code).unwrap();
+        assert!(result.success, "Valid code should compile");
+    }
+
+    #[test]
+    fn test_invalid_code() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let code = r#"
             fn add(a: i32, b: i32) -> i32 {
                 a + // Missing operand
             }
         "#;
 
         let result = compiler.check_code(code).unwrap();
-        assert!(!result.success, "Invalid code should not compile");
-        assert!(!result.errors.is_empty(), "Should have errors");
+        assert!(!result.success, "Invalid code should not compile");
+        assert!(!result.errors.is_empty(), "Should have errors");
+    }
+
+    #[test]
+    fn test_include_str_asset() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let code = r#"
+            fn main() {
+                let data: &str = include_str!("data.txt");
+                println!("{}", data);
+            }
+        "#;
+
+        let mut options = CheckOptions::default();
+        options
+            .assets
+            .insert("data.txt".to_string(), "hello from an asset".to_string());
+
+        let result = compiler.check_with_options(code, &options).unwrap();
+        assert!(result.success, "Snippet including a supplied asset should compile");
+    }
+
+    #[test]
+    fn test_asset_path_sanitization() {
+        assert!(sanitize_relative_path("data/level1.txt").is_ok());
+        assert!(matches!(
+            sanitize_relative_path("../escape.txt"),
+            Err(CompilerError::InvalidInput(_))
+        ));
+        assert!(sanitize_relative_path("/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_explain_on_error() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let code = r#"
+            fn main() {
+                let x: i32 = "not a number";
+            }
+        "#;
+
+        let options = CheckOptions {
+            explain: true,
+            ..Default::default()
+        };
+
+        let result = compiler.check_with_options(code, &options).unwrap();
+        let error = result
+            .errors
+            .iter()
+            .find(|e| e.code.as_deref() == Some("E0308"))
+            .expect("Should report E0308");
+        let explanation = error.explanation.as_deref().expect("E0308 should be explained");
+        assert!(explanation.contains("Expected type did not match"));
+
+        let result = compiler.check_code(code).unwrap();
+        assert!(result.errors.iter().all(|e| e.explanation.is_none()));
+    }
+
+    #[test]
+    fn test_main_in_string_is_wrapped() {
+        let statements = r#"let s = "fn main"; println!("{}", s);"#;
+        let items = r#"fn describe() -> &'static str { "fn main() {}" }"#;
+        assert!(!has_main_fn(statements));
+        assert!(!has_main_fn(items));
+        assert!(!has_main_fn("// fn main() would go here\nlet x = 1;"));
+        assert!(has_main_fn("fn main() {}"));
+
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let result = compiler.check_code(statements).unwrap();
+        assert!(result.success, "Snippet should be wrapped in main and compile");
+    }
+
+    #[test]
+    fn test_wrap_mode() {
+        let prelude = "use std::fmt::Write;\n\nfn main() {\n{{code}}\n}\n";
+        let template = WrapMode::Template(prelude.to_string());
+        let code = "let mut s = String::new();\nwrite!(s, \"{}\", 1).unwrap();";
+        let wrapped = WrappedSnippet::with_mode(code, &template);
+        assert!(wrapped.source.starts_with("use std::fmt::Write;\n\nfn main() {\nlet mut s"));
+        assert_eq!(wrapped.code_line(5), 2);
+
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let options = CheckOptions {
+            wrap_mode: template,
+            ..Default::default()
+        };
+        let result = compiler.check_with_options(code, &options).unwrap();
+        assert!(result.success, "Errors: {:?}", result.errors);
+        assert_eq!(result.line_offset, 3);
+
+        // Without wrapping, a snippet has no main function
+        let options = CheckOptions {
+            wrap_mode: WrapMode::Never,
+            ..Default::default()
+        };
+        let result = compiler.check_with_options("let x = 1;", &options).unwrap();
+        assert!(!result.success);
+
+        let options = CheckOptions {
+            wrap_mode: WrapMode::Template("fn main() {}".into()),
+            ..Default::default()
+        };
+        let result = compiler.check_with_options(code, &options).unwrap();
+        assert!(result.errors[0].message.contains("placeholder"));
+    }
+
+    #[test]
+    fn test_items_hoisted_out_of_main() {
+        let code = "struct Counter(u32);\nlet c = Counter(2);\n\n\
+                    impl Counter {\n    fn double(&self) -> u32 { self.0 * 2 }\n}\nc.double()";
+        let wrapped = WrappedSnippet::new(code);
+        assert_eq!(
+            wrapped.source,
+            "struct Counter(u32);\n\n\
+             impl Counter {\n    fn double(&self) -> u32 { self.0 * 2 }\n}\n\
+             fn main() {\nlet c = Counter(2);\nc.double();\n}"
+        );
+        assert_eq!(wrapped.code_line(7), 2);
+        assert_eq!(wrapped.source_line(7), 8);
+
+        // An item sharing a line with a statement keeps the whole snippet in main
+        let wrapped = WrappedSnippet::new("struct A; let a = A;");
+        assert_eq!(wrapped.source, "fn main() {\nstruct A; let a = A;\n}");
+
+        // A macro call is a statement, even though it also parses as an item
+        let wrapped = WrappedSnippet::new("println!(\"hi\");");
+        assert_eq!(wrapped.source, "fn main() {\nprintln!(\"hi\");\n}");
+
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let result = compiler.check_code(code).unwrap();
+        assert!(result.success, "Errors: {:?}", result.errors);
+
+        let result = compiler.check_code(&code.replace("Counter(2)", "Counter(\"2\")")).unwrap();
+        assert_eq!(result.errors[0].line, Some(2), "Errors: {:?}", result.errors);
+    }
+
+    #[test]
+    fn test_diagnostics_grouped_by_severity() {
+        let compiler = RustCompiler::new().unwrap();
+        let stdout = [
+            r#"{"reason":"compiler-message","message":{"rendered":"error[E0308]: mismatched types","level":"error","message":"mismatched types","code":{"code":"E0308"},"spans":[{"file_name":"src/main.rs","line_start":2,"column_start":18}],"children":[{"level":"note","message":"expected `i32`, found `&str`","spans":[],"children":[],"rendered":null},{"level":"help","message":"try parsing the string","spans":[],"children":[],"rendered":null}]}}"#,
+            r#"{"reason":"compiler-message","message":{"rendered":"warning: unused variable: `x`","level":"warning","message":"unused variable: `x`","code":null,"spans":[],"children":[]}}"#,
+        ]
+        .join("\n");
+
+        let result = compiler.parse_cargo_output(stdout.as_bytes(), b"").unwrap();
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.warnings.len(), 1);
+        assert_eq!(result.notes.len(), 1);
+        assert_eq!(result.help.len(), 1);
+        assert_eq!(result.notes[0].message, "expected `i32`, found `&str`");
+        assert!(!result.success);
+    }
+
+    #[test]
+    fn test_docs_url() {
+        let compiler = RustCompiler::new().unwrap();
+        let stdout = [
+            r#"{"reason":"compiler-message","message":{"rendered":"error[E0382]: borrow of moved value: `v`","level":"error","message":"borrow of moved value: `v`","code":{"code":"E0382"},"spans":[],"children":[]}}"#,
+            r#"{"reason":"compiler-message","message":{"rendered":"warning: unused variable: `x`","level":"warning","message":"unused variable: `x`","code":{"code":"unused_variables"},"spans":[],"children":[]}}"#,
+            r#"{"reason":"compiler-message","message":{"rendered":"warning: unneeded `return` statement","level":"warning","message":"unneeded `return` statement","code":{"code":"clippy::needless_return"},"spans":[],"children":[]}}"#,
+        ]
+        .join("\n");
+
+        let result = compiler.parse_cargo_output(stdout.as_bytes(), b"").unwrap();
+        assert_eq!(
+            result.errors[0].docs_url.as_deref(),
+            Some("https://doc.rust-lang.org/error_codes/E0382.html")
+        );
+        let urls: Vec<_> = result.warnings.iter().map(|w| w.docs_url.as_deref()).collect();
+        assert_eq!(
+            urls,
+            [
+                Some(
+                    "https://doc.rust-lang.org/rustc/lints/listing/warn-by-default.html\
+                     #unused-variables"
+                ),
+                Some("https://rust-lang.github.io/rust-clippy/master/index.html#needless_return"),
+            ]
+        );
+        assert!(docs_url("unsafe_code").unwrap().contains("/allow-by-default.html#unsafe-code"));
+        assert_eq!(docs_url("rustdoc::broken_intra_doc_links"), None);
+        assert_eq!(docs_url("not_a_rustc_lint"), None);
+    }
+
+    #[test]
+    fn test_lint_tables_match_rustc() {
+        if !is_rust_available() {
+            println!("Skipping test: rustc not available");
+            return;
+        }
+
+        let output = Command::new("rustc").args(["-W", "help"]).output().unwrap();
+        let help = String::from_utf8_lossy(&output.stdout);
+        let lints = help
+            .split("Lint checks provided by rustc:")
+            .nth(1)
+            .and_then(|rest| rest.split("Lint groups provided by rustc:").next())
+            .unwrap();
+
+        let mut missing = Vec::new();
+        for line in lints.lines() {
+            let mut columns = line.split_whitespace();
+            let (Some(name), Some(level)) = (columns.next(), columns.next()) else {
+                continue;
+            };
+            let level = match level {
+                "allow" => LintLevel::Allow,
+                "warn" => LintLevel::Warn,
+                "deny" => LintLevel::Deny,
+                "forbid" => LintLevel::Forbid,
+                _ => continue,
+            };
+            let name = name.replace('-', "_");
+            match default_lint_level(&name) {
+                Some(listed) => assert_eq!(listed, level, "`{}` is in the wrong table", name),
+                None => missing.push(name),
+            }
+        }
+        if !missing.is_empty() {
+            println!("Lints missing from the tables, without documentation links: {:?}", missing);
+        }
+    }
+
+    #[test]
+    fn test_lint_groups() {
+        let compiler = RustCompiler::new().unwrap();
+        let lint = |code: &str, note: &str| {
+            format!(
+                r#"{{"reason":"compiler-message","message":{{"rendered":"warning: {code}","level":"warning","message":"{code}","code":{{"code":"{code}"}},"spans":[],"children":[{{"level":"note","message":"{note}","spans":[],"children":[],"rendered":null}}]}}}}"#
+            )
+        };
+        let stdout = [
+            lint(
+                "unused_variables",
+                "`#[warn(unused_variables)]` (part of `#[warn(unused)]`) on by default",
+            ),
+            lint("dead_code", "`#[warn(dead_code)]` on by default"),
+            lint("non_snake_case", "`#[warn(non_snake_case)]` on by default"),
+            lint("deprecated", "`#[warn(deprecated)]` on by default"),
+            lint("clippy::needless_return", "`#[warn(clippy::needless_return)]` on by default"),
+            lint(
+                "clippy::suspicious_else_formatting",
+                "`#[warn(clippy::suspicious_else_formatting)]` \
+                 implied by `#[warn(clippy::suspicious)]`",
+            ),
+        ]
+        .join("\n");
+
+        let result = compiler.parse_cargo_output(stdout.as_bytes(), b"").unwrap();
+        let groups: Vec<_> = result.warnings.iter().map(|w| w.lint_group).collect();
+        assert_eq!(
+            groups,
+            [
+                Some(LintGroup::Unused),
+                Some(LintGroup::Unused),
+                Some(LintGroup::Style),
+                Some(LintGroup::Deprecated),
+                Some(LintGroup::Style),
+                Some(LintGroup::Correctness),
+            ]
+        );
+        assert_eq!(
+            result.lint_groups,
+            BTreeMap::from([
+                (LintGroup::Style, 2),
+                (LintGroup::Correctness, 1),
+                (LintGroup::Unused, 2),
+                (LintGroup::Deprecated, 1),
+            ])
+        );
+        assert_eq!(serde_json::to_value(&result.lint_groups).unwrap()["unused"], 2);
+    }
+
+    #[test]
+    fn test_diagnostics_deduplicated_and_sorted() {
+        let compiler = RustCompiler::new().unwrap();
+        let warning = |file: &str, line: usize, name: &str| {
+            format!(
+                r#"{{"reason":"compiler-message","message":{{"rendered":"warning: unused variable: `{name}`","level":"warning","message":"unused variable: `{name}`","code":{{"code":"unused_variables"}},"spans":[{{"file_name":"{file}","line_start":{line},"column_start":9,"is_primary":true}}],"children":[{{"level":"help","message":"prefix it with an underscore","spans":[],"children":[],"rendered":null}}]}}}}"#
+            )
+        };
+        // The library's warnings, then the same ones again from its tests
+        let stdout = [
+            warning("src/main.rs", 7, "b"),
+            warning("src/lib.rs", 3, "c"),
+            warning("src/main.rs", 2, "a"),
+            warning("src/main.rs", 7, "b"),
+            warning("src/main.rs", 2, "a"),
+        ]
+        .join("\n");
+
+        let mut result = compiler.parse_cargo_output(stdout.as_bytes(), b"").unwrap();
+        result.sort_diagnostics();
+        let found: Vec<_> = result
+            .warnings
+            .iter()
+            .map(|w| (w.file.as_deref().unwrap(), w.line.unwrap()))
+            .collect();
+        assert_eq!(found, [("src/lib.rs", 3), ("src/main.rs", 2), ("src/main.rs", 7)]);
+        assert_eq!(result.help.len(), 3, "Duplicates' help should be dropped too");
+    }
+
+    #[test]
+    fn test_missing_dependency_preflight() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let result = compiler
+            .check_code_with_deps("fn main() {}", &[("blockly_no_such_crate_xyz", "1.0")])
+            .unwrap();
+
+        assert!(!result.success, "Unresolvable dependency should fail");
+        assert_eq!(result.errors[0].file.as_deref(), Some("Cargo.toml"));
+        assert!(result.stdout.is_empty(), "No compile should have been attempted");
+    }
+
+    #[test]
+    fn test_dependency_manifest_entry() {
+        assert_eq!(Dependency::new("rand", "0.8").manifest_entry(), r#"rand = "0.8""#);
+        let pair = ("rand".to_string(), "0.8".to_string());
+        assert_eq!(Dependency::from(pair), Dependency::new("rand", "0.8"));
+
+        let serde = Dependency::new("serde", "1").with_features(["derive"]);
+        assert_eq!(serde.manifest_entry(), r#"serde = { version = "1", features = ["derive"] }"#);
+
+        let tokio = Dependency {
+            default_features: false,
+            optional: true,
+            ..Dependency::new("tokio", "1")
+        };
+        assert_eq!(
+            tokio.manifest_entry(),
+            r#"tokio = { version = "1", default-features = false, optional = true }"#
+        );
+
+        let manifest = manifest(&CheckOptions {
+            dependencies: vec![serde, tokio],
+            ..Default::default()
+        });
+        assert!(manifest.contains("[dependencies]\nserde = { version = \"1\", features"));
+        assert!(manifest.contains("\ntokio = { version = \"1\""));
+
+        let parsed: Dependency =
+            serde_json::from_str(r#"{"name": "log", "version": "0.4"}"#).unwrap();
+        assert!(parsed.default_features && !parsed.optional && parsed.features.is_empty());
+    }
+
+    #[test]
+    fn test_check_project() {
+        let compiler = RustCompiler::new().unwrap();
+        let mut files = HashMap::new();
+        files.insert(
+            PathBuf::from("shapes.rs"),
+            "pub fn area(w: u32, h: u32) -> u32 {\n    w * h\n}\n".to_string(),
+        );
+        let result = compiler.check_project(&files, &CheckOptions::default()).unwrap();
+        assert!(result.errors[0].message.contains("no `main.rs`"));
+
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let main = "mod shapes;\n\nfn main() {\n    println!(\"{}\", shapes::area(2, 3));\n}\n";
+        files.insert(PathBuf::from("main.rs"), main.to_string());
+        let result = compiler.check_project(&files, &CheckOptions::default()).unwrap();
+        assert!(result.success, "Errors: {:?}", result.errors);
+
+        files.insert(PathBuf::from("shapes.rs"), "pub fn area() -> u32 {\n    \"6\"\n}\n".into());
+        let result = compiler.check_project(&files, &CheckOptions::default()).unwrap();
+        let error = result.errors.iter().find(|e| e.file.as_deref() == Some("src/shapes.rs"));
+        assert_eq!(error.and_then(|error| error.line), Some(2), "Errors: {:?}", result.errors);
+    }
+
+    #[test]
+    fn test_check_workspace() {
+        let genome = WorkspaceCrate {
+            name: "genome".to_string(),
+            manifest: "[package]\nname = \"genome\"\nversion = \"0.1.0\"\nedition = \"2021\"\n"
+                .to_string(),
+            files: HashMap::from([(
+                PathBuf::from("src/lib.rs"),
+                "pub struct Genome {\n    pub cells: u32, // TODO: energy\n}\n".to_string(),
+            )]),
+        };
+        let mut behavior = WorkspaceCrate {
+            name: "behavior".to_string(),
+            manifest: "[package]\nname = \"behavior\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+                       [dependencies]\ngenome = { path = \"../genome\" }\n"
+                .to_string(),
+            files: HashMap::from([(
+                PathBuf::from("src/main.rs"),
+                "fn main() {\n    let g = genome::Genome { cells: 1 };\n    \
+                 println!(\"{}\", g.cells);\n}\n"
+                    .to_string(),
+            )]),
+        };
+
+        let mut compiler = RustCompiler::new().unwrap();
+        compiler.add_linter(TodoLinter);
+        for entry in [
+            "genome = { path = \"/etc\" }",
+            "\"genome\" = { \"path\" = \"/etc\" }",
+            "genome.path = \"../genome/../../etc\"",
+            "genome = { git = \"file:///srv/genome\" }",
+        ] {
+            let mut outside = behavior.clone();
+            outside.manifest = outside.manifest.replace("genome = { path = \"../genome\" }", entry);
+            let crates = [genome.clone(), outside];
+            let result = compiler.check_workspace(&crates, &CheckOptions::default()).unwrap();
+            assert!(result.errors[0].message.contains("isn't a workspace member"), "{}", entry);
+        }
+        assert!(is_in_workspace(&behavior, "./src/../../genome/", std::slice::from_ref(&genome)));
+        let mut scripted = genome.clone();
+        scripted.manifest.push_str("\"build\" = \"src/lib.rs\"\n");
+        let result = compiler.check_workspace(&[scripted], &CheckOptions::default()).unwrap();
+        assert!(result.errors[0].message.contains("Build scripts are not allowed"));
+
+        assert_eq!(ManifestSummary::parse(&behavior.manifest).unwrap().dependencies, 1);
+        compiler.set_max_dependencies(Some(0));
+        let crates = [genome.clone(), behavior.clone()];
+        let result = compiler.check_workspace(&crates, &CheckOptions::default()).unwrap();
+        assert!(result.errors[0].message.starts_with("Too many dependencies"));
+        compiler.set_max_dependencies(None);
+
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let result = compiler.check_workspace(&crates, &CheckOptions::default()).unwrap();
+        assert!(result.success, "Errors: {:?}", result.errors);
+        // Linters see the members' files together, but report against each file
+        let todo = result.warnings.iter().find(|warning| warning.message.contains("TODO")).unwrap();
+        assert_eq!(todo.file.as_deref(), Some("genome/src/lib.rs"));
+        assert_eq!((todo.line, todo.crate_name.as_deref()), (Some(2), Some("genome")));
+
+        let main = behavior.files.get_mut(Path::new("src/main.rs")).unwrap();
+        *main = main.replace("cells: 1", "cells: \"1\"");
+        let crates = [genome, behavior];
+        let result = compiler.check_workspace(&crates, &CheckOptions::default()).unwrap();
+        let error = &result.errors[0];
+        assert_eq!(error.crate_name.as_deref(), Some("behavior"));
+        assert_eq!(error.file.as_deref(), Some("behavior/src/main.rs"));
+    }
+
+    #[test]
+    fn test_build_script() {
+        let script = "fn main() {\n    \
+                      let out = std::env::var(\"OUT_DIR\").unwrap();\n    \
+                      std::fs::write(format!(\"{}/gen.rs\", out), \"fn answer() -> u32 { 42 }\")\
+                      .unwrap();\n}\n";
+        let code = "include!(concat!(env!(\"OUT_DIR\"), \"/gen.rs\"));\n\n\
+                    fn main() {\n    println!(\"{}\", answer());\n}\n";
+        let options = CheckOptions {
+            build_script: Some(script.to_string()),
+            ..Default::default()
+        };
+
+        let mut compiler = RustCompiler::new().unwrap();
+        let result = compiler.check_with_options(code, &options).unwrap();
+        assert!(result.errors[0].message.contains("Build scripts are not allowed"));
+
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        compiler.set_allow_build_scripts(true);
+        let result = compiler.check_with_options(code, &options).unwrap();
+        assert!(result.success, "Errors: {:?}", result.errors);
+
+        let failing = CheckOptions {
+            build_script: Some("fn main() {\n    panic!(\"no codegen\");\n}\n".to_string()),
+            ..Default::default()
+        };
+        let result = compiler.check_with_options(code, &failing).unwrap();
+        assert!(!result.success);
+        let error = &result.errors[0];
+        assert!(matches!(error.category, Some(ErrorCategory::BuildScript)));
+        assert_eq!((error.file.as_deref(), error.line), (Some("build.rs"), Some(2)));
+        assert!(error.notes[0].contains("no codegen"), "Notes: {:?}", error.notes);
+    }
+
+    #[test]
+    fn test_proc_macro_crate() {
+        let presets = Dependency::proc_macro_presets();
+        assert_eq!(presets[0].manifest_entry(), r#"syn = { version = "2", features = ["full"] }"#);
+
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let code = "use proc_macro::TokenStream;\n\n\
+                    #[proc_macro_derive(Hello)]\n\
+                    pub fn hello(_input: TokenStream) -> TokenStream {\n    \
+                    TokenStream::new()\n}\n";
+        let compiler = RustCompiler::new().unwrap();
+        let options = CheckOptions {
+            crate_type: CrateType::ProcMacro,
+            ..Default::default()
+        };
+        let result = compiler.check_with_options(code, &options).unwrap();
+        assert!(result.success, "Errors: {:?}", result.errors);
+
+        let options = CheckOptions {
+            crate_type: CrateType::Lib,
+            ..Default::default()
+        };
+        let result = compiler.check_with_options(code, &options).unwrap();
+        assert!(!result.success, "Only proc-macro crates can define derives");
+    }
+
+    #[test]
+    fn test_custom_manifest() {
+        let options = CheckOptions {
+            manifest_extra: "[features]\ndefault = [\"fancy\"]\nfancy = []".to_string(),
+            ..Default::default()
+        };
+        assert!(manifest(&options).ends_with("\n[features]\ndefault = [\"fancy\"]\nfancy = []\n"));
+
+        let patched = CheckOptions {
+            manifest_extra: "[patch.crates-io]\nrand = { path = \"/src/rand\" }".to_string(),
+            ..Default::default()
+        };
+        assert!(has_custom_path_key(&patched));
+        assert!(!has_custom_path_key(&options));
+
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let code = "#[cfg(feature = \"fancy\")]\nfn main() {}";
+        let result = compiler.check_with_options(code, &options).unwrap();
+        assert!(result.success, "Errors: {:?}", result.errors);
+
+        let result = compiler.check_with_options(code, &patched).unwrap();
+        assert!(result.errors[0].message.contains("`path` key"));
+
+        let custom = CheckOptions {
+            manifest: Some(manifest(&options)),
+            ..Default::default()
+        };
+        let result = compiler.check_with_options(code, &custom).unwrap();
+        assert!(result.success, "Errors: {:?}", result.errors);
+    }
+
+    #[test]
+    fn test_manifest_keys_are_parsed() {
+        let compiler = RustCompiler::new().unwrap();
+        let rejected = |manifest_extra: &str| {
+            let options = CheckOptions {
+                manifest_extra: manifest_extra.to_string(),
+                ..Default::default()
+            };
+            let result = compiler.check_with_options("let x = 1;", &options).unwrap();
+            result.errors.first().map(|error| error.message.clone()).unwrap_or_default()
+        };
+
+        for extra in [
+            "[patch.crates-io]\n\"rand\" = { \"path\" = \"/etc\" }",
+            "[patch.crates-io]\nrand.path = \"/etc\"",
+            "[lib]\n'path' = \"/etc/passwd\"",
+            "[target.'cfg(unix)'.dependencies]\nrand = { git = \"file:///srv/rand\" }",
+        ] {
+            assert!(rejected(extra).contains("`path` key"), "{} wasn't rejected", extra);
+        }
+        assert!(rejected("[package]\nversion = \"0.2.0\"").starts_with("Invalid Cargo.toml"));
+
+        let custom = CheckOptions {
+            manifest: Some("[package]\nname = \"x\"\nversion = \"0.1.0\"\nbuild = false\n".into()),
+            ..Default::default()
+        };
+        let summary = ManifestSummary::parse(custom.manifest.as_deref().unwrap()).unwrap();
+        assert!(!summary.build_script && summary.paths.is_empty());
+        for manifest in ["[package]\n\"build\" = \"src/main.rs\"\n", "package.build = \"src/main.rs\"\n"] {
+            let custom = CheckOptions {
+                manifest: Some(manifest.to_string()),
+                ..Default::default()
+            };
+            let result = compiler.check_with_options("let x = 1;", &custom).unwrap();
+            assert!(result.errors[0].message.contains("Build scripts are not allowed"));
+        }
+    }
+
+    /// A local `helpers` crate with a `double` function, for path dependencies
+    fn helper_crate() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("blockly_helper_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(
+            dir.join("Cargo.toml"),
+            "[package]\nname = \"helpers\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        )
+        .unwrap();
+        fs::write(dir.join("src/lib.rs"), "pub fn double(x: i32) -> i32 { x * 2 }\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_git_and_path_dependencies() {
+        let git = Dependency {
+            branch: Some("main".to_string()),
+            ..Dependency::git("helpers", "https://example.com/helpers.git")
+        };
+        assert_eq!(
+            git.manifest_entry(),
+            r#"helpers = { git = "https://example.com/helpers.git", branch = "main" }"#
+        );
+
+        let helper_dir = helper_crate();
+        let helpers = Dependency::path("helpers", helper_dir.to_string_lossy());
+        let code = "fn main() { let _ = helpers::double(2); }";
+
+        let mut compiler = RustCompiler::new().unwrap();
+        let result = compiler.check_code_with_deps(code, std::slice::from_ref(&helpers)).unwrap();
+        assert!(!result.success);
+        assert!(result.errors[0].message.contains("not allowed"), "Got: {:?}", result.errors);
+
+        compiler.set_allow_path_dependencies(true);
+        let relative = Dependency::path("helpers", "../helpers");
+        let result = compiler.check_code_with_deps(code, &[relative]).unwrap();
+        assert!(result.errors[0].message.contains("must be absolute"));
+
+        if is_cargo_available() {
+            let result = compiler.check_code_with_deps(code, &[helpers]).unwrap();
+            assert!(result.success, "Errors: {:?}", result.errors);
+        }
+        let _ = fs::remove_dir_all(helper_dir);
+    }
+
+    #[test]
+    fn test_lockfile_round_trip() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let mut compiler = RustCompiler::new().unwrap();
+        compiler.set_allow_path_dependencies(true);
+        let helper_dir = helper_crate();
+        let code = "fn main() { let _ = helpers::double(2); }";
+        let mut options = CheckOptions {
+            dependencies: vec![Dependency::path("helpers", helper_dir.to_string_lossy())],
+            ..Default::default()
+        };
+
+        let first = compiler.check_with_options(code, &options).unwrap();
+        assert!(first.success, "Errors: {:?}", first.errors);
+        let lockfile = first.lockfile.expect("Checks with dependencies return their lockfile");
+        assert!(lockfile.contains("name = \"helpers\""), "Got: {}", lockfile);
+
+        options.lockfile = Some(lockfile.clone());
+        let second = compiler.check_with_options(code, &options).unwrap();
+        assert!(second.success, "Errors: {:?}", second.errors);
+        assert_eq!(second.lockfile, Some(lockfile));
+
+        let no_deps = compiler.check_code("fn main() {}").unwrap();
+        assert!(no_deps.lockfile.is_none());
+        let _ = fs::remove_dir_all(helper_dir);
+    }
+
+    #[test]
+    fn test_build_profile() {
+        let profile = BuildProfile {
+            opt_level: Some("s".to_string()),
+            debug: Some(false),
+            lto: Some("thin".to_string()),
+            ..BuildProfile::custom("classroom")
+        };
+        let manifest = manifest(&CheckOptions {
+            profile: Some(profile.clone()),
+            ..Default::default()
+        });
+        assert!(manifest.ends_with(
+            "[profile.classroom]\ninherits = \"release\"\nopt-level = \"s\"\ndebug = false\n\
+             lto = \"thin\"\n"
+        ));
+        let release = BuildProfile {
+            opt_level: Some("2".to_string()),
+            ..BuildProfile::release()
+        };
+        assert_eq!(release.manifest_section().unwrap(), "\n[profile.release]\nopt-level = 2\n");
+        assert!(BuildProfile::release().manifest_section().is_none());
+
+        let compiler = RustCompiler::new().unwrap();
+        let invalid = CheckOptions {
+            profile: Some(BuildProfile::custom("a]\n[b")),
+            ..Default::default()
+        };
+        let result = compiler.check_with_options("fn main() {}", &invalid).unwrap();
+        assert!(result.errors[0].message.contains("Invalid profile name"));
+
+        if is_cargo_available() {
+            let options = CheckOptions {
+                profile: Some(profile),
+                include_command: true,
+                ..Default::default()
+            };
+            let result = compiler.check_with_options("fn main() {}", &options).unwrap();
+            assert!(result.success, "Errors: {:?}", result.errors);
+            let args = result.command.unwrap().args;
+            assert!(args.windows(2).any(|pair| pair == ["--profile", "classroom"]));
+        }
+    }
+
+    #[test]
+    fn test_max_dependencies() {
+        let mut compiler = RustCompiler::new().unwrap();
+        compiler.set_max_dependencies(Some(2));
+
+        // None of these exist, so reaching cargo would report a resolution error instead
+        let deps = [
+            ("blockly_no_such_crate_a", "1.0"),
+            ("blockly_no_such_crate_b", "1.0"),
+            ("blockly_no_such_crate_c", "1.0"),
+        ];
+        let result = compiler.check_code_with_deps("fn main() {}", &deps).unwrap();
+
+        assert!(!result.success);
+        assert_eq!(result.errors.len(), 1);
+        assert!(result.errors[0].message.contains("at most 2"), "Got: {}", result.errors[0].message);
+        assert!(result.stderr.is_empty(), "Cargo should not have been invoked");
+
+        // Dependencies declared in the manifest count too, wherever they are
+        for manifest_extra in [
+            "\"a\" = \"1\"\n\"b\" = \"1\"\n\"c\" = \"1\"",
+            "[target.'cfg(unix)'.dependencies]\na = \"1\"\nb = \"1\"\nc = \"1\"",
+            "[dev_dependencies]\na = \"1\"\n[build-dependencies.b]\nversion = \"1\"\n\
+             [target.x.dev-dependencies]\nc.version = \"1\"",
+        ] {
+            let options = CheckOptions {
+                manifest_extra: manifest_extra.to_string(),
+                ..Default::default()
+            };
+            let result = compiler.check_with_options("fn main() {}", &options).unwrap();
+            assert!(result.errors[0].message.contains("3 declared"), "{}", manifest_extra);
+        }
+    }
+
+    #[test]
+    fn test_invalid_dependency_name() {
+        let compiler = RustCompiler::new().unwrap();
+        let injected = ("x = { path = \"/tmp\" }\ny", "1.0");
+        let result = compiler.check_code_with_deps("fn main() {}", &[injected]).unwrap();
+
+        assert!(!result.success);
+        assert!(result.errors[0].message.contains("Invalid dependency name"));
+        assert_eq!(result.errors[0].category, Some(ErrorCategory::Dependency));
+        assert!(result.stderr.is_empty(), "Cargo should not have been invoked");
+    }
+
+    #[test]
+    fn test_missing_crate_suggestion() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let result = compiler
+            .check_code("use rand::Rng;\nfn main() { let _ = my_module::f(); }")
+            .unwrap();
+
+        let import = result
+            .errors
+            .iter()
+            .find(|e| e.code.as_deref() == Some("E0432"))
+            .expect("The rand import should be unresolved");
+        let missing = import.missing_crate.as_ref().expect("rand should be suggested");
+        assert_eq!(missing.manifest_entry(), "rand = \"0.8\"");
+        assert!(import.notes.iter().any(|note| note.contains("rand = \"0.8\"")));
+
+        let unknown = result.errors.iter().find(|e| e.code.as_deref() == Some("E0433")).unwrap();
+        assert!(unknown.missing_crate.is_none(), "Only well-known crates are suggested");
+    }
+
+    #[test]
+    fn test_dependency_tree() {
+        let metadata = serde_json::json!({
+            "packages": [
+                {
+                    "id": "path+file:///tmp/check#blockly_check@0.1.0",
+                    "name": "blockly_check",
+                    "version": "0.1.0"
+                },
+                {
+                    "id": "registry+crates-io#rand@0.8.5",
+                    "name": "rand",
+                    "version": "0.8.5"
+                },
+                {
+                    "id": "registry+crates-io#rand_core@0.6.4",
+                    "name": "rand_core",
+                    "version": "0.6.4"
+                }
+            ],
+            "resolve": {
+                "root": "path+file:///tmp/check#blockly_check@0.1.0",
+                "nodes": [
+                    {
+                        "id": "path+file:///tmp/check#blockly_check@0.1.0",
+                        "dependencies": ["registry+crates-io#rand@0.8.5"],
+                        "features": []
+                    },
+                    {
+                        "id": "registry+crates-io#rand@0.8.5",
+                        "dependencies": ["registry+crates-io#rand_core@0.6.4"],
+                        "features": ["default", "std"]
+                    },
+                    {
+                        "id": "registry+crates-io#rand_core@0.6.4",
+                        "dependencies": [],
+                        "features": []
+                    }
+                ]
+            }
+        });
+
+        let tree = parse_dependency_tree(&metadata);
+        assert_eq!(tree.len(), 2, "The checked crate itself shouldn't be listed");
+        let rand = tree.iter().find(|node| node.name == "rand").unwrap();
+        assert!(rand.direct && rand.parents.is_empty());
+        assert_eq!(rand.features, ["default", "std"]);
+        let rand_core = tree.iter().find(|node| node.name == "rand_core").unwrap();
+        assert!(!rand_core.direct);
+        assert_eq!(rand_core.parents, ["rand@0.8.5"]);
+
+        // A crate the checked code declares can still have parents
+        let mut metadata = metadata;
+        metadata["resolve"]["nodes"][0]["dependencies"]
+            .as_array_mut()
+            .unwrap()
+            .push("registry+crates-io#rand_core@0.6.4".into());
+        let tree = parse_dependency_tree(&metadata);
+        let rand_core = tree.iter().find(|node| node.name == "rand_core").unwrap();
+        assert!(rand_core.direct);
+        assert_eq!(rand_core.parents, ["rand@0.8.5"]);
+
+        if is_cargo_available() {
+            let compiler = RustCompiler::new().unwrap();
+            let tree = compiler.dependency_tree("let x = 1;", &CheckOptions::default()).unwrap();
+            assert!(tree.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_parse_audit_report() {
+        let report = serde_json::json!({
+            "vulnerabilities": {
+                "found": true,
+                "count": 1,
+                "list": [{
+                    "advisory": {
+                        "id": "RUSTSEC-2020-0071",
+                        "title": "Potential segfault in the time crate",
+                        "url": "https://github.com/time-rs/time/issues/293"
+                    },
+                    "package": { "name": "time", "version": "0.1.45" }
+                }]
+            },
+            "warnings": {
+                "yanked": [{
+                    "kind": "yanked",
+                    "advisory": null,
+                    "package": { "name": "futures-util", "version": "0.3.2" }
+                }]
+            }
+        });
+
+        let advisories = parse_audit_report(&report);
+        assert_eq!(advisories.len(), 2);
+        assert_eq!(advisories[0].kind, "vulnerability");
+        assert_eq!(advisories[0].id.as_deref(), Some("RUSTSEC-2020-0071"));
+        assert_eq!(advisories[0].package, "time");
+        assert_eq!(advisories[0].version, "0.1.45");
+        assert_eq!(advisories[1].kind, "yanked");
+        assert_eq!(advisories[1].id, None);
+    }
+
+    #[test]
+    fn test_pooled_checks() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let temp_dir =
+            std::env::temp_dir().join(format!("blockly_pooled_{}", uuid::Uuid::new_v4()));
+        let mut compiler = RustCompiler::with_temp_dir(&temp_dir).unwrap();
+        compiler.enable_project_pool(PoolConfig::default()).unwrap();
+
+        let mut options = CheckOptions::default();
+        options.assets.insert("data.txt".to_string(), "first".to_string());
+        let code = r#"fn main() { let _ = include_str!("data.txt"); }"#;
+        assert!(compiler.check_with_options(code, &options).unwrap().success);
+
+        // The reused project must not still hold the previous check's asset
+        let result = compiler.check_code(code).unwrap();
+        assert!(!result.success, "Stale asset should have been removed");
+
+        let metrics = compiler.project_pool().unwrap().metrics();
+        assert_eq!((metrics.created, metrics.available, metrics.in_use), (1, 1, 0));
+
+        // Later checks only rewrite the sources of the warm project
+        let pool = fs::read_dir(temp_dir.join("pool")).unwrap().next().unwrap().unwrap().path();
+        let modified = || fs::metadata(pool.join("Cargo.toml")).unwrap().modified().unwrap();
+        let before = modified();
+        assert!(compiler.check_code("let x = 1;").unwrap().success);
+        assert_eq!(modified(), before, "Unchanged manifest should not be rewritten");
+        assert!(pool.join("target").exists(), "Build artifacts should be kept");
+
+        drop(compiler);
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_shared_target_dir() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let target_dir =
+            std::env::temp_dir().join(format!("blockly_target_{}", uuid::Uuid::new_v4()));
+        let mut compiler = RustCompiler::new().unwrap();
+        compiler.set_shared_target_dir(Some(target_dir.clone()));
+
+        let options = CheckOptions {
+            include_command: true,
+            ..Default::default()
+        };
+        let result = compiler.check_with_options("let x = 1;", &options).unwrap();
+        assert!(result.success);
+        assert_eq!(
+            result.command.unwrap().env.get("CARGO_TARGET_DIR").map(PathBuf::from),
+            Some(target_dir.clone())
+        );
+        assert!(target_dir.join("debug").exists(), "Artifacts should be in the shared directory");
+
+        let _ = fs::remove_dir_all(&target_dir);
+    }
+
+    #[test]
+    fn test_quick_check_metadata_only() {
+        if !is_rust_available() {
+            println!("Skipping test: rustc not available");
+            return;
+        }
+
+        let temp_dir = std::env::temp_dir().join(format!("blockly_quick_{}", uuid::Uuid::new_v4()));
+        let compiler = RustCompiler::with_temp_dir(&temp_dir).unwrap();
+
+        let result = compiler
+            .quick_check(r#"pub fn answer() -> i32 { "forty-two" }"#)
+            .unwrap();
+        assert!(!result.success, "Type error should be caught");
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].code.as_deref(), Some("E0308"));
+
+        let leftovers = fs::read_dir(&temp_dir).unwrap().count();
+        let _ = fs::remove_dir_all(&temp_dir);
+        assert_eq!(leftovers, 0, "Quick check should not leave output files behind");
+    }
+
+    #[test]
+    fn test_quick_check_crate_type() {
+        if !is_rust_available() {
+            println!("Skipping test: rustc not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let program = "fn main() { println!(\"hi\"); }";
+
+        let as_bin = compiler.quick_check_as(program, CrateType::Bin).unwrap();
+        assert!(as_bin.success);
+        assert!(as_bin.warnings.is_empty(), "main should not be dead code in a binary");
+
+        let as_lib = compiler.quick_check(program).unwrap();
+        assert!(as_lib.warnings.iter().any(|w| w.message.contains("never used")));
+
+        let missing_main = compiler.quick_check_as("pub fn f() {}", CrateType::Bin).unwrap();
+        assert!(!missing_main.success);
+        assert_eq!(missing_main.errors[0].code.as_deref(), Some("E0601"));
+    }
+
+    #[test]
+    fn test_edition_selection() {
+        assert_eq!("2018".parse::<Edition>(), Ok(Edition::E2018));
+        assert!("2019".parse::<Edition>().is_err());
+        assert!(serde_json::from_str::<Edition>("\"2019\"").is_err());
+
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        // `async` is only a keyword from 2018 on
+        let code = "let async = 1;\nprintln!(\"{}\", async);";
+        let compiler = RustCompiler::new().unwrap();
+        let options = CheckOptions {
+            edition: Edition::E2015,
+            ..Default::default()
+        };
+        assert!(compiler.check_with_options(code, &options).unwrap().success);
+        assert!(!compiler.check_code(code).unwrap().success);
+    }
+
+    #[test]
+    fn test_target_selection() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let host = host_target().unwrap();
+        assert!(is_target_installed(&host));
+
+        let compiler = RustCompiler::new().unwrap();
+        let options = |target: &str| CheckOptions {
+            target: Some(target.to_string()),
+            include_command: true,
+            ..Default::default()
+        };
+
+        let result = compiler.check_with_options("let x = 1;", &options(&host)).unwrap();
+        assert!(result.success);
+        let args = result.command.unwrap().args;
+        assert!(args.windows(2).any(|pair| pair == ["--target", host.as_str()]));
+        assert!(compiler.installed_targets.lock().unwrap().contains(&(None, host.clone())));
+
+        let result = compiler
+            .check_with_options("let x = 1;", &options("blockly-missing-target"))
+            .unwrap();
+        assert!(result.errors[0].message.contains("rustup target add blockly-missing-target"));
+    }
+
+    #[test]
+    fn test_build_wasm() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let build = compiler.build_wasm("let x = 1 + 2;", &CheckOptions::default()).unwrap();
+
+        if !is_target_installed(WASM_TARGET) {
+            assert!(build.wasm.is_none());
+            assert!(build.compilation.errors[0].message.contains("rustup target add"));
+            return;
+        }
+
+        assert!(build.compilation.success);
+        let wasm = build.wasm.expect("Should return the module");
+        assert_eq!(&wasm[..4], b"\0asm");
+    }
+
+    #[test]
+    fn test_run_tests() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let code = r#"
+            fn add(a: i32, b: i32) -> i32 { a + b }
+
+            #[test]
+            fn adds() { assert_eq!(add(1, 2), 3); }
+
+            #[test]
+            fn fails() {
+                println!("checking");
+                assert_eq!(add(1, 1), 3);
+            }
+
+            #[test]
+            #[ignore]
+            fn skipped() {}
+        "#;
+        let result = compiler.run_tests(code, &RunOptions::default()).unwrap();
+
+        assert!(result.compilation.success);
+        let outcome = |name: &str| {
+            let test = result.tests.iter().find(|t| t.name == name).unwrap();
+            (test.outcome, test.stdout.clone())
+        };
+        assert_eq!(outcome("adds").0, TestOutcome::Passed);
+        assert_eq!(outcome("skipped").0, TestOutcome::Ignored);
+        let (failed, stdout) = outcome("fails");
+        assert_eq!(failed, TestOutcome::Failed);
+        assert!(stdout.starts_with("checking\n"), "Got: {}", stdout);
+    }
+
+    #[test]
+    fn test_run_doctests() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let code = r#"
+/// ```
+/// assert_eq!(blockly_check::double(2), 4);
+/// ```
+pub fn double(x: i32) -> i32 { x * 2 }
+
+/// ```
+/// assert_eq!(blockly_check::double(2), 5);
+/// ```
+pub fn wrong_example() {}
+"#;
+        let result = compiler.run_doctests(code, &RunOptions::default()).unwrap();
+
+        assert!(result.compilation.success);
+        assert_eq!(result.tests.len(), 2, "Got: {:?}", result.tests);
+        let double = result.tests.iter().find(|t| t.name.contains("double")).unwrap();
+        assert_eq!(double.outcome, TestOutcome::Passed);
+        let wrong = result.tests.iter().find(|t| t.name.contains("wrong_example")).unwrap();
+        assert_eq!(wrong.outcome, TestOutcome::Failed);
+        assert!(wrong.stdout.contains("assertion"), "Got: {}", wrong.stdout);
+    }
+
+    #[test]
+    fn test_wasi_run_mode() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let options = RunOptions {
+            mode: RunMode::Wasi,
+            fuel: Some(10_000_000),
+            ..Default::default()
+        };
+        let result = compiler.run_code("println!(\"sandboxed\");", &options).unwrap();
+
+        if !is_wasmtime_available() || !is_target_installed(WASI_TARGET) {
+            assert!(!result.compilation.success);
+            assert_eq!(result.exit_code, None);
+            return;
+        }
+
+        assert_eq!(result.stdout, "sandboxed\n");
+
+        let result = compiler.run_code("loop {}", &options).unwrap();
+        assert!(result.out_of_fuel, "Got: {}", result.stderr);
+    }
+
+    #[test]
+    fn test_toolchain_selection() {
+        if !is_cargo_available() || !is_toolchain_available("stable") {
+            println!("Skipping test: rustup stable toolchain not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let options = |toolchain: &str| CheckOptions {
+            toolchain: Some(toolchain.to_string()),
+            include_command: true,
+            ..Default::default()
+        };
+
+        let result = compiler.check_with_options("let x = 1;", &options("stable")).unwrap();
+        assert!(result.success);
+        assert_eq!(result.command.unwrap().args[0], "+stable");
+
+        let result = compiler
+            .check_with_options("let x = 1;", &options("blockly-missing-toolchain"))
+            .unwrap();
+        assert!(!result.success);
+        assert!(result.errors[0].message.contains("is not installed"));
+
+        let result = compiler
+            .check_with_options("let x = 1;", &options("stable --offline"))
+            .unwrap();
+        assert!(result.errors[0].message.starts_with("Invalid toolchain name"));
+    }
+
+    #[test]
+    fn test_allowed_toolchains() {
+        let compiler = RustCompiler::builder().allowed_toolchains(["stable"]).build().unwrap();
+        let options = CheckOptions {
+            toolchain: Some("1.60.0".to_string()),
+            ..Default::default()
+        };
+
+        let result = compiler.check_with_options("let x = 1;", &options).unwrap();
+        assert!(!result.success);
+        assert_eq!(
+            result.errors[0].message,
+            "The `1.60.0` toolchain isn't available here. Use one of: `stable`."
+        );
+    }
+
+    #[test]
+    fn test_check_clippy() {
+        if !is_cargo_available() || !is_clippy_available() {
+            println!("Skipping test: clippy not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let code = r#"fn double(x: i32) -> i32 {
+    return x * 2;
+}
+
+fn main() {
+    double(1);
+}"#;
+        let result = compiler.check_clippy(code).unwrap();
+
+        assert!(result.success);
+        let lint = result
+            .warnings
+            .iter()
+            .find(|w| w.code.as_deref() == Some("clippy::needless_return"))
+            .expect("Expected a needless_return lint");
+        assert_eq!(lint.line, Some(2));
+    }
+
+    #[test]
+    fn test_contains_symbols() {
+        let code = r#"
+            struct Cell;
+
+            impl Cell {
+                fn divide(&self) {}
+            }
+
+            fn grow() {}
+        "#;
+
+        let names = vec!["divide".to_string(), "shrink".to_string()];
+        let found = contains_symbols(code, &names);
+        assert_eq!(found.len(), 2);
+        assert!(found["divide"]);
+        assert!(!found["shrink"]);
+    }
+
+    #[test]
+    fn test_include_command() {
+        assert!(is_secret_env("CARGO_REGISTRY_TOKEN"));
+        assert!(!is_secret_env("CARGO_TARGET_DIR"));
+
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let options = CheckOptions {
+            include_command: true,
+            ..Default::default()
+        };
+
+        let result = compiler.check_with_options("fn main() {}", &options).unwrap();
+        let command = result.command.expect("Command should be included");
+        assert_eq!(command.program, "cargo");
+        assert!(command.args.contains(&"check".to_string()));
+        assert!(command.args.contains(&"--message-format=json".to_string()));
+    }
+
+    #[test]
+    fn test_address_sanitizer_use_after_free() {
+        if !is_nightly_available() {
+            println!("Skipping test: nightly toolchain not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let code = r#"
+            fn main() {
+                let v = vec![1, 2, 3];
+                let p = v.as_ptr();
+                drop(v);
+                let x = unsafe { *p };
+                println!("{}", x);
+            }
+        "#;
+
+        let result = compiler.run_with_sanitizer(code, Sanitizer::Address).unwrap();
+        assert!(!result.success, "Use-after-free should be reported");
+        let report = &result.errors[0];
+        assert!(report.message.contains("heap-use-after-free"));
+        assert_eq!(report.line, Some(6));
+    }
+
+    #[test]
+    fn test_link_error() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let code = r#"
+            #[link(name = "blockly_missing_lib")]
+            extern "C" {
+                fn blockly_missing();
+            }
+
+            fn main() {
+                unsafe { blockly_missing() }
+            }
+        "#;
+
+        let options = CheckOptions {
+            build: true,
+            ..Default::default()
+        };
+
+        let result = compiler.check_with_options(code, &options).unwrap();
+        assert!(!result.success, "Missing library should fail to link");
+        let error = &result.errors[0];
+        assert_eq!(error.category, Some(ErrorCategory::Link));
+        assert!(error.message.contains("`blockly_missing_lib`"), "Got: {}", error.message);
+        assert!(error.suggestion.as_deref().unwrap().contains("libblockly_missing_lib-dev"));
+    }
+
+    #[test]
+    fn test_treat_warnings_as_errors() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let options = CheckOptions {
+            treat_warnings_as_errors: true,
+            ..Default::default()
+        };
+
+        let result = compiler.check_with_options("let unused = 1;", &options).unwrap();
+        assert!(!result.success, "The unused variable warning should fail the check");
+        assert!(result.errors.is_empty() && !result.warnings.is_empty());
+
+        let result = compiler.check_with_options("let used = 1; println!(\"{}\", used);", &options);
+        assert!(result.unwrap().success);
+    }
+
+    #[test]
+    fn test_parse_check() {
+        let compiler = RustCompiler::new().unwrap();
+        assert!(compiler.parse_check("fn main() { let x = 1; }").success);
+        assert!(compiler.parse_check("let x = 1;\nprintln!(\"{}\", x);").success);
+
+        let result = compiler.parse_check("fn main() {\n    let x = ;\n}");
+        assert!(!result.success);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!((result.errors[0].line, result.errors[0].column), (Some(2), Some(13)));
+
+        // Snippets are reported against their own lines, not the wrapped block's
+        let result = compiler.parse_check("let a = 1;\nlet b = a +;");
+        assert_eq!(result.errors[0].line, Some(2), "Errors: {:?}", result.errors);
+    }
+
+    #[test]
+    fn test_list_symbols() {
+        let compiler = RustCompiler::new().unwrap();
+        let code = "const LIMIT: u32 = 10;\n\n\
+                    pub fn area(w: f32, h: f32) -> f32 {\n    w * h\n}\n\n\
+                    struct Cell<T> {\n    value: T,\n}\n\n\
+                    impl Cell<u8> {\n    fn grow(&self) {}\n}\n";
+        let symbols = compiler.list_symbols(code);
+        let names: Vec<_> = symbols.iter().map(|s| (s.name.as_str(), s.kind)).collect();
+        assert_eq!(
+            names,
+            [
+                ("LIMIT", SymbolKind::Const),
+                ("area", SymbolKind::Function),
+                ("Cell", SymbolKind::Struct),
+            ]
+        );
+        assert_eq!(symbols[0].signature, "const LIMIT: u32");
+        assert_eq!(symbols[1].signature, "fn area(w: f32, h: f32) -> f32");
+        assert_eq!(symbols[2].signature, "struct Cell<T>");
+        assert_eq!((symbols[1].span.line_start, symbols[1].span.line_end), (3, 5));
+
+        // Snippets are reported against their own lines
+        let symbols = compiler.list_symbols("let x = 1;\nfn double(n: i32) -> i32 { n * 2 }");
+        assert_eq!(symbols.len(), 1);
+        assert_eq!((symbols[0].span.line_start, symbols[0].span.column_start), (2, 1));
+    }
+
+    #[test]
+    fn test_lint_presets() {
+        assert!(LintPreset::Standard.lints().is_empty());
+        let strict = CheckOptions {
+            lints: LintPreset::Strict.lints(),
+            ..Default::default()
+        };
+        assert!(manifest(&strict).contains("pedantic = { level = \"warn\", priority = -1 }"));
+
+        if !is_cargo_available() {
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let code = "fn helper() {}\nfn main() { let unused = 1; }";
+        let standard = compiler.check_code(code).unwrap();
+        assert_eq!(standard.warnings.len(), 2, "Warnings: {:?}", standard.warnings);
+
+        let beginner = CheckOptions {
+            lints: LintPreset::Beginner.lints(),
+            ..Default::default()
+        };
+        let result = compiler.check_with_options(code, &beginner).unwrap();
+        assert!(result.warnings.is_empty(), "Warnings: {:?}", result.warnings);
+    }
+
+    #[test]
+    fn test_lint_levels() {
+        let mut options = CheckOptions::default();
+        options.lints.insert("unused_variables".to_string(), LintLevel::Allow);
+        options.lints.insert("missing_docs".to_string(), LintLevel::Deny);
+        options.lints.insert("clippy::needless_return".to_string(), LintLevel::Warn);
+        assert!(manifest(&options).ends_with(
+            "\n[lints.clippy]\nneedless_return = \"warn\"\n\
+             \n[lints.rust]\nmissing_docs = \"deny\"\nunused_variables = \"allow\"\n"
+        ));
+
+        let compiler = RustCompiler::new().unwrap();
+        let mut invalid = CheckOptions::default();
+        invalid.lints.insert("unused = 1".to_string(), LintLevel::Allow);
+        let result = compiler.check_with_options("fn main() {}", &invalid).unwrap();
+        assert!(result.errors[0].message.contains("Invalid lint name"));
+
+        if is_cargo_available() {
+            let result = compiler.check_with_options("let unused = 1;", &options).unwrap();
+            assert!(result.warnings.iter().all(|w| w.code.as_deref() != Some("unused_variables")));
+            assert!(
+                result.errors.iter().any(|e| e.code.as_deref() == Some("missing_docs")),
+                "missing_docs is denied: {:?}",
+                result.errors
+            );
+        }
+    }
+
+    #[test]
+    fn test_feature_gates() {
+        let code = "#![feature(never_type, let_chains)]\n#![allow(unused)]\n\
+                    #![feature(try_blocks)]";
+        assert_eq!(
+            feature_gates(code),
+            [
+                (1, vec!["never_type".to_string(), "let_chains".to_string()]),
+                (3, vec!["try_blocks".to_string()]),
+            ]
+        );
+
+        let code = "// #![feature(never_type)]\n\
+                    fn main() {\n    let s = \"\n#![feature(try_blocks)]\";\n}";
+        assert!(feature_gates(code).is_empty());
+        assert!(feature_gates("/* #![feature(never_type)] */ let x = 1;").is_empty());
+
+        if !is_cargo_available() || is_default_toolchain_nightly() {
+            return;
+        }
+
+        let code = "#![feature(never_type)]\nfn main() { let _: Option<!> = None; }";
+        let mut compiler = RustCompiler::new().unwrap();
+        let result = compiler.check_code(code).unwrap();
+        assert_eq!(result.errors.len(), 1);
+        assert!(result.errors[0].message.contains("`never_type`"));
+        assert!(result.errors[0].message.contains("nightly"));
+        assert_eq!(result.errors[0].line, Some(1));
+
+        if is_nightly_available() {
+            compiler.set_nightly_for_feature_gates(true);
+            let result = compiler.check_code(code).unwrap();
+            assert!(result.success, "Errors: {:?}", result.errors);
+        }
+    }
+
+    #[test]
+    fn test_no_std() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let options = CheckOptions {
+            no_std: true,
+            ..Default::default()
+        };
+
+        let code = "pub fn checked_sum(values: &[u32]) -> Option<u32> {\n    \
+                    values.iter().try_fold(0u32, |sum, v| sum.checked_add(*v))\n}";
+        let result = compiler.check_with_options(code, &options).unwrap();
+        assert!(result.success, "Errors: {:?}", result.errors);
+
+        let uses_std = "pub fn names() -> Vec<String> { Vec::new() }";
+        let result = compiler.check_with_options(uses_std, &options).unwrap();
+        assert!(!result.success, "Vec isn't in the no_std prelude");
+        assert!(result.errors.iter().any(|e| e.file.as_deref() == Some("src/lib.rs")));
+    }
+
+    #[test]
+    fn test_rustflags_and_env() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let check = CheckOptions {
+            rustflags: vec![
+                "--cfg".to_string(),
+                "blockly_flag".to_string(),
+                "--cfg".to_string(),
+                "blockly_greeting=\"two words\"".to_string(),
+            ],
+            env: BTreeMap::from([("BLOCKLY_GREETING".to_string(), "hi".to_string())]),
+            ..Default::default()
+        };
+        let code = r#"
+            #[cfg(not(blockly_flag))]
+            compile_error!("--cfg blockly_flag was not passed");
+            #[cfg(not(blockly_greeting = "two words"))]
+            compile_error!("a flag with a space was split");
+
+            fn main() {
+                let built_with: &str = env!("BLOCKLY_GREETING");
+                println!("{} {}", built_with, std::env::var("BLOCKLY_GREETING").unwrap());
+            }
+        "#;
+
+        let result = compiler.check_with_options(code, &check).unwrap();
+        assert!(result.success, "Errors: {:?}", result.errors);
+
+        let options = RunOptions {
+            check,
+            ..Default::default()
+        };
+        let result = compiler.run_code(code, &options).unwrap();
+        assert_eq!(result.stdout, "hi hi\n", "stderr: {}", result.stderr);
+    }
+
+    #[test]
+    fn test_run_code() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+
+        let code = r#"println!("hello"); eprintln!("oops"); std::process::exit(3);"#;
+        let result = compiler.run_code(code, &RunOptions::default()).unwrap();
+        assert!(result.compilation.success);
+        assert_eq!(result.exit_code, Some(3));
+        assert_eq!(result.stdout, "hello\n");
+        assert_eq!(result.stderr, "oops\n");
+        assert!(!result.timed_out);
+
+        let options = RunOptions {
+            time_limit: Duration::from_millis(300),
+            ..Default::default()
+        };
+        let result = compiler
+            .run_code(r#"println!("started"); loop { std::thread::yield_now(); }"#, &options)
+            .unwrap();
+        assert!(result.timed_out, "Endless loop should be stopped");
+        assert_eq!(result.stdout, "started\n", "Output before the kill should be kept");
+
+        let options = RunOptions {
+            stdin: Some("Ferris\n".to_string()),
+            ..Default::default()
+        };
+        let code = r#"
+            let mut name = String::new();
+            std::io::stdin().read_line(&mut name).unwrap();
+            println!("Hello, {}!", name.trim());
+        "#;
+        let result = compiler.run_code(code, &options).unwrap();
+        assert_eq!(result.stdout, "Hello, Ferris!\n");
+
+        let result = compiler.run_code("let x: i32 = \"text\";", &options).unwrap();
+        assert!(!result.compilation.success);
+        assert_eq!(result.exit_code, None);
+    }
+
+    #[test]
+    fn test_autofix_until_clean() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        // The inner parentheses are only reported once the outer ones are gone
+        let code = "let mut x = ((1 + 2));\nprintln!(\"{}\", x);";
+
+        let (fixed, result) = compiler.autofix_until_clean(code, 5).unwrap();
+        assert!(result.success);
+        assert!(result.warnings.is_empty(), "Fixed code should be clean: {}", fixed);
+        assert!(!fixed.contains("mut") && !fixed.contains("(("), "Got: {}", fixed);
+    }
+
+    #[test]
+    fn test_fixes_as_diff() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let code = "let mut x = 1;\nprintln!(\"{}\", x);\n";
+
+        let result = compiler.check_code(code).unwrap();
+        let diff = result.fixes_as_diff(code);
+        assert!(diff.contains("-let mut x = 1;"), "Got: {}", diff);
+        assert!(diff.contains("+let x = 1;"), "Got: {}", diff);
+    }
+
+    #[test]
+    fn test_suggested_replacement() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let result = compiler.check_code("let count = 1;\n").unwrap();
+
+        let warning = &result.warnings[0];
+        assert_eq!(warning.suggestion.as_deref(), Some("_count"));
+        // Line 1 of the snippet as submitted, not of the one wrapped in main
+        assert_eq!(result.line_offset, 1);
+        assert_eq!(warning.line, Some(1));
+        let range = SourceRange {
+            line_start: 1,
+            column_start: 5,
+            line_end: 1,
+            column_end: 10,
+        };
+        assert_eq!(warning.suggestion_range, Some(range));
+
+        // The help carrying the suggestion travels with the warning
+        let related_help = warning.related.iter().find(|r| matches!(r.level, ErrorLevel::Help));
+        assert!(related_help.unwrap().message.contains("prefix it with an underscore"));
+    }
+
+    #[test]
+    fn test_diagnostic_span_range() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let code = "fn main() {\n    let x: i32 = \"text\";\n}\n";
+        let result = compiler.check_code(code).unwrap();
+
+        let error = &result.errors[0];
+        assert_eq!((error.line, error.column), (Some(2), Some(18)));
+        assert_eq!((error.line_end, error.column_end), (Some(2), Some(24)));
+        let (start, end) = (error.byte_start.unwrap(), error.byte_end.unwrap());
+        assert_eq!(&code[start..end], "\"text\"");
+    }
+
+    #[test]
+    fn test_multi_span_diagnostic() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let code = r#"fn main() {
+    let a = String::new();
+    let b = a;
+    println!("{} {}", a, b);
+}"#;
+        let result = compiler.check_code(code).unwrap();
+
+        let error = result
+            .errors
+            .iter()
+            .find(|e| e.code.as_deref() == Some("E0382"))
+            .expect("Should report use after move");
+        let label_line = |label: &str| {
+            error
+                .spans
+                .iter()
+                .find(|span| span.label.as_deref().is_some_and(|l| l.contains(label)))
+                .map(|span| span.line_start)
+        };
+        assert_eq!(label_line("move occurs"), Some(2));
+        assert_eq!(label_line("value moved here"), Some(3));
+        assert_eq!(label_line("borrowed here"), Some(4));
+        assert_eq!(error.line, Some(4), "Position fields should follow the primary span");
+    }
+
+    #[test]
+    fn test_fix_code() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let code = "let y = 2;\nlet mut x = y;\nprintln!(\"{}\", x);\n";
+
+        let (fixed, applied, result) = compiler.fix_code(code).unwrap();
+        assert_eq!(fixed, "let y = 2;\nlet x = y;\nprintln!(\"{}\", x);\n");
+        assert_eq!(applied.len(), 1, "Got: {:?}", applied);
+        assert_eq!((applied[0].line, applied[0].column), (2, 5));
+        assert!(applied[0].message.contains("mut"), "Got: {}", applied[0].message);
+        assert_eq!(result.warnings.len(), 1);
+
+        // Fixes map back through the wrapping the code was checked with
+        let options = CheckOptions {
+            wrap_mode: WrapMode::Template("// prelude\n\nfn main() {\n{{code}}\n}".to_string()),
+            ..Default::default()
+        };
+        let result = compiler.check_with_options(code, &options).unwrap();
+        assert_eq!(result.wrap_mode, options.wrap_mode);
+        assert_eq!(result.fixed_code(code), "let y = 2;\nlet x = y;\nprintln!(\"{}\", x);\n");
+    }
+
+    struct TodoLinter;
+
+    impl Linter for TodoLinter {
+        fn lint(&self, code: &str) -> Vec<CompilationError> {
+            code.lines()
+                .enumerate()
+                .filter(|(_, line)| line.contains("TODO"))
+                .map(|(index, _)| CompilationError {
+                    line: Some(index + 1),
+                    ..CompilationError::new(ErrorLevel::Warning, "TODO comment left in code")
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_diagnostics_sorted_after_linters() {
+        struct FirstLineLinter;
+
+        impl Linter for FirstLineLinter {
+            fn lint(&self, _code: &str) -> Vec<CompilationError> {
+                vec![CompilationError {
+                    file: Some("src/main.rs".to_string()),
+                    line: Some(1),
+                    ..CompilationError::new(ErrorLevel::Error, "from the linter")
+                }]
+            }
+        }
+
+        let mut compiler = RustCompiler::new().unwrap();
+        compiler.add_linter(FirstLineLinter);
+        let mut result = failed_result(CompilationError {
+            file: Some("src/main.rs".to_string()),
+            line: Some(3),
+            ..CompilationError::new(ErrorLevel::Error, "from rustc")
+        });
+
+        compiler.finish_result("", Instant::now(), &mut result);
+        let lines: Vec<_> = result.errors.iter().map(|error| error.line).collect();
+        assert_eq!(lines, [Some(1), Some(3)]);
+    }
+
+    #[test]
+    fn test_custom_linter() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let mut compiler = RustCompiler::new().unwrap();
+        compiler.add_linter(TodoLinter);
+
+        let code = "let unused = 1;\n// TODO: print something";
+        let result = compiler.check_code(code).unwrap();
+
+        assert!(result.success);
+        assert!(result
+            .warnings
+            .iter()
+            .any(|w| w.message == "TODO comment left in code" && w.line == Some(2)));
+        assert!(
+            result.warnings.iter().any(|w| w.message.contains("unused variable")),
+            "Compiler warnings should still be reported"
+        );
+    }
+
+    #[test]
+    fn test_public_api() {
+        if !is_nightly_available() {
+            println!("Skipping test: nightly toolchain not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let code = r#"
+            pub fn grow(cell: &mut Cell, amount: f32) -> bool {
+                cell.size += amount;
+                true
+            }
+
+            fn helper() {}pub fn bytes() -> [u8; 4] { [0; 4] }
+
+            pub const MASK: [u8; 4] = [0xff; 4];
+
+            pub struct Cell {
+                pub size: f32,
+            }
+
+            impl Cell {
+                pub fn new() -> Self {
+                    Cell { size: 1.0 }
+                }
+            }
+        "#;
+
+        let api = compiler.public_api(code).unwrap();
+        let grow = api.iter().find(|item| item.path == "grow").expect("grow should be listed");
+        assert_eq!(grow.kind, ApiItemKind::Function);
+        assert_eq!(grow.signature, "pub fn grow(cell: &mut Cell, amount: f32) -> bool");
+        let signature = |path: &str| {
+            api.iter().find(|item| item.path == path).map(|item| item.signature.as_str())
+        };
+        assert_eq!(signature("bytes"), Some("pub fn bytes() -> [u8; 4]"));
+        assert_eq!(signature("MASK"), Some("pub const MASK: [u8; 4]"));
+        assert_eq!(signature("Cell"), Some("pub struct Cell"));
+        assert!(api.iter().any(|item| item.path == "Cell" && item.kind == ApiItemKind::Struct));
+        assert!(api.iter().any(|item| item.path == "Cell::new"));
+        assert!(!api.iter().any(|item| item.path == "helper"));
+    }
+
+    #[test]
+    fn test_expand_code() {
+        if !is_nightly_available() {
+            println!("Skipping test: nightly toolchain not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let code = r#"
+            #[derive(Debug)]
+            struct Cell;
+
+            fn main() {
+                println!("{:?}", Cell);
+            }
+        "#;
+        let expanded = compiler.expand_code(code).unwrap();
+
+        assert!(expanded.contains("impl ::core::fmt::Debug for Cell"), "Got: {}", expanded);
+        assert!(!expanded.contains("println!"), "Got: {}", expanded);
+
+        assert!(compiler.expand_code("let x = ;").is_err());
+    }
+
+    #[test]
+    fn test_demangle_symbols() {
+        let linux = "call _ZN13blockly_check6double17h0123456789abcdefE";
+        assert_eq!(demangle_symbols(linux), "call blockly_check::double");
+        let macos = "bl __ZN13blockly_check6double17h0123456789abcdefE";
+        assert_eq!(demangle_symbols(macos), "bl blockly_check::double");
+        assert_eq!(demangle_symbols("__init _ZNothing"), "__init _ZNothing");
+    }
+
+    #[test]
+    fn test_emit_ir() {
+        if !is_rust_available() {
+            println!("Skipping test: rustc not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let code = "fn double(x: i32) -> i32 { x * 2 }\nfn main() { double(2); }";
+
+        let mir = compiler.emit_ir(code, IrKind::Mir).unwrap();
+        assert!(mir.contains("fn double(_1: i32) -> i32"), "Got: {}", mir);
+
+        let asm = compiler.emit_ir(code, IrKind::Asm).unwrap();
+        assert!(asm.contains("blockly_check::double"), "Got: {}", asm);
+        assert!(!asm.contains("_ZN13blockly_check6double"));
+
+        let llvm_ir = compiler.emit_ir(code, IrKind::LlvmIr).unwrap();
+        assert!(llvm_ir.contains("define"), "Got: {}", llvm_ir);
+
+        if is_nightly_available() {
+            let hir = compiler.emit_ir(code, IrKind::Hir).unwrap();
+            assert!(hir.contains("fn double(x: i32) -> i32"), "Got: {}", hir);
+        }
+    }
+
+    #[test]
+    fn test_format_code() {
+        if !is_rust_available() {
+            println!("Skipping test: rustc not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+
+        let formatted = compiler.format_code("let  x=1;println!(\"{}\",x);").unwrap();
+        assert_eq!(formatted.as_deref(), Some("let x = 1;\nprintln!(\"{}\", x);\n"));
+
+        let formatted = compiler.format_code("fn main(){let x=1;}").unwrap();
+        assert_eq!(formatted.as_deref(), Some("fn main() {\n    let x = 1;\n}\n"));
+
+        assert_eq!(compiler.format_code("fn main() { let x = ; }").unwrap(), None);
+
+        // The 2024 style sorts imports by version rather than character by character
+        let imports = "use shapes::{cell10, cell9};\n";
+        assert_eq!(compiler.format_code(imports).unwrap().as_deref(), Some(imports));
+
+        let mut compiler = compiler;
+        compiler.set_format_edition(Edition::E2024);
+        let formatted = compiler.format_code(imports).unwrap();
+        assert_eq!(formatted.as_deref(), Some("use shapes::{cell9, cell10};\n"));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_checks() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let (valid, invalid) = tokio::join!(
+            compiler.check_code_async("let x: i32 = 1;\nprintln!(\"{}\", x);"),
+            compiler.quick_check_async(r#"pub fn f() -> i32 { "text" }"#),
+        );
+
+        assert!(valid.unwrap().success);
+        let invalid = invalid.unwrap();
+        assert!(!invalid.success);
+        assert_eq!(invalid.errors[0].code.as_deref(), Some("E0308"));
+    }
+
+    #[test]
+    fn test_timeout() {
+        if !is_rust_available() {
+            println!("Skipping test: rustc not available");
+            return;
+        }
+
+        let mut compiler = RustCompiler::new().unwrap();
+        compiler.set_timeout(Some(Duration::from_millis(1)));
+
+        let started = Instant::now();
+        let result = compiler.quick_check("pub fn f() {}").unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(result.timed_out);
+        assert!(!result.success);
+        assert_eq!(result.errors[0].category, Some(ErrorCategory::Timeout));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_timeout_kills_process_tree() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "sleep 60 & echo $!; wait"]);
+        let timeout = Some(Duration::from_millis(300));
+        let limits = ResourceLimits::default();
+        let (output, killed) =
+            output_with_timeout(command, &limits, None, timeout, None, None).unwrap();
+        assert!(killed);
+
+        // The grandchild is gone, or a zombie waiting to be reaped
+        let pid = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        std::thread::sleep(Duration::from_millis(100));
+        let state = fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap_or_default();
+        let state = state.rsplit(") ").next().and_then(|rest| rest.chars().next());
+        assert!(matches!(state, None | Some('Z')), "sleep {} is still running", pid);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_output_is_capped() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "head -c 5000000 /dev/zero; echo done >&2"]);
+        let limits = ResourceLimits::default();
+        let (output, killed) =
+            output_with_timeout(command, &limits, None, None, None, None).unwrap();
+        assert!(!killed);
+        assert!(output.status.success(), "The writer shouldn't block once the cap is reached");
+
+        let note = format!("\n[output truncated after {} bytes]\n", MAX_CAPTURED_OUTPUT_BYTES);
+        assert_eq!(output.stdout.len(), MAX_CAPTURED_OUTPUT_BYTES + note.len());
+        assert!(output.stdout.ends_with(note.as_bytes()));
+        assert_eq!(output.stderr, b"done\n");
+    }
+
+    #[test]
+    fn test_result_cache() {
+        if !is_rust_available() {
+            println!("Skipping test: rustc not available");
+            return;
+        }
+
+        let mut compiler = RustCompiler::new().unwrap();
+        compiler.enable_result_cache(8);
+
+        let first = compiler.quick_check("pub fn f() {}").unwrap();
+        let second = compiler.quick_check("pub fn f() {}").unwrap();
+        let other = compiler.quick_check("pub fn g() {}").unwrap();
+
+        assert!(!first.cached);
+        assert!(second.cached, "Identical check should be served from the cache");
+        assert!(!other.cached, "Different code should not hit the cache");
+
+        compiler.set_deny_list(Some(DenyList::default()));
+        let reconfigured = compiler.quick_check("pub fn f() {}").unwrap();
+        assert!(!reconfigured.cached, "The checker's configuration is part of the key");
+    }
+
+    #[test]
+    fn test_transient_results_not_cached() {
+        let code_error = CompilationError {
+            file: Some("src/main.rs".to_string()),
+            ..CompilationError::new(ErrorLevel::Error, "mismatched types")
+        };
+        assert!(is_cacheable(&failed_result(code_error)));
+
+        let network = dependency_failure("failed to download `rand v0.8.5`".to_string());
+        assert!(!is_cacheable(&network));
+        let cargo = failed_result(CompilationError::new(ErrorLevel::Error, "No space left on device"));
+        assert!(!is_cacheable(&cargo));
+    }
+
+    #[test]
+    fn test_builder() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let temp_dir =
+            std::env::temp_dir().join(format!("blockly_builder_{}", uuid::Uuid::new_v4()));
+        let compiler = RustCompiler::builder()
+            .temp_dir(&temp_dir)
+            .timeout(Duration::from_secs(120))
+            .result_cache(4)
+            .keep_projects(true)
+            .build()
+            .unwrap();
+
+        let first = compiler.check_code("let x = 1;").unwrap();
+        let second = compiler.check_code("let x = 1;").unwrap();
+        assert!(first.success);
+        assert!(second.cached, "The builder should enable the result cache");
+
+        let kept = fs::read_dir(&temp_dir).unwrap().count();
+        let _ = fs::remove_dir_all(&temp_dir);
+        assert_eq!(kept, 1, "The checked project should be kept");
+    }
+
+    #[test]
+    fn test_cancel_check() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let temp_dir =
+            std::env::temp_dir().join(format!("blockly_cancel_{}", uuid::Uuid::new_v4()));
+        let compiler = RustCompiler::builder().temp_dir(&temp_dir).build().unwrap();
+        let cancel = CancellationToken::new();
+        let options = CheckOptions {
+            cancel: Some(cancel.clone()),
+            ..Default::default()
+        };
+
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            cancel.cancel();
+        });
+        let result = compiler.check_with_options("let x = 1;", &options);
+        canceller.join().unwrap();
+
+        let left = fs::read_dir(&temp_dir).unwrap().count();
+        let _ = fs::remove_dir_all(&temp_dir);
+        assert!(matches!(result, Err(CompilerError::Cancelled)), "{:?}", result);
+        assert_eq!(left, 0, "The cancelled check's project should be removed");
+        assert!(matches!(
+            compiler.check_with_options("let y = 2;", &options),
+            Err(CompilerError::Cancelled)
+        ));
+    }
+
+    #[derive(Default)]
+    struct RecordedPhases(Mutex<Vec<CheckPhase>>);
+
+    impl ProgressObserver for RecordedPhases {
+        fn on_phase(&self, phase: &CheckPhase) {
+            self.0.lock().unwrap().push(phase.clone());
+        }
+    }
+
+    #[test]
+    fn test_progress_observer() {
+        // Checks that end at a gate finish too
+        let mut compiler = RustCompiler::new().unwrap();
+        compiler.set_max_dependencies(Some(0));
+        let rejected = Arc::new(RecordedPhases::default());
+        let options = CheckOptions {
+            dependencies: vec![Dependency::new("rand", "0.8")],
+            progress: Some(rejected.clone()),
+            ..Default::default()
+        };
+        assert!(!compiler.check_with_options("let x = 1;", &options).unwrap().success);
+        assert_eq!(*rejected.0.lock().unwrap(), [CheckPhase::Finished]);
+
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let phases = Arc::new(RecordedPhases::default());
+        let options = CheckOptions {
+            progress: Some(phases.clone()),
+            ..Default::default()
+        };
+        assert!(compiler.check_with_options("let x = 1;", &options).unwrap().success);
+
+        let phases = phases.0.lock().unwrap();
+        assert_eq!(
+            *phases,
+            [
+                CheckPhase::Compiling {
+                    crate_name: "blockly_check".to_string(),
+                    current: 1,
+                    total: Some(1),
+                },
+                CheckPhase::Finished,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resource_limits() {
+        if !is_cargo_available() || !cfg!(unix) {
+            println!("Skipping test: cargo or rlimits not available");
+            return;
+        }
+
+        let compiler = RustCompiler::builder()
+            .resource_limits(ResourceLimits {
+                memory_bytes: None,
+                cpu_time: Some(Duration::from_secs(1)),
+            })
+            .build()
+            .unwrap();
+        let code = "#[allow(long_running_const_eval)]\n\
+                    const N: u64 = { let mut i = 0; while i < u64::MAX { i += 1; } i };\n\
+                    println!(\"{}\", N);";
+        let result = compiler.check_code(code).unwrap();
+
+        assert!(!result.success);
+        assert_eq!(result.errors[0].category, Some(ErrorCategory::ResourceLimit));
+        assert!(result.errors[0].message.contains("signal"), "{}", result.errors[0].message);
+    }
+
+    #[test]
+    fn test_forbid_unsafe() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let options = CheckOptions {
+            forbid_unsafe: true,
+            ..Default::default()
+        };
+        let code = "let x = 1;\nlet y = unsafe { *(&x as *const i32) };\nprintln!(\"{}\", y);";
+        assert!(compiler.check_code(code).unwrap().success);
+        let result = compiler.check_with_options(code, &options).unwrap();
+        assert!(!result.success);
+        assert_eq!(result.errors[0].code.as_deref(), Some("unsafe_code"));
+        assert_eq!((result.errors[0].line, result.errors[0].column), (Some(2), Some(9)));
+
+        // In a project, positions in the crate root and in modules are both kept
+        let mut files = HashMap::new();
+        files.insert(PathBuf::from("main.rs"), "mod raw;\nunsafe fn f() {}\nfn main() {}\n".into());
+        files.insert(PathBuf::from("raw.rs"), "pub fn g() {}\npub unsafe fn h() {}\n".into());
+        let result = compiler.check_project(&files, &options).unwrap();
+        let mut found: Vec<_> =
+            result.errors.iter().map(|error| (error.file.clone().unwrap(), error.line)).collect();
+        found.sort();
+        assert_eq!(
+            found,
+            [("src/main.rs".to_string(), Some(2)), ("src/raw.rs".to_string(), Some(2))]
+        );
+    }
+
+    #[test]
+    fn test_deny_list_rejects_before_compiling() {
+        let compiler = RustCompiler::builder().deny_list(DenyList::default()).build().unwrap();
+        let code = "let status = std::process::Command::new(\"ls\").status();";
+
+        let result = compiler.check_code(code).unwrap();
+        assert!(!result.success);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].category, Some(ErrorCategory::DeniedApi));
+        assert_eq!((result.errors[0].line, result.errors[0].column), (Some(1), Some(14)));
+        assert!(result.stderr.is_empty(), "Cargo shouldn't have run");
+
+        let run = compiler.run_code(code, &RunOptions::default()).unwrap();
+        assert_eq!(run.exit_code, None);
+        assert_eq!(run.compilation.errors[0].category, Some(ErrorCategory::DeniedApi));
+    }
+
+    #[test]
+    fn test_sandboxed_run() {
+        if !is_cargo_available() || !Sandbox::is_supported() {
+            println!("Skipping test: cargo or the sandbox not available");
+            return;
+        }
+
+        let compiler = RustCompiler::builder().sandbox(Sandbox::default()).build().unwrap();
+        let code = "println!(\"{}\", std::fs::write(\"/tmp/blockly_escape\", \"\").is_err());";
+        let result = compiler.run_code(code, &RunOptions::default()).unwrap();
+
+        assert!(result.compilation.success, "{:?}", result.compilation.errors);
+        assert_eq!(result.stdout, "true\n");
+    }
+
+    #[test]
+    fn test_sandboxed_rustc_only_paths() {
+        if !is_rust_available() || !Sandbox::is_supported() {
+            println!("Skipping test: rustc or the sandbox not available");
+            return;
+        }
+
+        let compiler = RustCompiler::builder().sandbox(Sandbox::default()).build().unwrap();
+        let result = compiler.quick_check("pub fn double(x: i32) -> i32 { x * 2 }").unwrap();
+        assert!(result.success, "{:?}", result.errors);
+
+        let mir = compiler.emit_ir("let x = 1 + 2;", IrKind::Mir).unwrap();
+        assert!(mir.contains("fn main()"), "{}", mir);
+
+        if is_nightly_available() {
+            let api = compiler.public_api("pub fn double(x: i32) -> i32 { x * 2 }").unwrap();
+            assert_eq!(api[0].signature, "pub fn double(x: i32) -> i32");
+        }
+    }
+
+    #[test]
+    fn test_container_mounts_only_project() {
+        let cargo_home = std::env::temp_dir().join("blockly_container_cargo_home");
+        let compiler = RustCompiler::builder()
+            .container(ContainerConfig::default())
+            .cargo_home(&cargo_home)
+            .shared_target_dir(std::env::temp_dir().join("blockly_container_target"))
+            .build()
+            .unwrap();
+        let project = compiler.temporary_dir().unwrap();
+        let command = compiler.check_command(project.path(), &CheckOptions::default()).unwrap();
+
+        let (wrapped, limits) = compiler.isolate(command, None).unwrap();
+        let args: Vec<_> =
+            wrapped.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect();
+        let volumes: Vec<_> = args.iter().filter(|arg| arg.starts_with("--volume=")).collect();
+        let volume = |path: &Path| format!("--volume={}:{}", path.display(), path.display());
+        assert_eq!(volumes, [&volume(project.path()), &volume(&cargo_home)]);
+        assert!(!args.iter().any(|arg| arg.starts_with("CARGO_TARGET_DIR=")));
+        assert!(limits.is_unlimited(), "The container applies the limits");
+    }
+
+    #[test]
+    fn test_check_many() {
+        if !is_cargo_available() {
+            println!("Skipping test: cargo not available");
+            return;
+        }
+
+        let compiler = RustCompiler::new().unwrap();
+        let snippets = ["let a = 1;", "let b: i32 = \"text\";", "let c = 3;"];
+        let results = compiler.check_many_with_options(&snippets, &CheckOptions::default(), 2);
+
+        assert_eq!(results.len(), 3);
+        let success: Vec<bool> = results.iter().map(|r| r.as_ref().unwrap().success).collect();
+        assert_eq!(success, [true, false, true], "Results should be in snippet order");
+        assert!(compiler.check_many(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_slow_check_note() {
+        if !is_rust_available() {
+            println!("Skipping test: rustc not available");
+            return;
+        }
+
+        let mut compiler = RustCompiler::new().unwrap();
+        compiler.set_slow_check_threshold(Duration::ZERO);
+
+        let result = compiler.quick_check("pub fn f() {}").unwrap();
+        assert!(result.success);
+        assert!(result.notes.iter().any(|n| n.message.contains("unusually slow")));
     }
 }
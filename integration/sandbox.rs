@@ -0,0 +1,396 @@
+// Sandbox - Confines the processes a check spawns, on Linux
+// Landlock limits which files they can touch and seccomp keeps them off the network
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Restrictions on cargo, rustc, build scripts and programs being run
+///
+/// Processes may read and execute files under `read_paths`, and change files
+/// only under their working directory (the check's project) and
+/// `write_paths`. Unless `allow_network` is set they can't open sockets of
+/// any kind, Unix sockets included, so dependencies must already be downloaded and unpacked (see
+/// `RustCompiler::set_cargo_home`).
+///
+/// Only Linux is supported, with Landlock (5.13 or later) for the file rules
+/// and seccomp for the network; checks fail with an I/O error elsewhere.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Sandbox {
+    /// Directories processes may read and execute files from
+    pub read_paths: Vec<PathBuf>,
+    /// Directories processes may also create, change and remove files in
+    pub write_paths: Vec<PathBuf>,
+    /// Let processes open sockets
+    pub allow_network: bool,
+}
+
+impl Default for Sandbox {
+    /// System directories, the toolchain and the parts of `CARGO_HOME` builds
+    /// need are readable
+    ///
+    /// Only the registry, git checkouts and `bin` (with the rustup proxies) can
+    /// be read in `CARGO_HOME`, keeping `credentials.toml` and `config.toml`
+    /// away from build scripts. Of those, only cargo's package cache lock,
+    /// registry index and downloads, and git databases are writable. `bin` and
+    /// unpacked registry sources are shared with builds outside the sandbox,
+    /// so a build script mustn't be able to change them.
+    fn default() -> Self {
+        let home = std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+        let tool_home = |variable: &str, default: &str| {
+            std::env::var_os(variable).map_or_else(|| home.join(default), PathBuf::from)
+        };
+        let cargo_home = tool_home("CARGO_HOME", ".cargo");
+        let system = ["/usr", "/lib", "/lib32", "/lib64", "/bin", "/sbin", "/etc", "/proc", "/sys"];
+        let cargo_readable =
+            ["registry/src", "registry/index", "registry/cache", "git", "bin"];
+        let cargo_writable = [
+            ".package-cache",
+            ".package-cache-mutate",
+            "registry/cache",
+            "registry/index",
+            "git",
+        ];
+        Self {
+            read_paths: system
+                .iter()
+                .map(PathBuf::from)
+                .chain([tool_home("RUSTUP_HOME", ".rustup")])
+                .chain(cargo_readable.iter().map(|path| cargo_home.join(path)))
+                .collect(),
+            write_paths: cargo_writable.iter().map(|path| cargo_home.join(path)).collect(),
+            allow_network: false,
+        }
+    }
+}
+
+impl Sandbox {
+    /// Whether the sandbox can be enforced on this machine
+    pub fn is_supported() -> bool {
+        linux::landlock_abi().is_some_and(|abi| abi > 0) && linux::AUDIT_ARCH.is_some()
+    }
+
+    /// Confine processes started by `command` before they execute, letting
+    /// them also write to `writable`, e.g. a shared target directory
+    ///
+    /// Temporary files go to the working directory, since `/tmp` isn't writable.
+    pub(crate) fn apply(&self, command: &mut Command, writable: &[&Path]) -> std::io::Result<()> {
+        let project = command.get_current_dir().map(Path::to_path_buf);
+        if let Some(project) = &project {
+            command.env("TMPDIR", project);
+        }
+        let write_paths: Vec<&Path> = self
+            .write_paths
+            .iter()
+            .map(PathBuf::as_path)
+            .chain(project.as_deref())
+            .chain(writable.iter().copied())
+            .collect();
+        linux::confine(command, &self.read_paths, &write_paths, self.allow_network)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::ffi::CString;
+    use std::io::{Error, ErrorKind};
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::process::CommandExt;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    // Landlock file access rights, from <linux/landlock.h>
+    const ACCESS_EXECUTE: u64 = 1 << 0;
+    const ACCESS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_READ_FILE: u64 = 1 << 2;
+    const ACCESS_READ_DIR: u64 = 1 << 3;
+    const ACCESS_REFER: u64 = 1 << 13;
+    const ACCESS_TRUNCATE: u64 = 1 << 14;
+    const ACCESS_IOCTL_DEV: u64 = 1 << 15;
+    const CREATE_RULESET_VERSION: u32 = 1 << 0;
+    const RULE_PATH_BENEATH: u32 = 1;
+
+    #[cfg(target_arch = "x86_64")]
+    pub(super) const AUDIT_ARCH: Option<u32> = Some(0xC000_003E);
+    #[cfg(target_arch = "aarch64")]
+    pub(super) const AUDIT_ARCH: Option<u32> = Some(0xC000_00B7);
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    pub(super) const AUDIT_ARCH: Option<u32> = None;
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    /// The kernel's Landlock ABI version, if it supports Landlock
+    pub(super) fn landlock_abi() -> Option<i64> {
+        // SAFETY: querying the version takes no attribute
+        let abi = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<RulesetAttr>(),
+                0usize,
+                CREATE_RULESET_VERSION,
+            )
+        };
+        (abi > 0).then_some(abi)
+    }
+
+    pub(super) fn confine(
+        command: &mut Command,
+        read_paths: &[PathBuf],
+        write_paths: &[&Path],
+        allow_network: bool,
+    ) -> std::io::Result<()> {
+        let unsupported = |what| Error::new(ErrorKind::Unsupported, what);
+        let abi = landlock_abi().ok_or_else(|| unsupported("Landlock is not enabled"))?;
+        let network_filter = match (allow_network, AUDIT_ARCH) {
+            (true, _) => None,
+            (false, Some(arch)) => Some(network_filter(arch)),
+            (false, None) => return Err(unsupported("seccomp filters need x86_64 or aarch64")),
+        };
+
+        // Build the ruleset here, since the child can't safely allocate
+        let mut handled = (1 << 13) - 1;
+        if abi >= 2 {
+            handled |= ACCESS_REFER;
+        }
+        if abi >= 3 {
+            handled |= ACCESS_TRUNCATE;
+        }
+        if abi >= 5 {
+            handled |= ACCESS_IOCTL_DEV;
+        }
+        let ruleset = create_ruleset(handled)?;
+        let read = ACCESS_EXECUTE | ACCESS_READ_FILE | ACCESS_READ_DIR;
+        for path in read_paths {
+            add_rule(&ruleset, path, read)?;
+        }
+        let devices = read | ACCESS_WRITE_FILE | (handled & (ACCESS_TRUNCATE | ACCESS_IOCTL_DEV));
+        add_rule(&ruleset, Path::new("/dev"), devices)?;
+        for path in write_paths {
+            add_rule(&ruleset, path, handled)?;
+        }
+
+        // SAFETY: the closure only makes system calls, without allocating
+        unsafe {
+            command.pre_exec(move || {
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0
+                    || libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0) != 0
+                {
+                    return Err(Error::last_os_error());
+                }
+                if let Some(filter) = &network_filter {
+                    let program = libc::sock_fprog {
+                        len: filter.len() as u16,
+                        filter: filter.as_ptr() as *mut _,
+                    };
+                    let program = &program as *const libc::sock_fprog;
+                    if libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, program) != 0 {
+                        return Err(Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    fn create_ruleset(handled_access_fs: u64) -> std::io::Result<OwnedFd> {
+        let attr = RulesetAttr { handled_access_fs };
+        // SAFETY: `attr` outlives the call, and a returned fd is owned by us
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr as *const RulesetAttr,
+                std::mem::size_of::<RulesetAttr>(),
+                0u32,
+            )
+        };
+        if fd < 0 {
+            return Err(Error::last_os_error());
+        }
+        // SAFETY: the kernel just returned this fd
+        Ok(unsafe { OwnedFd::from_raw_fd(fd as i32) })
+    }
+
+    /// Allow `access` beneath `path`; paths that don't exist are skipped
+    fn add_rule(ruleset: &OwnedFd, path: &Path, access: u64) -> std::io::Result<()> {
+        let Ok(name) = CString::new(path.as_os_str().as_bytes()) else {
+            return Ok(());
+        };
+        // SAFETY: `name` is a valid C string; the fd is closed on drop
+        let fd = unsafe { libc::open(name.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Ok(());
+        }
+        let parent = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        // Files only take file rights
+        let access = if path.is_dir() {
+            access
+        } else {
+            access & (ACCESS_EXECUTE | ACCESS_WRITE_FILE | ACCESS_READ_FILE | ACCESS_TRUNCATE)
+        };
+        let attr = PathBeneathAttr {
+            allowed_access: access,
+            parent_fd: parent.as_raw_fd(),
+        };
+        // SAFETY: `attr` and both fds outlive the call
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset.as_raw_fd(),
+                RULE_PATH_BENEATH,
+                &attr as *const PathBeneathAttr,
+                0u32,
+            )
+        };
+        if result != 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// A seccomp filter failing every `socket` call, and io_uring (which could
+    /// make them), with `EACCES`
+    ///
+    /// Unix sockets are denied too, since they could reach daemons on the
+    /// host such as Docker's. `socketpair` is still allowed; its sockets can
+    /// only talk to each other.
+    ///
+    /// On x86_64, x32 system calls share the architecture but set bit 30 of
+    /// the number, so they'd get past the number checks; they fail too.
+    fn network_filter(arch: u32) -> Vec<libc::sock_filter> {
+        use libc::{
+            BPF_ABS, BPF_JEQ, BPF_JMP, BPF_JSET, BPF_JUMP, BPF_K, BPF_LD, BPF_RET, BPF_STMT, BPF_W,
+        };
+
+        // Offsets into `struct seccomp_data`
+        const NR: u32 = 0;
+        const ARCH: u32 = 4;
+        // SAFETY: BPF_STMT and BPF_JUMP only fill in a struct
+        let statement = |code: u32, k| unsafe { BPF_STMT(code as u16, k) };
+        let load = |offset| statement(BPF_LD | BPF_W | BPF_ABS, offset);
+        let jump_if = |value, then, otherwise| unsafe {
+            BPF_JUMP((BPF_JMP | BPF_JEQ | BPF_K) as u16, value, then, otherwise)
+        };
+        let allow = statement(BPF_RET, libc::SECCOMP_RET_ALLOW);
+        let deny = statement(BPF_RET, libc::SECCOMP_RET_ERRNO | libc::EACCES as u32);
+
+        let mut filter = vec![
+            // Other architectures' system call numbers mean something else
+            load(ARCH),
+            jump_if(arch, 1, 0),
+            deny,
+            load(NR),
+        ];
+        if cfg!(target_arch = "x86_64") {
+            const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+            // SAFETY: BPF_JUMP only fills in a struct
+            let x32 = unsafe { BPF_JUMP((BPF_JMP | BPF_JSET | BPF_K) as u16, X32_SYSCALL_BIT, 3, 0) };
+            filter.push(x32);
+        }
+        filter.extend([
+            jump_if(libc::SYS_io_uring_setup as u32, 2, 0),
+            jump_if(libc::SYS_socket as u32, 1, 0),
+            allow,
+            deny,
+        ]);
+        filter
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod linux {
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    pub(super) const AUDIT_ARCH: Option<u32> = None;
+
+    pub(super) fn landlock_abi() -> Option<i64> {
+        None
+    }
+
+    pub(super) fn confine(
+        _command: &mut Command,
+        _read_paths: &[PathBuf],
+        _write_paths: &[&Path],
+        _allow_network: bool,
+    ) -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "The sandbox is only supported on Linux",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sandbox() {
+        if !Sandbox::is_supported() {
+            println!("Skipping test: Landlock not available");
+            return;
+        }
+
+        let project =
+            std::env::temp_dir().join(format!("blockly_sandbox_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&project).unwrap();
+        let outside =
+            std::env::temp_dir().join(format!("blockly_outside_{}", uuid::Uuid::new_v4()));
+
+        let script = format!(
+            "echo inside > inside.txt && cat inside.txt; echo outside > {}",
+            outside.display()
+        );
+        let mut command = Command::new("sh");
+        command.args(["-c", &script]).current_dir(&project);
+        Sandbox::default().apply(&mut command, &[]).unwrap();
+        let output = command.output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "inside\n");
+        assert!(!outside.exists(), "Writes outside the project should be denied");
+
+        for family in ["AF_INET", "AF_UNIX"] {
+            let script = format!("import socket; socket.socket(socket.{})", family);
+            let mut command = Command::new("python3");
+            command.args(["-c", &script]).current_dir(&project);
+            Sandbox::default().apply(&mut command, &[]).unwrap();
+            if let Ok(output) = command.output() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                assert!(stderr.contains("PermissionError"), "{}: {}", family, stderr);
+            }
+        }
+
+        // The x32 `socket` is the x86_64 one with bit 30 set
+        if cfg!(target_arch = "x86_64") {
+            let script = "import ctypes; libc = ctypes.CDLL(None, use_errno=True); \
+                          libc.syscall(0x40000000 | 41, 2, 1, 0); print(ctypes.get_errno())";
+            let mut command = Command::new("python3");
+            command.args(["-c", script]).current_dir(&project);
+            Sandbox::default().apply(&mut command, &[]).unwrap();
+            if let Ok(output) = command.output() {
+                let errno = String::from_utf8_lossy(&output.stdout);
+                assert_eq!(errno.trim(), libc::EACCES.to_string());
+            }
+        }
+        let _ = std::fs::remove_dir_all(&project);
+
+        let sandbox = Sandbox::default();
+        let cargo_home = sandbox.write_paths[0].parent().unwrap();
+        let credentials = cargo_home.join("credentials.toml");
+        assert!(!sandbox.read_paths.iter().any(|path| credentials.starts_with(path)));
+        assert!(sandbox.read_paths.contains(&cargo_home.join("registry/src")));
+        assert!(sandbox.write_paths.iter().all(|path| path.starts_with(cargo_home)));
+        assert!(!sandbox.write_paths.iter().any(|path| cargo_home.join("bin").starts_with(path)));
+    }
+}
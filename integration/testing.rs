@@ -0,0 +1,78 @@
+// Testing helpers for crates building on this one
+// Enabled with the `testing` feature
+
+use crate::rust_compiler::{self, CompilationResult, RustCompiler};
+
+/// Run `f` with a compiler, or print a note and skip it if cargo isn't installed
+///
+/// Returns `None` when skipped.
+pub fn with_compiler<R>(f: impl FnOnce(&RustCompiler) -> R) -> Option<R> {
+    if !rust_compiler::is_cargo_available() {
+        println!("Skipping: cargo not available");
+        return None;
+    }
+
+    let compiler = RustCompiler::new().expect("Failed to create RustCompiler");
+    Some(f(&compiler))
+}
+
+/// Assert that `code` compiles without errors, returning the result
+///
+/// Skips the check (and returns `None`) if cargo isn't installed.
+pub fn assert_compiles(code: &str) -> Option<CompilationResult> {
+    with_compiler(|compiler| {
+        let result = compiler.check_code(code).expect("Check failed to run");
+        assert!(
+            result.success,
+            "Expected code to compile, got errors:\n{}",
+            render_errors(&result)
+        );
+        result
+    })
+}
+
+/// Assert that `code` fails to compile with an error carrying `error_code`,
+/// such as `E0308`, returning the result
+///
+/// Skips the check (and returns `None`) if cargo isn't installed.
+pub fn assert_fails_with(code: &str, error_code: &str) -> Option<CompilationResult> {
+    with_compiler(|compiler| {
+        let result = compiler.check_code(code).expect("Check failed to run");
+        assert!(!result.success, "Expected code to fail with {}, but it compiled", error_code);
+        assert!(
+            result
+                .errors
+                .iter()
+                .any(|error| error.code.as_deref() == Some(error_code)),
+            "Expected an error with code {}, got:\n{}",
+            error_code,
+            render_errors(&result)
+        );
+        result
+    })
+}
+
+fn render_errors(result: &CompilationResult) -> String {
+    result
+        .errors
+        .iter()
+        .map(|error| error.message.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_helpers() {
+        assert_compiles("let x: i32 = 1 + 2;\nprintln!(\"{}\", x);");
+        assert_fails_with("let x: i32 = \"text\";", "E0308");
+
+        let quick = with_compiler(|compiler| compiler.quick_check("pub fn f() {}").unwrap());
+        if let Some(result) = quick {
+            assert!(result.success);
+        }
+    }
+}